@@ -1,9 +1,11 @@
 #![no_main]
 #![no_std]
-#![feature(exclusive_range_pattern)]
 
-use uefi_spec::efi::{self, Status};
-use uefi_spec::{global_data::GlobalData, protocols::simple_text_input};
+use core::fmt::Write;
+
+use uefi_spec::efi;
+use uefi_spec::global_data::GlobalData;
+use uefi_spec::protocols::{simple_text_input::ConsoleIn, simple_text_output::ConsoleOut};
 
 #[panic_handler]
 fn panic_handler(_info: &core::panic::PanicInfo) -> ! {
@@ -21,61 +23,28 @@ pub extern "C" fn main(_h: efi::Handle, st: *mut efi::SystemTable) -> efi::Statu
         return efi::Status::ABORTED;
     }
 
-    efi::Status::SUCCESS
-}
-
-struct Stdin;
-
-impl Stdin {
-    fn read(buf: &mut [u8]) -> Result<usize, Status> {
-        let buf_len = buf.len();
-        let mut buf_count = 0;
-
-        let st = match GLOBAL_SYSTEM_TABLE.load() {
-            Ok(x) => x,
-            Err(_) => return Err(efi::Status::ABORTED),
-        };
+    let st = match GLOBAL_SYSTEM_TABLE.load() {
+        Ok(x) => x,
+        Err(_) => return efi::Status::ABORTED,
+    };
 
-        // Max 3 bytes can be required to store a ucs2 character as utf8
-        while buf_len - buf_count >= 3 {
-            let ch = match simple_text_input::read_key_stroke(st) {
-                // Need to add check for non-printable keys
-                Ok(x) => x.unicode_char,
-                Err(_) => return Err(efi::Status::ABORTED),
-            };
+    let mut stdin = ConsoleIn::new(st);
+    let mut stdout = ConsoleOut::new(st);
+    let mut buf = [0u8; MAX_BUFFER_SIZE];
 
-            let l = utf16_to_utf8_char(ch, &mut buf[buf_count..]);
-            buf_count += l;
-        }
-
-        Ok(buf_count)
+    if write!(stdout, "What is your name? ").is_err() {
+        return efi::Status::ABORTED;
     }
-}
 
-fn utf16_to_utf8_char(ch: u16, buf: &mut [u8]) -> usize {
-    match ch {
-        0b0000_0000_0000_0000..0b0000_0000_0111_1111 => {
-            // 1-byte
-            buf[0] = ch as u8;
-            1
-        }
-        0b0000_0000_0111_1111..0b0000_0111_1111_1111 => {
-            // 2-byte
-            let a = ((ch & 0b0000_0111_1100_0000) >> 6) as u8;
-            let b = (ch & 0b0000_0000_0011_1111) as u8;
-            buf[0] = a | 0b1100_0000;
-            buf[1] = b | 0b1000_0000;
-            2
-        }
-        _ => {
-            // 3-byte
-            let a = ((ch & 0b1111_0000_0000_0000) >> 12) as u8;
-            let b = ((ch & 0b0000_1111_1100_0000) >> 6) as u8;
-            let c = (ch & 0b0000_0000_0011_1111) as u8;
-            buf[0] = a | 0b1110_0000;
-            buf[1] = b | 0b1000_0000;
-            buf[2] = c | 0b1000_0000;
-            3
-        }
+    // Blocks until Enter is pressed. The line is echoed and can be edited with Backspace.
+    let n = match stdin.read_line(&mut buf) {
+        Ok(x) => x,
+        Err(_) => return efi::Status::ABORTED,
+    };
+    let name = core::str::from_utf8(&buf[..n]).unwrap_or("").trim_end();
+
+    match writeln!(stdout, "Hello, {}!", name) {
+        Ok(_) => efi::Status::SUCCESS,
+        Err(_) => efi::Status::ABORTED,
     }
 }
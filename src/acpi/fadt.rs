@@ -0,0 +1,189 @@
+//! The Fixed ACPI Description Table, describing the fixed hardware registers.
+//!
+//! The table grew with every ACPI revision, so the fields past the ACPI 1.0 layout are only
+//! decoded if the table is long enough to hold them.
+
+use super::{read_u16, read_u32, read_u64, read_u8, GenericAddress, Result, Sdt};
+use crate::errors::AcpiError;
+
+/// Length of the ACPI 1.0 FADT body, up to and including `Flags`.
+const V1_BODY_SIZE: usize = 80;
+
+/// Offsets into the FADT body.
+const FIRMWARE_CTRL: usize = 0;
+const DSDT: usize = 4;
+const PREFERRED_PM_PROFILE: usize = 9;
+const SCI_INT: usize = 10;
+const SMI_CMD: usize = 12;
+const ACPI_ENABLE: usize = 16;
+const ACPI_DISABLE: usize = 17;
+const PM1A_EVT_BLK: usize = 20;
+const PM1A_CNT_BLK: usize = 28;
+const PM_TMR_BLK: usize = 40;
+const CENTURY: usize = 72;
+const IAPC_BOOT_ARCH: usize = 73;
+const FLAGS: usize = 76;
+const RESET_REG: usize = 80;
+const RESET_VALUE: usize = 92;
+const ARM_BOOT_ARCH: usize = 93;
+const MINOR_VERSION: usize = 95;
+const X_FIRMWARE_CTRL: usize = 96;
+const X_DSDT: usize = 104;
+const X_PM1A_EVT_BLK: usize = 112;
+const X_PM1A_CNT_BLK: usize = 136;
+const X_PM_TMR_BLK: usize = 172;
+
+/// `Flags`: the reset register is supported.
+pub const RESET_REG_SUP: u32 = 1 << 10;
+/// `Flags`: the platform is hardware-reduced, without the fixed hardware of PCs.
+pub const HW_REDUCED_ACPI: u32 = 1 << 20;
+
+/// `IA-PC Boot Architecture Flags`: the platform has an 8042 keyboard controller.
+pub const IAPC_8042: u16 = 1 << 1;
+/// `ARM Boot Architecture Flags`: PSCI is implemented.
+pub const ARM_PSCI_COMPLIANT: u16 = 1;
+
+/// The parsed FADT.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fadt {
+    pub revision: u8,
+    pub minor_version: u8,
+    pub firmware_ctrl: u32,
+    pub dsdt: u32,
+    pub preferred_pm_profile: u8,
+    pub sci_int: u16,
+    pub smi_cmd: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub pm1a_evt_blk: u32,
+    pub pm1a_cnt_blk: u32,
+    pub pm_tmr_blk: u32,
+    pub century: u8,
+    pub iapc_boot_arch: u16,
+    pub flags: u32,
+    /// The reset register and the value to write to it, from ACPI 2.0 on.
+    pub reset: Option<(GenericAddress, u8)>,
+    pub arm_boot_arch: u16,
+    pub x_firmware_ctrl: u64,
+    pub x_dsdt: u64,
+    pub x_pm1a_evt_blk: Option<GenericAddress>,
+    pub x_pm1a_cnt_blk: Option<GenericAddress>,
+    pub x_pm_tmr_blk: Option<GenericAddress>,
+}
+
+impl Fadt {
+    pub const SIGNATURE: &'static [u8; 4] = b"FACP";
+
+    pub fn parse(sdt: &Sdt<'_>) -> Result<Self> {
+        if sdt.signature() != Self::SIGNATURE {
+            return Err(AcpiError::InvalidSignature);
+        }
+        let body = sdt.body();
+        if body.len() < V1_BODY_SIZE {
+            return Err(AcpiError::Truncated);
+        }
+
+        // Fields which do not fit a shorter, older table read as zero.
+        let u8_at = |offset| read_u8(body, offset).unwrap_or(0);
+        let u16_at = |offset| read_u16(body, offset).unwrap_or(0);
+        let u64_at = |offset| read_u64(body, offset).unwrap_or(0);
+        let gas_at = |offset| {
+            body.get(offset..)
+                .and_then(|x| GenericAddress::parse(x).ok())
+        };
+
+        Ok(Self {
+            revision: sdt.revision(),
+            minor_version: u8_at(MINOR_VERSION),
+            firmware_ctrl: read_u32(body, FIRMWARE_CTRL)?,
+            dsdt: read_u32(body, DSDT)?,
+            preferred_pm_profile: read_u8(body, PREFERRED_PM_PROFILE)?,
+            sci_int: read_u16(body, SCI_INT)?,
+            smi_cmd: read_u32(body, SMI_CMD)?,
+            acpi_enable: read_u8(body, ACPI_ENABLE)?,
+            acpi_disable: read_u8(body, ACPI_DISABLE)?,
+            pm1a_evt_blk: read_u32(body, PM1A_EVT_BLK)?,
+            pm1a_cnt_blk: read_u32(body, PM1A_CNT_BLK)?,
+            pm_tmr_blk: read_u32(body, PM_TMR_BLK)?,
+            century: read_u8(body, CENTURY)?,
+            iapc_boot_arch: read_u16(body, IAPC_BOOT_ARCH)?,
+            flags: read_u32(body, FLAGS)?,
+            reset: gas_at(RESET_REG).zip(read_u8(body, RESET_VALUE).ok()),
+            arm_boot_arch: u16_at(ARM_BOOT_ARCH),
+            x_firmware_ctrl: u64_at(X_FIRMWARE_CTRL),
+            x_dsdt: u64_at(X_DSDT),
+            x_pm1a_evt_blk: gas_at(X_PM1A_EVT_BLK),
+            x_pm1a_cnt_blk: gas_at(X_PM1A_CNT_BLK),
+            x_pm_tmr_blk: gas_at(X_PM_TMR_BLK),
+        })
+    }
+
+    /// The address of the DSDT, preferring the 64-bit field.
+    pub fn dsdt_address(&self) -> u64 {
+        match self.x_dsdt {
+            0 => u64::from(self.dsdt),
+            x => x,
+        }
+    }
+
+    /// The address of the FACS, preferring the 64-bit field.
+    pub fn facs_address(&self) -> u64 {
+        match self.x_firmware_ctrl {
+            0 => u64::from(self.firmware_ctrl),
+            x => x,
+        }
+    }
+
+    pub fn is_hardware_reduced(&self) -> bool {
+        self.flags & HW_REDUCED_ACPI != 0
+    }
+
+    /// The reset register and value, if `RESET_REG_SUP` is set.
+    pub fn reset_register(&self) -> Option<(GenericAddress, u8)> {
+        self.reset.filter(|_| self.flags & RESET_REG_SUP != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acpi::make_table;
+
+    #[test]
+    fn versions() {
+        let mut body = std::vec![0u8; 244];
+        body[DSDT..DSDT + 4].copy_from_slice(&0x1000u32.to_le_bytes());
+        body[SCI_INT] = 9;
+        body[FLAGS..FLAGS + 4].copy_from_slice(&RESET_REG_SUP.to_le_bytes());
+        body[RESET_REG] = GenericAddress::SYSTEM_IO;
+        body[RESET_REG + 1] = 8;
+        body[RESET_REG + 4] = 0xf9;
+        body[RESET_REG + 5] = 0x0c;
+        body[RESET_VALUE] = 6;
+        body[X_DSDT..X_DSDT + 8].copy_from_slice(&0x1_0000_0000u64.to_le_bytes());
+        body[X_PM_TMR_BLK] = GenericAddress::SYSTEM_IO;
+        body[X_PM_TMR_BLK + 4] = 0x08;
+
+        let table = make_table(b"FACP", 6, &body);
+        let fadt = Fadt::parse(&Sdt::parse(&table).unwrap()).unwrap();
+        assert_eq!(fadt.sci_int, 9);
+        assert_eq!(fadt.dsdt_address(), 0x1_0000_0000);
+        let (reg, value) = fadt.reset_register().unwrap();
+        assert_eq!((reg.address_space_id, reg.address, value), (1, 0xcf9, 6));
+        assert_eq!(fadt.x_pm_tmr_blk.unwrap().address, 0x08);
+        assert!(!fadt.is_hardware_reduced());
+
+        // An ACPI 1.0 FADT ends after `Flags`.
+        let table = make_table(b"FACP", 1, &body[..V1_BODY_SIZE]);
+        let fadt = Fadt::parse(&Sdt::parse(&table).unwrap()).unwrap();
+        assert_eq!(fadt.dsdt_address(), 0x1000);
+        assert_eq!(fadt.reset_register(), None);
+        assert_eq!(fadt.x_pm_tmr_blk, None);
+
+        let table = make_table(b"FACP", 1, &body[..V1_BODY_SIZE - 1]);
+        assert_eq!(
+            Fadt::parse(&Sdt::parse(&table).unwrap()),
+            Err(AcpiError::Truncated)
+        );
+    }
+}
@@ -0,0 +1,71 @@
+//! The High Precision Event Timer table.
+
+use super::{read_u16, read_u32, read_u8, GenericAddress, Result, Sdt};
+use crate::errors::AcpiError;
+
+/// The parsed HPET table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hpet {
+    /// The hardware ID of the event timer block, as found in its capabilities register.
+    pub event_timer_block_id: u32,
+    pub base_address: GenericAddress,
+    pub hpet_number: u8,
+    /// The minimum clock tick in periodic mode, in units of the main counter.
+    pub minimum_tick: u16,
+    pub page_protection: u8,
+}
+
+impl Hpet {
+    pub const SIGNATURE: &'static [u8; 4] = b"HPET";
+
+    pub fn parse(sdt: &Sdt<'_>) -> Result<Self> {
+        if sdt.signature() != Self::SIGNATURE {
+            return Err(AcpiError::InvalidSignature);
+        }
+        let body = sdt.body();
+        Ok(Self {
+            event_timer_block_id: read_u32(body, 0)?,
+            base_address: GenericAddress::parse(body.get(4..).ok_or(AcpiError::Truncated)?)?,
+            hpet_number: read_u8(body, 16)?,
+            minimum_tick: read_u16(body, 17)?,
+            page_protection: read_u8(body, 19)?,
+        })
+    }
+
+    /// The number of comparators, from `event_timer_block_id`.
+    pub fn comparator_count(&self) -> u8 {
+        ((self.event_timer_block_id >> 8) & 0x1f) as u8 + 1
+    }
+
+    /// Whether the main counter is 64 bits wide, from `event_timer_block_id`.
+    pub fn is_64bit(&self) -> bool {
+        self.event_timer_block_id & (1 << 13) != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acpi::make_table;
+
+    #[test]
+    fn parse() {
+        let mut body = std::vec![0u8; 20];
+        body[..4].copy_from_slice(&0x8086_a201u32.to_le_bytes());
+        body[8..16].copy_from_slice(&0xfed0_0000u64.to_le_bytes());
+        body[17..19].copy_from_slice(&0x80u16.to_le_bytes());
+        let table = make_table(b"HPET", 1, &body);
+
+        let hpet = Hpet::parse(&Sdt::parse(&table).unwrap()).unwrap();
+        assert_eq!(hpet.base_address.address, 0xfed0_0000);
+        assert_eq!(hpet.minimum_tick, 0x80);
+        assert_eq!(hpet.comparator_count(), 3);
+        assert!(hpet.is_64bit());
+
+        let table = make_table(b"HPET", 1, &body[..19]);
+        assert_eq!(
+            Hpet::parse(&Sdt::parse(&table).unwrap()),
+            Err(AcpiError::Truncated)
+        );
+    }
+}
@@ -0,0 +1,264 @@
+//! The Multiple APIC Description Table, describing the interrupt controllers.
+
+use super::{read_u16, read_u32, read_u64, read_u8, Result, Sdt};
+use crate::errors::AcpiError;
+
+/// The interrupt controller structures follow the local interrupt controller address and flags.
+const ENTRIES_OFFSET: usize = 8;
+
+/// The system also has a PC-AT-compatible dual 8259 setup.
+pub const PCAT_COMPAT: u32 = 1;
+
+/// A local APIC or GIC CPU interface is enabled.
+pub const ENABLED: u32 = 1;
+/// A disabled processor can be enabled at runtime.
+pub const ONLINE_CAPABLE: u32 = 2;
+
+/// The parsed MADT.
+#[derive(Clone, Copy, Debug)]
+pub struct Madt<'a> {
+    pub local_apic_address: u32,
+    pub flags: u32,
+    entries: &'a [u8],
+}
+
+impl<'a> Madt<'a> {
+    pub const SIGNATURE: &'static [u8; 4] = b"APIC";
+
+    pub fn parse(sdt: &Sdt<'a>) -> Result<Self> {
+        if sdt.signature() != Self::SIGNATURE {
+            return Err(AcpiError::InvalidSignature);
+        }
+        let body = sdt.body();
+        Ok(Self {
+            local_apic_address: read_u32(body, 0)?,
+            flags: read_u32(body, 4)?,
+            entries: body.get(ENTRIES_OFFSET..).ok_or(AcpiError::Truncated)?,
+        })
+    }
+
+    /// Iterate over the interrupt controller structures. Stops after the first error.
+    pub fn entries(&self) -> MadtEntries<'a> {
+        MadtEntries { data: self.entries }
+    }
+}
+
+/// An interrupt controller structure.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MadtEntry<'a> {
+    LocalApic {
+        processor_uid: u8,
+        apic_id: u8,
+        flags: u32,
+    },
+    IoApic {
+        io_apic_id: u8,
+        address: u32,
+        global_system_interrupt_base: u32,
+    },
+    InterruptSourceOverride {
+        bus: u8,
+        source: u8,
+        global_system_interrupt: u32,
+        flags: u16,
+    },
+    NmiSource {
+        flags: u16,
+        global_system_interrupt: u32,
+    },
+    LocalApicNmi {
+        processor_uid: u8,
+        flags: u16,
+        lint: u8,
+    },
+    /// A 64-bit address replacing `Madt::local_apic_address`.
+    LocalApicAddressOverride { address: u64 },
+    LocalX2Apic {
+        x2apic_id: u32,
+        flags: u32,
+        processor_uid: u32,
+    },
+    LocalX2ApicNmi {
+        flags: u16,
+        processor_uid: u32,
+        lint: u8,
+    },
+    Gicc {
+        cpu_interface_number: u32,
+        processor_uid: u32,
+        flags: u32,
+        physical_base_address: u64,
+        mpidr: u64,
+    },
+    Gicd {
+        gic_id: u32,
+        physical_base_address: u64,
+        gic_version: u8,
+    },
+    /// A structure this parser does not decode, with its data following the type and length.
+    Unknown { entry_type: u8, data: &'a [u8] },
+}
+
+impl<'a> MadtEntry<'a> {
+    /// Decode a structure from its type and the data following the type and length.
+    fn parse(entry_type: u8, data: &'a [u8]) -> Result<Self> {
+        Ok(match entry_type {
+            0 => Self::LocalApic {
+                processor_uid: read_u8(data, 0)?,
+                apic_id: read_u8(data, 1)?,
+                flags: read_u32(data, 2)?,
+            },
+            1 => Self::IoApic {
+                io_apic_id: read_u8(data, 0)?,
+                address: read_u32(data, 2)?,
+                global_system_interrupt_base: read_u32(data, 6)?,
+            },
+            2 => Self::InterruptSourceOverride {
+                bus: read_u8(data, 0)?,
+                source: read_u8(data, 1)?,
+                global_system_interrupt: read_u32(data, 2)?,
+                flags: read_u16(data, 6)?,
+            },
+            3 => Self::NmiSource {
+                flags: read_u16(data, 0)?,
+                global_system_interrupt: read_u32(data, 2)?,
+            },
+            4 => Self::LocalApicNmi {
+                processor_uid: read_u8(data, 0)?,
+                flags: read_u16(data, 1)?,
+                lint: read_u8(data, 3)?,
+            },
+            5 => Self::LocalApicAddressOverride {
+                address: read_u64(data, 2)?,
+            },
+            9 => Self::LocalX2Apic {
+                x2apic_id: read_u32(data, 2)?,
+                flags: read_u32(data, 6)?,
+                processor_uid: read_u32(data, 10)?,
+            },
+            0xa => Self::LocalX2ApicNmi {
+                flags: read_u16(data, 0)?,
+                processor_uid: read_u32(data, 2)?,
+                lint: read_u8(data, 6)?,
+            },
+            0xb => Self::Gicc {
+                cpu_interface_number: read_u32(data, 2)?,
+                processor_uid: read_u32(data, 6)?,
+                flags: read_u32(data, 10)?,
+                physical_base_address: read_u64(data, 30)?,
+                mpidr: read_u64(data, 66)?,
+            },
+            0xc => Self::Gicd {
+                gic_id: read_u32(data, 2)?,
+                physical_base_address: read_u64(data, 6)?,
+                gic_version: read_u8(data, 18)?,
+            },
+            _ => Self::Unknown { entry_type, data },
+        })
+    }
+}
+
+/// An iterator over the interrupt controller structures, see `Madt::entries`.
+#[derive(Clone, Debug)]
+pub struct MadtEntries<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for MadtEntries<'a> {
+    type Item = Result<MadtEntry<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let entry = match self.data {
+            [_, length, ..] if *length < 2 => Err(AcpiError::InvalidLength),
+            [entry_type, length, ..] => match self.data.get(2..*length as usize) {
+                Some(x) => MadtEntry::parse(*entry_type, x).map(|x| (x, *length as usize)),
+                None => Err(AcpiError::Truncated),
+            },
+            _ => Err(AcpiError::Truncated),
+        };
+        match entry {
+            Ok((x, length)) => {
+                self.data = &self.data[length..];
+                Some(Ok(x))
+            }
+            Err(e) => {
+                self.data = &[];
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acpi::make_table;
+    use std::vec::Vec;
+
+    #[test]
+    fn entries() {
+        let mut body = Vec::new();
+        body.extend_from_slice(&0xfee0_0000u32.to_le_bytes());
+        body.extend_from_slice(&PCAT_COMPAT.to_le_bytes());
+        body.extend_from_slice(&[0, 8, 1, 2, 1, 0, 0, 0]);
+        body.extend_from_slice(&[1, 12, 3, 0, 0, 0, 0xc0, 0xfe, 0, 0, 0, 0]);
+        body.extend_from_slice(&[2, 10, 0, 0, 2, 0, 0, 0, 5, 0]);
+        body.extend_from_slice(&[0x7f, 3, 0xaa]);
+        let table = make_table(b"APIC", 4, &body);
+        let sdt = Sdt::parse(&table).unwrap();
+
+        let madt = Madt::parse(&sdt).unwrap();
+        assert_eq!(madt.local_apic_address, 0xfee0_0000);
+        assert_eq!(madt.flags, PCAT_COMPAT);
+        let entries: Vec<_> = madt.entries().map(|x| x.unwrap()).collect();
+        assert_eq!(
+            entries,
+            [
+                MadtEntry::LocalApic {
+                    processor_uid: 1,
+                    apic_id: 2,
+                    flags: ENABLED,
+                },
+                MadtEntry::IoApic {
+                    io_apic_id: 3,
+                    address: 0xfec0_0000,
+                    global_system_interrupt_base: 0,
+                },
+                MadtEntry::InterruptSourceOverride {
+                    bus: 0,
+                    source: 0,
+                    global_system_interrupt: 2,
+                    flags: 5,
+                },
+                MadtEntry::Unknown {
+                    entry_type: 0x7f,
+                    data: &[0xaa],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_entries() {
+        let mut body = std::vec![0; 8];
+        body.extend_from_slice(&[0, 8, 1, 2, 1, 0, 0, 0]);
+        body.extend_from_slice(&[1, 12, 3, 0]);
+        let table = make_table(b"APIC", 4, &body);
+        let madt = Madt::parse(&Sdt::parse(&table).unwrap()).unwrap();
+
+        let mut entries = madt.entries();
+        assert!(entries.next().unwrap().is_ok());
+        assert_eq!(entries.next().unwrap(), Err(AcpiError::Truncated));
+        assert!(entries.next().is_none());
+
+        let table = make_table(b"FACP", 6, &body);
+        assert_eq!(
+            Madt::parse(&Sdt::parse(&table).unwrap()).unwrap_err(),
+            AcpiError::InvalidSignature
+        );
+    }
+}
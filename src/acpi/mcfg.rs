@@ -0,0 +1,107 @@
+//! The PCI Express memory mapped configuration space table.
+
+use super::{read_u16, read_u64, read_u8, Result, Sdt};
+use crate::errors::AcpiError;
+
+/// The allocations follow 8 reserved bytes.
+const ENTRIES_OFFSET: usize = 8;
+const ENTRY_SIZE: usize = 16;
+
+/// The configuration space of a range of buses in a PCI segment group.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct McfgEntry {
+    /// The address of the configuration space of bus 0, even if `start_bus` is larger.
+    pub base_address: u64,
+    pub segment_group: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+impl McfgEntry {
+    /// The address of the configuration space of a function, if its bus is in range.
+    pub fn config_address(&self, bus: u8, device: u8, function: u8) -> Option<u64> {
+        if bus < self.start_bus || bus > self.end_bus || device >= 32 || function >= 8 {
+            return None;
+        }
+        let offset =
+            (u64::from(bus) << 20) | (u64::from(device) << 15) | (u64::from(function) << 12);
+        Some(self.base_address + offset)
+    }
+}
+
+/// The parsed MCFG.
+#[derive(Clone, Copy, Debug)]
+pub struct Mcfg<'a> {
+    entries: &'a [u8],
+}
+
+impl<'a> Mcfg<'a> {
+    pub const SIGNATURE: &'static [u8; 4] = b"MCFG";
+
+    pub fn parse(sdt: &Sdt<'a>) -> Result<Self> {
+        if sdt.signature() != Self::SIGNATURE {
+            return Err(AcpiError::InvalidSignature);
+        }
+        let entries = sdt
+            .body()
+            .get(ENTRIES_OFFSET..)
+            .ok_or(AcpiError::Truncated)?;
+        if !entries.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(AcpiError::InvalidLength);
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn entries(&self) -> impl ExactSizeIterator<Item = McfgEntry> + 'a {
+        self.entries.chunks_exact(ENTRY_SIZE).map(|x| McfgEntry {
+            // The chunks are exactly as long as the entries, so the reads cannot fail.
+            base_address: read_u64(x, 0).unwrap(),
+            segment_group: read_u16(x, 8).unwrap(),
+            start_bus: read_u8(x, 10).unwrap(),
+            end_bus: read_u8(x, 11).unwrap(),
+        })
+    }
+
+    /// The entry covering `bus` in `segment_group`.
+    pub fn find(&self, segment_group: u16, bus: u8) -> Option<McfgEntry> {
+        self.entries()
+            .find(|x| x.segment_group == segment_group && (x.start_bus..=x.end_bus).contains(&bus))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acpi::make_table;
+    use std::vec::Vec;
+
+    #[test]
+    fn parse() {
+        let mut body = std::vec![0u8; 8];
+        for (base, segment, start, end) in [
+            (0xe000_0000u64, 0u16, 0u8, 0x3fu8),
+            (0xf000_0000, 1, 0x80, 0xff),
+        ] {
+            body.extend_from_slice(&base.to_le_bytes());
+            body.extend_from_slice(&segment.to_le_bytes());
+            body.extend_from_slice(&[start, end, 0, 0, 0, 0]);
+        }
+        let table = make_table(b"MCFG", 1, &body);
+
+        let mcfg = Mcfg::parse(&Sdt::parse(&table).unwrap()).unwrap();
+        assert_eq!(
+            mcfg.entries().map(|x| x.segment_group).collect::<Vec<_>>(),
+            [0, 1]
+        );
+        assert!(mcfg.find(0, 0x40).is_none());
+        let entry = mcfg.find(1, 0x80).unwrap();
+        assert_eq!(entry.config_address(0x80, 1, 2), Some(0xf800_a000));
+        assert_eq!(entry.config_address(0x7f, 0, 0), None);
+
+        let table = make_table(b"MCFG", 1, &body[..20]);
+        assert_eq!(
+            Mcfg::parse(&Sdt::parse(&table).unwrap()).unwrap_err(),
+            AcpiError::InvalidLength
+        );
+    }
+}
@@ -0,0 +1,466 @@
+//! This module contains a parser for the ACPI tables found through the RSDP configuration table.
+//!
+//! Parsing works on byte slices and never dereferences the physical addresses stored in the
+//! tables. Callers resolve them with a closure, so the same code runs on firmware, where memory
+//! is identity mapped (see `identity_mapped`), and on table dumps captured from a running system.
+
+pub mod fadt;
+pub mod hpet;
+pub mod madt;
+pub mod mcfg;
+pub mod spcr;
+
+use core::ffi::c_void;
+
+use crate::errors::AcpiError;
+
+type Result<T> = core::result::Result<T, AcpiError>;
+
+/// Size of the ACPI 1.0 RSDP, which is covered by the first checksum.
+pub const RSDP_V1_SIZE: usize = 20;
+/// Size of the ACPI 2.0 RSDP.
+pub const RSDP_V2_SIZE: usize = 36;
+/// Size of the header common to all system description tables.
+pub const SDT_HEADER_SIZE: usize = 36;
+
+/// Read `N` bytes at `offset`.
+fn read<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N]> {
+    data.get(offset..offset + N)
+        .and_then(|x| x.try_into().ok())
+        .ok_or(AcpiError::Truncated)
+}
+
+fn read_u8(data: &[u8], offset: usize) -> Result<u8> {
+    data.get(offset).copied().ok_or(AcpiError::Truncated)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    read(data, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    read(data, offset).map(u32::from_le_bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64> {
+    read(data, offset).map(u64::from_le_bytes)
+}
+
+/// Check that `data` sums to zero, as required for every checksummed ACPI structure.
+pub fn validate_checksum(data: &[u8]) -> Result<()> {
+    match data.iter().fold(0u8, |acc, x| acc.wrapping_add(*x)) {
+        0 => Ok(()),
+        _ => Err(AcpiError::InvalidChecksum),
+    }
+}
+
+/// The Root System Description Pointer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rsdp {
+    pub oem_id: [u8; 6],
+    pub revision: u8,
+    pub rsdt_address: u32,
+    /// The XSDT address, present from ACPI 2.0 on.
+    pub xsdt_address: Option<u64>,
+}
+
+impl Rsdp {
+    pub const SIGNATURE: &'static [u8; 8] = b"RSD PTR ";
+
+    /// Parse and validate the RSDP at the start of `data`.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let v1 = data.get(..RSDP_V1_SIZE).ok_or(AcpiError::Truncated)?;
+        if &v1[..8] != Self::SIGNATURE {
+            return Err(AcpiError::InvalidSignature);
+        }
+        validate_checksum(v1)?;
+
+        let revision = v1[15];
+        let mut rsdp = Self {
+            oem_id: read(v1, 9)?,
+            revision,
+            rsdt_address: read_u32(v1, 16)?,
+            xsdt_address: None,
+        };
+        if revision >= 2 {
+            let length = read_u32(data, 20)? as usize;
+            if length < RSDP_V2_SIZE {
+                return Err(AcpiError::InvalidLength);
+            }
+            validate_checksum(data.get(..length).ok_or(AcpiError::Truncated)?)?;
+            rsdp.xsdt_address = Some(read_u64(data, 24)?);
+        }
+        Ok(rsdp)
+    }
+
+    /// Parse the RSDP at `ptr`, e.g. the table returned by `configuration_table::acpi_rsdp`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to readable memory holding an RSDP, or at least 20 bytes.
+    pub unsafe fn from_ptr(ptr: *const c_void) -> Result<Self> {
+        let ptr = ptr.cast::<u8>();
+        let v1 = unsafe { core::slice::from_raw_parts(ptr, RSDP_V1_SIZE) };
+        if &v1[..8] != Self::SIGNATURE {
+            return Err(AcpiError::InvalidSignature);
+        }
+        let len = match v1[15] {
+            0 | 1 => RSDP_V1_SIZE,
+            _ => (read_u32(unsafe { core::slice::from_raw_parts(ptr, 24) }, 20)? as usize)
+                .max(RSDP_V1_SIZE),
+        };
+        Self::parse(unsafe { core::slice::from_raw_parts(ptr, len) })
+    }
+
+    /// The address of the XSDT if present, or else of the RSDT.
+    pub fn root_table_address(&self) -> u64 {
+        match self.xsdt_address {
+            Some(x) if x != 0 => x,
+            _ => u64::from(self.rsdt_address),
+        }
+    }
+}
+
+/// The header common to all system description tables.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+impl SdtHeader {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        Ok(Self {
+            signature: read(data, 0)?,
+            length: read_u32(data, 4)?,
+            revision: read_u8(data, 8)?,
+            checksum: read_u8(data, 9)?,
+            oem_id: read(data, 10)?,
+            oem_table_id: read(data, 16)?,
+            oem_revision: read_u32(data, 24)?,
+            creator_id: read_u32(data, 28)?,
+            creator_revision: read_u32(data, 32)?,
+        })
+    }
+
+    /// Write the header to the start of `out`, as is. See `finalize_table` to fix the length and
+    /// checksum afterwards.
+    pub fn write(&self, out: &mut [u8]) -> Result<()> {
+        let out = out.get_mut(..SDT_HEADER_SIZE).ok_or(AcpiError::Truncated)?;
+        out[0..4].copy_from_slice(&self.signature);
+        out[4..8].copy_from_slice(&self.length.to_le_bytes());
+        out[8] = self.revision;
+        out[9] = self.checksum;
+        out[10..16].copy_from_slice(&self.oem_id);
+        out[16..24].copy_from_slice(&self.oem_table_id);
+        out[24..28].copy_from_slice(&self.oem_revision.to_le_bytes());
+        out[28..32].copy_from_slice(&self.creator_id.to_le_bytes());
+        out[32..36].copy_from_slice(&self.creator_revision.to_le_bytes());
+        Ok(())
+    }
+}
+
+/// Set the length of the table in `table` to the length of the slice, and recompute its checksum.
+pub fn finalize_table(table: &mut [u8]) -> Result<()> {
+    if table.len() < SDT_HEADER_SIZE {
+        return Err(AcpiError::Truncated);
+    }
+    let length = u32::try_from(table.len()).map_err(|_| AcpiError::InvalidLength)?;
+    table[4..8].copy_from_slice(&length.to_le_bytes());
+    table[9] = 0;
+    let sum = table.iter().fold(0u8, |acc, x| acc.wrapping_add(*x));
+    table[9] = 0u8.wrapping_sub(sum);
+    Ok(())
+}
+
+/// A system description table whose length and checksum were validated.
+#[derive(Clone, Copy, Debug)]
+pub struct Sdt<'a> {
+    header: SdtHeader,
+    data: &'a [u8],
+}
+
+impl<'a> Sdt<'a> {
+    /// Parse and validate the table at the start of `data`. Trailing bytes are ignored.
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        let header = SdtHeader::parse(data)?;
+        let length = header.length as usize;
+        if length < SDT_HEADER_SIZE {
+            return Err(AcpiError::InvalidLength);
+        }
+        let data = data.get(..length).ok_or(AcpiError::Truncated)?;
+        validate_checksum(data)?;
+        Ok(Self { header, data })
+    }
+
+    /// Like `parse`, but also check the signature.
+    pub fn parse_with_signature(data: &'a [u8], signature: &[u8; 4]) -> Result<Self> {
+        let sdt = Self::parse(data)?;
+        if sdt.signature() != signature {
+            return Err(AcpiError::InvalidSignature);
+        }
+        Ok(sdt)
+    }
+
+    pub fn header(&self) -> &SdtHeader {
+        &self.header
+    }
+
+    pub fn signature(&self) -> &[u8; 4] {
+        &self.header.signature
+    }
+
+    pub fn revision(&self) -> u8 {
+        self.header.revision
+    }
+
+    /// The data following the header.
+    pub fn body(&self) -> &'a [u8] {
+        &self.data[SDT_HEADER_SIZE..]
+    }
+
+    /// The whole table, header included.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+/// The RSDT or XSDT, listing the addresses of all other tables except the FACS and the DSDT.
+#[derive(Clone, Copy, Debug)]
+pub struct RootTable<'a> {
+    sdt: Sdt<'a>,
+    entry_size: usize,
+}
+
+impl<'a> RootTable<'a> {
+    /// Parse an RSDT, with 32-bit entries, or an XSDT, with 64-bit entries.
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        let sdt = Sdt::parse(data)?;
+        let entry_size = match sdt.signature() {
+            b"RSDT" => 4,
+            b"XSDT" => 8,
+            _ => return Err(AcpiError::InvalidSignature),
+        };
+        Ok(Self { sdt, entry_size })
+    }
+
+    pub fn sdt(&self) -> &Sdt<'a> {
+        &self.sdt
+    }
+
+    /// The physical addresses of the tables.
+    pub fn entries(&self) -> impl Iterator<Item = u64> + 'a {
+        let entry_size = self.entry_size;
+        self.sdt.body().chunks_exact(entry_size).map(move |x| {
+            let mut buf = [0; 8];
+            buf[..entry_size].copy_from_slice(x);
+            u64::from_le_bytes(buf)
+        })
+    }
+
+    /// Iterate over the tables. `map` returns the memory at a physical address, holding at least
+    /// the whole table, or `None` if it is not accessible.
+    pub fn tables<F>(&self, map: F) -> Sdts<'a, F>
+    where
+        F: FnMut(u64) -> Option<&'a [u8]>,
+    {
+        Sdts {
+            root: *self,
+            index: 0,
+            map,
+        }
+    }
+
+    /// The first table with `signature`. Tables which cannot be mapped or fail to parse are
+    /// skipped.
+    pub fn find<F>(&self, signature: &[u8; 4], map: F) -> Option<Sdt<'a>>
+    where
+        F: FnMut(u64) -> Option<&'a [u8]>,
+    {
+        self.tables(map)
+            .filter_map(|x| x.ok())
+            .find(|x| x.signature() == signature)
+    }
+}
+
+/// An iterator over the tables listed in the RSDT or XSDT, see `RootTable::tables`.
+pub struct Sdts<'a, F> {
+    root: RootTable<'a>,
+    index: usize,
+    map: F,
+}
+
+impl<'a, F> Iterator for Sdts<'a, F>
+where
+    F: FnMut(u64) -> Option<&'a [u8]>,
+{
+    type Item = Result<Sdt<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let address = self.root.entries().nth(self.index)?;
+        self.index += 1;
+        Some(match (self.map)(address) {
+            Some(x) => Sdt::parse(x),
+            None => Err(AcpiError::Truncated),
+        })
+    }
+}
+
+/// Map a table at a physical address when memory is identity mapped, as during boot services.
+/// The returned slice covers the length given in the table header.
+///
+/// # Safety
+///
+/// `address` must point to readable memory holding a system description table, and the memory
+/// must not be freed or changed while the slice is in use.
+pub unsafe fn identity_mapped(address: u64) -> Option<&'static [u8]> {
+    let ptr = usize::try_from(address).ok()? as *const u8;
+    if ptr.is_null() {
+        return None;
+    }
+    let header = unsafe { core::slice::from_raw_parts(ptr, SDT_HEADER_SIZE) };
+    let length = (read_u32(header, 4).ok()? as usize).max(SDT_HEADER_SIZE);
+    Some(unsafe { core::slice::from_raw_parts(ptr, length) })
+}
+
+/// The ACPI Generic Address Structure, describing a register in some address space.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GenericAddress {
+    pub address_space_id: u8,
+    pub register_bit_width: u8,
+    pub register_bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+impl GenericAddress {
+    pub const SIZE: usize = 12;
+
+    pub const SYSTEM_MEMORY: u8 = 0;
+    pub const SYSTEM_IO: u8 = 1;
+    pub const PCI_CONFIGURATION: u8 = 2;
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        Ok(Self {
+            address_space_id: read_u8(data, 0)?,
+            register_bit_width: read_u8(data, 1)?,
+            register_bit_offset: read_u8(data, 2)?,
+            access_size: read_u8(data, 3)?,
+            address: read_u64(data, 4)?,
+        })
+    }
+}
+
+/// Build a table with a valid checksum, for the tests of the table parsers.
+#[cfg(test)]
+pub(crate) fn make_table(signature: &[u8; 4], revision: u8, body: &[u8]) -> std::vec::Vec<u8> {
+    let mut table = std::vec::Vec::new();
+    table.extend_from_slice(signature);
+    table.extend_from_slice(&((SDT_HEADER_SIZE + body.len()) as u32).to_le_bytes());
+    table.push(revision);
+    table.push(0);
+    table.extend_from_slice(b"OEMID ");
+    table.extend_from_slice(b"OEMTABLE");
+    table.extend_from_slice(&1u32.to_le_bytes());
+    table.extend_from_slice(b"TEST");
+    table.extend_from_slice(&1u32.to_le_bytes());
+    table.extend_from_slice(body);
+    table[9] = fix_checksum(&table);
+    table
+}
+
+#[cfg(test)]
+fn fix_checksum(data: &[u8]) -> u8 {
+    0u8.wrapping_sub(data.iter().fold(0u8, |acc, x| acc.wrapping_add(*x)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn make_rsdp(xsdt: u64) -> Vec<u8> {
+        let mut rsdp = Vec::new();
+        rsdp.extend_from_slice(Rsdp::SIGNATURE);
+        rsdp.push(0);
+        rsdp.extend_from_slice(b"OEMID ");
+        rsdp.push(2);
+        rsdp.extend_from_slice(&0x1000u32.to_le_bytes());
+        rsdp.extend_from_slice(&(RSDP_V2_SIZE as u32).to_le_bytes());
+        rsdp.extend_from_slice(&xsdt.to_le_bytes());
+        rsdp.extend_from_slice(&[0; 4]);
+        rsdp[8] = fix_checksum(&rsdp[..RSDP_V1_SIZE]);
+        rsdp[32] = fix_checksum(&rsdp);
+        rsdp
+    }
+
+    #[test]
+    fn rsdp() {
+        let mut data = make_rsdp(0x2000);
+        let rsdp = Rsdp::parse(&data).unwrap();
+        assert_eq!(rsdp.revision, 2);
+        assert_eq!(&rsdp.oem_id, b"OEMID ");
+        assert_eq!(rsdp.rsdt_address, 0x1000);
+        assert_eq!(rsdp.root_table_address(), 0x2000);
+        assert_eq!(unsafe { Rsdp::from_ptr(data.as_ptr().cast()) }, Ok(rsdp));
+
+        assert_eq!(
+            Rsdp::parse(&data[..RSDP_V2_SIZE - 1]),
+            Err(AcpiError::Truncated)
+        );
+        data[30] ^= 1;
+        assert_eq!(Rsdp::parse(&data), Err(AcpiError::InvalidChecksum));
+        data[0] = b'X';
+        assert_eq!(Rsdp::parse(&data), Err(AcpiError::InvalidSignature));
+    }
+
+    #[test]
+    fn enumerate_tables() {
+        let tables = [make_table(b"APIC", 4, &[0; 8]), make_table(b"HPET", 1, &[])];
+        let mut body = Vec::new();
+        for addr in [0x1000u64, 0x3000, 0x2000] {
+            body.extend_from_slice(&addr.to_le_bytes());
+        }
+        let xsdt = make_table(b"XSDT", 1, &body);
+        let map = |addr: u64| match addr {
+            0x1000 => Some(&tables[0][..]),
+            0x2000 => Some(&tables[1][..]),
+            _ => None,
+        };
+
+        let root = RootTable::parse(&xsdt).unwrap();
+        assert_eq!(root.entries().collect::<Vec<_>>(), [0x1000, 0x3000, 0x2000]);
+        let found: Vec<_> = root
+            .tables(map)
+            .map(|x| x.map(|x| *x.signature()))
+            .collect();
+        assert_eq!(
+            found,
+            [Ok(*b"APIC"), Err(AcpiError::Truncated), Ok(*b"HPET")]
+        );
+        assert_eq!(root.find(b"HPET", map).unwrap().revision(), 1);
+        assert!(root.find(b"MCFG", map).is_none());
+
+        let mut bad = tables[0].clone();
+        bad[SDT_HEADER_SIZE] = 1;
+        assert_eq!(Sdt::parse(&bad).unwrap_err(), AcpiError::InvalidChecksum);
+        assert_eq!(
+            Sdt::parse(&tables[0][..40]).unwrap_err(),
+            AcpiError::Truncated
+        );
+        assert_eq!(
+            RootTable::parse(&tables[1]).unwrap_err(),
+            AcpiError::InvalidSignature
+        );
+
+        let identity = unsafe { identity_mapped(tables[1].as_ptr() as u64) }.unwrap();
+        assert_eq!(identity, &tables[1][..]);
+    }
+}
@@ -0,0 +1,133 @@
+//! The Serial Port Console Redirection table, describing the console UART.
+
+use super::{read_u16, read_u32, read_u8, GenericAddress, Result, Sdt};
+use crate::errors::AcpiError;
+
+/// Length of the revision 2 SPCR body.
+const V2_BODY_SIZE: usize = 44;
+
+/// `Interface Type` values.
+pub const INTERFACE_16550: u8 = 0;
+pub const INTERFACE_16450: u8 = 1;
+pub const INTERFACE_PL011: u8 = 3;
+pub const INTERFACE_16550_GAS: u8 = 0x12;
+
+/// `Interrupt Type` bits.
+pub const INTERRUPT_PIC: u8 = 1;
+pub const INTERRUPT_IO_APIC: u8 = 2;
+pub const INTERRUPT_IO_SAPIC: u8 = 4;
+pub const INTERRUPT_GIC: u8 = 8;
+
+/// The parsed SPCR.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Spcr {
+    pub revision: u8,
+    pub interface_type: u8,
+    pub base_address: GenericAddress,
+    pub interrupt_type: u8,
+    pub irq: u8,
+    pub global_system_interrupt: u32,
+    /// The raw `Configured Baud Rate` code, see `baud_rate`.
+    pub configured_baud_rate: u8,
+    pub parity: u8,
+    pub stop_bits: u8,
+    pub flow_control: u8,
+    pub terminal_type: u8,
+    pub pci_device_id: u16,
+    pub pci_vendor_id: u16,
+    pub pci_bus: u8,
+    pub pci_device: u8,
+    pub pci_function: u8,
+    pub pci_flags: u32,
+    pub pci_segment: u8,
+    /// The UART clock in Hz, from revision 3 on. Zero if unknown.
+    pub uart_clock_frequency: u32,
+}
+
+impl Spcr {
+    pub const SIGNATURE: &'static [u8; 4] = b"SPCR";
+
+    pub fn parse(sdt: &Sdt<'_>) -> Result<Self> {
+        if sdt.signature() != Self::SIGNATURE {
+            return Err(AcpiError::InvalidSignature);
+        }
+        let body = sdt.body();
+        if body.len() < V2_BODY_SIZE {
+            return Err(AcpiError::Truncated);
+        }
+
+        Ok(Self {
+            revision: sdt.revision(),
+            interface_type: read_u8(body, 0)?,
+            base_address: GenericAddress::parse(&body[4..])?,
+            interrupt_type: read_u8(body, 16)?,
+            irq: read_u8(body, 17)?,
+            global_system_interrupt: read_u32(body, 18)?,
+            configured_baud_rate: read_u8(body, 22)?,
+            parity: read_u8(body, 23)?,
+            stop_bits: read_u8(body, 24)?,
+            flow_control: read_u8(body, 25)?,
+            terminal_type: read_u8(body, 26)?,
+            pci_device_id: read_u16(body, 28)?,
+            pci_vendor_id: read_u16(body, 30)?,
+            pci_bus: read_u8(body, 32)?,
+            pci_device: read_u8(body, 33)?,
+            pci_function: read_u8(body, 34)?,
+            pci_flags: read_u32(body, 35)?,
+            pci_segment: read_u8(body, 39)?,
+            uart_clock_frequency: match sdt.revision() {
+                0..=2 => 0,
+                _ => read_u32(body, 40)?,
+            },
+        })
+    }
+
+    /// The baud rate in bits per second, or `None` if the UART is used as configured by the
+    /// firmware or the code is unknown.
+    pub fn baud_rate(&self) -> Option<u32> {
+        match self.configured_baud_rate {
+            3 => Some(9600),
+            4 => Some(19200),
+            6 => Some(57600),
+            7 => Some(115200),
+            _ => None,
+        }
+    }
+
+    /// Whether the UART is on a PCI device, rather than at `base_address`.
+    pub fn is_pci(&self) -> bool {
+        self.pci_device_id != 0xffff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acpi::make_table;
+
+    #[test]
+    fn parse() {
+        let mut body = std::vec![0u8; V2_BODY_SIZE];
+        body[0] = INTERFACE_PL011;
+        body[8..16].copy_from_slice(&0x0900_0000u64.to_le_bytes());
+        body[16] = INTERRUPT_GIC;
+        body[18..22].copy_from_slice(&33u32.to_le_bytes());
+        body[22] = 7;
+        body[24] = 1;
+        body[28..32].copy_from_slice(&[0xff; 4]);
+        body[40..44].copy_from_slice(&24_000_000u32.to_le_bytes());
+
+        let table = make_table(b"SPCR", 2, &body);
+        let spcr = Spcr::parse(&Sdt::parse(&table).unwrap()).unwrap();
+        assert_eq!(spcr.interface_type, INTERFACE_PL011);
+        assert_eq!(spcr.base_address.address, 0x0900_0000);
+        assert_eq!(spcr.global_system_interrupt, 33);
+        assert_eq!(spcr.baud_rate(), Some(115200));
+        assert!(!spcr.is_pci());
+        assert_eq!(spcr.uart_clock_frequency, 0);
+
+        let table = make_table(b"SPCR", 3, &body);
+        let spcr = Spcr::parse(&Sdt::parse(&table).unwrap()).unwrap();
+        assert_eq!(spcr.uart_clock_frequency, 24_000_000);
+    }
+}
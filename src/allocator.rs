@@ -0,0 +1,382 @@
+//! This module provides a `GlobalAlloc` implementation on top of the memory allocation boot
+//! services.
+//!
+//! Small allocations come from pool memory, while large or page-aligned ones are backed by whole
+//! pages. Alignments above what the firmware guarantees are handled by over-allocating and
+//! storing the original pointer right before the returned one.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ffi::c_void;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::boot_services::event_services;
+use crate::boot_services::memory_allocation_services::{self, PAGE_SIZE};
+use crate::efi::{MemoryType, SystemTable, LOADER_DATA};
+use crate::global_data::GlobalData;
+use crate::status::Completion;
+use r_efi::efi;
+
+/// Alignment of the memory returned by EFI_ALLOCATE_POOL.
+const POOL_ALIGNMENT: usize = 8;
+
+/// Allocations of at least this size are backed by pages rather than pool memory.
+const PAGE_THRESHOLD: usize = 16 * PAGE_SIZE;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Backing {
+    Pool,
+    Pages,
+}
+
+impl Backing {
+    fn of(layout: Layout) -> Self {
+        if layout.align() >= PAGE_SIZE || layout.size() >= PAGE_THRESHOLD {
+            Self::Pages
+        } else {
+            Self::Pool
+        }
+    }
+
+    /// The alignment the firmware guarantees without any extra work.
+    fn alignment(self) -> usize {
+        match self {
+            Self::Pool => POOL_ALIGNMENT,
+            Self::Pages => PAGE_SIZE,
+        }
+    }
+
+    /// The number of bytes to request from the firmware for `layout`.
+    fn size(self, layout: Layout) -> usize {
+        if layout.align() > self.alignment() {
+            // Room to move the pointer up to the alignment, and for the `Header`.
+            layout.size() + layout.align()
+        } else {
+            layout.size()
+        }
+    }
+}
+
+/// Stored right before an over-aligned allocation, to find the pointer to free.
+#[repr(C)]
+struct Header(*mut u8);
+
+/// A `GlobalAlloc` using the boot services of the `SystemTable` stored in a `GlobalData`.
+///
+/// Boot services are gone after ExitBootServices. From then on, allocations fail and
+/// deallocations leak, instead of calling into the firmware. Use
+/// `register_exit_boot_services_event` or `exit_boot_services` to let the allocator know.
+pub struct UefiAllocator {
+    st: &'static GlobalData<SystemTable>,
+    memory_type: MemoryType,
+    exited: AtomicBool,
+}
+
+impl UefiAllocator {
+    /// Create an allocator handing out `LOADER_DATA` memory. This is constant so that it can be
+    /// used in statics.
+    pub const fn new(st: &'static GlobalData<SystemTable>) -> Self {
+        Self {
+            st,
+            memory_type: LOADER_DATA,
+            exited: AtomicBool::new(false),
+        }
+    }
+
+    /// Hand out memory of another type, e.g. `BOOT_SERVICES_DATA` for a driver.
+    pub const fn with_memory_type(self, memory_type: MemoryType) -> Self {
+        Self {
+            memory_type,
+            ..self
+        }
+    }
+
+    pub fn memory_type(&self) -> MemoryType {
+        self.memory_type
+    }
+
+    /// Stop using boot services. This must be called before or right after ExitBootServices.
+    pub fn exit_boot_services(&self) {
+        self.exited.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the allocator still uses boot services.
+    pub fn is_active(&self) -> bool {
+        !self.exited.load(Ordering::SeqCst)
+    }
+
+    /// Create an event which calls `exit_boot_services` on this allocator when boot services
+    /// are exited. The event must not be closed while the allocator is in use.
+    /// SAFETY : The `SystemTable` in the `GlobalData` must be initialized.
+    pub fn register_exit_boot_services_event(
+        &'static self,
+    ) -> event_services::Result<Completion<efi::Event>> {
+        let st = self.st.load()?;
+        event_services::create_event(
+            st,
+            efi::EVT_SIGNAL_EXIT_BOOT_SERVICES,
+            efi::TPL_NOTIFY,
+            Some(exit_boot_services_notify),
+            self as *const Self as *mut c_void,
+        )
+    }
+
+    fn system_table(&self) -> Option<*mut SystemTable> {
+        if !self.is_active() {
+            return None;
+        }
+        self.st.load().ok()
+    }
+
+    /// Whether a reallocation from `old` to `new` can keep the same memory.
+    fn fits_in_place(old: Layout, new: Layout) -> bool {
+        let backing = Backing::of(old);
+        if Backing::of(new) != backing {
+            return false;
+        }
+
+        match backing {
+            // The size of a pool allocation is unknown, so it can only shrink.
+            Backing::Pool => new.size() <= old.size(),
+            // Freeing uses the page count derived from the layout, which must stay the same.
+            Backing::Pages => {
+                backing.size(old).div_ceil(PAGE_SIZE) == backing.size(new).div_ceil(PAGE_SIZE)
+            }
+        }
+    }
+}
+
+extern "efiapi" fn exit_boot_services_notify(_event: efi::Event, context: *mut c_void) {
+    let allocator = unsafe { &*(context as *const UefiAllocator) };
+    allocator.exit_boot_services();
+}
+
+unsafe impl GlobalAlloc for UefiAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let st = match self.system_table() {
+            Some(x) => x,
+            None => return core::ptr::null_mut(),
+        };
+
+        let backing = Backing::of(layout);
+        let size = backing.size(layout);
+
+        let ptr: *mut u8 = match backing {
+            Backing::Pool => {
+                let mut ptr: *mut c_void = core::ptr::null_mut();
+                let r =
+                    memory_allocation_services::allocate_pool(st, self.memory_type, size, &mut ptr);
+                if r.is_err() {
+                    return core::ptr::null_mut();
+                }
+                ptr.cast()
+            }
+            Backing::Pages => {
+                let mut addr: efi::PhysicalAddress = 0;
+                let r = memory_allocation_services::allocate_pages(
+                    st,
+                    efi::ALLOCATE_ANY_PAGES,
+                    self.memory_type,
+                    size.div_ceil(PAGE_SIZE),
+                    &mut addr,
+                );
+                if r.is_err() {
+                    return core::ptr::null_mut();
+                }
+                addr as usize as *mut u8
+            }
+        };
+
+        if ptr.is_null() {
+            return ptr;
+        }
+        unsafe { align_ptr(ptr, layout.align(), backing.alignment()) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // Leak the memory if boot services are gone.
+        let st = match self.system_table() {
+            Some(x) => x,
+            None => return,
+        };
+
+        let backing = Backing::of(layout);
+        let ptr = unsafe { unalign_ptr(ptr, layout.align(), backing.alignment()) };
+
+        let _ = match backing {
+            Backing::Pool => memory_allocation_services::free_pool(st, ptr.cast()),
+            Backing::Pages => memory_allocation_services::free_pages(
+                st,
+                ptr as usize as efi::PhysicalAddress,
+                backing.size(layout).div_ceil(PAGE_SIZE),
+            ),
+        };
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // SAFETY: The caller guarantees that `new_size` rounded up to the alignment does not
+        // overflow.
+        let new_layout = unsafe { Layout::from_size_align_unchecked(new_size, layout.align()) };
+        if Self::fits_in_place(layout, new_layout) {
+            return ptr;
+        }
+
+        let new_ptr = unsafe { self.alloc(new_layout) };
+        if !new_ptr.is_null() {
+            let size = layout.size().min(new_size);
+            unsafe {
+                core::ptr::copy_nonoverlapping(ptr, new_ptr, size);
+                self.dealloc(ptr, layout);
+            }
+        }
+        new_ptr
+    }
+}
+
+/// Move `ptr` up to `align`, storing the original pointer in a `Header` before it.
+/// SAFETY: `ptr` must be aligned to `natural` and point to an allocation which is `align` bytes
+/// larger than needed if `align` is larger than `natural`.
+unsafe fn align_ptr(ptr: *mut u8, align: usize, natural: usize) -> *mut u8 {
+    if align <= natural {
+        return ptr;
+    }
+
+    // `offset` is a multiple of `natural` in `natural..=align`, so there is room for the header.
+    let offset = align - (ptr as usize & (align - 1));
+    let aligned = unsafe { ptr.add(offset) };
+    unsafe { core::ptr::write((aligned as *mut Header).offset(-1), Header(ptr)) };
+
+    aligned
+}
+
+/// Find the pointer returned by the firmware for a pointer returned by `align_ptr`.
+/// SAFETY: `ptr` must have been returned by `align_ptr` with the same `align` and `natural`.
+unsafe fn unalign_ptr(ptr: *mut u8, align: usize, natural: usize) -> *mut u8 {
+    if align <= natural {
+        return ptr;
+    }
+
+    unsafe { core::ptr::read((ptr as *mut Header).offset(-1)).0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::efi::Status;
+    use crate::mock::{Call, MockSystemTable};
+    use std::boxed::Box;
+
+    fn allocator(mock: &mut MockSystemTable) -> &'static UefiAllocator {
+        let st: &'static GlobalData<SystemTable> = Box::leak(Box::new(GlobalData::new()));
+        st.init(mock.system_table()).unwrap();
+        Box::leak(Box::new(UefiAllocator::new(st)))
+    }
+
+    #[test]
+    fn pool_allocations() {
+        let mut mock = MockSystemTable::new();
+        let a = allocator(&mut mock);
+
+        for align in [1, 8, 64, 2048] {
+            let layout = Layout::from_size_align(100, align).unwrap();
+            let ptr = unsafe { a.alloc(layout) };
+            assert!(!ptr.is_null());
+            assert_eq!(ptr as usize % align, 0);
+            assert_eq!(mock.outstanding_pool_allocations(), 1);
+            assert_eq!(mock.outstanding_page_allocations(), 0);
+
+            unsafe { a.dealloc(ptr, layout) };
+            assert_eq!(mock.outstanding_pool_allocations(), 0);
+        }
+    }
+
+    #[test]
+    fn page_allocations() {
+        let mut mock = MockSystemTable::new();
+        let a = allocator(&mut mock);
+
+        for (size, align) in [(100, PAGE_SIZE), (PAGE_THRESHOLD, 8), (100, 4 * PAGE_SIZE)] {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            let ptr = unsafe { a.alloc(layout) };
+            assert!(!ptr.is_null());
+            assert_eq!(ptr as usize % align, 0);
+            assert_eq!(mock.outstanding_page_allocations(), 1);
+
+            unsafe { a.dealloc(ptr, layout) };
+            assert_eq!(mock.outstanding_page_allocations(), 0);
+        }
+    }
+
+    #[test]
+    fn realloc_in_place_and_moving() {
+        let mut mock = MockSystemTable::new();
+        let a = allocator(&mut mock);
+
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        let ptr = unsafe { a.alloc(layout) };
+        unsafe { core::ptr::write_bytes(ptr, 0xab, 64) };
+
+        let shrunk = unsafe { a.realloc(ptr, layout, 32) };
+        assert_eq!(shrunk, ptr);
+
+        let layout = Layout::from_size_align(32, 8).unwrap();
+        let grown = unsafe { a.realloc(shrunk, layout, 2 * PAGE_THRESHOLD) };
+        assert_ne!(grown, shrunk);
+        assert_eq!(unsafe { *grown.add(31) }, 0xab);
+        assert_eq!(mock.outstanding_pool_allocations(), 0);
+        assert_eq!(mock.outstanding_page_allocations(), 1);
+
+        let layout = Layout::from_size_align(2 * PAGE_THRESHOLD, 8).unwrap();
+        let same_pages = unsafe { a.realloc(grown, layout, 2 * PAGE_THRESHOLD - 10) };
+        assert_eq!(same_pages, grown);
+
+        let layout = Layout::from_size_align(2 * PAGE_THRESHOLD - 10, 8).unwrap();
+        unsafe { a.dealloc(same_pages, layout) };
+        assert_eq!(mock.outstanding_page_allocations(), 0);
+    }
+
+    #[test]
+    fn memory_type() {
+        let mut mock = MockSystemTable::new();
+        let st: &'static GlobalData<SystemTable> = Box::leak(Box::new(GlobalData::new()));
+        st.init(mock.system_table()).unwrap();
+        let a = UefiAllocator::new(st).with_memory_type(efi::BOOT_SERVICES_DATA);
+
+        mock.set_hook(|call| match call {
+            Call::AllocatePool { memory_type, .. } if *memory_type != efi::BOOT_SERVICES_DATA => {
+                Status::INVALID_PARAMETER
+            }
+            _ => Status::SUCCESS,
+        });
+
+        let layout = Layout::from_size_align(16, 8).unwrap();
+        let ptr = unsafe { a.alloc(layout) };
+        assert!(!ptr.is_null());
+        unsafe { a.dealloc(ptr, layout) };
+    }
+
+    #[test]
+    fn refuses_after_exit_boot_services() {
+        let mut mock = MockSystemTable::new();
+        let a = allocator(&mut mock);
+        a.register_exit_boot_services_event().unwrap().into_value();
+
+        let layout = Layout::from_size_align(16, 8).unwrap();
+        let ptr = unsafe { a.alloc(layout) };
+        assert!(!ptr.is_null());
+
+        crate::boot_services::image_services::exit_boot_services(
+            mock.system_table(),
+            0x1000 as efi::Handle,
+        )
+        .unwrap()
+        .into_value();
+        assert!(!a.is_active());
+        // The memory map is never freed.
+        let outstanding = mock.outstanding_pool_allocations();
+
+        assert!(unsafe { a.alloc(layout) }.is_null());
+        // Freeing leaks the memory rather than calling into the firmware.
+        unsafe { a.dealloc(ptr, layout) };
+        assert_eq!(mock.outstanding_pool_allocations(), outstanding);
+    }
+}
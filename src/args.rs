@@ -0,0 +1,314 @@
+//! This module splits the command line of an image into arguments, as needed for
+//! `std::env::args`.
+//!
+//! Applications started by the UEFI Shell get an `EFI_SHELL_PARAMETERS_PROTOCOL` holding the
+//! arguments, which is used when installed. Otherwise the load options of the image are split the
+//! way the shell does it:
+//! - Arguments are separated by spaces and tabs.
+//! - `"` starts and ends a quoted part, in which spaces and tabs do not separate arguments. The
+//!   quotes are removed.
+//! - `^` makes the next character literal, so `^"` is a quote and `^^` a caret. The caret is
+//!   removed.
+//!
+//! Arguments borrow from the `CommandLine` and are not copied. With the `alloc` feature, they can
+//! also be converted to `String`s.
+
+use core::fmt;
+
+use crate::efi::{Handle, SystemTable};
+use crate::errors;
+use crate::protocols::loaded_image::LoadedImage;
+use crate::protocols::shell_parameters::{Argv, ShellParameters};
+use crate::status::{Completion, StatusCode};
+#[cfg(any(test, feature = "alloc"))]
+use alloc::string::{String, ToString};
+
+pub type Result<T> = core::result::Result<T, errors::StatusNullError>;
+
+const SPACE: u16 = b' ' as u16;
+const TAB: u16 = b'\t' as u16;
+const QUOTE: u16 = b'"' as u16;
+const CARET: u16 = b'^' as u16;
+
+enum Source {
+    Shell(ShellParameters),
+    LoadOptions(LoadedImage),
+}
+
+/// The command line of an image.
+pub struct CommandLine {
+    source: Source,
+}
+
+impl CommandLine {
+    /// Open the command line of `image`, e.g. the handle passed to `efi_main`. The Shell
+    /// Parameters Protocol is used if installed on `image`, otherwise the Loaded Image Protocol.
+    /// SAFETY : The `st` pointer must be valid for the lifetime of the returned value. This is
+    /// gaurenteed if `GlobalData` is used to store the pointer.
+    pub fn open(st: *mut SystemTable, image: Handle) -> Result<Completion<Self>> {
+        match ShellParameters::open(st, image) {
+            Ok(r) => {
+                return Ok(r.map(|x| Self {
+                    source: Source::Shell(x),
+                }))
+            }
+            Err(errors::StatusNullError::UefiError(StatusCode::Unsupported)) => {}
+            Err(e) => return Err(e),
+        }
+        let r = LoadedImage::open(st, image)?;
+        Ok(r.map(|x| Self {
+            source: Source::LoadOptions(x),
+        }))
+    }
+
+    /// Whether the arguments come from the shell rather than the load options.
+    pub fn is_from_shell(&self) -> bool {
+        matches!(self.source, Source::Shell(_))
+    }
+
+    /// The arguments, usually starting with the name of the image. There are none if the load
+    /// options are not a UCS-2 string, e.g. binary data passed by a boot option.
+    pub fn args(&self) -> Args<'_> {
+        match &self.source {
+            Source::Shell(x) => Args {
+                inner: ArgsInner::Shell(x.argv()),
+            },
+            Source::LoadOptions(x) => split(x.load_options_u16().unwrap_or(&[])),
+        }
+    }
+
+    /// The arguments as UTF-8 strings. Surrogates are replaced with U+FFFD.
+    #[cfg(any(test, feature = "alloc"))]
+    pub fn strings(&self) -> impl Iterator<Item = String> + '_ {
+        self.args().map(|x| x.to_string())
+    }
+}
+
+/// Split a UCS-2 command line into arguments. The line ends at the first NUL, if any.
+pub fn split(line: &[u16]) -> Args<'_> {
+    let end = line.iter().position(|x| *x == 0).unwrap_or(line.len());
+    Args {
+        inner: ArgsInner::Line(&line[..end]),
+    }
+}
+
+/// An iterator over the arguments of a command line, created by [`CommandLine::args`] or
+/// [`split`].
+pub struct Args<'a> {
+    inner: ArgsInner<'a>,
+}
+
+enum ArgsInner<'a> {
+    Shell(Argv<'a>),
+    Line(&'a [u16]),
+}
+
+impl<'a> Iterator for Args<'a> {
+    type Item = Arg<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.inner {
+            ArgsInner::Shell(argv) => argv.next().map(|x| Arg {
+                raw: x.as_slice(),
+                escaped: false,
+            }),
+            ArgsInner::Line(line) => {
+                let start = line.iter().position(|x| !is_whitespace(*x))?;
+                let rest = &line[start..];
+                let mut quoted = false;
+                let mut i = 0;
+                while i < rest.len() {
+                    match rest[i] {
+                        CARET => i += 1,
+                        QUOTE => quoted = !quoted,
+                        x if is_whitespace(x) && !quoted => break,
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                let end = i.min(rest.len());
+                *line = &rest[end..];
+                Some(Arg {
+                    raw: &rest[..end],
+                    escaped: true,
+                })
+            }
+        }
+    }
+}
+
+fn is_whitespace(c: u16) -> bool {
+    c == SPACE || c == TAB
+}
+
+/// A single argument.
+#[derive(Clone, Copy)]
+pub struct Arg<'a> {
+    raw: &'a [u16],
+    escaped: bool,
+}
+
+impl<'a> Arg<'a> {
+    /// The argument as found in the command line, with quotes and carets if split from the load
+    /// options.
+    pub fn raw(&self) -> &'a [u16] {
+        self.raw
+    }
+
+    /// The UCS-2 code units of the argument, with quotes and carets removed.
+    pub fn units(&self) -> Units<'a> {
+        Units {
+            raw: self.raw,
+            escaped: self.escaped,
+        }
+    }
+
+    /// The characters of the argument. Surrogates are replaced with U+FFFD.
+    pub fn chars(&self) -> impl Iterator<Item = char> + 'a {
+        self.units()
+            .map(|c| char::from_u32(u32::from(c)).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+}
+
+impl PartialEq<str> for Arg<'_> {
+    fn eq(&self, other: &str) -> bool {
+        self.units().eq(other.encode_utf16())
+    }
+}
+
+impl PartialEq<&str> for Arg<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl fmt::Display for Arg<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use fmt::Write;
+        for c in self.chars() {
+            f.write_char(c)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Arg<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in self.chars() {
+            fmt::Display::fmt(&c.escape_debug(), f)?;
+        }
+        f.write_str("\"")
+    }
+}
+
+/// An iterator over the UCS-2 code units of an [`Arg`], with quotes and carets removed.
+#[derive(Clone)]
+pub struct Units<'a> {
+    raw: &'a [u16],
+    escaped: bool,
+}
+
+impl Iterator for Units<'_> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (&c, rest) = self.raw.split_first()?;
+            self.raw = rest;
+            if !self.escaped {
+                return Some(c);
+            }
+            match c {
+                QUOTE => continue,
+                // A trailing caret escapes nothing and is kept.
+                CARET => match self.raw.split_first() {
+                    Some((&c, rest)) => {
+                        self.raw = rest;
+                        return Some(c);
+                    }
+                    None => return Some(CARET),
+                },
+                _ => return Some(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockSystemTable;
+    use std::vec::Vec;
+
+    fn ucs2(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    fn strings(line: &str) -> Vec<String> {
+        let line = ucs2(line);
+        split(&line).map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn split_line() {
+        assert!(strings("").is_empty());
+        assert!(strings(" \t ").is_empty());
+        assert_eq!(strings("app.efi -v  x"), ["app.efi", "-v", "x"]);
+        assert_eq!(strings("\tapp.efi\t"), ["app.efi"]);
+        assert_eq!(strings("a \"b c\" d"), ["a", "b c", "d"]);
+        assert_eq!(strings("a\"b c\"d e"), ["ab cd", "e"]);
+        assert_eq!(strings("\"\" x"), ["", "x"]);
+        assert_eq!(strings("\"unterminated x"), ["unterminated x"]);
+        assert_eq!(strings("^\"a b^\""), ["\"a", "b\""]);
+        assert_eq!(strings("a^ b ^^ c^"), ["a b", "^", "c^"]);
+        assert_eq!(strings("\"a ^\" b\" c"), ["a \" b", "c"]);
+        assert_eq!(strings("a\0b"), ["a"]);
+
+        let line = ucs2("x \"a b\"");
+        let arg = split(&line).nth(1).unwrap();
+        assert_eq!(arg.raw(), ucs2("\"a b\""));
+        assert_eq!(arg, "a b");
+        assert_eq!(std::format!("{:?}", arg), "\"a b\"");
+    }
+
+    #[test]
+    fn load_options() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        let options: Vec<u8> = "app.efi \"a b\" ^^\0"
+            .encode_utf16()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        let image = mock.install_loaded_image(&options);
+
+        let cmd = CommandLine::open(st, image).unwrap().into_value();
+        assert!(!cmd.is_from_shell());
+        assert_eq!(cmd.strings().collect::<Vec<_>>(), ["app.efi", "a b", "^"]);
+
+        let binary = mock.install_loaded_image(&[1, 2, 3]);
+        let cmd = CommandLine::open(st, binary).unwrap().into_value();
+        assert_eq!(cmd.args().count(), 0);
+    }
+
+    #[test]
+    fn shell_parameters() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        let options: Vec<u8> = "ignored\0"
+            .encode_utf16()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        let image = mock.install_loaded_image(&options);
+        mock.install_shell_parameters(image, &["app.efi", "^\"a b\""]);
+
+        let cmd = CommandLine::open(st, image).unwrap().into_value();
+        assert!(cmd.is_from_shell());
+        // The shell already removed quotes and carets, so the arguments are taken as is.
+        let args: Vec<_> = cmd.args().collect();
+        assert_eq!(args.len(), 2);
+        assert_eq!(args[0], "app.efi");
+        assert_eq!(args[1], "^\"a b\"");
+        assert_eq!(args[1].raw(), ucs2("^\"a b\""));
+    }
+}
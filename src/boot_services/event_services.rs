@@ -0,0 +1,479 @@
+//! This module contains functions related to Event, Timer, and Task Priority Services.
+
+use core::ffi::c_void;
+
+use crate::{
+    efi::{EventNotify, Guid, SystemTable, Tpl},
+    errors, helpers,
+    status::{Completion, StatusCode},
+};
+use r_efi::efi::{self, TimerDelay};
+
+pub type Result<T> = core::result::Result<T, errors::StatusNullError>;
+
+/// When a timer set with `set_timer` fires. Times are in units of 100ns.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimerTrigger {
+    /// Cancel the timer.
+    Cancel,
+    /// Fire every `n` units, starting `n` units from now.
+    Periodic(u64),
+    /// Fire once, `n` units from now.
+    Relative(u64),
+}
+
+impl TimerTrigger {
+    fn as_raw(&self) -> (TimerDelay, u64) {
+        match *self {
+            Self::Cancel => (efi::TIMER_CANCEL, 0),
+            Self::Periodic(x) => (efi::TIMER_PERIODIC, x),
+            Self::Relative(x) => (efi::TIMER_RELATIVE, x),
+        }
+    }
+}
+
+/// Call EFI_CREATE_EVENT boot service function.
+/// The returned event must be closed with `close_event`. Use `Event` for an owned event.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn create_event(
+    st: *mut SystemTable,
+    event_type: u32,
+    notify_tpl: Tpl,
+    notify_function: Option<EventNotify>,
+    notify_context: *mut c_void,
+) -> Result<Completion<efi::Event>> {
+    let boot_services = super::get_boot_services(st)?;
+    let create_event_ptr = unsafe { (*boot_services).create_event };
+
+    let mut event: efi::Event = core::ptr::null_mut();
+    let status = (create_event_ptr)(
+        event_type,
+        notify_tpl,
+        notify_function,
+        notify_context,
+        &mut event,
+    );
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| event))
+}
+
+/// Call EFI_CREATE_EVENT_EX boot service function.
+/// If `event_group` is given, the event is signaled together with all other events of the group.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn create_event_ex(
+    st: *mut SystemTable,
+    event_type: u32,
+    notify_tpl: Tpl,
+    notify_function: Option<EventNotify>,
+    notify_context: *const c_void,
+    event_group: Option<&Guid>,
+) -> Result<Completion<efi::Event>> {
+    let boot_services = super::get_boot_services(st)?;
+    let create_event_ex_ptr = unsafe { (*boot_services).create_event_ex };
+
+    let mut event: efi::Event = core::ptr::null_mut();
+    let event_group = match event_group {
+        Some(x) => x as *const Guid,
+        None => core::ptr::null(),
+    };
+    let status = (create_event_ex_ptr)(
+        event_type,
+        notify_tpl,
+        notify_function,
+        notify_context,
+        event_group,
+        &mut event,
+    );
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| event))
+}
+
+/// Call EFI_CLOSE_EVENT boot service function.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn close_event(st: *mut SystemTable, event: efi::Event) -> Result<Completion<()>> {
+    let boot_services = super::get_boot_services(st)?;
+    let close_event_ptr = unsafe { (*boot_services).close_event };
+
+    let status = (close_event_ptr)(event);
+
+    helpers::status_to_result(status).map_err(|x| x.into())
+}
+
+/// Call EFI_SIGNAL_EVENT boot service function.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn signal_event(st: *mut SystemTable, event: efi::Event) -> Result<Completion<()>> {
+    let boot_services = super::get_boot_services(st)?;
+    let signal_event_ptr = unsafe { (*boot_services).signal_event };
+
+    let status = (signal_event_ptr)(event);
+
+    helpers::status_to_result(status).map_err(|x| x.into())
+}
+
+/// Call EFI_WAIT_FOR_EVENT boot service function.
+/// Blocks until one of `events` is signaled and returns its index.
+/// This must be called at `TPL_APPLICATION`.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn wait_for_event(
+    st: *mut SystemTable,
+    events: &mut [efi::Event],
+) -> Result<Completion<usize>> {
+    let boot_services = super::get_boot_services(st)?;
+    let wait_for_event_ptr = unsafe { (*boot_services).wait_for_event };
+
+    let mut index = 0;
+    let status = (wait_for_event_ptr)(events.len(), events.as_mut_ptr(), &mut index);
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| index))
+}
+
+/// Call EFI_CHECK_EVENT boot service function.
+/// Returns whether the event was signaled. `EFI_NOT_READY` is reported as `false` rather than as
+/// an error.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn check_event(st: *mut SystemTable, event: efi::Event) -> Result<Completion<bool>> {
+    let boot_services = super::get_boot_services(st)?;
+    let check_event_ptr = unsafe { (*boot_services).check_event };
+
+    let status = (check_event_ptr)(event);
+
+    match helpers::status_to_result(status) {
+        Ok(r) => Ok(r.map(|_| true)),
+        Err(e) if e.status() == StatusCode::NotReady => Ok(Completion::new(false)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Call EFI_SET_TIMER boot service function.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn set_timer(
+    st: *mut SystemTable,
+    event: efi::Event,
+    trigger: TimerTrigger,
+) -> Result<Completion<()>> {
+    let boot_services = super::get_boot_services(st)?;
+    let set_timer_ptr = unsafe { (*boot_services).set_timer };
+
+    let (timer_type, trigger_time) = trigger.as_raw();
+    let status = (set_timer_ptr)(event, timer_type, trigger_time);
+
+    helpers::status_to_result(status).map_err(|x| x.into())
+}
+
+/// Call EFI_RAISE_TPL boot service function and return the previous TPL.
+/// Prefer `TplGuard`, which restores the previous TPL when dropped.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn raise_tpl(
+    st: *mut SystemTable,
+    new_tpl: Tpl,
+) -> core::result::Result<Tpl, errors::NullPtrError> {
+    let boot_services = super::get_boot_services(st)?;
+    let raise_tpl_ptr = unsafe { (*boot_services).raise_tpl };
+
+    Ok((raise_tpl_ptr)(new_tpl))
+}
+
+/// Call EFI_RESTORE_TPL boot service function.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn restore_tpl(
+    st: *mut SystemTable,
+    old_tpl: Tpl,
+) -> core::result::Result<(), errors::NullPtrError> {
+    let boot_services = super::get_boot_services(st)?;
+    let restore_tpl_ptr = unsafe { (*boot_services).restore_tpl };
+
+    (restore_tpl_ptr)(old_tpl);
+
+    Ok(())
+}
+
+/// An owned event, which is closed when dropped.
+pub struct Event {
+    st: *mut SystemTable,
+    event: efi::Event,
+}
+
+impl Event {
+    /// Create an event with `create_event`.
+    /// SAFETY : The `st` pointer must be valid for the lifetime of the event. This is gaurenteed
+    /// if `GlobalData` is used to store the pointer.
+    pub fn new(
+        st: *mut SystemTable,
+        event_type: u32,
+        notify_tpl: Tpl,
+        notify_function: Option<EventNotify>,
+        notify_context: *mut c_void,
+    ) -> Result<Completion<Self>> {
+        let r = create_event(st, event_type, notify_tpl, notify_function, notify_context)?;
+        Ok(r.map(|event| Self { st, event }))
+    }
+
+    /// Create an event with `create_event_ex`.
+    /// SAFETY : The `st` pointer must be valid for the lifetime of the event. This is gaurenteed
+    /// if `GlobalData` is used to store the pointer.
+    pub fn new_ex(
+        st: *mut SystemTable,
+        event_type: u32,
+        notify_tpl: Tpl,
+        notify_function: Option<EventNotify>,
+        notify_context: *const c_void,
+        event_group: Option<&Guid>,
+    ) -> Result<Completion<Self>> {
+        let r = create_event_ex(
+            st,
+            event_type,
+            notify_tpl,
+            notify_function,
+            notify_context,
+            event_group,
+        )?;
+        Ok(r.map(|event| Self { st, event }))
+    }
+
+    /// Create a timer event without notification function, to be used with `wait` or `check`.
+    /// SAFETY : The `st` pointer must be valid for the lifetime of the event. This is gaurenteed
+    /// if `GlobalData` is used to store the pointer.
+    pub fn timer(st: *mut SystemTable) -> Result<Completion<Self>> {
+        Self::new(
+            st,
+            efi::EVT_TIMER,
+            efi::TPL_APPLICATION,
+            None,
+            core::ptr::null_mut(),
+        )
+    }
+
+    /// Take ownership of an event created by other means.
+    ///
+    /// # Safety
+    /// `event` must be a valid event which is not closed by anyone else, and `st` must stay valid
+    /// for the lifetime of the returned value.
+    pub unsafe fn from_raw(st: *mut SystemTable, event: efi::Event) -> Self {
+        Self { st, event }
+    }
+
+    pub fn as_raw(&self) -> efi::Event {
+        self.event
+    }
+
+    /// Give up ownership without closing the event.
+    pub fn into_raw(self) -> efi::Event {
+        let event = self.event;
+        core::mem::forget(self);
+        event
+    }
+
+    pub fn signal(&self) -> Result<Completion<()>> {
+        signal_event(self.st, self.event)
+    }
+
+    pub fn check(&self) -> Result<Completion<bool>> {
+        check_event(self.st, self.event)
+    }
+
+    pub fn set_timer(&self, trigger: TimerTrigger) -> Result<Completion<()>> {
+        set_timer(self.st, self.event, trigger)
+    }
+
+    /// Block until this event is signaled.
+    pub fn wait(&self) -> Result<Completion<()>> {
+        let r = wait_for_event(self.st, &mut [self.event])?;
+        Ok(r.map(|_| ()))
+    }
+
+    /// Close the event, returning any error. Dropping the event closes it as well, but ignores
+    /// errors.
+    pub fn close(self) -> Result<Completion<()>> {
+        let (st, event) = (self.st, self.into_raw());
+        close_event(st, event)
+    }
+}
+
+impl Drop for Event {
+    fn drop(&mut self) {
+        let _ = close_event(self.st, self.event);
+    }
+}
+
+/// Raises the TPL while alive, and restores the previous TPL when dropped.
+pub struct TplGuard {
+    st: *mut SystemTable,
+    old_tpl: Tpl,
+}
+
+impl TplGuard {
+    /// SAFETY : The `st` pointer must be valid for the lifetime of the guard. This is gaurenteed
+    /// if `GlobalData` is used to store the pointer.
+    pub fn raise(
+        st: *mut SystemTable,
+        new_tpl: Tpl,
+    ) -> core::result::Result<Self, errors::NullPtrError> {
+        let old_tpl = raise_tpl(st, new_tpl)?;
+        Ok(Self { st, old_tpl })
+    }
+
+    /// The TPL which will be restored.
+    pub fn old_tpl(&self) -> Tpl {
+        self.old_tpl
+    }
+}
+
+impl Drop for TplGuard {
+    fn drop(&mut self) {
+        let _ = restore_tpl(self.st, self.old_tpl);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::efi::Status;
+    use crate::mock::{Call, MockSystemTable};
+
+    extern "efiapi" fn count_notify(_event: efi::Event, context: *mut c_void) {
+        unsafe { *(context as *mut u32) += 1 };
+    }
+
+    #[test]
+    fn signal_and_check() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let event = Event::new(st, 0, efi::TPL_CALLBACK, None, core::ptr::null_mut())
+            .unwrap()
+            .into_value();
+        assert!(!event.check().unwrap().into_value());
+
+        event.signal().unwrap().into_value();
+        assert!(event.check().unwrap().into_value());
+        // Checking a signaled event clears it.
+        assert!(!event.check().unwrap().into_value());
+    }
+
+    #[test]
+    fn notify_signal_group() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        let group = Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]);
+
+        let mut count = 0u32;
+        let ctx = &mut count as *mut u32 as *const c_void;
+        let a = Event::new_ex(
+            st,
+            efi::EVT_NOTIFY_SIGNAL,
+            efi::TPL_CALLBACK,
+            Some(count_notify),
+            ctx,
+            Some(&group),
+        )
+        .unwrap()
+        .into_value();
+        let _b = Event::new_ex(
+            st,
+            efi::EVT_NOTIFY_SIGNAL,
+            efi::TPL_CALLBACK,
+            Some(count_notify),
+            ctx,
+            Some(&group),
+        )
+        .unwrap()
+        .into_value();
+
+        a.signal().unwrap().into_value();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn timer_wait() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let timer = Event::timer(st).unwrap().into_value();
+        timer
+            .set_timer(TimerTrigger::Relative(10_000))
+            .unwrap()
+            .into_value();
+        timer.wait().unwrap().into_value();
+        assert_eq!(mock.time(), 10_000);
+
+        timer
+            .set_timer(TimerTrigger::Periodic(100))
+            .unwrap()
+            .into_value();
+        mock.advance_time(250);
+        assert!(timer.check().unwrap().into_value());
+
+        timer.set_timer(TimerTrigger::Cancel).unwrap().into_value();
+        assert!(timer.wait().is_err());
+    }
+
+    #[test]
+    fn wait_for_event_index() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let a = Event::timer(st).unwrap().into_value();
+        let b = Event::timer(st).unwrap().into_value();
+        mock.signal_event(b.as_raw());
+
+        let mut events = [a.as_raw(), b.as_raw()];
+        assert_eq!(wait_for_event(st, &mut events).unwrap().into_value(), 1);
+    }
+
+    #[test]
+    fn check_event_error() {
+        let mut mock = MockSystemTable::new();
+        mock.set_hook(|call| match call {
+            Call::CheckEvent { .. } => Status::INVALID_PARAMETER,
+            _ => Status::SUCCESS,
+        });
+        let st = mock.system_table();
+
+        let event = Event::timer(st).unwrap().into_value();
+        assert_eq!(
+            event.check().unwrap_err(),
+            errors::StatusNullError::UefiError(StatusCode::InvalidParameter)
+        );
+    }
+
+    #[test]
+    fn drop_closes_event() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let event = Event::timer(st).unwrap().into_value();
+        assert_eq!(mock.open_events(), 1);
+        drop(event);
+        assert_eq!(mock.open_events(), 0);
+
+        let raw = Event::timer(st).unwrap().into_value().into_raw();
+        assert_eq!(mock.open_events(), 1);
+        close_event(st, raw).unwrap().into_value();
+        assert_eq!(mock.open_events(), 0);
+    }
+
+    #[test]
+    fn tpl_guard_restores() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        {
+            let guard = TplGuard::raise(st, efi::TPL_NOTIFY).unwrap();
+            assert_eq!(guard.old_tpl(), efi::TPL_APPLICATION);
+            assert_eq!(mock.tpl(), efi::TPL_NOTIFY);
+        }
+        assert_eq!(mock.tpl(), efi::TPL_APPLICATION);
+    }
+}
@@ -1,19 +1,55 @@
 use core::ffi::c_void;
 
-use crate::efi::{Handle, Status, SystemTable};
+use crate::efi::{Handle, MemoryDescriptor, Status, SystemTable};
+use crate::status::{Completion, StatusCode};
+#[cfg(any(test, feature = "alloc"))]
+use crate::string::CString16;
 use crate::{errors, helpers};
 use r_efi::efi::Boolean;
 use r_efi::protocols::device_path;
 
+use super::memory_allocation_services::{MemoryMap, MemoryMapInfo};
+
 type Result<T> = core::result::Result<T, errors::StatusNullError>;
 
+/// How many times `exit_boot_services` fetches a new memory map before giving up.
+const EXIT_BOOT_SERVICES_ATTEMPTS: usize = 8;
+
+/// Where `load_image` takes the image from.
+#[derive(Clone, Copy, Debug)]
+pub enum ImageSource<'a> {
+    /// An image which is already in memory. `device_path` is optional, and is recorded as the
+    /// file path of the loaded image.
+    Buffer {
+        buffer: &'a [u8],
+        device_path: *mut device_path::Protocol,
+    },
+    /// An image loaded by the firmware from `device_path`. If `boot_policy` is set, the path
+    /// may be a boot option rather than an exact file path.
+    DevicePath {
+        device_path: *mut device_path::Protocol,
+        boot_policy: bool,
+    },
+}
+
+/// The result of running an image with `start_image`.
+#[cfg(any(test, feature = "alloc"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageExit {
+    /// The status the image returned from its entry point or passed to `exit`.
+    pub status: StatusCode,
+    /// The string the image passed to `exit`, if any. Binary data following the string is
+    /// dropped.
+    pub exit_data: Option<CString16>,
+}
+
 /// SAFETY: The caller must ensure that `st` is not null.
 pub fn exit(
     st: *mut SystemTable,
     image_handle: Handle,
     exit_status: Status,
     exit_data: &mut [u16],
-) -> Result<()> {
+) -> Result<Completion<()>> {
     let exit_data_size: usize = core::mem::size_of_val(exit_data);
     let boot_services = super::get_boot_services(st)?;
     let exit_ptr = unsafe { (*boot_services).exit };
@@ -25,7 +61,292 @@ pub fn exit(
         exit_data.as_mut_ptr(),
     );
 
-    helpers::status_to_result(r)?;
+    helpers::status_to_result(r).map_err(|x| x.into())
+}
+
+/// Call EFI_IMAGE_LOAD boot service function.
+/// Returns the handle of the loaded image, which can be run with `start_image`.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn load_image(
+    st: *mut SystemTable,
+    parent_image_handle: Handle,
+    source: ImageSource<'_>,
+) -> Result<Completion<Handle>> {
+    let boot_services = super::get_boot_services(st)?;
+    let load_image_ptr = unsafe { (*boot_services).load_image };
+
+    let (boot_policy, device_path, source_buffer, source_size) = match source {
+        ImageSource::Buffer {
+            buffer,
+            device_path,
+        } => (
+            false,
+            device_path,
+            buffer.as_ptr() as *mut c_void,
+            buffer.len(),
+        ),
+        ImageSource::DevicePath {
+            device_path,
+            boot_policy,
+        } => (boot_policy, device_path, core::ptr::null_mut(), 0),
+    };
+
+    let mut image_handle: Handle = core::ptr::null_mut();
+    let status = (load_image_ptr)(
+        Boolean::from(boot_policy),
+        parent_image_handle,
+        device_path,
+        source_buffer,
+        source_size,
+        &mut image_handle,
+    );
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| image_handle))
+}
+
+/// Call EFI_IMAGE_START boot service function.
+/// The status of the image is returned as part of `ImageExit` rather than as an error, as it
+/// comes with the exit data. The exit data is copied and its pool allocation freed.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+#[cfg(any(test, feature = "alloc"))]
+pub fn start_image(
+    st: *mut SystemTable,
+    image_handle: Handle,
+) -> core::result::Result<ImageExit, errors::NullPtrError> {
+    let boot_services = super::get_boot_services(st)?;
+    let start_image_ptr = unsafe { (*boot_services).start_image };
+
+    let mut exit_data_size = 0;
+    let mut exit_data: *mut u16 = core::ptr::null_mut();
+    let status = (start_image_ptr)(image_handle, &mut exit_data_size, &mut exit_data);
+
+    let exit_data = if exit_data.is_null() {
+        None
+    } else {
+        let data = unsafe { core::slice::from_raw_parts(exit_data, exit_data_size / 2) };
+        let s = CString16::from_u16_lossy(data);
+        let _ = super::memory_allocation_services::free_pool(st, exit_data.cast());
+        Some(s)
+    };
+
+    Ok(ImageExit {
+        status: status.into(),
+        exit_data,
+    })
+}
+
+/// Call EFI_IMAGE_UNLOAD boot service function.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn unload_image(st: *mut SystemTable, image_handle: Handle) -> Result<Completion<()>> {
+    let boot_services = super::get_boot_services(st)?;
+    let unload_image_ptr = unsafe { (*boot_services).unload_image };
+
+    let status = (unload_image_ptr)(image_handle);
+
+    helpers::status_to_result(status).map_err(|x| x.into())
+}
+
+/// Call EFI_EXIT_BOOT_SERVICES boot service function.
+/// The memory map is fetched into a pool buffer sized with some slack, and fetched again into
+/// the same buffer if the map key turned out to be stale, since no memory can be allocated once
+/// the first attempt failed. On success, the final memory map is returned, and can be read with
+/// `MemoryMapIter`. The buffer is never freed, as `free_pool` may not be called anymore.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer. No boot services may be used after this returns successfully.
+pub fn exit_boot_services(
+    st: *mut SystemTable,
+    image_handle: Handle,
+) -> Result<Completion<(&'static mut [MemoryDescriptor], MemoryMapInfo)>> {
+    let boot_services = super::get_boot_services(st)?;
+    let exit_boot_services_ptr = unsafe { (*boot_services).exit_boot_services };
+
+    let mut map = MemoryMap::new(st)?.into_value();
+    let r = exit_with_map(exit_boot_services_ptr, image_handle, &mut map);
+    // Boot services may be partially shut down after the first attempt, even if it failed.
+    let (buffer, info) = map.leak();
+    Ok(r?.map(|_| (buffer, info)))
+}
+
+fn exit_with_map(
+    exit_boot_services_ptr: r_efi::efi::BootExitBootServices,
+    image_handle: Handle,
+    map: &mut MemoryMap,
+) -> Result<Completion<()>> {
+    for _ in 0..EXIT_BOOT_SERVICES_ATTEMPTS {
+        let status = (exit_boot_services_ptr)(image_handle, map.map_key());
+        // EFI_INVALID_PARAMETER means the memory map changed since it was fetched.
+        if status != Status::INVALID_PARAMETER {
+            return helpers::status_to_result(status).map_err(|x| x.into());
+        }
+        map.refresh()?.into_value();
+    }
+
+    Err(errors::StatusNullError::UefiError(
+        StatusCode::InvalidParameter,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boot_services::memory_allocation_services::MemoryMapIter;
+    use crate::mock::{Call, MockSystemTable};
+
+    #[test]
+    fn exit_records_data() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        let image = 0x1000 as Handle;
+
+        let mut data = [0x0041u16, 0x0000];
+        assert!(exit(st, image, Status::ABORTED, &mut data).is_ok());
+
+        let record = mock.exit_record().unwrap();
+        assert_eq!(record.image_handle, image);
+        assert_eq!(record.status, Status::ABORTED);
+        assert_eq!(record.data, [0x0041, 0x0000]);
+    }
+
+    #[test]
+    fn load_start_unload() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        let parent = 0x1000 as Handle;
+
+        let source = ImageSource::Buffer {
+            buffer: b"MZ\0\0",
+            device_path: core::ptr::null_mut(),
+        };
+        let image = load_image(st, parent, source).unwrap().into_value();
+        assert_eq!(mock.loaded_images(), 1);
+
+        let r = start_image(st, image).unwrap();
+        assert_eq!(r.status, StatusCode::Success);
+        assert_eq!(r.exit_data, None);
+
+        unload_image(st, image).unwrap().into_value();
+        assert_eq!(mock.loaded_images(), 0);
+    }
+
+    #[test]
+    fn load_image_invalid() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let source = ImageSource::Buffer {
+            buffer: b"\x7fELF",
+            device_path: core::ptr::null_mut(),
+        };
+        assert_eq!(
+            load_image(st, 0x1000 as Handle, source),
+            Err(errors::StatusNullError::UefiError(StatusCode::LoadError))
+        );
+    }
+
+    #[test]
+    fn start_image_exit_data() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        mock.set_image_entry(move |image| {
+            let mut data = [0x0062u16, 0x0061, 0x0064, 0x0000];
+            let _ = exit(st, image, Status::ABORTED, &mut data);
+            Status::SUCCESS
+        });
+
+        let source = ImageSource::Buffer {
+            buffer: b"MZ",
+            device_path: core::ptr::null_mut(),
+        };
+        let image = load_image(st, 0x1000 as Handle, source)
+            .unwrap()
+            .into_value();
+
+        let r = start_image(st, image).unwrap();
+        assert_eq!(r.status, StatusCode::Aborted);
+        assert_eq!(r.exit_data.unwrap(), "bad");
+        assert_eq!(mock.outstanding_pool_allocations(), 0);
+    }
+
+    #[test]
+    fn exit_boot_services_retries() {
+        let mut mock = MockSystemTable::new();
+        let mut attempts = 0;
+        mock.set_hook(move |call| match call {
+            Call::ExitBootServices { .. } => {
+                attempts += 1;
+                if attempts == 1 {
+                    Status::INVALID_PARAMETER
+                } else {
+                    Status::SUCCESS
+                }
+            }
+            _ => Status::SUCCESS,
+        });
+        let st = mock.system_table();
+
+        let (buffer, info) = exit_boot_services(st, 0x1000 as Handle)
+            .unwrap()
+            .into_value();
+        assert_eq!(info.map_key, mock.map_key());
+        let map = MemoryMapIter::new(buffer, &info).unwrap();
+        assert_eq!(map.len(), 7);
+        assert!(mock.boot_services_exited());
+    }
+
+    fn grow_memory_map(count: u64) -> impl FnMut(&Call<'_>) -> Status {
+        let mut attempts = 0;
+        move |call| match call {
+            Call::ExitBootServices { .. } => {
+                attempts += 1;
+                if attempts > 1 {
+                    return Status::SUCCESS;
+                }
+                for i in 0..count {
+                    crate::mock::push_memory_descriptor(MemoryDescriptor {
+                        r#type: r_efi::efi::BOOT_SERVICES_DATA,
+                        physical_start: 0x100_0000 + i * 0x1000,
+                        virtual_start: 0,
+                        number_of_pages: 1,
+                        attribute: r_efi::efi::MEMORY_WB,
+                    });
+                }
+                Status::SUCCESS
+            }
+            _ => Status::SUCCESS,
+        }
+    }
+
+    #[test]
+    fn exit_boot_services_map_grows() {
+        let mut mock = MockSystemTable::new();
+        mock.set_hook(grow_memory_map(2));
+        let st = mock.system_table();
+
+        let (buffer, info) = exit_boot_services(st, 0x1000 as Handle)
+            .unwrap()
+            .into_value();
+        assert_eq!(info.map_key, mock.map_key());
+        let map = MemoryMapIter::new(buffer, &info).unwrap();
+        assert_eq!(map.len(), 9);
+        assert!(mock.boot_services_exited());
+    }
+
+    #[test]
+    fn exit_boot_services_map_outgrows_buffer() {
+        let mut mock = MockSystemTable::new();
+        mock.set_hook(grow_memory_map(3));
+        let st = mock.system_table();
 
-    Ok(())
+        assert_eq!(
+            exit_boot_services(st, 0x1000 as Handle).map(|_| ()),
+            Err(errors::StatusNullError::UefiError(
+                StatusCode::BufferTooSmall
+            ))
+        );
+        assert!(!mock.boot_services_exited());
+    }
 }
@@ -1,16 +1,38 @@
 //! This module contains functions related to Memory Allocation.
 
 use crate::{
-    efi::{AllocateType, MemoryDescriptor, MemoryType, PhysicalAddress, SystemTable},
+    efi::{AllocateType, MemoryDescriptor, MemoryType, PhysicalAddress, SystemTable, LOADER_DATA},
     errors, helpers,
+    status::{Completion, StatusCode},
 };
 use core::ffi::c_void;
+use core::marker::PhantomData;
 
 pub type Result<T> = core::result::Result<T, errors::StatusNullError>;
 
+/// Size of a page as used by `allocate_pages` and the memory map.
+pub const PAGE_SIZE: usize = 4096;
+
+/// Extra descriptors to make room for when fetching the memory map.
+const MEMORY_MAP_SLACK: usize = 2;
+
+/// How many times `MemoryMap::new` grows its buffer before giving up.
+const MEMORY_MAP_ATTEMPTS: usize = 8;
+
+/// The values returned by EFI_GET_MEMORY_MAP alongside the descriptors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryMapInfo {
+    /// Size of the memory map in bytes.
+    pub map_size: usize,
+    /// Key identifying this version of the memory map, as needed by `exit_boot_services`.
+    pub map_key: usize,
+    /// Size of each descriptor in bytes. This may be larger than `size_of::<MemoryDescriptor>()`.
+    pub descriptor_size: usize,
+    pub descriptor_version: u32,
+}
+
 /// Call EFI_ALLOCATE_POOL boot service function.
-/// This function treats Warnings as error right now. This might
-/// change in the future though.
+/// Warnings are returned as part of the `Completion`.
 /// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
 /// the pointer.
 pub fn allocate_pool(
@@ -18,7 +40,7 @@ pub fn allocate_pool(
     memtype: MemoryType,
     allocate_size: usize,
     ptr: &mut *mut c_void,
-) -> Result<()> {
+) -> Result<Completion<()>> {
     let boot_services = super::get_boot_services(st)?;
     // TODO: Check if the assumption that allocate_pool_ptr will be valid as long as boot_services
     // ptr is valid. Else this might need change upstream in r-efi
@@ -32,7 +54,7 @@ pub fn allocate_pool(
 /// Call EFI_FREE_POOL boot service function
 /// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
 /// the pointer.
-pub fn free_pool(st: *mut SystemTable, ptr: *mut c_void) -> Result<()> {
+pub fn free_pool(st: *mut SystemTable, ptr: *mut c_void) -> Result<Completion<()>> {
     let boot_services = super::get_boot_services(st)?;
     // TODO: Check if the assumption that free_pool_ptr will be valid as long as boot_services
     // ptr is valid. Else this might need change upstream in r-efi
@@ -52,7 +74,7 @@ pub fn allocate_pages(
     memtype: MemoryType,
     pages: usize,
     memory_address: *mut PhysicalAddress,
-) -> Result<()> {
+) -> Result<Completion<()>> {
     let boot_services = super::get_boot_services(st)?;
     // TODO: Check if the assumption that allocate_pages_ptr will be valid as long as boot_services
     // ptr is valid. Else this might need change upstream in r-efi
@@ -70,7 +92,7 @@ pub fn free_pages(
     st: *mut SystemTable,
     memory_address: PhysicalAddress,
     pages: usize,
-) -> Result<()> {
+) -> Result<Completion<()>> {
     let boot_services = super::get_boot_services(st)?;
 
     // TODO: Check if the assumption that free_pages_ptr will be valid as long as boot_services
@@ -82,29 +104,658 @@ pub fn free_pages(
     helpers::status_to_result(status).map_err(|x| x.into())
 }
 
-/// Call EFI_GET_MEMORY_MAP boot service function
+/// Call EFI_GET_MEMORY_MAP boot service function.
+/// The descriptors in `buffer` are `info.descriptor_size` bytes apart, which may be more than
+/// `size_of::<MemoryDescriptor>()`. On `EFI_BUFFER_TOO_SMALL`, `info.map_size` is set to the size
+/// needed. `MemoryMap::new` sizes the buffer automatically.
 /// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
 /// the pointer.
 pub fn get_memory_map(
     st: *mut SystemTable,
-    memory_map_address: &mut usize,
-    memory_map: &mut MemoryDescriptor,
-    map_key: &mut usize,
-    descriptor_size: &mut usize,
-    descriptor_version: &mut u32,
-) -> Result<()> {
+    buffer: &mut [MemoryDescriptor],
+    info: &mut MemoryMapInfo,
+) -> Result<Completion<()>> {
     let boot_services = super::get_boot_services(st)?;
     // TODO: Check if the assumption that get_memory_map_ptr will be valid as long as boot_services
     // ptr is valid. Else this might need change upstream in r-efi
     let get_memory_map_ptr = unsafe { (*boot_services).get_memory_map };
 
+    info.map_size = core::mem::size_of_val(buffer);
     let status = (get_memory_map_ptr)(
-        memory_map_address,
-        memory_map,
-        map_key,
-        descriptor_size,
-        descriptor_version,
+        &mut info.map_size,
+        buffer.as_mut_ptr(),
+        &mut info.map_key,
+        &mut info.descriptor_size,
+        &mut info.descriptor_version,
     );
 
     helpers::status_to_result(status).map_err(|x| x.into())
 }
+
+/// A snapshot of the memory map, stored in pool memory which is freed when dropped.
+pub struct MemoryMap {
+    buffer: PoolBuffer<u8>,
+    info: MemoryMapInfo,
+}
+
+impl MemoryMap {
+    /// Fetch the current memory map into a buffer of the right size.
+    /// Allocating the buffer may split a descriptor, so some slack is added to the size reported
+    /// by the firmware, and the whole process is repeated a few times if that was not enough.
+    /// SAFETY : The `st` pointer must be valid for the lifetime of the map. This is gaurenteed if
+    /// `GlobalData` is used to store the pointer.
+    pub fn new(st: *mut SystemTable) -> Result<Completion<Self>> {
+        let mut info = MemoryMapInfo::default();
+        let mut buffer = unsafe { PoolBuffer::from_raw_parts(st, core::ptr::null_mut(), 0) };
+
+        for _ in 0..MEMORY_MAP_ATTEMPTS {
+            match get_memory_map(st, descriptors_mut(&mut buffer), &mut info) {
+                Ok(r) => {
+                    check_map(&info, buffer.len())?;
+                    return Ok(r.map(|_| Self { buffer, info }));
+                }
+                Err(errors::StatusNullError::UefiError(StatusCode::BufferTooSmall)) => {}
+                Err(e) => return Err(e),
+            }
+
+            let size = info.map_size + MEMORY_MAP_SLACK * info.descriptor_size;
+            let size = size.next_multiple_of(core::mem::size_of::<MemoryDescriptor>());
+
+            // Free the old buffer first, so that it can be reused.
+            drop(buffer);
+            let mut ptr = core::ptr::null_mut();
+            allocate_pool(st, LOADER_DATA, size, &mut ptr)?.into_value();
+            buffer = unsafe { PoolBuffer::from_raw_parts(st, ptr.cast(), size) };
+        }
+
+        Err(errors::StatusNullError::UefiError(
+            StatusCode::BufferTooSmall,
+        ))
+    }
+
+    pub fn info(&self) -> MemoryMapInfo {
+        self.info
+    }
+
+    /// Fetch the current memory map again into the same buffer, without allocating.
+    /// Fails with `BufferTooSmall` if the map outgrew the buffer, in which case the previous
+    /// snapshot is kept.
+    pub fn refresh(&mut self) -> Result<Completion<()>> {
+        let mut info = self.info;
+        let r = get_memory_map(self.buffer.st, descriptors_mut(&mut self.buffer), &mut info)?;
+        check_map(&info, self.buffer.len())?;
+        self.info = info;
+        Ok(r)
+    }
+
+    /// Give up ownership of the buffer without freeing it, e.g. once boot services are gone and
+    /// `free_pool` may no longer be called. The descriptors are `info.descriptor_size` bytes
+    /// apart, and can be read with `MemoryMapIter`.
+    pub fn leak(mut self) -> (&'static mut [MemoryDescriptor], MemoryMapInfo) {
+        let len = descriptors_mut(&mut self.buffer).len();
+        let ptr = self.buffer.into_raw();
+        let buffer = if len == 0 {
+            &mut []
+        } else {
+            unsafe { core::slice::from_raw_parts_mut(ptr.cast(), len) }
+        };
+        (buffer, self.info)
+    }
+
+    /// The key to pass to `exit_boot_services`.
+    pub fn map_key(&self) -> usize {
+        self.info.map_key
+    }
+
+    /// Number of descriptors.
+    pub fn len(&self) -> usize {
+        self.info
+            .map_size
+            .checked_div(self.info.descriptor_size)
+            .unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<MemoryDescriptor> {
+        self.iter().nth(index)
+    }
+
+    pub fn iter(&self) -> MemoryMapIter<'_> {
+        MemoryMapIter {
+            ptr: self.buffer.as_ptr(),
+            len: self.len(),
+            descriptor_size: self.info.descriptor_size,
+            index: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The descriptors of the given type.
+    pub fn filter_type(
+        &self,
+        memory_type: MemoryType,
+    ) -> impl Iterator<Item = MemoryDescriptor> + '_ {
+        self.iter().filter(move |x| x.r#type == memory_type)
+    }
+
+    /// Sort the descriptors by physical address.
+    pub fn sort(&mut self) {
+        // An insertion sort, as the descriptors cannot be moved with `[T]::sort` when
+        // `descriptor_size` differs from `size_of::<MemoryDescriptor>()`. Memory maps are short.
+        for i in 1..self.len() {
+            let mut j = i;
+            while j > 0 && self.read(j - 1).physical_start > self.read(j).physical_start {
+                unsafe {
+                    core::ptr::swap_nonoverlapping(
+                        self.entry_ptr(j - 1),
+                        self.entry_ptr(j),
+                        self.info.descriptor_size,
+                    )
+                };
+                j -= 1;
+            }
+        }
+    }
+
+    /// Keep only the descriptors for which `f` returns `true`.
+    pub fn retain<F: FnMut(&MemoryDescriptor) -> bool>(&mut self, mut f: F) {
+        let mut len = 0;
+        for i in 0..self.len() {
+            if f(&self.read(i)) {
+                self.move_entry(i, len);
+                len += 1;
+            }
+        }
+        self.info.map_size = len * self.info.descriptor_size;
+    }
+
+    /// Sort the descriptors, then merge adjacent ones of the same type and attributes.
+    pub fn coalesce(&mut self) {
+        self.sort();
+
+        let mut len = 0;
+        for i in 0..self.len() {
+            let next = self.read(i);
+            if len > 0 {
+                let mut last = self.read(len - 1);
+                let pages = last.number_of_pages.checked_add(next.number_of_pages);
+                match pages {
+                    Some(pages) if contiguous(&last, &next) => {
+                        last.number_of_pages = pages;
+                        self.write(len - 1, last);
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            self.move_entry(i, len);
+            len += 1;
+        }
+        self.info.map_size = len * self.info.descriptor_size;
+    }
+
+    fn entry_ptr(&self, index: usize) -> *mut u8 {
+        debug_assert!(index < self.len());
+        unsafe { (self.buffer.as_ptr() as *mut u8).add(index * self.info.descriptor_size) }
+    }
+
+    fn read(&self, index: usize) -> MemoryDescriptor {
+        unsafe { core::ptr::read_unaligned(self.entry_ptr(index).cast()) }
+    }
+
+    /// Overwrite the known fields of a descriptor, leaving any trailing bytes alone.
+    fn write(&mut self, index: usize, desc: MemoryDescriptor) {
+        unsafe { core::ptr::write_unaligned(self.entry_ptr(index).cast(), desc) }
+    }
+
+    fn move_entry(&mut self, from: usize, to: usize) {
+        if from != to {
+            let (src, dst) = (self.entry_ptr(from), self.entry_ptr(to));
+            unsafe { core::ptr::copy_nonoverlapping(src, dst, self.info.descriptor_size) };
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a MemoryMap {
+    type Item = MemoryDescriptor;
+    type IntoIter = MemoryMapIter<'a>;
+
+    fn into_iter(self) -> MemoryMapIter<'a> {
+        self.iter()
+    }
+}
+
+impl core::fmt::Debug for MemoryMap {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Whether `b` starts right where `a` ends and can be merged into it.
+/// A descriptor whose end does not fit in 64 bits is never contiguous with another one.
+fn contiguous(a: &MemoryDescriptor, b: &MemoryDescriptor) -> bool {
+    let end = |start: u64| {
+        a.number_of_pages
+            .checked_mul(PAGE_SIZE as u64)
+            .and_then(|size| start.checked_add(size))
+    };
+    let virtual_contiguous = if a.virtual_start == 0 && b.virtual_start == 0 {
+        true
+    } else {
+        end(a.virtual_start) == Some(b.virtual_start)
+    };
+    a.r#type == b.r#type
+        && a.attribute == b.attribute
+        && end(a.physical_start) == Some(b.physical_start)
+        && virtual_contiguous
+}
+
+/// Whether the map described by `info` can be read from `size` bytes, with a whole
+/// `MemoryDescriptor` at every `descriptor_size` step.
+fn map_fits(info: &MemoryMapInfo, size: usize) -> bool {
+    let desc_size = core::mem::size_of::<MemoryDescriptor>();
+    if info.descriptor_size < desc_size {
+        return false;
+    }
+    match info.map_size / info.descriptor_size {
+        0 => true,
+        len => (len - 1)
+            .checked_mul(info.descriptor_size)
+            .and_then(|x| x.checked_add(desc_size))
+            .is_some_and(|x| x <= size),
+    }
+}
+
+/// Check the map returned by the firmware, as `MemoryMap` relies on it. A map which does not fit
+/// its buffer is reported as `EFI_DEVICE_ERROR`.
+fn check_map(info: &MemoryMapInfo, size: usize) -> Result<()> {
+    if map_fits(info, size) {
+        Ok(())
+    } else {
+        Err(errors::StatusNullError::UefiError(StatusCode::DeviceError))
+    }
+}
+
+/// View a pool buffer as descriptors, to pass it to `get_memory_map`.
+fn descriptors_mut(buffer: &mut PoolBuffer<u8>) -> &mut [MemoryDescriptor] {
+    let len = buffer.len() / core::mem::size_of::<MemoryDescriptor>();
+    if len == 0 {
+        return &mut [];
+    }
+    unsafe { core::slice::from_raw_parts_mut(buffer.as_ptr() as *mut MemoryDescriptor, len) }
+}
+
+/// Iterates over the descriptors of a memory map, `descriptor_size` bytes apart.
+#[derive(Clone)]
+pub struct MemoryMapIter<'a> {
+    ptr: *const u8,
+    len: usize,
+    descriptor_size: usize,
+    index: usize,
+    _marker: PhantomData<&'a [u8]>,
+}
+
+impl<'a> MemoryMapIter<'a> {
+    /// Iterate over a memory map fetched into `buffer` with the returned `info`, e.g. by
+    /// `get_memory_map` or `exit_boot_services`. Returns `None` if `info.descriptor_size` is
+    /// smaller than `MemoryDescriptor`, or if the map does not fit in `buffer`.
+    pub fn new(buffer: &'a [MemoryDescriptor], info: &MemoryMapInfo) -> Option<Self> {
+        if !map_fits(info, core::mem::size_of_val(buffer)) {
+            return None;
+        }
+        Some(Self {
+            ptr: buffer.as_ptr().cast(),
+            len: info.map_size / info.descriptor_size,
+            descriptor_size: info.descriptor_size,
+            index: 0,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl Iterator for MemoryMapIter<'_> {
+    type Item = MemoryDescriptor;
+
+    fn next(&mut self) -> Option<MemoryDescriptor> {
+        if self.index >= self.len {
+            return None;
+        }
+        let ptr = unsafe { self.ptr.add(self.index * self.descriptor_size) };
+        self.index += 1;
+        Some(unsafe { core::ptr::read_unaligned(ptr.cast()) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.len - self.index;
+        (n, Some(n))
+    }
+}
+
+impl ExactSizeIterator for MemoryMapIter<'_> {}
+
+/// A buffer which the firmware allocated from pool memory on behalf of the caller, e.g. the
+/// handles returned by `locate_handle_buffer`. It is freed with `free_pool` when dropped.
+pub struct PoolBuffer<T> {
+    st: *mut SystemTable,
+    ptr: *mut T,
+    len: usize,
+}
+
+impl<T> PoolBuffer<T> {
+    /// Take ownership of a pool allocation holding `len` values of `T`.
+    ///
+    /// # Safety
+    /// `ptr` must be null with a `len` of 0, or be allocated with `allocate_pool` and point to
+    /// `len` initialized values of `T`. `st` must stay valid for the lifetime of the buffer.
+    pub unsafe fn from_raw_parts(st: *mut SystemTable, ptr: *mut T, len: usize) -> Self {
+        Self { st, ptr, len }
+    }
+
+    pub fn as_ptr(&self) -> *const T {
+        self.ptr
+    }
+
+    /// Give up ownership without freeing the buffer.
+    pub fn into_raw(self) -> *mut T {
+        let ptr = self.ptr;
+        core::mem::forget(self);
+        ptr
+    }
+}
+
+impl<T> core::ops::Deref for PoolBuffer<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        if self.ptr.is_null() {
+            &[]
+        } else {
+            unsafe { core::slice::from_raw_parts(self.ptr, self.len) }
+        }
+    }
+}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for PoolBuffer<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> Drop for PoolBuffer<T> {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            let _ = free_pool(self.st, self.ptr.cast());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::efi::{Status, LOADER_DATA};
+    use crate::mock::{Call, MockSystemTable};
+    use crate::status::StatusCode;
+
+    #[test]
+    fn allocate_and_free_pool() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let mut ptr: *mut c_void = core::ptr::null_mut();
+        assert!(allocate_pool(st, LOADER_DATA, 64, &mut ptr).is_ok());
+        assert!(!ptr.is_null());
+        assert_eq!(mock.outstanding_pool_allocations(), 1);
+
+        assert!(free_pool(st, ptr).is_ok());
+        assert_eq!(mock.outstanding_pool_allocations(), 0);
+    }
+
+    #[test]
+    fn allocate_pool_error() {
+        let mut mock = MockSystemTable::new();
+        mock.set_hook(|call| match call {
+            Call::AllocatePool { .. } => Status::OUT_OF_RESOURCES,
+            _ => Status::SUCCESS,
+        });
+        let st = mock.system_table();
+
+        let mut ptr: *mut c_void = core::ptr::null_mut();
+        assert_eq!(
+            allocate_pool(st, LOADER_DATA, 64, &mut ptr),
+            Err(errors::StatusNullError::UefiError(
+                StatusCode::OutOfResources
+            ))
+        );
+        assert_eq!(mock.outstanding_pool_allocations(), 0);
+    }
+
+    #[test]
+    fn null_boot_services() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        unsafe { (*st).boot_services = core::ptr::null_mut() };
+
+        let mut ptr: *mut c_void = core::ptr::null_mut();
+        assert_eq!(
+            allocate_pool(st, LOADER_DATA, 64, &mut ptr),
+            Err(errors::StatusNullError::NullPtrError("Boot Services"))
+        );
+    }
+
+    #[test]
+    fn allocate_and_free_pages() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let mut addr: PhysicalAddress = 0;
+        assert!(allocate_pages(
+            st,
+            r_efi::efi::ALLOCATE_ANY_PAGES,
+            LOADER_DATA,
+            2,
+            &mut addr
+        )
+        .is_ok());
+        assert_ne!(addr, 0);
+        assert_eq!(addr % 4096, 0);
+        assert_eq!(mock.outstanding_page_allocations(), 1);
+
+        assert!(free_pages(st, addr, 2).is_ok());
+        assert_eq!(mock.outstanding_page_allocations(), 0);
+    }
+
+    fn desc(r#type: MemoryType, physical_start: u64, number_of_pages: u64) -> MemoryDescriptor {
+        MemoryDescriptor {
+            r#type,
+            physical_start,
+            virtual_start: 0,
+            number_of_pages,
+            attribute: r_efi::efi::MEMORY_WB,
+        }
+    }
+
+    #[test]
+    fn get_memory_map_too_small() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let mut buffer = [desc(0, 0, 0)];
+        let mut info = MemoryMapInfo::default();
+
+        let r = get_memory_map(st, &mut buffer, &mut info);
+        assert_eq!(
+            r,
+            Err(errors::StatusNullError::UefiError(
+                StatusCode::BufferTooSmall
+            ))
+        );
+        assert!(info.map_size > core::mem::size_of::<MemoryDescriptor>());
+        assert_eq!(
+            info.descriptor_size,
+            core::mem::size_of::<MemoryDescriptor>()
+        );
+    }
+
+    #[test]
+    fn memory_map_honors_descriptor_size() {
+        let mut mock = MockSystemTable::new();
+        mock.set_descriptor_size(core::mem::size_of::<MemoryDescriptor>() + 8);
+        let st = mock.system_table();
+
+        let map = MemoryMap::new(st).unwrap().into_value();
+        assert_eq!(map.map_key(), mock.map_key());
+        assert_eq!(map.len(), 7);
+        assert_eq!(map.get(1).unwrap().r#type, r_efi::efi::CONVENTIONAL_MEMORY);
+        assert_eq!(map.get(1).unwrap().physical_start, 0x1000);
+        assert_eq!(map.filter_type(r_efi::efi::CONVENTIONAL_MEMORY).count(), 2);
+
+        drop(map);
+        assert_eq!(mock.outstanding_pool_allocations(), 0);
+    }
+
+    #[test]
+    fn memory_map_keeps_growing() {
+        let mut mock = MockSystemTable::new();
+        let mut calls = 0;
+        mock.set_hook(move |call| {
+            if let Call::GetMemoryMap { .. } = call {
+                calls += 1;
+                assert!(calls <= MEMORY_MAP_ATTEMPTS);
+                for i in 0..=MEMORY_MAP_SLACK as u64 {
+                    crate::mock::push_memory_descriptor(desc(
+                        LOADER_DATA,
+                        0x100_0000 + i * 0x1000,
+                        1,
+                    ));
+                }
+            }
+            Status::SUCCESS
+        });
+        let st = mock.system_table();
+
+        assert_eq!(
+            MemoryMap::new(st).map(|_| ()),
+            Err(errors::StatusNullError::UefiError(
+                StatusCode::BufferTooSmall
+            ))
+        );
+        assert_eq!(mock.outstanding_pool_allocations(), 0);
+    }
+
+    #[test]
+    fn memory_map_sort_and_coalesce() {
+        let mut mock = MockSystemTable::new();
+        mock.set_memory_map(std::vec![
+            desc(LOADER_DATA, 0x3000, 1),
+            desc(LOADER_DATA, 0x1000, 1),
+            desc(r_efi::efi::CONVENTIONAL_MEMORY, 0x5000, 1),
+            desc(LOADER_DATA, 0x2000, 1),
+            desc(LOADER_DATA, 0x6000, 1),
+        ]);
+        let st = mock.system_table();
+
+        let mut map = MemoryMap::new(st).unwrap().into_value();
+        map.coalesce();
+        let starts: std::vec::Vec<_> = map
+            .iter()
+            .map(|x| (x.physical_start, x.number_of_pages))
+            .collect();
+        assert_eq!(starts, [(0x1000, 3), (0x5000, 1), (0x6000, 1)]);
+
+        map.retain(|x| x.r#type == LOADER_DATA);
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn contiguous_descriptors() {
+        let a = desc(LOADER_DATA, 0x1000, 1);
+        assert!(contiguous(&a, &desc(LOADER_DATA, 0x2000, 1)));
+        assert!(!contiguous(&a, &desc(LOADER_DATA, 0x3000, 1)));
+        assert!(!contiguous(&a, &desc(r_efi::efi::LOADER_CODE, 0x2000, 1)));
+
+        // Either both are unmapped, or the virtual ranges follow each other too.
+        let mapped = |mut x: MemoryDescriptor, virtual_start| {
+            x.virtual_start = virtual_start;
+            x
+        };
+        assert!(!contiguous(
+            &a,
+            &mapped(desc(LOADER_DATA, 0x2000, 1), 0x8000)
+        ));
+        assert!(!contiguous(
+            &mapped(a, 0x7000),
+            &desc(LOADER_DATA, 0x2000, 1)
+        ));
+        assert!(contiguous(
+            &mapped(a, 0x7000),
+            &mapped(desc(LOADER_DATA, 0x2000, 1), 0x8000)
+        ));
+
+        // Descriptors reaching the end of the address space, or beyond.
+        let top = desc(LOADER_DATA, u64::MAX - 0xfff, 1);
+        assert!(!contiguous(&top, &desc(LOADER_DATA, 0, 1)));
+        let huge = desc(LOADER_DATA, 0x1000, u64::MAX);
+        assert!(!contiguous(&huge, &desc(LOADER_DATA, 0x1000, 1)));
+    }
+
+    #[test]
+    fn memory_map_iter_bounds() {
+        let buffer = [desc(LOADER_DATA, 0x1000, 1), desc(LOADER_DATA, 0x2000, 1)];
+        let size = core::mem::size_of::<MemoryDescriptor>();
+        let info = |map_size, descriptor_size| MemoryMapInfo {
+            map_size,
+            map_key: 0,
+            descriptor_size,
+            descriptor_version: r_efi::efi::MEMORY_DESCRIPTOR_VERSION,
+        };
+
+        assert_eq!(
+            MemoryMapIter::new(&buffer, &info(2 * size, size))
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(MemoryMapIter::new(&[], &info(0, size)).unwrap().len(), 0);
+        assert!(MemoryMapIter::new(&buffer, &info(3 * size, size)).is_none());
+        assert!(MemoryMapIter::new(&buffer, &info(2 * size, 1)).is_none());
+        assert!(MemoryMapIter::new(&buffer, &info(0, 0)).is_none());
+        // The second descriptor would end past the buffer.
+        assert!(MemoryMapIter::new(&buffer, &info(2 * (size + 8), size + 8)).is_none());
+        assert!(MemoryMapIter::new(&buffer, &info(usize::MAX, size)).is_none());
+    }
+
+    #[test]
+    fn memory_map_refresh() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let mut map = MemoryMap::new(st).unwrap().into_value();
+        let mut descriptors: std::vec::Vec<_> = map.iter().collect();
+        descriptors.push(desc(LOADER_DATA, 0x80_0000, 1));
+        mock.set_memory_map(descriptors.clone());
+        map.refresh().unwrap().into_value();
+        assert_eq!(map.len(), 8);
+        assert_eq!(map.map_key(), mock.map_key());
+
+        // The buffer only has room for `MEMORY_MAP_SLACK` more descriptors.
+        descriptors.extend([
+            desc(LOADER_DATA, 0x90_0000, 1),
+            desc(LOADER_DATA, 0xa0_0000, 1),
+        ]);
+        mock.set_memory_map(descriptors);
+        assert_eq!(
+            map.refresh(),
+            Err(errors::StatusNullError::UefiError(
+                StatusCode::BufferTooSmall
+            ))
+        );
+        assert_eq!(map.len(), 8);
+
+        let (buffer, info) = map.leak();
+        assert_eq!(MemoryMapIter::new(buffer, &info).unwrap().len(), 8);
+        assert_eq!(mock.outstanding_pool_allocations(), 1);
+    }
+}
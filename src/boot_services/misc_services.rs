@@ -0,0 +1,95 @@
+//! This module contains functions related to Miscellaneous Boot Services.
+
+use core::ffi::c_void;
+
+use crate::efi::{Guid, SystemTable};
+use crate::status::Completion;
+use crate::{errors, helpers};
+
+type Result<T> = core::result::Result<T, errors::StatusNullError>;
+
+/// Call EFI_GET_NEXT_MONOTONIC_COUNT boot service function.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn get_next_monotonic_count(st: *mut SystemTable) -> Result<Completion<u64>> {
+    let boot_services = super::get_boot_services(st)?;
+    let get_next_monotonic_count_ptr = unsafe { (*boot_services).get_next_monotonic_count };
+
+    let mut count = 0;
+    let status = (get_next_monotonic_count_ptr)(&mut count);
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| count))
+}
+
+/// Call EFI_STALL boot service function.
+/// Busy-waits for at least `microseconds`.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn stall(st: *mut SystemTable, microseconds: usize) -> Result<Completion<()>> {
+    let boot_services = super::get_boot_services(st)?;
+    let stall_ptr = unsafe { (*boot_services).stall };
+
+    let status = (stall_ptr)(microseconds);
+
+    helpers::status_to_result(status).map_err(|x| x.into())
+}
+
+/// Call EFI_INSTALL_CONFIGURATION_TABLE boot service function.
+/// Adds, updates or, if `table` is null, removes the configuration table entry for `guid`.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn install_configuration_table(
+    st: *mut SystemTable,
+    guid: &Guid,
+    table: *mut c_void,
+) -> Result<Completion<()>> {
+    let boot_services = super::get_boot_services(st)?;
+    let install_configuration_table_ptr = unsafe { (*boot_services).install_configuration_table };
+
+    let mut guid = *guid;
+    let status = (install_configuration_table_ptr)(&mut guid, table);
+
+    helpers::status_to_result(status).map_err(|x| x.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boot_services::event_services::{Event, TimerTrigger};
+    use crate::mock::MockSystemTable;
+    use crate::runtime_services::time::get_next_high_monotonic_count;
+
+    #[test]
+    fn monotonic_count() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let first = get_next_monotonic_count(st).unwrap().into_value();
+        let second = get_next_monotonic_count(st).unwrap().into_value();
+        assert!(second > first);
+
+        get_next_high_monotonic_count(st).unwrap().into_value();
+        let third = get_next_monotonic_count(st).unwrap().into_value();
+        assert_eq!(third >> 32, 1);
+        assert!(third > second);
+    }
+
+    #[test]
+    fn stall_advances_timers() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let event = Event::timer(st).unwrap().into_value();
+        event
+            .set_timer(TimerTrigger::Relative(1000))
+            .unwrap()
+            .into_value();
+
+        stall(st, 50).unwrap().into_value();
+        assert!(!event.check().unwrap().into_value());
+        stall(st, 50).unwrap().into_value();
+        assert!(event.check().unwrap().into_value());
+        assert_eq!(mock.time(), 1000);
+    }
+}
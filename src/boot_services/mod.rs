@@ -1,7 +1,10 @@
 //! This module provides APIS for various boot services.
 
+pub mod event_services;
 pub mod image_services;
 pub mod memory_allocation_services;
+pub mod misc_services;
+pub mod protocol_handler_services;
 
 use crate::{efi, errors, helpers};
 
@@ -0,0 +1,586 @@
+//! This module contains functions related to Protocol Handler Services.
+//!
+//! These give access to protocols installed on any handle, not only the ones referenced from the
+//! `SystemTable`.
+
+use core::ffi::c_void;
+
+use crate::{
+    efi::{Event, Guid, Handle, SystemTable},
+    errors, helpers,
+    status::Completion,
+};
+use r_efi::efi::{self, OpenProtocolInformationEntry};
+use r_efi::protocols::device_path;
+
+use super::memory_allocation_services::PoolBuffer;
+
+pub type Result<T> = core::result::Result<T, errors::StatusNullError>;
+
+/// Which handles `locate_handle` and `locate_handle_buffer` return.
+#[derive(Clone, Copy, Debug)]
+pub enum SearchType<'a> {
+    /// Every handle in the system.
+    AllHandles,
+    /// The next handle on which a protocol was installed since the last call with the same
+    /// registration key, as returned by `register_protocol_notify`.
+    ByRegisterNotify(*mut c_void),
+    /// Every handle supporting the protocol.
+    ByProtocol(&'a Guid),
+}
+
+impl SearchType<'_> {
+    fn as_raw(&self) -> (efi::LocateSearchType, *mut Guid, *mut c_void) {
+        match *self {
+            Self::AllHandles => (
+                efi::ALL_HANDLES,
+                core::ptr::null_mut(),
+                core::ptr::null_mut(),
+            ),
+            Self::ByRegisterNotify(key) => (efi::BY_REGISTER_NOTIFY, core::ptr::null_mut(), key),
+            Self::ByProtocol(guid) => (efi::BY_PROTOCOL, guid_ptr(guid), core::ptr::null_mut()),
+        }
+    }
+}
+
+/// The firmware takes the GUIDs as `*mut`, but never writes through them.
+fn guid_ptr(guid: &Guid) -> *mut Guid {
+    guid as *const Guid as *mut Guid
+}
+
+/// Call EFI_INSTALL_PROTOCOL_INTERFACE boot service function.
+/// A new handle is created if `handle` is `None`. Returns the handle the interface was
+/// installed on.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn install_protocol_interface(
+    st: *mut SystemTable,
+    handle: Option<Handle>,
+    protocol: &Guid,
+    interface: *mut c_void,
+) -> Result<Completion<Handle>> {
+    let boot_services = super::get_boot_services(st)?;
+    let install_protocol_interface_ptr = unsafe { (*boot_services).install_protocol_interface };
+
+    let mut handle = handle.unwrap_or(core::ptr::null_mut());
+    let status = (install_protocol_interface_ptr)(
+        &mut handle,
+        guid_ptr(protocol),
+        efi::NATIVE_INTERFACE,
+        interface,
+    );
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| handle))
+}
+
+/// Call EFI_UNINSTALL_PROTOCOL_INTERFACE boot service function.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn uninstall_protocol_interface(
+    st: *mut SystemTable,
+    handle: Handle,
+    protocol: &Guid,
+    interface: *mut c_void,
+) -> Result<Completion<()>> {
+    let boot_services = super::get_boot_services(st)?;
+    let uninstall_protocol_interface_ptr = unsafe { (*boot_services).uninstall_protocol_interface };
+
+    let status = (uninstall_protocol_interface_ptr)(handle, guid_ptr(protocol), interface);
+
+    helpers::status_to_result(status).map_err(|x| x.into())
+}
+
+/// Call EFI_REINSTALL_PROTOCOL_INTERFACE boot service function.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn reinstall_protocol_interface(
+    st: *mut SystemTable,
+    handle: Handle,
+    protocol: &Guid,
+    old_interface: *mut c_void,
+    new_interface: *mut c_void,
+) -> Result<Completion<()>> {
+    let boot_services = super::get_boot_services(st)?;
+    let reinstall_protocol_interface_ptr = unsafe { (*boot_services).reinstall_protocol_interface };
+
+    let status = (reinstall_protocol_interface_ptr)(
+        handle,
+        guid_ptr(protocol),
+        old_interface,
+        new_interface,
+    );
+
+    helpers::status_to_result(status).map_err(|x| x.into())
+}
+
+/// Call EFI_HANDLE_PROTOCOL boot service function.
+/// New code should prefer `open_protocol`.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn handle_protocol(
+    st: *mut SystemTable,
+    handle: Handle,
+    protocol: &Guid,
+) -> Result<Completion<*mut c_void>> {
+    let boot_services = super::get_boot_services(st)?;
+    let handle_protocol_ptr = unsafe { (*boot_services).handle_protocol };
+
+    let mut interface: *mut c_void = core::ptr::null_mut();
+    let status = (handle_protocol_ptr)(handle, guid_ptr(protocol), &mut interface);
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| interface))
+}
+
+/// Call EFI_OPEN_PROTOCOL boot service function.
+/// The protocol is closed again when the returned `ScopedProtocol` is dropped. `attributes` is a
+/// combination of the `OPEN_PROTOCOL_*` constants.
+/// SAFETY : The `st` pointer must be valid for the lifetime of the returned value. This is
+/// gaurenteed if `GlobalData` is used to store the pointer.
+pub fn open_protocol<T>(
+    st: *mut SystemTable,
+    handle: Handle,
+    protocol: &Guid,
+    agent_handle: Handle,
+    controller_handle: Handle,
+    attributes: u32,
+) -> Result<Completion<ScopedProtocol<T>>> {
+    let boot_services = super::get_boot_services(st)?;
+    let open_protocol_ptr = unsafe { (*boot_services).open_protocol };
+
+    let mut interface: *mut c_void = core::ptr::null_mut();
+    let status = (open_protocol_ptr)(
+        handle,
+        guid_ptr(protocol),
+        &mut interface,
+        agent_handle,
+        controller_handle,
+        attributes,
+    );
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| ScopedProtocol {
+        st,
+        interface: interface.cast(),
+        handle,
+        protocol: *protocol,
+        agent_handle,
+        controller_handle,
+    }))
+}
+
+/// Call EFI_CLOSE_PROTOCOL boot service function.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn close_protocol(
+    st: *mut SystemTable,
+    handle: Handle,
+    protocol: &Guid,
+    agent_handle: Handle,
+    controller_handle: Handle,
+) -> Result<Completion<()>> {
+    let boot_services = super::get_boot_services(st)?;
+    let close_protocol_ptr = unsafe { (*boot_services).close_protocol };
+
+    let status = (close_protocol_ptr)(handle, guid_ptr(protocol), agent_handle, controller_handle);
+
+    helpers::status_to_result(status).map_err(|x| x.into())
+}
+
+/// Call EFI_OPEN_PROTOCOL_INFORMATION boot service function.
+/// SAFETY : The `st` pointer must be valid for the lifetime of the returned buffer. This is
+/// gaurenteed if `GlobalData` is used to store the pointer.
+pub fn open_protocol_information(
+    st: *mut SystemTable,
+    handle: Handle,
+    protocol: &Guid,
+) -> Result<Completion<PoolBuffer<OpenProtocolInformationEntry>>> {
+    let boot_services = super::get_boot_services(st)?;
+    let open_protocol_information_ptr = unsafe { (*boot_services).open_protocol_information };
+
+    let mut buffer: *mut OpenProtocolInformationEntry = core::ptr::null_mut();
+    let mut count = 0;
+    let status =
+        (open_protocol_information_ptr)(handle, guid_ptr(protocol), &mut buffer, &mut count);
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| unsafe { PoolBuffer::from_raw_parts(st, buffer, count) }))
+}
+
+/// Call EFI_PROTOCOLS_PER_HANDLE boot service function.
+/// The returned GUID pointers are owned by the firmware and must not be freed.
+/// SAFETY : The `st` pointer must be valid for the lifetime of the returned buffer. This is
+/// gaurenteed if `GlobalData` is used to store the pointer.
+pub fn protocols_per_handle(
+    st: *mut SystemTable,
+    handle: Handle,
+) -> Result<Completion<PoolBuffer<*mut Guid>>> {
+    let boot_services = super::get_boot_services(st)?;
+    let protocols_per_handle_ptr = unsafe { (*boot_services).protocols_per_handle };
+
+    let mut buffer: *mut *mut Guid = core::ptr::null_mut();
+    let mut count = 0;
+    let status = (protocols_per_handle_ptr)(handle, &mut buffer, &mut count);
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| unsafe { PoolBuffer::from_raw_parts(st, buffer, count) }))
+}
+
+/// Call EFI_LOCATE_HANDLE boot service function.
+/// On success and on `EFI_BUFFER_TOO_SMALL`, `count` is set to the number of matching handles.
+/// Use `locate_handle_buffer` to let the firmware allocate a large enough buffer.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn locate_handle(
+    st: *mut SystemTable,
+    search_type: SearchType<'_>,
+    buffer: &mut [Handle],
+    count: &mut usize,
+) -> Result<Completion<()>> {
+    let boot_services = super::get_boot_services(st)?;
+    let locate_handle_ptr = unsafe { (*boot_services).locate_handle };
+
+    let (search_type, protocol, search_key) = search_type.as_raw();
+    let mut buffer_size = core::mem::size_of_val(buffer);
+    let status = (locate_handle_ptr)(
+        search_type,
+        protocol,
+        search_key,
+        &mut buffer_size,
+        buffer.as_mut_ptr(),
+    );
+    *count = buffer_size / core::mem::size_of::<Handle>();
+
+    helpers::status_to_result(status).map_err(|x| x.into())
+}
+
+/// Call EFI_LOCATE_HANDLE_BUFFER boot service function.
+/// SAFETY : The `st` pointer must be valid for the lifetime of the returned buffer. This is
+/// gaurenteed if `GlobalData` is used to store the pointer.
+pub fn locate_handle_buffer(
+    st: *mut SystemTable,
+    search_type: SearchType<'_>,
+) -> Result<Completion<PoolBuffer<Handle>>> {
+    let boot_services = super::get_boot_services(st)?;
+    let locate_handle_buffer_ptr = unsafe { (*boot_services).locate_handle_buffer };
+
+    let (search_type, protocol, search_key) = search_type.as_raw();
+    let mut buffer: *mut Handle = core::ptr::null_mut();
+    let mut count = 0;
+    let status =
+        (locate_handle_buffer_ptr)(search_type, protocol, search_key, &mut count, &mut buffer);
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| unsafe { PoolBuffer::from_raw_parts(st, buffer, count) }))
+}
+
+/// Call EFI_LOCATE_PROTOCOL boot service function.
+/// Returns the first interface of `protocol`, or with a `registration` key from
+/// `register_protocol_notify`, the next one installed since the last call.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn locate_protocol(
+    st: *mut SystemTable,
+    protocol: &Guid,
+    registration: Option<*mut c_void>,
+) -> Result<Completion<*mut c_void>> {
+    let boot_services = super::get_boot_services(st)?;
+    let locate_protocol_ptr = unsafe { (*boot_services).locate_protocol };
+
+    let mut interface: *mut c_void = core::ptr::null_mut();
+    let status = (locate_protocol_ptr)(
+        guid_ptr(protocol),
+        registration.unwrap_or(core::ptr::null_mut()),
+        &mut interface,
+    );
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| interface))
+}
+
+/// Call EFI_LOCATE_DEVICE_PATH boot service function.
+/// Returns the handle supporting `protocol` whose device path is the longest prefix of
+/// `device_path`. `device_path` is advanced past the matched part.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn locate_device_path(
+    st: *mut SystemTable,
+    protocol: &Guid,
+    device_path: &mut *mut device_path::Protocol,
+) -> Result<Completion<Handle>> {
+    let boot_services = super::get_boot_services(st)?;
+    let locate_device_path_ptr = unsafe { (*boot_services).locate_device_path };
+
+    let mut handle: Handle = core::ptr::null_mut();
+    let status = (locate_device_path_ptr)(guid_ptr(protocol), device_path, &mut handle);
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| handle))
+}
+
+/// Call EFI_REGISTER_PROTOCOL_NOTIFY boot service function.
+/// `event` is signaled whenever `protocol` is installed or reinstalled. Returns the registration
+/// key to use with `SearchType::ByRegisterNotify` and `locate_protocol`.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn register_protocol_notify(
+    st: *mut SystemTable,
+    protocol: &Guid,
+    event: Event,
+) -> Result<Completion<*mut c_void>> {
+    let boot_services = super::get_boot_services(st)?;
+    let register_protocol_notify_ptr = unsafe { (*boot_services).register_protocol_notify };
+
+    let mut registration: *mut c_void = core::ptr::null_mut();
+    let status = (register_protocol_notify_ptr)(guid_ptr(protocol), event, &mut registration);
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| registration))
+}
+
+/// A protocol opened with `open_protocol`, which is closed when dropped.
+pub struct ScopedProtocol<T> {
+    st: *mut SystemTable,
+    interface: *mut T,
+    handle: Handle,
+    protocol: Guid,
+    agent_handle: Handle,
+    controller_handle: Handle,
+}
+
+impl<T> ScopedProtocol<T> {
+    /// The protocol interface. This is null if opened with `OPEN_PROTOCOL_TEST_PROTOCOL`.
+    pub fn as_ptr(&self) -> *mut T {
+        self.interface
+    }
+
+    /// The handle the protocol was opened on.
+    pub fn handle(&self) -> Handle {
+        self.handle
+    }
+
+    /// Give up ownership without closing the protocol.
+    pub fn into_raw(self) -> *mut T {
+        let interface = self.interface;
+        core::mem::forget(self);
+        interface
+    }
+
+    /// Close the protocol, returning any error. Dropping the guard closes it as well, but ignores
+    /// errors.
+    pub fn close(self) -> Result<Completion<()>> {
+        let r = close_protocol(
+            self.st,
+            self.handle,
+            &self.protocol,
+            self.agent_handle,
+            self.controller_handle,
+        );
+        core::mem::forget(self);
+        r
+    }
+}
+
+impl<T> Drop for ScopedProtocol<T> {
+    fn drop(&mut self) {
+        let _ = close_protocol(
+            self.st,
+            self.handle,
+            &self.protocol,
+            self.agent_handle,
+            self.controller_handle,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockSystemTable;
+    use crate::status::StatusCode;
+
+    const GUID_A: Guid = Guid::from_fields(0xa, 0, 0, 0, 0, &[0; 6]);
+    const GUID_B: Guid = Guid::from_fields(0xb, 0, 0, 0, 0, &[0; 6]);
+
+    fn iface(x: usize) -> *mut c_void {
+        x as *mut c_void
+    }
+
+    #[test]
+    fn install_and_locate() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let handle = install_protocol_interface(st, None, &GUID_A, iface(0x10))
+            .unwrap()
+            .into_value();
+        install_protocol_interface(st, Some(handle), &GUID_B, iface(0x20))
+            .unwrap()
+            .into_value();
+
+        let r = handle_protocol(st, handle, &GUID_B).unwrap().into_value();
+        assert_eq!(r, iface(0x20));
+        let r = locate_protocol(st, &GUID_A, None).unwrap().into_value();
+        assert_eq!(r, iface(0x10));
+
+        let guids = protocols_per_handle(st, handle).unwrap().into_value();
+        assert_eq!(unsafe { (*guids[0], *guids[1]) }, (GUID_A, GUID_B));
+        drop(guids);
+
+        uninstall_protocol_interface(st, handle, &GUID_A, iface(0x10))
+            .unwrap()
+            .into_value();
+        assert_eq!(
+            locate_protocol(st, &GUID_A, None).unwrap_err(),
+            errors::StatusNullError::UefiError(StatusCode::NotFound)
+        );
+        assert_eq!(mock.outstanding_pool_allocations(), 0);
+    }
+
+    #[test]
+    fn scoped_protocol_closes_on_drop() {
+        let mut mock = MockSystemTable::new();
+        let handle = mock.install_protocol(None, &GUID_A, iface(0x10));
+        let agent = mock.install_protocol(None, &GUID_B, iface(0x20));
+        let st = mock.system_table();
+
+        {
+            let p = open_protocol::<u8>(
+                st,
+                handle,
+                &GUID_A,
+                agent,
+                core::ptr::null_mut(),
+                efi::OPEN_PROTOCOL_GET_PROTOCOL,
+            )
+            .unwrap()
+            .into_value();
+            assert_eq!(p.as_ptr(), iface(0x10).cast());
+
+            let info = open_protocol_information(st, handle, &GUID_A)
+                .unwrap()
+                .into_value();
+            assert_eq!(info.len(), 1);
+            assert_eq!(info[0].agent_handle, agent);
+        }
+        assert!(mock.open_protocol_information(handle, &GUID_A).is_empty());
+        assert_eq!(mock.outstanding_pool_allocations(), 0);
+    }
+
+    #[test]
+    fn open_protocol_by_driver_conflict() {
+        let mut mock = MockSystemTable::new();
+        let handle = mock.install_protocol(None, &GUID_A, iface(0x10));
+        let agent_a = mock.install_protocol(None, &GUID_B, iface(0x20));
+        let agent_b = mock.install_protocol(None, &GUID_B, iface(0x30));
+        let st = mock.system_table();
+
+        let open = |agent| {
+            open_protocol::<u8>(
+                st,
+                handle,
+                &GUID_A,
+                agent,
+                handle,
+                efi::OPEN_PROTOCOL_BY_DRIVER,
+            )
+        };
+        let p = open(agent_a).unwrap().into_value();
+        assert_eq!(
+            open(agent_b).err(),
+            Some(errors::StatusNullError::UefiError(StatusCode::AccessDenied))
+        );
+        assert_eq!(
+            uninstall_protocol_interface(st, handle, &GUID_A, iface(0x10)),
+            Err(errors::StatusNullError::UefiError(StatusCode::AccessDenied))
+        );
+
+        p.close().unwrap().into_value();
+        open(agent_b)
+            .unwrap()
+            .into_value()
+            .close()
+            .unwrap()
+            .into_value();
+    }
+
+    #[test]
+    fn locate_handles() {
+        let mut mock = MockSystemTable::new();
+        let a = mock.install_protocol(None, &GUID_A, iface(0x10));
+        let b = mock.install_protocol(None, &GUID_B, iface(0x20));
+        mock.install_protocol(Some(b), &GUID_A, iface(0x30));
+        let st = mock.system_table();
+
+        let handles = locate_handle_buffer(st, SearchType::ByProtocol(&GUID_A))
+            .unwrap()
+            .into_value();
+        assert_eq!(&*handles, [a, b]);
+        drop(handles);
+        assert_eq!(mock.outstanding_pool_allocations(), 0);
+
+        let mut buffer = [core::ptr::null_mut(); 1];
+        let mut count = 0;
+        assert_eq!(
+            locate_handle(st, SearchType::AllHandles, &mut buffer, &mut count),
+            Err(errors::StatusNullError::UefiError(
+                StatusCode::BufferTooSmall
+            ))
+        );
+        assert_eq!(count, 2);
+
+        let mut buffer = [core::ptr::null_mut(); 2];
+        locate_handle(st, SearchType::ByProtocol(&GUID_B), &mut buffer, &mut count)
+            .unwrap()
+            .into_value();
+        assert_eq!(&buffer[..count], [b]);
+    }
+
+    #[test]
+    fn register_notify() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let event = crate::boot_services::event_services::Event::timer(st)
+            .unwrap()
+            .into_value();
+        let key = register_protocol_notify(st, &GUID_A, event.as_raw())
+            .unwrap()
+            .into_value();
+        assert!(!event.check().unwrap().into_value());
+
+        let handle = mock.install_protocol(None, &GUID_A, iface(0x10));
+        assert!(event.check().unwrap().into_value());
+
+        let handles = locate_handle_buffer(st, SearchType::ByRegisterNotify(key))
+            .unwrap()
+            .into_value();
+        assert_eq!(&*handles, [handle]);
+        assert!(locate_protocol(st, &GUID_A, Some(key)).is_err());
+    }
+
+    #[test]
+    fn locate_device_path_prefix() {
+        let mut mock = MockSystemTable::new();
+
+        // A hardware node of 4 bytes, followed by an end node.
+        let mut short: [u8; 8] = [0x01, 0x01, 4, 0, 0x7f, 0xff, 4, 0];
+        let mut long: [u8; 12] = [0x01, 0x01, 4, 0, 0x01, 0x02, 4, 0, 0x7f, 0xff, 4, 0];
+
+        let handle = mock.install_protocol(None, &GUID_A, iface(0x10));
+        mock.install_protocol(
+            Some(handle),
+            &device_path::PROTOCOL_GUID,
+            short.as_mut_ptr().cast(),
+        );
+        let st = mock.system_table();
+
+        let mut path: *mut device_path::Protocol = long.as_mut_ptr().cast();
+        let r = locate_device_path(st, &GUID_A, &mut path)
+            .unwrap()
+            .into_value();
+        assert_eq!(r, handle);
+        assert_eq!(path.cast::<u8>(), long[4..].as_mut_ptr());
+    }
+}
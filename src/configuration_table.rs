@@ -0,0 +1,381 @@
+//! This module provides access to the configuration tables of the `SystemTable`.
+//!
+//! The configuration tables are an array of GUID and pointer pairs, through which the firmware
+//! hands over tables such as the ACPI RSDP or the SMBIOS entry point. The array may be
+//! reallocated when a table is installed, so it is read again from the system table on every
+//! access.
+
+use core::ffi::c_void;
+
+use crate::{
+    boot_services::memory_allocation_services::{MemoryMapInfo, MemoryMapIter},
+    efi::{Guid, MemoryDescriptor, SystemTable},
+    errors, helpers,
+    status::StatusCode,
+};
+use r_efi::efi::{self, ConfigurationTable};
+
+type Result<T> = core::result::Result<T, errors::NullPtrError>;
+
+/// The GUID of the table of loaded images maintained for debuggers.
+pub const DEBUG_IMAGE_INFO_TABLE_GUID: Guid = Guid::from_fields(
+    0x49152e77,
+    0x1ada,
+    0x4764,
+    0xb7,
+    0xa2,
+    &[0x7a, 0xfe, 0xfe, 0xd9, 0x5e, 0x8b],
+);
+
+/// The GUID of the PI hand-off block list.
+pub const HOB_LIST_GUID: Guid = Guid::from_fields(
+    0x7739f24c,
+    0x93d7,
+    0x11d4,
+    0x9a,
+    0x3a,
+    &[0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d],
+);
+
+/// The configuration tables known to this crate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WellKnownTable {
+    /// The ACPI 1.0 RSDP.
+    Acpi10,
+    /// The ACPI 2.0 or later RSDP.
+    Acpi20,
+    /// The SMBIOS 2.x entry point.
+    Smbios,
+    /// The SMBIOS 3.x entry point.
+    Smbios3,
+    /// The flattened device tree blob.
+    DeviceTree,
+    /// The `EFI_MEMORY_ATTRIBUTES_TABLE`.
+    MemoryAttributes,
+    /// The deprecated `EFI_PROPERTIES_TABLE`.
+    Properties,
+    /// The `EFI_DEBUG_IMAGE_INFO_TABLE_HEADER`.
+    DebugImageInfo,
+    /// The PI hand-off block list.
+    HobList,
+}
+
+impl WellKnownTable {
+    const ALL: [Self; 9] = [
+        Self::Acpi10,
+        Self::Acpi20,
+        Self::Smbios,
+        Self::Smbios3,
+        Self::DeviceTree,
+        Self::MemoryAttributes,
+        Self::Properties,
+        Self::DebugImageInfo,
+        Self::HobList,
+    ];
+
+    pub const fn guid(&self) -> Guid {
+        match self {
+            Self::Acpi10 => efi::ACPI_10_TABLE_GUID,
+            Self::Acpi20 => efi::ACPI_20_TABLE_GUID,
+            Self::Smbios => efi::SMBIOS_TABLE_GUID,
+            Self::Smbios3 => efi::SMBIOS3_TABLE_GUID,
+            Self::DeviceTree => efi::DTB_TABLE_GUID,
+            Self::MemoryAttributes => efi::MEMORY_ATTRIBUTES_TABLE_GUID,
+            Self::Properties => efi::PROPERTIES_TABLE_GUID,
+            Self::DebugImageInfo => DEBUG_IMAGE_INFO_TABLE_GUID,
+            Self::HobList => HOB_LIST_GUID,
+        }
+    }
+
+    pub fn from_guid(guid: &Guid) -> Option<Self> {
+        Self::ALL.into_iter().find(|x| x.guid() == *guid)
+    }
+}
+
+/// An iterator over the configuration table entries, see `configuration_tables`.
+#[derive(Clone, Debug)]
+pub struct ConfigurationTableIter {
+    st: *mut SystemTable,
+    index: usize,
+}
+
+impl Iterator for ConfigurationTableIter {
+    type Item = ConfigurationTable;
+
+    fn next(&mut self) -> Option<ConfigurationTable> {
+        let (tables, count) = unsafe {
+            (
+                (*self.st).configuration_table,
+                (*self.st).number_of_table_entries,
+            )
+        };
+        if tables.is_null() || self.index >= count {
+            return None;
+        }
+
+        let entry = unsafe { *tables.add(self.index) };
+        self.index += 1;
+        Some(entry)
+    }
+}
+
+/// Iterate over the configuration table entries of the system table.
+/// SAFETY : The `st` pointer must be valid for the lifetime of the iterator. This is gaurenteed
+/// if `GlobalData` is used to store the pointer.
+pub fn configuration_tables(st: *mut SystemTable) -> Result<ConfigurationTableIter> {
+    helpers::null_check_mut(st, "System Table")?;
+    Ok(ConfigurationTableIter { st, index: 0 })
+}
+
+/// The table installed with `guid`, if any.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn find_table(st: *mut SystemTable, guid: &Guid) -> Result<Option<*mut c_void>> {
+    Ok(configuration_tables(st)?
+        .find(|x| x.vendor_guid == *guid)
+        .map(|x| x.vendor_table))
+}
+
+/// The table of a well-known kind, if installed.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn find_well_known_table(
+    st: *mut SystemTable,
+    table: WellKnownTable,
+) -> Result<Option<*mut c_void>> {
+    find_table(st, &table.guid())
+}
+
+/// The ACPI Root System Description Pointer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AcpiRsdp {
+    /// An ACPI 1.0 RSDP, pointing to the RSDT.
+    V1(*mut c_void),
+    /// An ACPI 2.0 or later RSDP, which also points to the XSDT.
+    V2(*mut c_void),
+}
+
+impl AcpiRsdp {
+    pub fn as_ptr(&self) -> *mut c_void {
+        match self {
+            Self::V1(x) | Self::V2(x) => *x,
+        }
+    }
+}
+
+/// Find the ACPI RSDP, preferring the ACPI 2.0 one.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn acpi_rsdp(st: *mut SystemTable) -> Result<Option<AcpiRsdp>> {
+    if let Some(x) = find_well_known_table(st, WellKnownTable::Acpi20)? {
+        return Ok(Some(AcpiRsdp::V2(x)));
+    }
+    Ok(find_well_known_table(st, WellKnownTable::Acpi10)?.map(AcpiRsdp::V1))
+}
+
+/// The SMBIOS entry point structure.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmbiosEntryPoint {
+    /// A 32-bit SMBIOS 2.x entry point, anchored by `_SM_`.
+    V2(*mut c_void),
+    /// A 64-bit SMBIOS 3.x entry point, anchored by `_SM3_`.
+    V3(*mut c_void),
+}
+
+impl SmbiosEntryPoint {
+    pub fn as_ptr(&self) -> *mut c_void {
+        match self {
+            Self::V2(x) | Self::V3(x) => *x,
+        }
+    }
+}
+
+/// Find the SMBIOS entry point, preferring the SMBIOS 3.x one.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn smbios_entry_point(st: *mut SystemTable) -> Result<Option<SmbiosEntryPoint>> {
+    if let Some(x) = find_well_known_table(st, WellKnownTable::Smbios3)? {
+        return Ok(Some(SmbiosEntryPoint::V3(x)));
+    }
+    Ok(find_well_known_table(st, WellKnownTable::Smbios)?.map(SmbiosEntryPoint::V2))
+}
+
+/// Find the flattened device tree blob.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn device_tree(st: *mut SystemTable) -> Result<Option<*mut c_void>> {
+    find_well_known_table(st, WellKnownTable::DeviceTree)
+}
+
+/// Find the PI hand-off block list.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn hob_list(st: *mut SystemTable) -> Result<Option<*mut c_void>> {
+    find_well_known_table(st, WellKnownTable::HobList)
+}
+
+/// Find the `EFI_DEBUG_IMAGE_INFO_TABLE_HEADER`.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn debug_image_info_table(st: *mut SystemTable) -> Result<Option<*mut c_void>> {
+    find_well_known_table(st, WellKnownTable::DebugImageInfo)
+}
+
+/// Read the `EFI_PROPERTIES_TABLE`.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn properties_table(st: *mut SystemTable) -> Result<Option<efi::PropertiesTable>> {
+    let table = find_well_known_table(st, WellKnownTable::Properties)?;
+    Ok(table.map(|x| unsafe { (x as *const efi::PropertiesTable).read_unaligned() }))
+}
+
+/// Iterate over the descriptors of the `EFI_MEMORY_ATTRIBUTES_TABLE`, which describe the
+/// permissions of the runtime code and data. A malformed table, e.g. with descriptors smaller
+/// than `MemoryDescriptor`, is reported as `EFI_DEVICE_ERROR`.
+///
+/// # Safety
+///
+/// The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store the
+/// pointer. The table must not be freed or changed while the iterator is in use.
+pub unsafe fn memory_attributes(
+    st: *mut SystemTable,
+) -> core::result::Result<Option<MemoryMapIter<'static>>, errors::StatusNullError> {
+    let table = match find_well_known_table(st, WellKnownTable::MemoryAttributes)? {
+        Some(x) => x as *const efi::MemoryAttributesTable,
+        None => return Ok(None),
+    };
+
+    let malformed = || errors::StatusNullError::UefiError(StatusCode::DeviceError);
+    let header = unsafe { table.read_unaligned() };
+    let info = MemoryMapInfo {
+        map_size: (header.number_of_entries as usize)
+            .checked_mul(header.descriptor_size as usize)
+            .ok_or_else(malformed)?,
+        map_key: 0,
+        descriptor_size: header.descriptor_size as usize,
+        // `header.version` is the version of the table, not of its descriptors.
+        descriptor_version: efi::MEMORY_DESCRIPTOR_VERSION,
+    };
+    if info.descriptor_size < core::mem::size_of::<MemoryDescriptor>() {
+        return Err(malformed());
+    }
+    let entries = unsafe {
+        core::slice::from_raw_parts(
+            (table as *const u8)
+                .add(core::mem::size_of::<efi::MemoryAttributesTable>())
+                .cast::<MemoryDescriptor>(),
+            info.map_size
+                .div_ceil(core::mem::size_of::<MemoryDescriptor>()),
+        )
+    };
+    MemoryMapIter::new(entries, &info)
+        .map(Some)
+        .ok_or_else(malformed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boot_services::misc_services::install_configuration_table;
+    use crate::mock::MockSystemTable;
+
+    #[test]
+    fn iterate_and_find() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        assert_eq!(configuration_tables(st).unwrap().count(), 0);
+
+        let mut rsdp1 = 1u8;
+        let mut rsdp2 = 2u8;
+        let mut hob = 3u8;
+        let ptr = |x: &mut u8| x as *mut u8 as *mut c_void;
+        mock.install_configuration_table(&efi::ACPI_10_TABLE_GUID, ptr(&mut rsdp1));
+        install_configuration_table(st, &HOB_LIST_GUID, ptr(&mut hob))
+            .unwrap()
+            .into_value();
+
+        let guids: std::vec::Vec<_> = configuration_tables(st)
+            .unwrap()
+            .map(|x| WellKnownTable::from_guid(&x.vendor_guid))
+            .collect();
+        assert_eq!(
+            guids,
+            [Some(WellKnownTable::Acpi10), Some(WellKnownTable::HobList)]
+        );
+        assert_eq!(hob_list(st).unwrap(), Some(ptr(&mut hob)));
+        assert_eq!(device_tree(st).unwrap(), None);
+        assert_eq!(acpi_rsdp(st).unwrap(), Some(AcpiRsdp::V1(ptr(&mut rsdp1))));
+
+        mock.install_configuration_table(&efi::ACPI_20_TABLE_GUID, ptr(&mut rsdp2));
+        assert_eq!(acpi_rsdp(st).unwrap(), Some(AcpiRsdp::V2(ptr(&mut rsdp2))));
+
+        install_configuration_table(st, &HOB_LIST_GUID, core::ptr::null_mut())
+            .unwrap()
+            .into_value();
+        assert_eq!(hob_list(st).unwrap(), None);
+        assert_eq!(configuration_tables(st).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn smbios_prefers_v3() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        assert_eq!(smbios_entry_point(st).unwrap(), None);
+
+        let mut v2 = *b"_SM_";
+        let mut v3 = *b"_SM3_";
+        mock.install_configuration_table(&efi::SMBIOS_TABLE_GUID, v2.as_mut_ptr().cast());
+        assert_eq!(
+            smbios_entry_point(st).unwrap(),
+            Some(SmbiosEntryPoint::V2(v2.as_mut_ptr().cast()))
+        );
+        mock.install_configuration_table(&efi::SMBIOS3_TABLE_GUID, v3.as_mut_ptr().cast());
+        assert_eq!(
+            smbios_entry_point(st).unwrap().map(|x| x.as_ptr()),
+            Some(v3.as_mut_ptr().cast())
+        );
+    }
+
+    #[test]
+    fn memory_attributes_table() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        assert!(unsafe { memory_attributes(st) }.unwrap().is_none());
+
+        let desc = |r#type, physical_start, attribute| MemoryDescriptor {
+            r#type,
+            physical_start,
+            virtual_start: 0,
+            number_of_pages: 1,
+            attribute,
+        };
+        let mut table = efi::MemoryAttributesTable::<2> {
+            version: 1,
+            number_of_entries: 2,
+            descriptor_size: core::mem::size_of::<MemoryDescriptor>() as u32,
+            reserved: 0,
+            entry: [
+                desc(efi::RUNTIME_SERVICES_CODE, 0x30_0000, efi::MEMORY_RO),
+                desc(efi::RUNTIME_SERVICES_DATA, 0x30_1000, efi::MEMORY_XP),
+            ],
+        };
+        let table = &mut table as *mut efi::MemoryAttributesTable<2>;
+        mock.install_configuration_table(&efi::MEMORY_ATTRIBUTES_TABLE_GUID, table.cast());
+
+        let entries: std::vec::Vec<_> = unsafe { memory_attributes(st) }
+            .unwrap()
+            .unwrap()
+            .map(|x| (x.physical_start, x.attribute))
+            .collect();
+        assert_eq!(
+            entries,
+            [(0x30_0000, efi::MEMORY_RO), (0x30_1000, efi::MEMORY_XP)]
+        );
+
+        unsafe { (*table).descriptor_size = 8 };
+        assert_eq!(
+            unsafe { memory_attributes(st) }.map(|x| x.is_some()),
+            Err(errors::StatusNullError::UefiError(StatusCode::DeviceError))
+        );
+    }
+}
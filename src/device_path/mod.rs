@@ -0,0 +1,471 @@
+//! This module contains a parser and a builder for device paths.
+//!
+//! A device path is a packed sequence of nodes, each starting with a type, a sub-type and a
+//! 16-bit length covering the whole node. The path ends with an End Entire Device Path node. A
+//! path with several instances, e.g. the `ConOut` variable, separates them with End Instance
+//! nodes.
+//!
+//! Parsing works on byte slices, so it can run on paths read from variables or from untrusted
+//! input. Nodes are not aligned, so all fields are read byte-wise. `DevicePath::from_ptr` reads a
+//! path handed out by the firmware, e.g. the file path of a loaded image.
+
+pub mod nodes;
+pub mod text;
+
+#[cfg(any(test, feature = "alloc"))]
+use alloc::vec::Vec;
+
+use crate::errors::DevicePathError;
+#[cfg(any(test, feature = "alloc"))]
+use crate::string::CStr16;
+use r_efi::protocols::device_path;
+
+pub use nodes::Decoded;
+#[cfg(any(test, feature = "alloc"))]
+pub use nodes::EncodeNode;
+
+type Result<T> = core::result::Result<T, DevicePathError>;
+
+pub use device_path::{TYPE_ACPI, TYPE_BIOS, TYPE_END, TYPE_HARDWARE, TYPE_MEDIA, TYPE_MESSAGING};
+
+/// Sub-type of the end node separating instances.
+pub const END_INSTANCE: u8 = device_path::End::SUBTYPE_INSTANCE;
+/// Sub-type of the end node ending the path.
+pub const END_ENTIRE: u8 = device_path::End::SUBTYPE_ENTIRE;
+
+/// Size of the header at the start of every node.
+pub const NODE_HEADER_SIZE: usize = 4;
+
+/// A single node, including its header.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Node<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Node<'a> {
+    /// Parse the node at the start of `data`. Bytes after the node are ignored.
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        let header = data
+            .get(..NODE_HEADER_SIZE)
+            .ok_or(DevicePathError::Truncated)?;
+        let length = u16::from_le_bytes([header[2], header[3]]) as usize;
+        if length < NODE_HEADER_SIZE {
+            return Err(DevicePathError::InvalidLength);
+        }
+        let data = data.get(..length).ok_or(DevicePathError::Truncated)?;
+        Ok(Self { data })
+    }
+
+    pub fn node_type(&self) -> u8 {
+        self.data[0]
+    }
+
+    pub fn sub_type(&self) -> u8 {
+        self.data[1]
+    }
+
+    /// The length of the node, including the header.
+    pub fn length(&self) -> usize {
+        self.data.len()
+    }
+
+    /// The data following the header.
+    pub fn data(&self) -> &'a [u8] {
+        &self.data[NODE_HEADER_SIZE..]
+    }
+
+    /// The whole node, including the header.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Whether this is an End Instance or End Entire node.
+    pub fn is_end(&self) -> bool {
+        self.node_type() == TYPE_END
+    }
+
+    /// Decode the node. Nodes of unknown types are returned as `Decoded::Unknown`, while known
+    /// nodes too short for their data are an error.
+    pub fn decode(&self) -> Result<Decoded<'a>> {
+        nodes::decode(*self)
+    }
+}
+
+impl core::fmt::Debug for Node<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Node")
+            .field("type", &self.node_type())
+            .field("sub_type", &self.sub_type())
+            .field("data", &self.data())
+            .finish()
+    }
+}
+
+/// A validated device path: a sequence of well-formed nodes ending with an End Entire node.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DevicePath<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> DevicePath<'a> {
+    /// Parse and validate the path at the start of `data`. Bytes after the end node are ignored.
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        let mut offset = 0;
+        loop {
+            if offset == data.len() {
+                return Err(DevicePathError::MissingEnd);
+            }
+            let node = Node::parse(&data[offset..])?;
+            offset += node.length();
+            if node.is_end() && node.sub_type() == END_ENTIRE {
+                // End nodes have no data, which `nodes` relies on.
+                if node.length() != NODE_HEADER_SIZE {
+                    return Err(DevicePathError::InvalidLength);
+                }
+                return Ok(Self {
+                    data: &data[..offset],
+                });
+            }
+        }
+    }
+
+    /// Parse the path at `ptr`, e.g. the file path of a loaded image.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to a device path whose nodes are all readable, up to its end node or the
+    /// first malformed node.
+    pub unsafe fn from_ptr(ptr: *const device_path::Protocol) -> Result<Self> {
+        let ptr = ptr.cast::<u8>();
+        let mut len = 0;
+        loop {
+            let header = unsafe { core::slice::from_raw_parts(ptr.add(len), NODE_HEADER_SIZE) };
+            let length = u16::from_le_bytes([header[2], header[3]]) as usize;
+            if length < NODE_HEADER_SIZE {
+                return Err(DevicePathError::InvalidLength);
+            }
+            len += length;
+            if header[0] == TYPE_END && header[1] == END_ENTIRE {
+                break;
+            }
+        }
+        Self::parse(unsafe { core::slice::from_raw_parts(ptr, len) })
+    }
+
+    /// The path as passed to the firmware. It points into the parsed data.
+    pub fn as_ptr(&self) -> *const device_path::Protocol {
+        self.data.as_ptr().cast()
+    }
+
+    /// The whole path, including the end node.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// The size of the path in bytes, including the end node.
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Iterate over the nodes, including End Instance nodes but not the final End Entire node.
+    pub fn nodes(&self) -> Nodes<'a> {
+        Nodes {
+            data: &self.data[..self.data.len() - NODE_HEADER_SIZE],
+        }
+    }
+
+    /// Whether the path has more than one instance.
+    pub fn is_multi_instance(&self) -> bool {
+        self.nodes()
+            .any(|x| x.is_end() && x.sub_type() == END_INSTANCE)
+    }
+
+    /// Iterate over the instances. Each yields the nodes of the instance, without end nodes.
+    pub fn instances(&self) -> Instances<'a> {
+        Instances {
+            nodes: Some(self.nodes()),
+        }
+    }
+}
+
+/// An iterator over the nodes of a `DevicePath`.
+#[derive(Clone)]
+pub struct Nodes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        // The path was validated, so every node parses.
+        let node = Node::parse(self.data).ok()?;
+        self.data = &self.data[node.length()..];
+        Some(node)
+    }
+}
+
+/// An iterator over the instances of a `DevicePath`.
+#[derive(Clone)]
+pub struct Instances<'a> {
+    nodes: Option<Nodes<'a>>,
+}
+
+impl<'a> Iterator for Instances<'a> {
+    type Item = Nodes<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let nodes = self.nodes.as_mut()?;
+        let start = nodes.data;
+        let mut len = 0;
+        for node in nodes.by_ref() {
+            if node.is_end() && node.sub_type() == END_INSTANCE {
+                return Some(Nodes {
+                    data: &start[..len],
+                });
+            }
+            len += node.length();
+        }
+        self.nodes = None;
+        Some(Nodes { data: start })
+    }
+}
+
+/// An owned device path, created by `DevicePathBuilder`.
+#[cfg(any(test, feature = "alloc"))]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DevicePathBuf {
+    data: Vec<u8>,
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl DevicePathBuf {
+    /// Copy a borrowed path.
+    pub fn from_path(path: DevicePath<'_>) -> Self {
+        Self {
+            data: path.as_bytes().to_vec(),
+        }
+    }
+
+    pub fn as_path(&self) -> DevicePath<'_> {
+        DevicePath { data: &self.data }
+    }
+
+    pub fn as_ptr(&self) -> *const device_path::Protocol {
+        self.data.as_ptr().cast()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Builds a device path from nodes, appending the end node.
+#[cfg(any(test, feature = "alloc"))]
+#[derive(Clone, Debug, Default)]
+pub struct DevicePathBuilder {
+    data: Vec<u8>,
+    error: Option<DevicePathError>,
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl DevicePathBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start with the nodes of `path`, e.g. to append a file path to the path of a device.
+    /// All instances are kept.
+    pub fn from_path(path: DevicePath<'_>) -> Self {
+        Self {
+            data: path.as_bytes()[..path.size() - NODE_HEADER_SIZE].to_vec(),
+            error: None,
+        }
+    }
+
+    /// Append a node with the given header fields and data.
+    pub fn raw_node(mut self, node_type: u8, sub_type: u8, data: &[u8]) -> Self {
+        match u16::try_from(NODE_HEADER_SIZE + data.len()) {
+            Ok(length) => {
+                self.data.extend_from_slice(&[node_type, sub_type]);
+                self.data.extend_from_slice(&length.to_le_bytes());
+                self.data.extend_from_slice(data);
+            }
+            Err(_) => {
+                self.error.get_or_insert(DevicePathError::InvalidLength);
+            }
+        }
+        self
+    }
+
+    /// Append a copy of a parsed node.
+    pub fn node(self, node: Node<'_>) -> Self {
+        self.raw_node(node.node_type(), node.sub_type(), node.data())
+    }
+
+    /// Append a decoded node.
+    pub fn push(self, node: &impl EncodeNode) -> Self {
+        let (node_type, sub_type) = node.header();
+        let mut data = Vec::new();
+        node.encode(&mut data);
+        self.raw_node(node_type, sub_type, &data)
+    }
+
+    /// Append a File Path node.
+    pub fn file_path(self, path: &CStr16) -> Self {
+        let data: Vec<u8> = path
+            .as_slice_with_nul()
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        self.raw_node(TYPE_MEDIA, nodes::MEDIA_FILE_PATH, &data)
+    }
+
+    /// End the current instance and start a new one.
+    pub fn end_instance(self) -> Self {
+        self.raw_node(TYPE_END, END_INSTANCE, &[])
+    }
+
+    /// The path, with the end node appended. Fails if a node was too long.
+    pub fn build(&self) -> Result<DevicePathBuf> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let mut data = self.data.clone();
+        data.extend_from_slice(&[TYPE_END, END_ENTIRE, NODE_HEADER_SIZE as u8, 0]);
+        Ok(DevicePathBuf { data })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::string::CString16;
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(DevicePath::parse(&[]), Err(DevicePathError::MissingEnd));
+        assert_eq!(
+            DevicePath::parse(&[TYPE_END, END_ENTIRE, 4]),
+            Err(DevicePathError::Truncated)
+        );
+        assert_eq!(
+            DevicePath::parse(&[TYPE_END, END_ENTIRE, 3, 0]),
+            Err(DevicePathError::InvalidLength)
+        );
+        assert_eq!(
+            DevicePath::parse(&[TYPE_END, END_ENTIRE, 5, 0, 0]),
+            Err(DevicePathError::InvalidLength)
+        );
+        assert_eq!(
+            DevicePath::parse(&[TYPE_HARDWARE, 1, 6, 0, 0, 0]),
+            Err(DevicePathError::MissingEnd)
+        );
+        assert_eq!(
+            DevicePath::parse(&[TYPE_HARDWARE, 1, 8, 0, 0, 0]),
+            Err(DevicePathError::Truncated)
+        );
+        // An End Instance node does not end the path.
+        assert_eq!(
+            DevicePath::parse(&[TYPE_END, END_INSTANCE, 4, 0]),
+            Err(DevicePathError::MissingEnd)
+        );
+
+        let path = DevicePath::parse(&[TYPE_END, END_ENTIRE, 4, 0, 0xaa]).unwrap();
+        assert_eq!(path.size(), 4);
+        assert_eq!(path.nodes().count(), 0);
+        assert_eq!(path.instances().count(), 1);
+    }
+
+    #[test]
+    fn build_and_iterate() {
+        let file = CString16::try_from_str("\\EFI\\BOOT\\BOOTX64.EFI").unwrap();
+        let path = DevicePathBuilder::new()
+            .push(&nodes::Acpi {
+                hid: nodes::Acpi::PCI_ROOT_HID,
+                uid: 0,
+            })
+            .push(&nodes::Pci {
+                function: 0,
+                device: 0x1f,
+            })
+            .file_path(&file)
+            .build()
+            .unwrap();
+        let path = path.as_path();
+        assert_eq!(path.size(), 12 + 6 + 4 + 44 + 4);
+        assert!(!path.is_multi_instance());
+
+        let nodes: std::vec::Vec<_> = path.nodes().collect();
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(nodes[1].node_type(), TYPE_HARDWARE);
+        assert_eq!(nodes[1].data(), [0, 0x1f]);
+        match nodes[2].decode().unwrap() {
+            Decoded::FilePath(x) => assert_eq!(x.to_string_lossy(), "\\EFI\\BOOT\\BOOTX64.EFI"),
+            x => panic!("unexpected node {:?}", x),
+        }
+
+        let ptr_path = unsafe { DevicePath::from_ptr(path.as_ptr()) }.unwrap();
+        assert_eq!(ptr_path, path);
+
+        let copy = DevicePathBuilder::from_path(path)
+            .node(nodes[0])
+            .build()
+            .unwrap();
+        assert_eq!(copy.as_path().nodes().count(), 4);
+
+        let r = DevicePathBuilder::new()
+            .raw_node(TYPE_MEDIA, 0, &[0; 0x10000])
+            .build();
+        assert_eq!(r, Err(DevicePathError::InvalidLength));
+    }
+
+    #[test]
+    fn malformed_input() {
+        let path = DevicePathBuilder::new()
+            .push(&nodes::Usb {
+                parent_port_number: 1,
+                interface_number: 0,
+            })
+            .raw_node(TYPE_MEDIA, nodes::MEDIA_FILE_PATH, &[b'a', 0, 0, 0])
+            .build()
+            .unwrap()
+            .into_bytes();
+
+        // Every truncation and every single byte change must fail cleanly or decode.
+        for len in 0..path.len() {
+            assert!(DevicePath::parse(&path[..len]).is_err());
+        }
+        for i in 0..path.len() {
+            for x in [0x00, 0x01, 0x04, 0x7f, 0xff] {
+                let mut data = path.clone();
+                data[i] = x;
+                if let Ok(path) = DevicePath::parse(&data) {
+                    path.instances().flatten().for_each(|x| {
+                        let _ = x.decode();
+                    });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn instances() {
+        let path = DevicePathBuilder::new()
+            .raw_node(TYPE_HARDWARE, 1, &[0, 1])
+            .end_instance()
+            .raw_node(TYPE_HARDWARE, 1, &[0, 2])
+            .raw_node(TYPE_HARDWARE, 1, &[0, 3])
+            .build()
+            .unwrap();
+        let path = path.as_path();
+        assert!(path.is_multi_instance());
+        assert_eq!(path.nodes().count(), 4);
+
+        let instances: std::vec::Vec<usize> = path.instances().map(|x| x.count()).collect();
+        assert_eq!(instances, [1, 2]);
+    }
+}
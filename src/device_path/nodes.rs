@@ -0,0 +1,638 @@
+//! Decoded forms of common device path nodes.
+//!
+//! Nodes of older revisions of the specification, which lack trailing fields, are accepted. The
+//! missing fields are zero, and the nodes are encoded again in their current form.
+
+use core::fmt;
+
+use super::{Node, Result, TYPE_ACPI, TYPE_END, TYPE_HARDWARE, TYPE_MEDIA, TYPE_MESSAGING};
+use crate::efi::Guid;
+use crate::errors::DevicePathError;
+#[cfg(any(test, feature = "alloc"))]
+use alloc::{string::String, vec::Vec};
+
+pub const HARDWARE_PCI: u8 = 0x01;
+pub const HARDWARE_VENDOR: u8 = 0x04;
+pub const ACPI_ACPI: u8 = 0x01;
+pub const MESSAGING_USB: u8 = 0x05;
+pub const MESSAGING_VENDOR: u8 = 0x0a;
+pub const MESSAGING_MAC: u8 = 0x0b;
+pub const MESSAGING_IPV4: u8 = 0x0c;
+pub const MESSAGING_IPV6: u8 = 0x0d;
+pub const MESSAGING_SATA: u8 = 0x12;
+pub const MESSAGING_NVME: u8 = 0x17;
+pub const MESSAGING_URI: u8 = 0x18;
+pub const MEDIA_HARD_DRIVE: u8 = 0x01;
+pub const MEDIA_VENDOR: u8 = 0x03;
+pub const MEDIA_FILE_PATH: u8 = 0x04;
+
+/// A decoded node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decoded<'a> {
+    Pci(Pci),
+    Acpi(Acpi),
+    Usb(Usb),
+    Mac(Mac),
+    Ipv4(Ipv4),
+    Ipv6(Ipv6),
+    Sata(Sata),
+    Nvme(Nvme),
+    Uri(Uri<'a>),
+    HardDrive(HardDrive),
+    FilePath(FilePath<'a>),
+    /// A Hardware, Messaging or Media Vendor node.
+    Vendor(Vendor<'a>),
+    /// An End Instance node.
+    EndInstance,
+    /// Any other node.
+    Unknown(Node<'a>),
+}
+
+pub(super) fn decode(node: Node<'_>) -> Result<Decoded<'_>> {
+    let data = node.data();
+    let r = match (node.node_type(), node.sub_type()) {
+        (TYPE_HARDWARE, HARDWARE_PCI) => Decoded::Pci(Pci {
+            function: read_u8(data, 0)?,
+            device: read_u8(data, 1)?,
+        }),
+        (TYPE_ACPI, ACPI_ACPI) => Decoded::Acpi(Acpi {
+            hid: read_u32(data, 0)?,
+            uid: read_u32(data, 4)?,
+        }),
+        (TYPE_MESSAGING, MESSAGING_USB) => Decoded::Usb(Usb {
+            parent_port_number: read_u8(data, 0)?,
+            interface_number: read_u8(data, 1)?,
+        }),
+        (TYPE_MESSAGING, MESSAGING_MAC) => Decoded::Mac(Mac {
+            mac_address: read(data, 0)?,
+            if_type: read_u8(data, 32)?,
+        }),
+        (TYPE_MESSAGING, MESSAGING_IPV4) => Decoded::Ipv4(Ipv4 {
+            local_ip_address: read(data, 0)?,
+            remote_ip_address: read(data, 4)?,
+            local_port: read_u16(data, 8)?,
+            remote_port: read_u16(data, 10)?,
+            protocol: read_u16(data, 12)?,
+            static_ip_address: read_u8(data, 14)? != 0,
+            gateway_ip_address: read(data, 15).unwrap_or_default(),
+            subnet_mask: read(data, 19).unwrap_or_default(),
+        }),
+        (TYPE_MESSAGING, MESSAGING_IPV6) => Decoded::Ipv6(Ipv6 {
+            local_ip_address: read(data, 0)?,
+            remote_ip_address: read(data, 16)?,
+            local_port: read_u16(data, 32)?,
+            remote_port: read_u16(data, 34)?,
+            protocol: read_u16(data, 36)?,
+            ip_address_origin: read_u8(data, 38)?,
+            prefix_length: read_u8(data, 39).unwrap_or_default(),
+            gateway_ip_address: read(data, 40).unwrap_or_default(),
+        }),
+        (TYPE_MESSAGING, MESSAGING_SATA) => Decoded::Sata(Sata {
+            hba_port_number: read_u16(data, 0)?,
+            port_multiplier_port_number: read_u16(data, 2)?,
+            lun: read_u16(data, 4)?,
+        }),
+        (TYPE_MESSAGING, MESSAGING_NVME) => Decoded::Nvme(Nvme {
+            namespace_id: read_u32(data, 0)?,
+            ieee_eui_64: read(data, 4)?,
+        }),
+        (TYPE_MESSAGING, MESSAGING_URI) => Decoded::Uri(Uri { uri: data }),
+        (TYPE_MEDIA, MEDIA_HARD_DRIVE) => Decoded::HardDrive(HardDrive {
+            partition_number: read_u32(data, 0)?,
+            partition_start: read_u64(data, 4)?,
+            partition_size: read_u64(data, 12)?,
+            partition_signature: read(data, 20)?,
+            partition_format: read_u8(data, 36)?,
+            signature_type: read_u8(data, 37)?,
+        }),
+        (TYPE_MEDIA, MEDIA_FILE_PATH) => {
+            if !data.len().is_multiple_of(2) {
+                return Err(DevicePathError::InvalidLength);
+            }
+            Decoded::FilePath(FilePath { path: data })
+        }
+        (TYPE_HARDWARE, HARDWARE_VENDOR)
+        | (TYPE_MESSAGING, MESSAGING_VENDOR)
+        | (TYPE_MEDIA, MEDIA_VENDOR) => Decoded::Vendor(Vendor {
+            node_type: node.node_type(),
+            guid: Guid::from_bytes(&read(data, 0)?),
+            data: &data[16..],
+        }),
+        (TYPE_END, super::END_INSTANCE) => Decoded::EndInstance,
+        _ => Decoded::Unknown(node),
+    };
+    Ok(r)
+}
+
+fn read<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N]> {
+    data.get(offset..offset + N)
+        .map(|x| x.try_into().unwrap())
+        .ok_or(DevicePathError::InvalidLength)
+}
+
+fn read_u8(data: &[u8], offset: usize) -> Result<u8> {
+    read::<1>(data, offset).map(|x| x[0])
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    read(data, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    read(data, offset).map(u32::from_le_bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64> {
+    read(data, offset).map(u64::from_le_bytes)
+}
+
+/// A decoded node which can be appended with `DevicePathBuilder::push`.
+#[cfg(any(test, feature = "alloc"))]
+pub trait EncodeNode {
+    /// The type and sub-type of the node.
+    fn header(&self) -> (u8, u8);
+
+    /// Append the data following the header to `data`.
+    fn encode(&self, data: &mut Vec<u8>);
+}
+
+/// PCI device path node, giving the function and device of a PCI device on the bus of the
+/// preceding node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pci {
+    pub function: u8,
+    pub device: u8,
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl EncodeNode for Pci {
+    fn header(&self) -> (u8, u8) {
+        (TYPE_HARDWARE, HARDWARE_PCI)
+    }
+
+    fn encode(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&[self.function, self.device]);
+    }
+}
+
+/// ACPI device path node, identifying a device by its `_HID` and `_UID`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Acpi {
+    /// The `_HID`, as a compressed EISA ID.
+    pub hid: u32,
+    pub uid: u32,
+}
+
+impl Acpi {
+    /// `PNP0A03`, a PCI root bridge.
+    pub const PCI_ROOT_HID: u32 = eisa_id(*b"PNP", 0x0a03);
+    /// `PNP0A08`, a PCI Express root bridge.
+    pub const PCIE_ROOT_HID: u32 = eisa_id(*b"PNP", 0x0a08);
+}
+
+/// Compress an EISA ID such as `PNP0A03` into the form used by ACPI nodes. `vendor` must be
+/// upper case letters.
+pub const fn eisa_id(vendor: [u8; 3], product: u16) -> u32 {
+    let vendor = ((vendor[0] - 0x40) as u32) << 10
+        | ((vendor[1] - 0x40) as u32) << 5
+        | (vendor[2] - 0x40) as u32;
+    vendor | (product as u32) << 16
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl EncodeNode for Acpi {
+    fn header(&self) -> (u8, u8) {
+        (TYPE_ACPI, ACPI_ACPI)
+    }
+
+    fn encode(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.hid.to_le_bytes());
+        data.extend_from_slice(&self.uid.to_le_bytes());
+    }
+}
+
+/// USB device path node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Usb {
+    pub parent_port_number: u8,
+    pub interface_number: u8,
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl EncodeNode for Usb {
+    fn header(&self) -> (u8, u8) {
+        (TYPE_MESSAGING, MESSAGING_USB)
+    }
+
+    fn encode(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&[self.parent_port_number, self.interface_number]);
+    }
+}
+
+/// MAC address device path node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mac {
+    /// The address, padded with zeros.
+    pub mac_address: [u8; 32],
+    /// The network interface type, as defined by IANA `ifType`. 1 is Ethernet.
+    pub if_type: u8,
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl EncodeNode for Mac {
+    fn header(&self) -> (u8, u8) {
+        (TYPE_MESSAGING, MESSAGING_MAC)
+    }
+
+    fn encode(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.mac_address);
+        data.push(self.if_type);
+    }
+}
+
+/// IPv4 device path node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ipv4 {
+    pub local_ip_address: [u8; 4],
+    pub remote_ip_address: [u8; 4],
+    pub local_port: u16,
+    pub remote_port: u16,
+    /// The IP protocol number, e.g. 6 for TCP or 17 for UDP.
+    pub protocol: u16,
+    /// Whether the local address is static rather than assigned by DHCP.
+    pub static_ip_address: bool,
+    pub gateway_ip_address: [u8; 4],
+    pub subnet_mask: [u8; 4],
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl EncodeNode for Ipv4 {
+    fn header(&self) -> (u8, u8) {
+        (TYPE_MESSAGING, MESSAGING_IPV4)
+    }
+
+    fn encode(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.local_ip_address);
+        data.extend_from_slice(&self.remote_ip_address);
+        data.extend_from_slice(&self.local_port.to_le_bytes());
+        data.extend_from_slice(&self.remote_port.to_le_bytes());
+        data.extend_from_slice(&self.protocol.to_le_bytes());
+        data.push(self.static_ip_address as u8);
+        data.extend_from_slice(&self.gateway_ip_address);
+        data.extend_from_slice(&self.subnet_mask);
+    }
+}
+
+/// IPv6 device path node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ipv6 {
+    pub local_ip_address: [u8; 16],
+    pub remote_ip_address: [u8; 16],
+    pub local_port: u16,
+    pub remote_port: u16,
+    /// The IP protocol number, e.g. 6 for TCP or 17 for UDP.
+    pub protocol: u16,
+    /// 0 for a manually configured address, 1 for stateless and 2 for stateful autoconfiguration.
+    pub ip_address_origin: u8,
+    pub prefix_length: u8,
+    pub gateway_ip_address: [u8; 16],
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl EncodeNode for Ipv6 {
+    fn header(&self) -> (u8, u8) {
+        (TYPE_MESSAGING, MESSAGING_IPV6)
+    }
+
+    fn encode(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.local_ip_address);
+        data.extend_from_slice(&self.remote_ip_address);
+        data.extend_from_slice(&self.local_port.to_le_bytes());
+        data.extend_from_slice(&self.remote_port.to_le_bytes());
+        data.extend_from_slice(&self.protocol.to_le_bytes());
+        data.extend_from_slice(&[self.ip_address_origin, self.prefix_length]);
+        data.extend_from_slice(&self.gateway_ip_address);
+    }
+}
+
+/// SATA device path node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sata {
+    pub hba_port_number: u16,
+    /// 0xffff if the device is directly connected to the port.
+    pub port_multiplier_port_number: u16,
+    pub lun: u16,
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl EncodeNode for Sata {
+    fn header(&self) -> (u8, u8) {
+        (TYPE_MESSAGING, MESSAGING_SATA)
+    }
+
+    fn encode(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.hba_port_number.to_le_bytes());
+        data.extend_from_slice(&self.port_multiplier_port_number.to_le_bytes());
+        data.extend_from_slice(&self.lun.to_le_bytes());
+    }
+}
+
+/// NVM Express namespace device path node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Nvme {
+    pub namespace_id: u32,
+    pub ieee_eui_64: [u8; 8],
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl EncodeNode for Nvme {
+    fn header(&self) -> (u8, u8) {
+        (TYPE_MESSAGING, MESSAGING_NVME)
+    }
+
+    fn encode(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.namespace_id.to_le_bytes());
+        data.extend_from_slice(&self.ieee_eui_64);
+    }
+}
+
+/// URI device path node. An empty URI means the URI is unknown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Uri<'a> {
+    /// The URI, which is not NUL-terminated.
+    pub uri: &'a [u8],
+}
+
+impl<'a> Uri<'a> {
+    /// The URI as a string, if it is valid UTF-8.
+    pub fn as_str(&self) -> Option<&'a str> {
+        core::str::from_utf8(self.uri).ok()
+    }
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl EncodeNode for Uri<'_> {
+    fn header(&self) -> (u8, u8) {
+        (TYPE_MESSAGING, MESSAGING_URI)
+    }
+
+    fn encode(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(self.uri);
+    }
+}
+
+/// Hard drive media device path node, identifying a partition.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HardDrive {
+    /// The 1-based partition number, or 0 for the whole disk.
+    pub partition_number: u32,
+    /// The first logical block of the partition.
+    pub partition_start: u64,
+    /// The size of the partition in logical blocks.
+    pub partition_size: u64,
+    /// The MBR disk signature in the first 4 bytes, or the GPT partition GUID.
+    pub partition_signature: [u8; 16],
+    pub partition_format: u8,
+    pub signature_type: u8,
+}
+
+impl HardDrive {
+    pub const FORMAT_MBR: u8 = 0x01;
+    pub const FORMAT_GPT: u8 = 0x02;
+    pub const SIGNATURE_NONE: u8 = 0x00;
+    pub const SIGNATURE_MBR: u8 = 0x01;
+    pub const SIGNATURE_GUID: u8 = 0x02;
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl EncodeNode for HardDrive {
+    fn header(&self) -> (u8, u8) {
+        (TYPE_MEDIA, MEDIA_HARD_DRIVE)
+    }
+
+    fn encode(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.partition_number.to_le_bytes());
+        data.extend_from_slice(&self.partition_start.to_le_bytes());
+        data.extend_from_slice(&self.partition_size.to_le_bytes());
+        data.extend_from_slice(&self.partition_signature);
+        data.extend_from_slice(&[self.partition_format, self.signature_type]);
+    }
+}
+
+/// File path media device path node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FilePath<'a> {
+    /// The path as NUL-terminated UCS-2. It is not aligned.
+    pub path: &'a [u8],
+}
+
+impl<'a> FilePath<'a> {
+    /// The UCS-2 code units of the path, up to the NUL.
+    pub fn units(&self) -> impl Iterator<Item = u16> + 'a {
+        self.path
+            .chunks_exact(2)
+            .map(|x| u16::from_le_bytes([x[0], x[1]]))
+            .take_while(|x| *x != 0)
+    }
+
+    /// The characters of the path. Surrogates are replaced with U+FFFD.
+    pub fn chars(&self) -> impl Iterator<Item = char> + 'a {
+        self.units()
+            .map(|c| char::from_u32(u32::from(c)).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    #[cfg(any(test, feature = "alloc"))]
+    pub fn to_string_lossy(&self) -> String {
+        self.chars().collect()
+    }
+}
+
+impl fmt::Display for FilePath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use fmt::Write;
+        for c in self.chars() {
+            f.write_char(c)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl EncodeNode for FilePath<'_> {
+    fn header(&self) -> (u8, u8) {
+        (TYPE_MEDIA, MEDIA_FILE_PATH)
+    }
+
+    fn encode(&self, data: &mut Vec<u8>) {
+        data.extend(self.units().chain([0]).flat_map(|x| x.to_le_bytes()));
+    }
+}
+
+/// Vendor-defined device path node, of the Hardware, Messaging or Media type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Vendor<'a> {
+    pub node_type: u8,
+    pub guid: Guid,
+    pub data: &'a [u8],
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl EncodeNode for Vendor<'_> {
+    fn header(&self) -> (u8, u8) {
+        let sub_type = match self.node_type {
+            TYPE_MESSAGING => MESSAGING_VENDOR,
+            TYPE_MEDIA => MEDIA_VENDOR,
+            _ => HARDWARE_VENDOR,
+        };
+        (self.node_type, sub_type)
+    }
+
+    fn encode(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(self.guid.as_bytes());
+        data.extend_from_slice(self.data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_path::{DevicePath, DevicePathBuilder};
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(Acpi::PCI_ROOT_HID, 0x0a03_41d0);
+        let guid = Guid::from_fields(0x12345678, 0x9abc, 0xdef0, 0x12, 0x34, &[1, 2, 3, 4, 5, 6]);
+        let mut mac = [0; 32];
+        mac[..6].copy_from_slice(&[0x52, 0x54, 0, 0x12, 0x34, 0x56]);
+        let mut drive_signature = [0; 16];
+        drive_signature[..4].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+
+        let expected = [
+            Decoded::Acpi(Acpi {
+                hid: Acpi::PCIE_ROOT_HID,
+                uid: 1,
+            }),
+            Decoded::Pci(Pci {
+                function: 2,
+                device: 3,
+            }),
+            Decoded::Usb(Usb {
+                parent_port_number: 4,
+                interface_number: 0,
+            }),
+            Decoded::Mac(Mac {
+                mac_address: mac,
+                if_type: 1,
+            }),
+            Decoded::Ipv4(Ipv4 {
+                local_ip_address: [192, 168, 0, 2],
+                remote_ip_address: [192, 168, 0, 1],
+                local_port: 68,
+                remote_port: 67,
+                protocol: 17,
+                static_ip_address: false,
+                gateway_ip_address: [192, 168, 0, 1],
+                subnet_mask: [255, 255, 255, 0],
+            }),
+            Decoded::Ipv6(Ipv6 {
+                local_ip_address: [0xfe; 16],
+                remote_ip_address: [0; 16],
+                local_port: 546,
+                remote_port: 547,
+                protocol: 17,
+                ip_address_origin: 1,
+                prefix_length: 64,
+                gateway_ip_address: [0; 16],
+            }),
+            Decoded::Sata(Sata {
+                hba_port_number: 1,
+                port_multiplier_port_number: 0xffff,
+                lun: 0,
+            }),
+            Decoded::Nvme(Nvme {
+                namespace_id: 1,
+                ieee_eui_64: [1, 2, 3, 4, 5, 6, 7, 8],
+            }),
+            Decoded::Uri(Uri {
+                uri: b"http://example.com/boot.efi",
+            }),
+            Decoded::HardDrive(HardDrive {
+                partition_number: 1,
+                partition_start: 2048,
+                partition_size: 0x10_0000,
+                partition_signature: drive_signature,
+                partition_format: HardDrive::FORMAT_MBR,
+                signature_type: HardDrive::SIGNATURE_MBR,
+            }),
+            Decoded::Vendor(Vendor {
+                node_type: TYPE_MESSAGING,
+                guid,
+                data: &[9, 8, 7],
+            }),
+        ];
+
+        let mut builder = DevicePathBuilder::new();
+        for node in &expected {
+            builder = match node {
+                Decoded::Acpi(x) => builder.push(x),
+                Decoded::Pci(x) => builder.push(x),
+                Decoded::Usb(x) => builder.push(x),
+                Decoded::Mac(x) => builder.push(x),
+                Decoded::Ipv4(x) => builder.push(x),
+                Decoded::Ipv6(x) => builder.push(x),
+                Decoded::Sata(x) => builder.push(x),
+                Decoded::Nvme(x) => builder.push(x),
+                Decoded::Uri(x) => builder.push(x),
+                Decoded::HardDrive(x) => builder.push(x),
+                Decoded::Vendor(x) => builder.push(x),
+                _ => unreachable!(),
+            };
+        }
+        let path = builder.build().unwrap();
+        let decoded: std::vec::Vec<_> = path
+            .as_path()
+            .nodes()
+            .map(|x| x.decode().unwrap())
+            .collect();
+        assert_eq!(decoded, expected);
+
+        let lengths: std::vec::Vec<_> = path.as_path().nodes().map(|x| x.length()).collect();
+        assert_eq!(lengths, [12, 6, 6, 37, 27, 60, 10, 16, 31, 42, 23]);
+    }
+
+    #[test]
+    fn short_nodes() {
+        // A UEFI 1.x IPv4 node without gateway and subnet mask.
+        let mut old = std::vec![TYPE_MESSAGING, MESSAGING_IPV4, 19, 0];
+        old.extend_from_slice(&[10, 0, 0, 2, 10, 0, 0, 1, 0, 0, 69, 0, 17, 0, 1]);
+        old.extend_from_slice(&[0x7f, 0xff, 4, 0]);
+        let path = DevicePath::parse(&old).unwrap();
+        match path.nodes().next().unwrap().decode().unwrap() {
+            Decoded::Ipv4(x) => {
+                assert_eq!(x.remote_port, 69);
+                assert!(x.static_ip_address);
+                assert_eq!(x.subnet_mask, [0; 4]);
+            }
+            x => panic!("unexpected node {:?}", x),
+        }
+
+        let short = [TYPE_HARDWARE, HARDWARE_PCI, 5, 0, 0, 0x7f, 0xff, 4, 0];
+        let path = DevicePath::parse(&short).unwrap();
+        assert_eq!(
+            path.nodes().next().unwrap().decode(),
+            Err(DevicePathError::InvalidLength)
+        );
+
+        let odd = [TYPE_MEDIA, MEDIA_FILE_PATH, 5, 0, b'a', 0x7f, 0xff, 4, 0];
+        let path = DevicePath::parse(&odd).unwrap();
+        assert_eq!(
+            path.nodes().next().unwrap().decode(),
+            Err(DevicePathError::InvalidLength)
+        );
+
+        let unknown = [TYPE_HARDWARE, 0x80, 4, 0, 0x7f, 0xff, 4, 0];
+        let path = DevicePath::parse(&unknown).unwrap();
+        let node = path.nodes().next().unwrap();
+        assert_eq!(node.decode(), Ok(Decoded::Unknown(node)));
+    }
+}
@@ -0,0 +1,631 @@
+//! This module converts device paths to and from the text form defined by the specification,
+//! e.g. `PciRoot(0x0)/Pci(0x1F,0x2)/Sata(0x0,0xFFFF,0x0)/HD(1,GPT,<GUID>,0x800,0x100000)`.
+//!
+//! It is a fallback for firmware without the Device Path To Text and From Text protocols, and
+//! produces the same text as the EDK2 implementation of these protocols, without the
+//! display-only forms. Nodes without a specific form use the generic `Path(Type,SubType,Data)`
+//! form. Like in EDK2, the ports of IP nodes are not part of the text and are lost.
+//!
+//! Formatting is done through `Display` and works without `alloc`. Parsing needs `alloc`.
+
+use core::fmt::{self, Write};
+
+#[cfg(any(test, feature = "alloc"))]
+use super::nodes::{self, Ipv4, Ipv6, Mac, Nvme, Pci, Sata, Uri, Usb, Vendor};
+use super::nodes::{Acpi, HardDrive};
+use super::{Decoded, DevicePath, Node, TYPE_HARDWARE, TYPE_MESSAGING};
+#[cfg(any(test, feature = "alloc"))]
+use super::{DevicePathBuf, DevicePathBuilder, EncodeNode, Result, TYPE_MEDIA};
+use crate::efi::Guid;
+#[cfg(any(test, feature = "alloc"))]
+use crate::errors::DevicePathError;
+#[cfg(any(test, feature = "alloc"))]
+use alloc::vec::Vec;
+
+const TCP: u16 = 6;
+const UDP: u16 = 17;
+
+/// Names of the IPv6 address origins, by value.
+const IPV6_ORIGINS: [&str; 3] = ["Static", "StatelessAutoConfigure", "StatefulAutoConfigure"];
+
+impl fmt::Display for Node<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.decode() {
+            Ok(Decoded::Pci(x)) => write!(f, "Pci({:#X},{:#X})", x.device, x.function),
+            Ok(Decoded::Acpi(x)) => match x.hid {
+                Acpi::PCI_ROOT_HID => write!(f, "PciRoot({:#X})", x.uid),
+                Acpi::PCIE_ROOT_HID => write!(f, "PcieRoot({:#X})", x.uid),
+                // Bit 15 is not part of the compressed form.
+                hid if hid & 0x8000 != 0 => write!(f, "Acpi({:#X},{:#X})", hid, x.uid),
+                hid => {
+                    f.write_str("Acpi(")?;
+                    for shift in [10, 5, 0] {
+                        f.write_char((((hid >> shift) & 0x1f) as u8 + 0x40) as char)?;
+                    }
+                    write!(f, "{:04X},{:#X})", hid >> 16, x.uid)
+                }
+            },
+            Ok(Decoded::Usb(x)) => write!(
+                f,
+                "USB({:#X},{:#X})",
+                x.parent_port_number, x.interface_number
+            ),
+            Ok(Decoded::Mac(x)) => {
+                let len = if x.if_type <= 1 { 6 } else { 32 };
+                f.write_str("MAC(")?;
+                write_hex(f, &x.mac_address[..len])?;
+                write!(f, ",{:#X})", x.if_type)
+            }
+            Ok(Decoded::Ipv4(x)) => {
+                f.write_str("IPv4(")?;
+                write_ipv4(f, &x.remote_ip_address)?;
+                f.write_char(',')?;
+                write_protocol(f, x.protocol)?;
+                let origin = if x.static_ip_address {
+                    "Static"
+                } else {
+                    "DHCP"
+                };
+                write!(f, ",{},", origin)?;
+                write_ipv4(f, &x.local_ip_address)?;
+                f.write_char(',')?;
+                write_ipv4(f, &x.gateway_ip_address)?;
+                f.write_char(',')?;
+                write_ipv4(f, &x.subnet_mask)?;
+                f.write_char(')')
+            }
+            Ok(Decoded::Ipv6(x)) => {
+                f.write_str("IPv6(")?;
+                write_ipv6(f, &x.remote_ip_address)?;
+                f.write_char(',')?;
+                write_protocol(f, x.protocol)?;
+                match IPV6_ORIGINS.get(x.ip_address_origin as usize) {
+                    Some(origin) => write!(f, ",{},", origin)?,
+                    None => write!(f, ",{:#X},", x.ip_address_origin)?,
+                }
+                write_ipv6(f, &x.local_ip_address)?;
+                write!(f, ",{:#X},", x.prefix_length)?;
+                write_ipv6(f, &x.gateway_ip_address)?;
+                f.write_char(')')
+            }
+            Ok(Decoded::Sata(x)) => write!(
+                f,
+                "Sata({:#X},{:#X},{:#X})",
+                x.hba_port_number, x.port_multiplier_port_number, x.lun
+            ),
+            Ok(Decoded::Nvme(x)) => {
+                write!(f, "NVMe({:#X},", x.namespace_id)?;
+                // The EUI-64 is stored least significant byte first.
+                for (i, b) in x.ieee_eui_64.iter().rev().enumerate() {
+                    if i != 0 {
+                        f.write_char('-')?;
+                    }
+                    write!(f, "{:02X}", b)?;
+                }
+                f.write_char(')')
+            }
+            Ok(Decoded::Uri(x)) => match x.as_str() {
+                Some(uri) => write!(f, "Uri({})", uri),
+                None => write_generic(f, self),
+            },
+            Ok(Decoded::HardDrive(x)) => {
+                write!(f, "HD({},", x.partition_number)?;
+                match x.signature_type {
+                    HardDrive::SIGNATURE_MBR => {
+                        let signature =
+                            u32::from_le_bytes(x.partition_signature[..4].try_into().unwrap());
+                        write!(f, "MBR,{:#010X},", signature)?;
+                    }
+                    HardDrive::SIGNATURE_GUID => {
+                        f.write_str("GPT,")?;
+                        write_guid(f, &Guid::from_bytes(&x.partition_signature))?;
+                        f.write_char(',')?;
+                    }
+                    other => write!(f, "{},0,", other)?,
+                }
+                write!(f, "{:#X},{:#X})", x.partition_start, x.partition_size)
+            }
+            Ok(Decoded::FilePath(x)) => write!(f, "{}", x),
+            Ok(Decoded::Vendor(x)) => {
+                let name = match x.node_type {
+                    TYPE_HARDWARE => "VenHw",
+                    TYPE_MESSAGING => "VenMsg",
+                    _ => "VenMedia",
+                };
+                write!(f, "{}(", name)?;
+                write_guid(f, &x.guid)?;
+                if !x.data.is_empty() {
+                    f.write_char(',')?;
+                    write_hex(f, x.data)?;
+                }
+                f.write_char(')')
+            }
+            Ok(Decoded::EndInstance) => f.write_char(','),
+            Ok(Decoded::Unknown(_)) | Err(_) => write_generic(f, self),
+        }
+    }
+}
+
+/// Nodes are separated by `/` and instances by `,`.
+impl fmt::Display for DevicePath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, instance) in self.instances().enumerate() {
+            if i != 0 {
+                f.write_char(',')?;
+            }
+            for (j, node) in instance.enumerate() {
+                if j != 0 {
+                    f.write_char('/')?;
+                }
+                write!(f, "{}", node)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_generic(f: &mut fmt::Formatter<'_>, node: &Node<'_>) -> fmt::Result {
+    write!(f, "Path({},{}", node.node_type(), node.sub_type())?;
+    if !node.data().is_empty() {
+        f.write_char(',')?;
+        write_hex(f, node.data())?;
+    }
+    f.write_char(')')
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, data: &[u8]) -> fmt::Result {
+    data.iter().try_for_each(|x| write!(f, "{:02X}", x))
+}
+
+fn write_guid(f: &mut fmt::Formatter<'_>, guid: &Guid) -> fmt::Result {
+    let (a, b, c, d, e, node) = guid.as_fields();
+    write!(f, "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-", a, b, c, d, e)?;
+    write_hex(f, node)
+}
+
+fn write_ipv4(f: &mut fmt::Formatter<'_>, ip: &[u8; 4]) -> fmt::Result {
+    write!(f, "{}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3])
+}
+
+/// All 8 groups are written, like EDK2 does.
+fn write_ipv6(f: &mut fmt::Formatter<'_>, ip: &[u8; 16]) -> fmt::Result {
+    for (i, group) in ip.chunks_exact(2).enumerate() {
+        if i != 0 {
+            f.write_char(':')?;
+        }
+        write!(f, "{:x}", u16::from_be_bytes([group[0], group[1]]))?;
+    }
+    Ok(())
+}
+
+fn write_protocol(f: &mut fmt::Formatter<'_>, protocol: u16) -> fmt::Result {
+    match protocol {
+        TCP => f.write_str("TCP"),
+        UDP => f.write_str("UDP"),
+        x => write!(f, "{:#X}", x),
+    }
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl DevicePathBuf {
+    /// Parse the text form of a path. Nodes are separated by `/` and instances by `,`.
+    pub fn from_text(text: &str) -> Result<Self> {
+        let mut builder = DevicePathBuilder::new();
+        let mut depth = 0usize;
+        let mut start = 0;
+        for (i, c) in text.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth = depth.checked_sub(1).ok_or(DevicePathError::InvalidText)?,
+                '/' | ',' if depth == 0 => {
+                    if i > start {
+                        builder = push_text_node(builder, &text[start..i])?;
+                    }
+                    if c == ',' {
+                        builder = builder.end_instance();
+                    }
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        if depth != 0 {
+            return Err(DevicePathError::InvalidText);
+        }
+        if text.len() > start {
+            builder = push_text_node(builder, &text[start..])?;
+        }
+        builder.build()
+    }
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl DevicePathBuilder {
+    /// Append a node given in text form. An invalid node makes `build` fail.
+    pub fn text_node(mut self, text: &str) -> Self {
+        match node_from_text(text) {
+            Ok((node_type, sub_type, data)) => self.raw_node(node_type, sub_type, &data),
+            Err(e) => {
+                self.error.get_or_insert(e);
+                self
+            }
+        }
+    }
+}
+
+/// A node as its type, sub-type and data.
+#[cfg(any(test, feature = "alloc"))]
+type RawNode = (u8, u8, Vec<u8>);
+
+#[cfg(any(test, feature = "alloc"))]
+fn push_text_node(builder: DevicePathBuilder, text: &str) -> Result<DevicePathBuilder> {
+    let (node_type, sub_type, data) = node_from_text(text)?;
+    Ok(builder.raw_node(node_type, sub_type, &data))
+}
+
+#[cfg(any(test, feature = "alloc"))]
+fn encode(node: &impl EncodeNode) -> RawNode {
+    let (node_type, sub_type) = node.header();
+    let mut data = Vec::new();
+    node.encode(&mut data);
+    (node_type, sub_type, data)
+}
+
+/// Parse the node `text`. Text which is not of the form `Name(Arguments)` is a file path.
+#[cfg(any(test, feature = "alloc"))]
+fn node_from_text(text: &str) -> Result<RawNode> {
+    let named = text
+        .strip_suffix(')')
+        .and_then(|x| x.split_once('('))
+        .filter(|(name, _)| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric()));
+    let (name, inner) = match named {
+        Some(x) => x,
+        None => return Ok(file_path(text)),
+    };
+    if name == "Uri" {
+        return Ok(encode(&Uri {
+            uri: inner.as_bytes(),
+        }));
+    }
+
+    let args: Vec<&str> = inner.split(',').map(str::trim).collect();
+    let arg = |i: usize| args.get(i).copied().filter(|x| !x.is_empty());
+    let required = |i: usize| arg(i).ok_or(DevicePathError::InvalidText);
+
+    let node = match name {
+        "Pci" => encode(&Pci {
+            device: number(required(0)?)?,
+            function: number(required(1)?)?,
+        }),
+        "PciRoot" | "PcieRoot" => encode(&Acpi {
+            hid: match name {
+                "PciRoot" => Acpi::PCI_ROOT_HID,
+                _ => Acpi::PCIE_ROOT_HID,
+            },
+            uid: number(arg(0).unwrap_or("0"))?,
+        }),
+        "Acpi" => encode(&Acpi {
+            hid: eisa_id(required(0)?)?,
+            uid: number(arg(1).unwrap_or("0"))?,
+        }),
+        "USB" => encode(&Usb {
+            parent_port_number: number(required(0)?)?,
+            interface_number: number(required(1)?)?,
+        }),
+        "MAC" => {
+            let address = hex(required(0)?)?;
+            let mut mac_address = [0; 32];
+            mac_address
+                .get_mut(..address.len())
+                .ok_or(DevicePathError::InvalidText)?
+                .copy_from_slice(&address);
+            encode(&Mac {
+                mac_address,
+                if_type: number(arg(1).unwrap_or("0"))?,
+            })
+        }
+        "IPv4" => encode(&Ipv4 {
+            remote_ip_address: ipv4(required(0)?)?,
+            protocol: protocol(arg(1).unwrap_or("0"))?,
+            static_ip_address: match arg(2).unwrap_or("DHCP") {
+                "Static" => true,
+                "DHCP" => false,
+                _ => return Err(DevicePathError::InvalidText),
+            },
+            local_ip_address: ipv4(arg(3).unwrap_or("0.0.0.0"))?,
+            gateway_ip_address: ipv4(arg(4).unwrap_or("0.0.0.0"))?,
+            subnet_mask: ipv4(arg(5).unwrap_or("0.0.0.0"))?,
+            local_port: 0,
+            remote_port: 0,
+        }),
+        "IPv6" => encode(&Ipv6 {
+            remote_ip_address: ipv6(required(0)?)?,
+            protocol: protocol(arg(1).unwrap_or("0"))?,
+            ip_address_origin: match arg(2) {
+                Some(x) => match IPV6_ORIGINS.iter().position(|o| *o == x) {
+                    Some(i) => i as u8,
+                    None => number(x)?,
+                },
+                None => 0,
+            },
+            local_ip_address: ipv6(arg(3).unwrap_or("::"))?,
+            prefix_length: number(arg(4).unwrap_or("0"))?,
+            gateway_ip_address: ipv6(arg(5).unwrap_or("::"))?,
+            local_port: 0,
+            remote_port: 0,
+        }),
+        "Sata" => encode(&Sata {
+            hba_port_number: number(required(0)?)?,
+            port_multiplier_port_number: number(arg(1).unwrap_or("0xFFFF"))?,
+            lun: number(arg(2).unwrap_or("0"))?,
+        }),
+        "NVMe" => {
+            let mut ieee_eui_64 = [0; 8];
+            let bytes: Vec<&str> = required(1)?.split('-').collect();
+            if bytes.len() != 8 {
+                return Err(DevicePathError::InvalidText);
+            }
+            for (x, text) in ieee_eui_64.iter_mut().zip(bytes.iter().rev()) {
+                *x = u8::from_str_radix(text, 16).map_err(|_| DevicePathError::InvalidText)?;
+            }
+            encode(&Nvme {
+                namespace_id: number(required(0)?)?,
+                ieee_eui_64,
+            })
+        }
+        "HD" => {
+            let (signature_type, partition_format) = match required(1)? {
+                "MBR" => (HardDrive::SIGNATURE_MBR, HardDrive::FORMAT_MBR),
+                "GPT" => (HardDrive::SIGNATURE_GUID, HardDrive::FORMAT_GPT),
+                x => (number(x)?, 0),
+            };
+            let signature = required(2)?;
+            let mut partition_signature = [0; 16];
+            match signature_type {
+                HardDrive::SIGNATURE_MBR => partition_signature[..4]
+                    .copy_from_slice(&number::<u32>(signature)?.to_le_bytes()),
+                HardDrive::SIGNATURE_GUID => {
+                    partition_signature = *guid(signature)?.as_bytes();
+                }
+                _ => {}
+            }
+            encode(&HardDrive {
+                partition_number: number(required(0)?)?,
+                partition_start: number(required(3)?)?,
+                partition_size: number(required(4)?)?,
+                partition_signature,
+                partition_format,
+                signature_type,
+            })
+        }
+        "VenHw" | "VenMsg" | "VenMedia" => {
+            let data = match arg(1) {
+                Some(x) => hex(x)?,
+                None => Vec::new(),
+            };
+            encode(&Vendor {
+                node_type: match name {
+                    "VenHw" => TYPE_HARDWARE,
+                    "VenMsg" => TYPE_MESSAGING,
+                    _ => TYPE_MEDIA,
+                },
+                guid: guid(required(0)?)?,
+                data: &data,
+            })
+        }
+        "Path" => {
+            let data = match arg(2) {
+                Some(x) => hex(x)?,
+                None => Vec::new(),
+            };
+            (number(required(0)?)?, number(required(1)?)?, data)
+        }
+        _ => return Err(DevicePathError::InvalidText),
+    };
+    Ok(node)
+}
+
+#[cfg(any(test, feature = "alloc"))]
+fn file_path(text: &str) -> RawNode {
+    let data: Vec<u8> = text
+        .encode_utf16()
+        .chain([0])
+        .flat_map(|x| x.to_le_bytes())
+        .collect();
+    (TYPE_MEDIA, nodes::MEDIA_FILE_PATH, data)
+}
+
+/// Parse a hexadecimal number starting with `0x`, or a decimal number.
+#[cfg(any(test, feature = "alloc"))]
+fn number<T: TryFrom<u64>>(text: &str) -> Result<T> {
+    let r = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(x) => u64::from_str_radix(x, 16),
+        None => text.parse(),
+    };
+    r.ok()
+        .and_then(|x| T::try_from(x).ok())
+        .ok_or(DevicePathError::InvalidText)
+}
+
+#[cfg(any(test, feature = "alloc"))]
+fn hex(text: &str) -> Result<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return Err(DevicePathError::InvalidText);
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).map_err(|_| DevicePathError::InvalidText))
+        .collect()
+}
+
+/// Parse an EISA ID such as `PNP0A03`, or a number.
+#[cfg(any(test, feature = "alloc"))]
+fn eisa_id(text: &str) -> Result<u32> {
+    let bytes = text.as_bytes();
+    if bytes.len() == 7 && bytes[..3].iter().all(|x| (0x40..0x60).contains(x)) {
+        let product =
+            u16::from_str_radix(&text[3..], 16).map_err(|_| DevicePathError::InvalidText)?;
+        return Ok(nodes::eisa_id(bytes[..3].try_into().unwrap(), product));
+    }
+    number(text)
+}
+
+#[cfg(any(test, feature = "alloc"))]
+fn guid(text: &str) -> Result<Guid> {
+    let parts: Vec<&str> = text.split('-').collect();
+    let lengths: Vec<usize> = parts.iter().map(|x| x.len()).collect();
+    if lengths != [8, 4, 4, 4, 12] {
+        return Err(DevicePathError::InvalidText);
+    }
+    let a = u32::from_str_radix(parts[0], 16).map_err(|_| DevicePathError::InvalidText)?;
+    let b = u16::from_str_radix(parts[1], 16).map_err(|_| DevicePathError::InvalidText)?;
+    let c = u16::from_str_radix(parts[2], 16).map_err(|_| DevicePathError::InvalidText)?;
+    let d = hex(parts[3])?;
+    let node: [u8; 6] = hex(parts[4])?.try_into().unwrap();
+    Ok(Guid::from_fields(a, b, c, d[0], d[1], &node))
+}
+
+#[cfg(any(test, feature = "alloc"))]
+fn ipv4(text: &str) -> Result<[u8; 4]> {
+    let mut ip = [0; 4];
+    let mut parts = text.split('.');
+    for x in ip.iter_mut() {
+        *x = parts
+            .next()
+            .and_then(|x| x.parse().ok())
+            .ok_or(DevicePathError::InvalidText)?;
+    }
+    match parts.next() {
+        Some(_) => Err(DevicePathError::InvalidText),
+        None => Ok(ip),
+    }
+}
+
+/// Parse an IPv6 address, in which `::` may replace a run of zero groups.
+#[cfg(any(test, feature = "alloc"))]
+fn ipv6(text: &str) -> Result<[u8; 16]> {
+    fn groups(text: &str) -> Result<Vec<u16>> {
+        if text.is_empty() {
+            return Ok(Vec::new());
+        }
+        text.split(':')
+            .map(|x| match x.len() {
+                1..=4 => u16::from_str_radix(x, 16).map_err(|_| DevicePathError::InvalidText),
+                _ => Err(DevicePathError::InvalidText),
+            })
+            .collect()
+    }
+
+    let groups = match text.split_once("::") {
+        Some((head, tail)) => {
+            let head = groups(head)?;
+            let tail = groups(tail)?;
+            if head.len() + tail.len() > 7 {
+                return Err(DevicePathError::InvalidText);
+            }
+            let mut all = head;
+            all.resize(8 - tail.len(), 0);
+            all.extend(tail);
+            all
+        }
+        None => groups(text)?,
+    };
+    if groups.len() != 8 {
+        return Err(DevicePathError::InvalidText);
+    }
+    let mut ip = [0; 16];
+    for (x, group) in ip.chunks_exact_mut(2).zip(groups) {
+        x.copy_from_slice(&group.to_be_bytes());
+    }
+    Ok(ip)
+}
+
+#[cfg(any(test, feature = "alloc"))]
+fn protocol(text: &str) -> Result<u16> {
+    match text {
+        "TCP" => Ok(TCP),
+        "UDP" => Ok(UDP),
+        x => number(x),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::ToString;
+
+    fn round_trip(text: &str) {
+        let path = DevicePathBuf::from_text(text).unwrap();
+        assert_eq!(path.as_path().to_string(), text);
+    }
+
+    #[test]
+    fn format_and_parse() {
+        round_trip(
+            "PciRoot(0x0)/Pci(0x1F,0x2)/Sata(0x0,0xFFFF,0x0)/\
+             HD(1,GPT,0C1F8B2A-1F6D-4C4E-9A3B-0123456789AB,0x800,0x100000)/\\EFI\\BOOT\\BOOTX64.EFI",
+        );
+        round_trip("PcieRoot(0x1)/Pci(0x0,0x0)/NVMe(0x1,01-02-03-04-05-06-07-08)");
+        round_trip("PciRoot(0x0)/Pci(0x2,0x0)/USB(0x3,0x0)/HD(2,MBR,0xDEADBEEF,0x3F,0x1000)");
+        round_trip("Acpi(PNP0501,0x0),Acpi(PNP0303,0x1)");
+        round_trip("Acpi(0x8001,0x2)");
+        round_trip(
+            "PciRoot(0x0)/Pci(0x3,0x0)/MAC(525400123456,0x1)/\
+             IPv4(192.168.0.1,UDP,DHCP,192.168.0.2,192.168.0.1,255.255.255.0)/\
+             Uri(http://example.com/boot.efi)",
+        );
+        round_trip(
+            "MAC(525400123456,0x1)/IPv6(2001:db8:0:0:0:0:0:1,TCP,StatelessAutoConfigure,\
+             fe80:0:0:0:0:0:0:2,0x40,0:0:0:0:0:0:0:0)",
+        );
+        round_trip(
+            "VenHw(12345678-9ABC-DEF0-1234-010203040506)/\
+             VenMedia(12345678-9ABC-DEF0-1234-010203040506,0A0B)",
+        );
+        round_trip("Path(1,128,0102)/Path(4,255)");
+        round_trip("");
+
+        // Other spellings are accepted.
+        let path =
+            DevicePathBuf::from_text("/Pci(31,2)/IPv6(2001:db8::1)/HD(3,4,0,10,20)").unwrap();
+        assert_eq!(
+            path.as_path().to_string(),
+            "Pci(0x1F,0x2)/IPv6(2001:db8:0:0:0:0:0:1,0x0,Static,0:0:0:0:0:0:0:0,0x0,0:0:0:0:0:0:0:0)/\
+             HD(3,4,0,0xA,0x14)"
+        );
+        let path = DevicePathBuf::from_text("Acpi(PNP0A03,0)").unwrap();
+        assert_eq!(path.as_path().to_string(), "PciRoot(0x0)");
+    }
+
+    #[test]
+    fn invalid_text() {
+        for text in [
+            "Pci(0x1F",
+            "Pci(0x1F))",
+            "Pci(0x1F)",
+            "Pci(0x100,0x0)",
+            "Foo(1)",
+            "MAC(5254001234)/IPv4(1.2.3)",
+            "IPv6(1::2::3)",
+            "IPv6(1:2:3:4:5:6:7:8:9)",
+            "HD(1,GPT,1234,0,0)",
+            "NVMe(0x1,01-02)",
+            "VenHw(123)",
+            "Path(1,2,abc)",
+        ] {
+            assert_eq!(
+                DevicePathBuf::from_text(text).err(),
+                Some(DevicePathError::InvalidText),
+                "{}",
+                text
+            );
+        }
+
+        let r = DevicePathBuilder::new()
+            .text_node("Pci(0x1,0x0)")
+            .text_node("Sata(x)")
+            .text_node("Pci(0x1,0x0)")
+            .build();
+        assert_eq!(r, Err(DevicePathError::InvalidText));
+    }
+}
@@ -1,10 +1,14 @@
 //! This module contains various error types used in this crate
 
+use core::fmt;
+
+use crate::status::StatusCode;
+
 #[derive(PartialEq, Eq, Debug)]
 pub enum StatusNullError {
     NullPtrError(&'static str),
-    UefiWarning(usize),
-    UefiError(usize),
+    UefiWarning(StatusCode),
+    UefiError(StatusCode),
 }
 
 impl From<StatusError> for StatusNullError {
@@ -22,10 +26,38 @@ impl From<NullPtrError> for StatusNullError {
     }
 }
 
+impl fmt::Display for StatusNullError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NullPtrError(x) => fmt::Display::fmt(&NullPtrError(x), f),
+            Self::UefiWarning(x) => fmt::Display::fmt(&StatusError::UefiWarning(*x), f),
+            Self::UefiError(x) => fmt::Display::fmt(&StatusError::UefiError(*x), f),
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Debug)]
 pub enum StatusError {
-    UefiWarning(usize),
-    UefiError(usize),
+    UefiWarning(StatusCode),
+    UefiError(StatusCode),
+}
+
+impl StatusError {
+    /// The status code carried by this error.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::UefiWarning(x) | Self::UefiError(x) => *x,
+        }
+    }
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UefiWarning(x) => write!(f, "UEFI warning: {}", x),
+            Self::UefiError(x) => write!(f, "UEFI error: {}", x),
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -36,3 +68,221 @@ impl NullPtrError {
         Self(ptr_name)
     }
 }
+
+impl fmt::Display for NullPtrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is NULL", self.0)
+    }
+}
+
+/// Errors from creating or converting the UCS-2 strings in `string`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Ucs2Error {
+    /// A NUL was found before the end of the string. `index` is its position in UCS-2 code
+    /// units, in the string being created.
+    InteriorNul { index: usize },
+    /// The string does not end with a NUL.
+    NotNulTerminated,
+    /// A surrogate was found. UCS-2 has no surrogate pairs. `index` is its position in UCS-2 code
+    /// units.
+    Surrogate { index: usize },
+    /// A character outside the Basic Multilingual Plane was found. `index` is the position in
+    /// UCS-2 code units it would have taken in the string being created, not its byte offset in
+    /// the `&str`.
+    Unrepresentable { index: usize },
+    /// The output buffer cannot hold the string and its NUL.
+    BufferTooSmall,
+}
+
+impl fmt::Display for Ucs2Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InteriorNul { index } => write!(f, "interior NUL at index {}", index),
+            Self::NotNulTerminated => f.write_str("string is not NUL-terminated"),
+            Self::Surrogate { index } => write!(f, "surrogate at index {}", index),
+            Self::Unrepresentable { index } => {
+                write!(
+                    f,
+                    "character at index {} is not representable in UCS-2",
+                    index
+                )
+            }
+            Self::BufferTooSmall => f.write_str("buffer too small"),
+        }
+    }
+}
+
+/// Errors from creating or converting an `EfiTime`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimeError {
+    /// A field is outside of its valid range.
+    InvalidField { field: &'static str },
+    /// The time falls outside the years 1900 to 9999.
+    OutOfRange,
+}
+
+impl fmt::Display for TimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidField { field } => write!(f, "invalid {}", field),
+            Self::OutOfRange => f.write_str("time out of range"),
+        }
+    }
+}
+
+/// Errors from building or parsing capsules in `runtime_services::capsule_services`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CapsuleError {
+    /// The data is shorter than the header or the sizes it declares.
+    Truncated,
+    /// `HeaderSize` is smaller than `EFI_CAPSULE_HEADER` or larger than `CapsuleImageSize`.
+    InvalidHeaderSize,
+    /// `CAPSULE_FLAGS_POPULATE_SYSTEM_TABLE` or `CAPSULE_FLAGS_INITIATE_RESET` is set without
+    /// `CAPSULE_FLAGS_PERSIST_ACROSS_RESET`.
+    InvalidFlags,
+    /// The capsule does not fit the 32-bit `CapsuleImageSize`.
+    TooLarge,
+}
+
+impl fmt::Display for CapsuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => f.write_str("capsule is truncated"),
+            Self::InvalidHeaderSize => f.write_str("invalid capsule header size"),
+            Self::InvalidFlags => f.write_str("invalid capsule flags"),
+            Self::TooLarge => f.write_str("capsule too large"),
+        }
+    }
+}
+
+/// Errors from parsing ACPI tables in `acpi`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AcpiError {
+    /// The data is shorter than the structure or the length it declares.
+    Truncated,
+    /// The signature does not match the expected table.
+    InvalidSignature,
+    /// The bytes covered by a checksum do not sum to zero.
+    InvalidChecksum,
+    /// A length field is smaller than the structure it describes.
+    InvalidLength,
+}
+
+impl fmt::Display for AcpiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => f.write_str("ACPI table is truncated"),
+            Self::InvalidSignature => f.write_str("invalid ACPI signature"),
+            Self::InvalidChecksum => f.write_str("invalid ACPI checksum"),
+            Self::InvalidLength => f.write_str("invalid ACPI length"),
+        }
+    }
+}
+
+/// Errors from parsing SMBIOS data in `smbios`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SmbiosError {
+    /// The data is shorter than the structure or the length it declares.
+    Truncated,
+    /// The entry point does not start with `_SM_` or `_SM3_`.
+    InvalidAnchor,
+    /// The bytes covered by a checksum do not sum to zero.
+    InvalidChecksum,
+    /// A length field is smaller than the structure it describes.
+    InvalidLength,
+}
+
+impl fmt::Display for SmbiosError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => f.write_str("SMBIOS data is truncated"),
+            Self::InvalidAnchor => f.write_str("invalid SMBIOS anchor"),
+            Self::InvalidChecksum => f.write_str("invalid SMBIOS checksum"),
+            Self::InvalidLength => f.write_str("invalid SMBIOS length"),
+        }
+    }
+}
+
+/// Errors from parsing and building device paths in `device_path`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DevicePathError {
+    /// The data ends inside a node.
+    Truncated,
+    /// A node length is smaller than the node header, or too small or too large for its data.
+    InvalidLength,
+    /// The path does not end with an End Entire Device Path node.
+    MissingEnd,
+    /// The text form of a path or node cannot be parsed.
+    InvalidText,
+}
+
+impl fmt::Display for DevicePathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => f.write_str("device path is truncated"),
+            Self::InvalidLength => f.write_str("invalid device path node length"),
+            Self::MissingEnd => f.write_str("device path has no end node"),
+            Self::InvalidText => f.write_str("invalid device path text"),
+        }
+    }
+}
+
+/// Errors from parsing and building the file information structures in `protocols::file`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FileInfoError {
+    /// The data is shorter than the structure or the size it declares.
+    Truncated,
+    /// The `Size` field is smaller than the structure.
+    InvalidSize,
+    /// The name does not end with a NUL.
+    MissingNul,
+    /// The output buffer cannot hold the structure and its name.
+    BufferTooSmall,
+}
+
+impl fmt::Display for FileInfoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => f.write_str("file information is truncated"),
+            Self::InvalidSize => f.write_str("invalid file information size"),
+            Self::MissingNul => f.write_str("file name is not NUL-terminated"),
+            Self::BufferTooSmall => f.write_str("buffer too small"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::format;
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            format!("{}", StatusError::UefiError(StatusCode::NotFound)),
+            "UEFI error: EFI_NOT_FOUND"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                StatusNullError::UefiWarning(StatusCode::WarnUnknownGlyph)
+            ),
+            "UEFI warning: EFI_WARN_UNKNOWN_GLYPH"
+        );
+        assert_eq!(
+            format!("{}", StatusNullError::NullPtrError("Boot Services")),
+            "Boot Services is NULL"
+        );
+    }
+
+    #[test]
+    fn debug() {
+        assert_eq!(
+            format!(
+                "{:?}",
+                StatusNullError::UefiError(StatusCode::OutOfResources)
+            ),
+            "UefiError(EFI_OUT_OF_RESOURCES)"
+        );
+    }
+}
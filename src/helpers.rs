@@ -1,4 +1,5 @@
 use crate::errors::{NullPtrError, StatusError};
+use crate::status::Completion;
 
 #[inline]
 pub(crate) fn null_check_mut<T>(ptr: *mut T, err: &'static str) -> Result<(), NullPtrError> {
@@ -10,12 +11,12 @@ pub(crate) fn null_check_mut<T>(ptr: *mut T, err: &'static str) -> Result<(), Nu
 }
 
 #[inline]
-pub(crate) fn status_to_result(status: r_efi::efi::Status) -> Result<(), StatusError> {
+pub(crate) fn status_to_result(status: r_efi::efi::Status) -> Result<Completion<()>, StatusError> {
     if status.is_error() {
-        Err(StatusError::UefiError(status.as_usize()))
+        Err(StatusError::UefiError(status.into()))
     } else if status.is_warning() {
-        Err(StatusError::UefiWarning(status.as_usize()))
+        Ok(Completion::with_warning((), status.into()))
     } else {
-        Ok(())
+        Ok(Completion::new(()))
     }
 }
@@ -1,15 +1,29 @@
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "mock")), no_std)]
 #![deny(unsafe_op_in_unsafe_fn)]
 
+#[cfg(any(test, feature = "alloc"))]
+extern crate alloc;
+
+pub mod acpi;
+pub mod allocator;
+pub mod args;
 pub mod boot_services;
+pub mod configuration_table;
+pub mod device_path;
 pub mod errors;
 pub mod global_data;
 mod helpers;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
 pub mod protocols;
+pub mod runtime_services;
+pub mod smbios;
+pub mod status;
+pub mod string;
 
 pub mod efi {
     pub use r_efi::efi::{
-        AllocateType, Boolean, BootServices, Handle, MemoryDescriptor, MemoryType, PhysicalAddress,
-        Status, SystemTable, LOADER_DATA,
+        AllocateType, Boolean, BootServices, Event, EventNotify, Guid, Handle, MemoryDescriptor,
+        MemoryType, PhysicalAddress, RuntimeServices, Status, SystemTable, Tpl, LOADER_DATA,
     };
 }
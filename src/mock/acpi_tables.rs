@@ -0,0 +1,82 @@
+//! Mock implementation of the ACPI Table Protocol.
+//!
+//! Installed tables are copied and recorded, but not linked into any RSDT or XSDT.
+
+use std::vec::Vec;
+
+use core::ffi::c_void;
+
+use r_efi::efi::Status;
+
+use super::{intercept, protocols, with_state, Call};
+use crate::acpi::SDT_HEADER_SIZE;
+use crate::protocols::acpi_table::{Protocol, PROTOCOL_GUID};
+
+/// The protocol holds no data, so a single instance is shared by all mocks.
+static PROTOCOL: Protocol = Protocol {
+    install_acpi_table,
+    uninstall_acpi_table,
+};
+
+pub(super) fn install_protocol() {
+    protocols::install(
+        core::ptr::null_mut(),
+        &PROTOCOL_GUID,
+        &PROTOCOL as *const Protocol as *mut c_void,
+    )
+    .expect("ACPI table protocol already installed");
+}
+
+extern "efiapi" fn install_acpi_table(
+    _this: *mut Protocol,
+    buffer: *mut c_void,
+    size: usize,
+    key: *mut usize,
+) -> Status {
+    if buffer.is_null() || key.is_null() || size < SDT_HEADER_SIZE {
+        return Status::INVALID_PARAMETER;
+    }
+    let table = unsafe { core::slice::from_raw_parts(buffer as *const u8, size) };
+
+    let r = intercept(&Call::InstallAcpiTable { table });
+    if r.is_error() {
+        return r;
+    }
+
+    let length = u32::from_le_bytes([table[4], table[5], table[6], table[7]]);
+    if length as usize != size {
+        return Status::INVALID_PARAMETER;
+    }
+
+    // Like real firmware, fix the checksum of the copy.
+    let mut table = table.to_vec();
+    crate::acpi::finalize_table(&mut table).unwrap();
+    let new_key = with_state(|s| {
+        s.next_acpi_table_key += 1;
+        s.acpi_tables.push((s.next_acpi_table_key, table));
+        s.next_acpi_table_key
+    });
+    unsafe { *key = new_key };
+    r
+}
+
+extern "efiapi" fn uninstall_acpi_table(_this: *mut Protocol, table_key: usize) -> Status {
+    let r = intercept(&Call::UninstallAcpiTable { table_key });
+    if r.is_error() {
+        return r;
+    }
+
+    let removed = with_state(|s| {
+        let pos = s.acpi_tables.iter().position(|(k, _)| *k == table_key)?;
+        Some(s.acpi_tables.remove(pos))
+    });
+    match removed {
+        Some(_) => r,
+        None => Status::NOT_FOUND,
+    }
+}
+
+/// The tables installed so far, in order.
+pub(super) fn tables() -> Vec<Vec<u8>> {
+    with_state(|s| s.acpi_tables.iter().map(|(_, x)| x.clone()).collect())
+}
@@ -0,0 +1,557 @@
+//! Mock implementations of the `BootServices` table.
+
+use std::alloc::Layout;
+use std::vec::Vec;
+
+use core::ffi::c_void;
+
+use r_efi::efi::{
+    self, AllocateType, Boolean, BootServices, Char16, Guid, Handle, MemoryDescriptor, MemoryType,
+    PhysicalAddress, Status,
+};
+use r_efi::protocols::device_path;
+
+use super::{events, intercept, protocols, with_state, Call, ExitRecord};
+
+pub(super) const PAGE_SIZE: usize = 4096;
+const POOL_ALIGNMENT: usize = 8;
+
+pub(super) fn page_layout(pages: usize) -> Layout {
+    Layout::from_size_align(pages.max(1) * PAGE_SIZE, PAGE_SIZE).unwrap()
+}
+
+pub(super) fn default_memory_map() -> Vec<MemoryDescriptor> {
+    let desc = |r#type, physical_start, number_of_pages, attribute| MemoryDescriptor {
+        r#type,
+        physical_start,
+        virtual_start: 0,
+        number_of_pages,
+        attribute,
+    };
+
+    std::vec![
+        desc(efi::BOOT_SERVICES_CODE, 0x0, 0x1, efi::MEMORY_WB),
+        desc(efi::CONVENTIONAL_MEMORY, 0x1000, 0x9f, efi::MEMORY_WB),
+        desc(efi::LOADER_CODE, 0x10_0000, 0x100, efi::MEMORY_WB),
+        desc(efi::LOADER_DATA, 0x20_0000, 0x100, efi::MEMORY_WB),
+        desc(
+            efi::RUNTIME_SERVICES_CODE,
+            0x30_0000,
+            0x10,
+            efi::MEMORY_WB | efi::MEMORY_RUNTIME,
+        ),
+        desc(
+            efi::RUNTIME_SERVICES_DATA,
+            0x31_0000,
+            0x10,
+            efi::MEMORY_WB | efi::MEMORY_RUNTIME,
+        ),
+        desc(efi::CONVENTIONAL_MEMORY, 0x40_0000, 0x3c00, efi::MEMORY_WB),
+    ]
+}
+
+pub(super) fn table() -> BootServices {
+    BootServices {
+        hdr: super::table_header::<BootServices>(efi::BOOT_SERVICES_SIGNATURE),
+        raise_tpl: events::raise_tpl,
+        restore_tpl: events::restore_tpl,
+        allocate_pages,
+        free_pages,
+        get_memory_map,
+        allocate_pool,
+        free_pool,
+        create_event: events::create_event,
+        set_timer: events::set_timer,
+        wait_for_event: events::wait_for_event,
+        signal_event: events::signal_event,
+        close_event: events::close_event,
+        check_event: events::check_event,
+        install_protocol_interface: protocols::install_protocol_interface,
+        reinstall_protocol_interface: protocols::reinstall_protocol_interface,
+        uninstall_protocol_interface: protocols::uninstall_protocol_interface,
+        handle_protocol: protocols::handle_protocol,
+        reserved: core::ptr::null_mut(),
+        register_protocol_notify: protocols::register_protocol_notify,
+        locate_handle: protocols::locate_handle,
+        locate_device_path: protocols::locate_device_path,
+        install_configuration_table,
+        load_image,
+        start_image,
+        exit,
+        unload_image,
+        exit_boot_services,
+        get_next_monotonic_count,
+        stall,
+        set_watchdog_timer,
+        connect_controller,
+        disconnect_controller,
+        open_protocol: protocols::open_protocol,
+        close_protocol: protocols::close_protocol,
+        open_protocol_information: protocols::open_protocol_information,
+        protocols_per_handle: protocols::protocols_per_handle,
+        locate_handle_buffer: protocols::locate_handle_buffer,
+        locate_protocol: protocols::locate_protocol,
+        install_multiple_protocol_interfaces,
+        uninstall_multiple_protocol_interfaces,
+        calculate_crc32,
+        copy_mem,
+        set_mem,
+        create_event_ex: events::create_event_ex,
+    }
+}
+
+/// Define a boot service which is not backed by the mock yet.
+macro_rules! unsupported {
+    ($($name:ident($($arg:ty),*);)*) => {
+        $(
+            extern "efiapi" fn $name($(_: $arg),*) -> Status {
+                Status::UNSUPPORTED
+            }
+        )*
+    };
+}
+
+unsupported! {
+    set_watchdog_timer(usize, u64, usize, *mut Char16);
+    connect_controller(Handle, *mut Handle, *mut device_path::Protocol, Boolean);
+    disconnect_controller(Handle, Handle, Handle);
+    install_multiple_protocol_interfaces(*mut Handle, *mut c_void, *mut c_void);
+    uninstall_multiple_protocol_interfaces(Handle, *mut c_void, *mut c_void);
+    calculate_crc32(*mut c_void, usize, *mut u32);
+}
+
+extern "efiapi" fn allocate_pool(
+    memory_type: MemoryType,
+    size: usize,
+    buffer: *mut *mut c_void,
+) -> Status {
+    if buffer.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let r = intercept(&Call::AllocatePool { memory_type, size });
+    if r.is_error() {
+        return r;
+    }
+
+    let ptr = pool_alloc(size);
+    if ptr.is_null() {
+        return Status::OUT_OF_RESOURCES;
+    }
+    unsafe { *buffer = ptr.cast() };
+
+    r
+}
+
+/// Allocate pool memory without going through the hook, e.g. for buffers the firmware returns.
+pub(super) fn pool_alloc(size: usize) -> *mut u8 {
+    let layout = Layout::from_size_align(size.max(1), POOL_ALIGNMENT).unwrap();
+    let ptr = unsafe { std::alloc::alloc(layout) };
+    if !ptr.is_null() {
+        with_state(|s| {
+            s.pool.insert(ptr as usize, layout);
+            s.map_key += 1;
+        });
+    }
+    ptr
+}
+
+extern "efiapi" fn free_pool(buffer: *mut c_void) -> Status {
+    let r = intercept(&Call::FreePool { buffer });
+    if r.is_error() {
+        return r;
+    }
+
+    let layout = with_state(|s| {
+        s.map_key += 1;
+        s.pool.remove(&(buffer as usize))
+    });
+    match layout {
+        Some(layout) => {
+            unsafe { std::alloc::dealloc(buffer.cast(), layout) };
+            r
+        }
+        None => Status::INVALID_PARAMETER,
+    }
+}
+
+extern "efiapi" fn allocate_pages(
+    alloc_type: AllocateType,
+    memory_type: MemoryType,
+    pages: usize,
+    memory: *mut PhysicalAddress,
+) -> Status {
+    if memory.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let r = intercept(&Call::AllocatePages {
+        alloc_type,
+        memory_type,
+        pages,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    // Host memory cannot be placed at a given address.
+    if alloc_type == efi::ALLOCATE_ADDRESS {
+        return Status::NOT_FOUND;
+    }
+
+    let ptr = unsafe { std::alloc::alloc_zeroed(page_layout(pages)) };
+    if ptr.is_null() {
+        return Status::OUT_OF_RESOURCES;
+    }
+
+    let addr = ptr as usize as PhysicalAddress;
+    if alloc_type == efi::ALLOCATE_MAX_ADDRESS && addr > unsafe { *memory } {
+        unsafe { std::alloc::dealloc(ptr, page_layout(pages)) };
+        return Status::OUT_OF_RESOURCES;
+    }
+
+    with_state(|s| {
+        s.pages.insert(addr, pages);
+        s.map_key += 1;
+    });
+    unsafe { *memory = addr };
+
+    r
+}
+
+extern "efiapi" fn free_pages(memory: PhysicalAddress, pages: usize) -> Status {
+    let r = intercept(&Call::FreePages { memory, pages });
+    if r.is_error() {
+        return r;
+    }
+
+    let found = with_state(|s| match s.pages.get(&memory) {
+        Some(&p) if p == pages => {
+            s.pages.remove(&memory);
+            s.map_key += 1;
+            true
+        }
+        _ => false,
+    });
+    if !found {
+        return Status::NOT_FOUND;
+    }
+
+    unsafe { std::alloc::dealloc(memory as usize as *mut u8, page_layout(pages)) };
+    r
+}
+
+extern "efiapi" fn get_memory_map(
+    memory_map_size: *mut usize,
+    memory_map: *mut MemoryDescriptor,
+    map_key: *mut usize,
+    descriptor_size: *mut usize,
+    descriptor_version: *mut u32,
+) -> Status {
+    if memory_map_size.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let size = unsafe { *memory_map_size };
+    let r = intercept(&Call::GetMemoryMap { size });
+    if r.is_error() {
+        return r;
+    }
+
+    with_state(|s| {
+        let needed = s.memory_map.len() * s.descriptor_size;
+        unsafe { *memory_map_size = needed };
+        if !descriptor_size.is_null() {
+            unsafe { *descriptor_size = s.descriptor_size };
+        }
+        if !descriptor_version.is_null() {
+            unsafe { *descriptor_version = efi::MEMORY_DESCRIPTOR_VERSION };
+        }
+
+        if size < needed {
+            return Status::BUFFER_TOO_SMALL;
+        }
+        if memory_map.is_null() || map_key.is_null() {
+            return Status::INVALID_PARAMETER;
+        }
+
+        let base = memory_map.cast::<u8>();
+        unsafe { core::ptr::write_bytes(base, 0, needed) };
+        for (i, desc) in s.memory_map.iter().enumerate() {
+            unsafe { core::ptr::write_unaligned(base.add(i * s.descriptor_size).cast(), *desc) };
+        }
+        unsafe { *map_key = s.map_key };
+
+        r
+    })
+}
+
+extern "efiapi" fn exit(
+    image_handle: Handle,
+    status: Status,
+    exit_data_size: usize,
+    exit_data: *mut Char16,
+) -> Status {
+    let data: &[u16] = if exit_data.is_null() {
+        &[]
+    } else {
+        unsafe { core::slice::from_raw_parts(exit_data, exit_data_size / 2) }
+    };
+
+    let r = intercept(&Call::Exit {
+        image_handle,
+        status,
+        data,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    let record = ExitRecord {
+        image_handle,
+        status,
+        data: data.to_vec(),
+    };
+    with_state(|s| s.exit = Some(record));
+
+    r
+}
+
+extern "efiapi" fn load_image(
+    boot_policy: Boolean,
+    parent_image_handle: Handle,
+    device_path: *mut device_path::Protocol,
+    source_buffer: *mut c_void,
+    source_size: usize,
+    image_handle: *mut Handle,
+) -> Status {
+    if image_handle.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let r = intercept(&Call::LoadImage {
+        boot_policy: boot_policy.into(),
+        parent_image_handle,
+        source_size,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    // There is no file system to load from.
+    if source_buffer.is_null() {
+        return if device_path.is_null() {
+            Status::INVALID_PARAMETER
+        } else {
+            Status::NOT_FOUND
+        };
+    }
+
+    // Only the DOS header signature of a PE image is checked.
+    let source = unsafe { core::slice::from_raw_parts(source_buffer.cast::<u8>(), source_size) };
+    if !source.starts_with(b"MZ") {
+        return Status::LOAD_ERROR;
+    }
+
+    let handle = with_state(|s| {
+        s.next_handle += 1;
+        let handle = 0xa4d0_0000usize + s.next_handle * 8;
+        s.images.insert(handle, false);
+        handle
+    });
+    unsafe { *image_handle = handle as Handle };
+
+    r
+}
+
+extern "efiapi" fn start_image(
+    image_handle: Handle,
+    exit_data_size: *mut usize,
+    exit_data: *mut *mut Char16,
+) -> Status {
+    let r = intercept(&Call::StartImage { image_handle });
+    if r.is_error() {
+        return r;
+    }
+
+    let valid = with_state(|s| match s.images.get_mut(&(image_handle as usize)) {
+        Some(started) if !*started => {
+            *started = true;
+            s.exit = None;
+            true
+        }
+        _ => false,
+    });
+    if !valid {
+        return Status::INVALID_PARAMETER;
+    }
+
+    // The entry point is taken out of the state while it runs, as it calls back into the mock.
+    let entry = with_state(|s| s.image_entry.take());
+    let status = match entry {
+        Some(mut entry) => {
+            let status = entry(image_handle);
+            with_state(|s| {
+                if s.image_entry.is_none() {
+                    s.image_entry = Some(entry);
+                }
+            });
+            status
+        }
+        None => Status::SUCCESS,
+    };
+
+    // An image which called `Exit` returns with the status and data passed to it.
+    let record = with_state(|s| match &s.exit {
+        Some(x) if x.image_handle == image_handle => s.exit.take(),
+        _ => None,
+    });
+    match record {
+        Some(record) => {
+            if !record.data.is_empty() && !exit_data.is_null() && !exit_data_size.is_null() {
+                let ptr = pool_alloc(record.data.len() * 2).cast::<u16>();
+                unsafe {
+                    core::ptr::copy_nonoverlapping(record.data.as_ptr(), ptr, record.data.len());
+                    *exit_data = ptr;
+                    *exit_data_size = record.data.len() * 2;
+                }
+            }
+            record.status
+        }
+        None => status,
+    }
+}
+
+extern "efiapi" fn unload_image(image_handle: Handle) -> Status {
+    let r = intercept(&Call::UnloadImage { image_handle });
+    if r.is_error() {
+        return r;
+    }
+
+    match with_state(|s| s.images.remove(&(image_handle as usize))) {
+        Some(_) => r,
+        None => Status::INVALID_PARAMETER,
+    }
+}
+
+extern "efiapi" fn exit_boot_services(image_handle: Handle, map_key: usize) -> Status {
+    let r = intercept(&Call::ExitBootServices {
+        image_handle,
+        map_key,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    let to_signal = with_state(|s| {
+        if s.map_key != map_key {
+            return None;
+        }
+        s.boot_services_exited = true;
+        Some(events::group_events(
+            s,
+            efi::EVT_SIGNAL_EXIT_BOOT_SERVICES,
+            &efi::EVENT_GROUP_EXIT_BOOT_SERVICES,
+        ))
+    });
+
+    match to_signal {
+        Some(x) => {
+            for event in x {
+                events::signal(event);
+            }
+            r
+        }
+        None => Status::INVALID_PARAMETER,
+    }
+}
+
+extern "efiapi" fn install_configuration_table(guid: *mut Guid, table: *mut c_void) -> Status {
+    if guid.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let guid = unsafe { *guid };
+
+    let r = intercept(&Call::InstallConfigurationTable { guid, table });
+    if r.is_error() {
+        return r;
+    }
+
+    let status = install_table(&guid, table);
+    if status.is_error() {
+        return status;
+    }
+
+    // Installing a table signals the event group named by its GUID.
+    events::signal_group(&guid);
+    r
+}
+
+/// Add, replace or, if `table` is null, remove the configuration table with `guid`, and update
+/// the system table to point to the new array.
+pub(super) fn install_table(guid: &Guid, table: *mut c_void) -> Status {
+    with_state(|s| {
+        let pos = s
+            .configuration_tables
+            .iter()
+            .position(|x| x.vendor_guid == *guid);
+        match (pos, table.is_null()) {
+            (Some(i), true) => {
+                s.configuration_tables.remove(i);
+            }
+            (Some(i), false) => s.configuration_tables[i].vendor_table = table,
+            (None, true) => return Status::NOT_FOUND,
+            (None, false) => s.configuration_tables.push(efi::ConfigurationTable {
+                vendor_guid: *guid,
+                vendor_table: table,
+            }),
+        }
+
+        unsafe {
+            (*s.system_table).configuration_table = s.configuration_tables.as_mut_ptr();
+            (*s.system_table).number_of_table_entries = s.configuration_tables.len();
+        }
+        Status::SUCCESS
+    })
+}
+
+extern "efiapi" fn get_next_monotonic_count(count: *mut u64) -> Status {
+    if count.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let r = intercept(&Call::GetNextMonotonicCount);
+    if r.is_error() {
+        return r;
+    }
+
+    let next = with_state(|s| {
+        // Only the low 32 bits are incremented. The high 32 bits are advanced by
+        // `GetNextHighMonotonicCount`.
+        let low = (s.monotonic_count as u32).wrapping_add(1);
+        let high = (s.monotonic_count >> 32) + u64::from(low == 0);
+        s.monotonic_count = (high << 32) | u64::from(low);
+        s.monotonic_count
+    });
+    unsafe { *count = next };
+    r
+}
+
+/// Stalls return right away, but advance the time seen by timers.
+extern "efiapi" fn stall(microseconds: usize) -> Status {
+    let r = intercept(&Call::Stall { microseconds });
+    if r.is_error() {
+        return r;
+    }
+
+    events::advance(microseconds as u64 * 10);
+    r
+}
+
+extern "efiapi" fn copy_mem(destination: *mut c_void, source: *mut c_void, length: usize) {
+    unsafe { core::ptr::copy(source.cast::<u8>(), destination.cast::<u8>(), length) }
+}
+
+extern "efiapi" fn set_mem(buffer: *mut c_void, size: usize, value: u8) {
+    unsafe { core::ptr::write_bytes(buffer.cast::<u8>(), value, size) }
+}
@@ -0,0 +1,154 @@
+//! Mock implementations of the capsule services.
+//!
+//! Capsules are checked and recorded, but never processed. The scatter-gather list is walked
+//! through its continuation pointers and the data blocks are recorded with the update.
+
+use std::vec::Vec;
+
+use r_efi::efi::{self, CapsuleBlockDescriptor, CapsuleHeader, PhysicalAddress, ResetType, Status};
+
+use super::{intercept, with_state, Call};
+
+/// The largest capsule accepted by `UpdateCapsule`.
+pub(crate) const MAXIMUM_CAPSULE_SIZE: u64 = 0x100_0000;
+
+/// Descriptors walked before a scatter-gather list is considered to loop.
+const MAXIMUM_DESCRIPTORS: usize = 0x1000;
+
+/// Arguments passed to `UpdateCapsule`, as recorded by the mock.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapsuleUpdate {
+    /// The bytes of each capsule, header included.
+    pub capsules: Vec<Vec<u8>>,
+    pub scatter_gather_list: PhysicalAddress,
+    /// The data blocks of the scatter-gather list, as `(address, length)`.
+    pub data_blocks: Vec<(PhysicalAddress, u64)>,
+}
+
+/// Check the headers and return the capsules, and whether any persists across reset.
+fn read_capsules(
+    capsule_header_array: *mut *mut CapsuleHeader,
+    capsule_count: usize,
+) -> Result<(Vec<Vec<u8>>, bool), Status> {
+    if capsule_header_array.is_null() || capsule_count == 0 {
+        return Err(Status::INVALID_PARAMETER);
+    }
+
+    let mut capsules = Vec::new();
+    let mut persist = false;
+    for i in 0..capsule_count {
+        let header = unsafe { *capsule_header_array.add(i) };
+        if header.is_null() {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        let h = unsafe { *header };
+        if (h.header_size as usize) < core::mem::size_of::<CapsuleHeader>()
+            || h.header_size > h.capsule_image_size
+        {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        let reset_flags =
+            efi::CAPSULE_FLAGS_POPULATE_SYSTEM_TABLE | efi::CAPSULE_FLAGS_INITIATE_RESET;
+        let persists = h.flags & efi::CAPSULE_FLAGS_PERSIST_ACROSS_RESET != 0;
+        if h.flags & reset_flags != 0 && !persists {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        if u64::from(h.capsule_image_size) > MAXIMUM_CAPSULE_SIZE {
+            return Err(Status::OUT_OF_RESOURCES);
+        }
+        persist |= persists;
+
+        let bytes = unsafe {
+            core::slice::from_raw_parts(header as *const u8, h.capsule_image_size as usize)
+        };
+        capsules.push(bytes.to_vec());
+    }
+    Ok((capsules, persist))
+}
+
+/// Walk a scatter-gather list through its continuation pointers.
+fn data_blocks(mut address: PhysicalAddress) -> Result<Vec<(PhysicalAddress, u64)>, Status> {
+    let mut r = Vec::new();
+    for _ in 0..MAXIMUM_DESCRIPTORS {
+        let descriptor = unsafe { *(address as usize as *const CapsuleBlockDescriptor) };
+        if descriptor.length != 0 {
+            r.push((unsafe { descriptor.data.data_block }, descriptor.length));
+            address += core::mem::size_of::<CapsuleBlockDescriptor>() as u64;
+            continue;
+        }
+        match unsafe { descriptor.data.continuation_pointer } {
+            0 => return Ok(r),
+            next => address = next,
+        }
+    }
+    Err(Status::INVALID_PARAMETER)
+}
+
+pub(super) extern "efiapi" fn update_capsule(
+    capsule_header_array: *mut *mut CapsuleHeader,
+    capsule_count: usize,
+    scatter_gather_list: PhysicalAddress,
+) -> Status {
+    let (capsules, persist) = match read_capsules(capsule_header_array, capsule_count) {
+        Ok(x) => x,
+        Err(e) => return e,
+    };
+    if persist && scatter_gather_list == 0 {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let r = intercept(&Call::UpdateCapsule {
+        capsule_count,
+        scatter_gather_list,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    let data_blocks = if scatter_gather_list == 0 {
+        Vec::new()
+    } else {
+        match data_blocks(scatter_gather_list) {
+            Ok(x) => x,
+            Err(e) => return e,
+        }
+    };
+    with_state(|s| {
+        s.capsule_updates.push(CapsuleUpdate {
+            capsules,
+            scatter_gather_list,
+            data_blocks,
+        })
+    });
+    r
+}
+
+pub(super) extern "efiapi" fn query_capsule_capabilities(
+    capsule_header_array: *mut *mut CapsuleHeader,
+    capsule_count: usize,
+    maximum_capsule_size: *mut u64,
+    reset_type: *mut ResetType,
+) -> Status {
+    if maximum_capsule_size.is_null() || reset_type.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let persist = match read_capsules(capsule_header_array, capsule_count) {
+        Ok((_, persist)) => persist,
+        Err(e) => return e,
+    };
+
+    let r = intercept(&Call::QueryCapsuleCapabilities { capsule_count });
+    if r.is_error() {
+        return r;
+    }
+
+    unsafe {
+        *maximum_capsule_size = MAXIMUM_CAPSULE_SIZE;
+        *reset_type = if persist {
+            efi::RESET_WARM
+        } else {
+            efi::RESET_COLD
+        };
+    }
+    r
+}
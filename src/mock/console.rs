@@ -0,0 +1,228 @@
+//! Mock implementations of `con_in` and `con_out`.
+
+use r_efi::efi::{Boolean, Char16, Status};
+use r_efi::protocols::{simple_text_input, simple_text_output};
+
+use super::{intercept, read_cstr16, with_state, Call};
+
+/// Modes reported by `QueryMode` as (columns, rows).
+const MODES: [(usize, usize); 2] = [(80, 25), (80, 50)];
+
+pub(super) fn con_in() -> simple_text_input::Protocol {
+    simple_text_input::Protocol {
+        reset: con_in_reset,
+        read_key_stroke,
+        wait_for_key: super::key_event(),
+    }
+}
+
+pub(super) fn con_out() -> simple_text_output::Protocol {
+    simple_text_output::Protocol {
+        reset: con_out_reset,
+        output_string,
+        test_string,
+        query_mode,
+        set_mode,
+        set_attribute,
+        clear_screen,
+        set_cursor_position,
+        enable_cursor,
+        mode: core::ptr::null_mut(),
+    }
+}
+
+pub(super) fn mode() -> simple_text_output::Mode {
+    simple_text_output::Mode {
+        max_mode: MODES.len() as i32,
+        mode: 0,
+        attribute: 0x07,
+        cursor_column: 0,
+        cursor_row: 0,
+        cursor_visible: Boolean::TRUE,
+    }
+}
+
+extern "efiapi" fn con_in_reset(
+    _this: *mut simple_text_input::Protocol,
+    extended_verification: Boolean,
+) -> Status {
+    let r = intercept(&Call::ConInReset {
+        extended_verification: extended_verification.into(),
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    with_state(|s| s.keys.clear());
+    r
+}
+
+extern "efiapi" fn read_key_stroke(
+    _this: *mut simple_text_input::Protocol,
+    key: *mut simple_text_input::InputKey,
+) -> Status {
+    if key.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let r = intercept(&Call::ReadKeyStroke);
+    if r.is_error() {
+        return r;
+    }
+
+    match with_state(|s| s.keys.pop_front()) {
+        Some(k) => {
+            unsafe { *key = k };
+            r
+        }
+        None => Status::NOT_READY,
+    }
+}
+
+extern "efiapi" fn con_out_reset(
+    this: *mut simple_text_output::Protocol,
+    extended_verification: Boolean,
+) -> Status {
+    let r = intercept(&Call::ConOutReset {
+        extended_verification: extended_verification.into(),
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    let mode = unsafe { &mut *(*this).mode };
+    mode.cursor_column = 0;
+    mode.cursor_row = 0;
+    r
+}
+
+extern "efiapi" fn output_string(
+    _this: *mut simple_text_output::Protocol,
+    string: *mut Char16,
+) -> Status {
+    if string.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let s = unsafe { read_cstr16(string) };
+    let r = intercept(&Call::OutputString(s));
+    if r.is_error() {
+        return r;
+    }
+
+    with_state(|state| state.console_out.extend_from_slice(s));
+    r
+}
+
+extern "efiapi" fn test_string(
+    _this: *mut simple_text_output::Protocol,
+    string: *mut Char16,
+) -> Status {
+    if string.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let s = unsafe { read_cstr16(string) };
+    intercept(&Call::TestString(s))
+}
+
+extern "efiapi" fn query_mode(
+    _this: *mut simple_text_output::Protocol,
+    mode_number: usize,
+    columns: *mut usize,
+    rows: *mut usize,
+) -> Status {
+    let r = intercept(&Call::QueryMode { mode_number });
+    if r.is_error() {
+        return r;
+    }
+
+    match MODES.get(mode_number) {
+        Some(&(c, rw)) => {
+            unsafe {
+                *columns = c;
+                *rows = rw;
+            }
+            r
+        }
+        None => Status::UNSUPPORTED,
+    }
+}
+
+extern "efiapi" fn set_mode(this: *mut simple_text_output::Protocol, mode_number: usize) -> Status {
+    let r = intercept(&Call::SetMode { mode_number });
+    if r.is_error() {
+        return r;
+    }
+
+    if mode_number >= MODES.len() {
+        return Status::UNSUPPORTED;
+    }
+
+    let mode = unsafe { &mut *(*this).mode };
+    mode.mode = mode_number as i32;
+    mode.cursor_column = 0;
+    mode.cursor_row = 0;
+    r
+}
+
+extern "efiapi" fn set_attribute(
+    this: *mut simple_text_output::Protocol,
+    attribute: usize,
+) -> Status {
+    let r = intercept(&Call::SetAttribute { attribute });
+    if r.is_error() {
+        return r;
+    }
+
+    unsafe { (*(*this).mode).attribute = attribute as i32 };
+    r
+}
+
+extern "efiapi" fn clear_screen(this: *mut simple_text_output::Protocol) -> Status {
+    let r = intercept(&Call::ClearScreen);
+    if r.is_error() {
+        return r;
+    }
+
+    let mode = unsafe { &mut *(*this).mode };
+    mode.cursor_column = 0;
+    mode.cursor_row = 0;
+    r
+}
+
+extern "efiapi" fn set_cursor_position(
+    this: *mut simple_text_output::Protocol,
+    column: usize,
+    row: usize,
+) -> Status {
+    let r = intercept(&Call::SetCursorPosition { column, row });
+    if r.is_error() {
+        return r;
+    }
+
+    let mode = unsafe { &mut *(*this).mode };
+    let (columns, rows) = MODES[mode.mode as usize];
+    if column >= columns || row >= rows {
+        return Status::UNSUPPORTED;
+    }
+
+    mode.cursor_column = column as i32;
+    mode.cursor_row = row as i32;
+    r
+}
+
+extern "efiapi" fn enable_cursor(
+    this: *mut simple_text_output::Protocol,
+    visible: Boolean,
+) -> Status {
+    let r = intercept(&Call::EnableCursor {
+        visible: visible.into(),
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    unsafe { (*(*this).mode).cursor_visible = visible };
+    r
+}
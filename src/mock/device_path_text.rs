@@ -0,0 +1,156 @@
+//! Mock implementation of the Device Path To Text and Device Path From Text protocols.
+//!
+//! The conversions are done by `device_path::text`. It has no display-only or shortcut forms, so
+//! the flags are ignored.
+
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+use core::ffi::c_void;
+
+use r_efi::efi::Boolean;
+use r_efi::protocols::{device_path, device_path_from_text, device_path_to_text};
+
+use super::{boot_services, intercept, protocols, Call};
+use crate::device_path::{DevicePath, DevicePathBuf, DevicePathBuilder, Node, NODE_HEADER_SIZE};
+
+/// The protocols hold no data, so a single instance of each is shared by all mocks.
+static TO_TEXT: device_path_to_text::Protocol = device_path_to_text::Protocol {
+    convert_device_node_to_text,
+    convert_device_path_to_text,
+};
+
+static FROM_TEXT: device_path_from_text::Protocol = device_path_from_text::Protocol {
+    convert_text_to_device_node,
+    convert_text_to_device_path,
+};
+
+pub(super) fn install_protocols() {
+    let handle = protocols::install(
+        core::ptr::null_mut(),
+        &device_path_to_text::PROTOCOL_GUID,
+        &TO_TEXT as *const device_path_to_text::Protocol as *mut c_void,
+    )
+    .expect("device path text protocols already installed");
+    protocols::install(
+        handle,
+        &device_path_from_text::PROTOCOL_GUID,
+        &FROM_TEXT as *const device_path_from_text::Protocol as *mut c_void,
+    )
+    .unwrap();
+}
+
+extern "efiapi" fn convert_device_node_to_text(
+    node: *mut device_path::Protocol,
+    display_only: Boolean,
+    allow_shortcuts: Boolean,
+) -> *mut u16 {
+    if node.is_null() {
+        return core::ptr::null_mut();
+    }
+    let length = u16::from_le_bytes(unsafe { (*node).length }) as usize;
+    let data =
+        unsafe { core::slice::from_raw_parts(node.cast::<u8>(), length.max(NODE_HEADER_SIZE)) };
+    let node = match Node::parse(data) {
+        Ok(x) => x,
+        Err(_) => return core::ptr::null_mut(),
+    };
+
+    let r = intercept(&Call::ConvertDeviceNodeToText {
+        node: node.as_bytes(),
+        display_only: display_only.into(),
+        allow_shortcuts: allow_shortcuts.into(),
+    });
+    if r.is_error() {
+        return core::ptr::null_mut();
+    }
+
+    to_pool_string(&node.to_string())
+}
+
+extern "efiapi" fn convert_device_path_to_text(
+    path: *mut device_path::Protocol,
+    display_only: Boolean,
+    allow_shortcuts: Boolean,
+) -> *mut u16 {
+    if path.is_null() {
+        return core::ptr::null_mut();
+    }
+    let path = match unsafe { DevicePath::from_ptr(path) } {
+        Ok(x) => x,
+        Err(_) => return core::ptr::null_mut(),
+    };
+
+    let r = intercept(&Call::ConvertDevicePathToText {
+        path: path.as_bytes(),
+        display_only: display_only.into(),
+        allow_shortcuts: allow_shortcuts.into(),
+    });
+    if r.is_error() {
+        return core::ptr::null_mut();
+    }
+
+    to_pool_string(&path.to_string())
+}
+
+extern "efiapi" fn convert_text_to_device_node(text: *const u16) -> *mut device_path::Protocol {
+    let text = match read_text(text) {
+        Some(x) => x,
+        None => return core::ptr::null_mut(),
+    };
+
+    let r = intercept(&Call::ConvertTextToDeviceNode { text: &text });
+    if r.is_error() {
+        return core::ptr::null_mut();
+    }
+
+    let path = DevicePathBuilder::new()
+        .text_node(&String::from_utf16_lossy(&text))
+        .build();
+    match path.as_ref().map(|x| x.as_path().nodes().next()) {
+        Ok(Some(node)) => to_pool(node.as_bytes()),
+        _ => core::ptr::null_mut(),
+    }
+}
+
+extern "efiapi" fn convert_text_to_device_path(text: *const u16) -> *mut device_path::Protocol {
+    let text = match read_text(text) {
+        Some(x) => x,
+        None => return core::ptr::null_mut(),
+    };
+
+    let r = intercept(&Call::ConvertTextToDevicePath { text: &text });
+    if r.is_error() {
+        return core::ptr::null_mut();
+    }
+
+    match DevicePathBuf::from_text(&String::from_utf16_lossy(&text)) {
+        Ok(path) => to_pool(path.as_path().as_bytes()),
+        Err(_) => core::ptr::null_mut(),
+    }
+}
+
+/// Copy a NUL-terminated string, without the NUL.
+fn read_text(text: *const u16) -> Option<Vec<u16>> {
+    if text.is_null() {
+        return None;
+    }
+    let mut len = 0;
+    while unsafe { *text.add(len) } != 0 {
+        len += 1;
+    }
+    Some(unsafe { core::slice::from_raw_parts(text, len) }.to_vec())
+}
+
+fn to_pool_string(s: &str) -> *mut u16 {
+    let s: Vec<u16> = s.encode_utf16().chain([0]).collect();
+    let ptr = boot_services::pool_alloc(s.len() * 2).cast::<u16>();
+    unsafe { core::ptr::copy_nonoverlapping(s.as_ptr(), ptr, s.len()) };
+    ptr
+}
+
+fn to_pool(data: &[u8]) -> *mut device_path::Protocol {
+    let ptr = boot_services::pool_alloc(data.len());
+    unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len()) };
+    ptr.cast()
+}
@@ -0,0 +1,391 @@
+//! Mock implementations of the event, timer and task priority services.
+//!
+//! Time does not pass on its own. `WaitForEvent` advances it to the next timer deadline if none
+//! of the events it waits for is signaled, and tests can advance it with
+//! `MockSystemTable::advance_time`.
+
+use std::vec::Vec;
+
+use core::ffi::c_void;
+
+use r_efi::efi::{self, Event, EventNotify, Guid, Status, TimerDelay, Tpl};
+
+use super::{intercept, with_state, Call, State};
+
+pub(crate) struct MockEvent {
+    event_type: u32,
+    notify_function: Option<EventNotify>,
+    notify_context: *mut c_void,
+    group: Option<Guid>,
+    signaled: bool,
+    /// Deadline and period of an armed timer. A period of 0 means one-shot.
+    timer: Option<(u64, u64)>,
+}
+
+fn key(event: Event) -> usize {
+    event as usize
+}
+
+fn create(
+    event_type: u32,
+    notify_function: Option<EventNotify>,
+    notify_context: *mut c_void,
+    group: Option<Guid>,
+    event: *mut Event,
+) -> Status {
+    if event.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let notify_type = event_type & (efi::EVT_NOTIFY_WAIT | efi::EVT_NOTIFY_SIGNAL);
+    if notify_type == efi::EVT_NOTIFY_WAIT | efi::EVT_NOTIFY_SIGNAL
+        || (notify_type != 0 && notify_function.is_none())
+    {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let handle = with_state(|s| {
+        s.next_event += 1;
+        let handle = 0xe7e0_0000usize + s.next_event * 8;
+        s.events.insert(
+            handle,
+            MockEvent {
+                event_type,
+                notify_function,
+                notify_context,
+                group,
+                signaled: false,
+                timer: None,
+            },
+        );
+        handle
+    });
+    unsafe { *event = handle as Event };
+
+    Status::SUCCESS
+}
+
+/// Signal `event` and, if it belongs to a group, all events of the group.
+pub(super) fn signal(event: Event) -> Status {
+    let targets: Option<Vec<usize>> = with_state(|s| {
+        let e = s.events.get(&key(event))?;
+        Some(match e.group {
+            Some(g) => s
+                .events
+                .iter()
+                .filter(|(_, x)| x.group == Some(g))
+                .map(|(&k, _)| k)
+                .collect(),
+            None => std::vec![key(event)],
+        })
+    });
+    let targets = match targets {
+        Some(x) => x,
+        None => return Status::INVALID_PARAMETER,
+    };
+
+    for k in targets {
+        let notify = with_state(|s| {
+            let e = s.events.get_mut(&k)?;
+            if e.event_type & efi::EVT_NOTIFY_SIGNAL != 0 {
+                e.notify_function.map(|f| (f, e.notify_context))
+            } else {
+                e.signaled = true;
+                None
+            }
+        });
+        // The notification function may call back into the mock.
+        if let Some((f, ctx)) = notify {
+            f(k as Event, ctx);
+        }
+    }
+
+    Status::SUCCESS
+}
+
+/// Signal all events of `group`, if any.
+pub(super) fn signal_group(group: &Guid) {
+    let first = with_state(|s| {
+        s.events
+            .iter()
+            .find(|(_, e)| e.group.as_ref() == Some(group))
+            .map(|(&k, _)| k as Event)
+    });
+    if let Some(event) = first {
+        signal(event);
+    }
+}
+
+/// The events to signal for a notification which exists both as an event type and an event
+/// group, e.g. `EVT_SIGNAL_EXIT_BOOT_SERVICES` and `EVENT_GROUP_EXIT_BOOT_SERVICES`.
+pub(super) fn group_events(s: &State, event_type: u32, group: &Guid) -> Vec<Event> {
+    s.events
+        .iter()
+        .filter(|(_, e)| e.event_type == event_type || e.group.as_ref() == Some(group))
+        .map(|(&k, _)| k as Event)
+        .collect()
+}
+
+/// The logic of `CheckEvent`, shared with `WaitForEvent`.
+fn check(event: Event) -> Status {
+    let r = with_state(|s| {
+        let e = s.events.get_mut(&key(event))?;
+        if e.event_type & efi::EVT_NOTIFY_SIGNAL != 0 {
+            return Some(Err(Status::INVALID_PARAMETER));
+        }
+        if e.signaled {
+            e.signaled = false;
+            return Some(Err(Status::SUCCESS));
+        }
+        Some(Ok(e.notify_function.map(|f| (f, e.notify_context))))
+    });
+
+    let notify = match r {
+        None => return Status::INVALID_PARAMETER,
+        Some(Err(status)) => return status,
+        Some(Ok(notify)) => notify,
+    };
+
+    // Give `EVT_NOTIFY_WAIT` events a chance to signal themselves.
+    if let Some((f, ctx)) = notify {
+        f(event, ctx);
+        let signaled = with_state(|s| match s.events.get_mut(&key(event)) {
+            Some(e) if e.signaled => {
+                e.signaled = false;
+                true
+            }
+            _ => false,
+        });
+        if signaled {
+            return Status::SUCCESS;
+        }
+    }
+
+    Status::NOT_READY
+}
+
+/// Advance the time by `delta`, firing all timers which expire in between.
+pub(super) fn advance(delta: u64) {
+    let until = with_state(|s| s.time) + delta;
+    while fire_next_timer(Some(until)) {}
+    with_state(|s| s.time = until);
+}
+
+/// Fire the timer with the earliest deadline at or before `until`, advancing the time to its
+/// deadline. Returns `false` if there is no such timer.
+pub(super) fn fire_next_timer(until: Option<u64>) -> bool {
+    let next = with_state(|s| {
+        let (&k, e) = s
+            .events
+            .iter()
+            .filter(|(_, e)| e.timer.is_some())
+            .min_by_key(|(_, e)| e.timer.unwrap().0)?;
+        let (deadline, period) = e.timer.unwrap();
+        if until.is_some_and(|u| deadline > u) {
+            return None;
+        }
+
+        s.time = s.time.max(deadline);
+        let e = s.events.get_mut(&k).unwrap();
+        e.timer = if period == 0 {
+            None
+        } else {
+            Some((deadline + period, period))
+        };
+        Some(k)
+    });
+
+    match next {
+        Some(k) => {
+            signal(k as Event);
+            true
+        }
+        None => false,
+    }
+}
+
+pub(super) extern "efiapi" fn create_event(
+    event_type: u32,
+    notify_tpl: Tpl,
+    notify_function: Option<EventNotify>,
+    notify_context: *mut c_void,
+    event: *mut Event,
+) -> Status {
+    let r = intercept(&Call::CreateEvent {
+        event_type,
+        notify_tpl,
+        event_group: None,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    match create(event_type, notify_function, notify_context, None, event) {
+        Status::SUCCESS => r,
+        x => x,
+    }
+}
+
+pub(super) extern "efiapi" fn create_event_ex(
+    event_type: u32,
+    notify_tpl: Tpl,
+    notify_function: Option<EventNotify>,
+    notify_context: *const c_void,
+    event_group: *const Guid,
+    event: *mut Event,
+) -> Status {
+    let group = if event_group.is_null() {
+        None
+    } else {
+        Some(unsafe { *event_group })
+    };
+
+    let r = intercept(&Call::CreateEvent {
+        event_type,
+        notify_tpl,
+        event_group: group,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    // Events in a group are created with a type of 0 by the spec, or with a signal type.
+    if group.is_some()
+        && (event_type == efi::EVT_SIGNAL_EXIT_BOOT_SERVICES
+            || event_type == efi::EVT_SIGNAL_VIRTUAL_ADDRESS_CHANGE)
+    {
+        return Status::INVALID_PARAMETER;
+    }
+
+    match create(
+        event_type,
+        notify_function,
+        notify_context as *mut c_void,
+        group,
+        event,
+    ) {
+        Status::SUCCESS => r,
+        x => x,
+    }
+}
+
+pub(super) extern "efiapi" fn close_event(event: Event) -> Status {
+    let r = intercept(&Call::CloseEvent { event });
+    if r.is_error() {
+        return r;
+    }
+
+    match with_state(|s| s.events.remove(&key(event))) {
+        Some(_) => r,
+        None => Status::INVALID_PARAMETER,
+    }
+}
+
+pub(super) extern "efiapi" fn signal_event(event: Event) -> Status {
+    let r = intercept(&Call::SignalEvent { event });
+    if r.is_error() {
+        return r;
+    }
+
+    match signal(event) {
+        Status::SUCCESS => r,
+        x => x,
+    }
+}
+
+pub(super) extern "efiapi" fn check_event(event: Event) -> Status {
+    let r = intercept(&Call::CheckEvent { event });
+    if r.is_error() {
+        return r;
+    }
+
+    match check(event) {
+        Status::SUCCESS => r,
+        x => x,
+    }
+}
+
+pub(super) extern "efiapi" fn wait_for_event(
+    number_of_events: usize,
+    event: *mut Event,
+    index: *mut usize,
+) -> Status {
+    if number_of_events == 0 || event.is_null() || index.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let events = unsafe { core::slice::from_raw_parts(event, number_of_events) };
+    let r = intercept(&Call::WaitForEvent { events });
+    if r.is_error() {
+        return r;
+    }
+
+    if with_state(|s| s.tpl) != efi::TPL_APPLICATION {
+        return Status::UNSUPPORTED;
+    }
+
+    loop {
+        let key_ready = with_state(|s| !s.keys.is_empty());
+        for (i, &e) in events.iter().enumerate() {
+            let status = if e == super::key_event() {
+                if key_ready {
+                    Status::SUCCESS
+                } else {
+                    Status::NOT_READY
+                }
+            } else {
+                check(e)
+            };
+
+            if status != Status::NOT_READY {
+                unsafe { *index = i };
+                return if status == Status::SUCCESS { r } else { status };
+            }
+        }
+
+        // Nothing else can signal the events while we wait, so this would block forever.
+        if !fire_next_timer(None) {
+            return Status::NOT_READY;
+        }
+    }
+}
+
+pub(super) extern "efiapi" fn set_timer(
+    event: Event,
+    timer_type: TimerDelay,
+    trigger_time: u64,
+) -> Status {
+    let r = intercept(&Call::SetTimer {
+        event,
+        timer_type,
+        trigger_time,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    with_state(|s| {
+        let now = s.time;
+        let e = match s.events.get_mut(&key(event)) {
+            Some(e) if e.event_type & efi::EVT_TIMER != 0 => e,
+            _ => return Status::INVALID_PARAMETER,
+        };
+
+        e.timer = match timer_type {
+            efi::TIMER_CANCEL => None,
+            efi::TIMER_RELATIVE => Some((now + trigger_time, 0)),
+            // A period of 0 fires on every tick, make it the smallest possible one.
+            efi::TIMER_PERIODIC => Some((now + trigger_time.max(1), trigger_time.max(1))),
+            _ => return Status::INVALID_PARAMETER,
+        };
+        r
+    })
+}
+
+pub(super) extern "efiapi" fn raise_tpl(new_tpl: Tpl) -> Tpl {
+    with_state(|s| core::mem::replace(&mut s.tpl, new_tpl))
+}
+
+pub(super) extern "efiapi" fn restore_tpl(old_tpl: Tpl) {
+    with_state(|s| s.tpl = old_tpl)
+}
@@ -0,0 +1,728 @@
+//! Mock implementation of the Simple File System and File protocols.
+//!
+//! Each volume is an in-memory tree, which is lost when the mock is dropped. Names are compared
+//! without regard to ASCII case, like on FAT. Every file handle is a separate boxed
+//! `file::Protocol`, whose address identifies the open file.
+//!
+//! The `*Ex` functions complete the request before returning: they store the status in the token,
+//! signal its event and return `EFI_SUCCESS`. The hook sees them as the plain calls, e.g. `ReadEx`
+//! as `FileRead`.
+
+use std::boxed::Box;
+use std::vec::Vec;
+
+use core::ffi::c_void;
+
+use r_efi::efi::{Guid, Handle, Status, Time};
+use r_efi::protocols::{file, simple_file_system};
+
+use super::{events, intercept, protocols, read_cstr16, with_state, Call, State};
+use crate::protocols::file::{
+    FileAttribute, FileInfo, FileInfoFields, FileSystemInfo, FileSystemInfoFields,
+    FileSystemVolumeLabel, InfoType,
+};
+use crate::string::CString16;
+
+const SEPARATOR: u16 = b'\\' as u16;
+const VOLUME_SIZE: u64 = 16 << 20;
+const BLOCK_SIZE: u32 = 512;
+
+type Path = Vec<Vec<u16>>;
+
+pub(super) struct Volume {
+    handle: Handle,
+    protocol: Box<simple_file_system::Protocol>,
+    /// The root directory comes first, and parents always before their children.
+    entries: Vec<Entry>,
+    label: Vec<u16>,
+}
+
+struct Entry {
+    path: Path,
+    data: Vec<u8>,
+    attribute: u64,
+    create_time: Time,
+    last_access_time: Time,
+    modification_time: Time,
+}
+
+pub(super) struct OpenFile {
+    _protocol: Box<file::Protocol>,
+    volume: usize,
+    path: Path,
+    mode: u64,
+    /// The byte offset in a file, or the index of the next entry in a directory.
+    position: u64,
+}
+
+impl Entry {
+    fn new(path: Path, attribute: u64, time: Time) -> Self {
+        Self {
+            path,
+            data: Vec::new(),
+            attribute,
+            create_time: time,
+            last_access_time: time,
+            modification_time: time,
+        }
+    }
+
+    fn is_directory(&self) -> bool {
+        self.attribute & file::DIRECTORY != 0
+    }
+
+    fn info(&self) -> Vec<u8> {
+        let name = self.path.last().map(|x| x.as_slice()).unwrap_or(&[]);
+        let name = CString16::from_u16_lossy(name);
+        let fields = FileInfoFields {
+            file_size: self.data.len() as u64,
+            physical_size: (self.data.len() as u64).next_multiple_of(u64::from(BLOCK_SIZE)),
+            create_time: self.create_time,
+            last_access_time: self.last_access_time,
+            modification_time: self.modification_time,
+            attribute: FileAttribute::from_bits(self.attribute),
+        };
+        let mut buffer = std::vec![0; FileInfo::required_size(&name)];
+        FileInfo::new_in(&mut buffer, &fields, &name).unwrap();
+        buffer
+    }
+}
+
+impl Volume {
+    fn find(&self, path: &[Vec<u16>]) -> Option<usize> {
+        self.entries.iter().position(|x| same_path(&x.path, path))
+    }
+
+    fn children<'a>(&'a self, path: &'a [Vec<u16>]) -> impl Iterator<Item = &'a Entry> + 'a {
+        self.entries.iter().filter(move |x| {
+            x.path.len() == path.len() + 1 && same_path(&x.path[..path.len()], path)
+        })
+    }
+
+    fn system_info(&self) -> Vec<u8> {
+        let label = CString16::from_u16_lossy(&self.label);
+        let used: u64 = self.entries.iter().map(|x| x.data.len() as u64).sum();
+        let fields = FileSystemInfoFields {
+            read_only: false,
+            volume_size: VOLUME_SIZE,
+            free_space: VOLUME_SIZE.saturating_sub(used),
+            block_size: BLOCK_SIZE,
+        };
+        let mut buffer = std::vec![0; FileSystemInfo::required_size(&label)];
+        FileSystemInfo::new_in(&mut buffer, &fields, &label).unwrap();
+        buffer
+    }
+}
+
+fn same_name(a: &[u16], b: &[u16]) -> bool {
+    fn upper(c: u16) -> u16 {
+        if (u16::from(b'a')..=u16::from(b'z')).contains(&c) {
+            c - 0x20
+        } else {
+            c
+        }
+    }
+    a.len() == b.len() && a.iter().zip(b).all(|(x, y)| upper(*x) == upper(*y))
+}
+
+fn same_path(a: &[Vec<u16>], b: &[Vec<u16>]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(x, y)| same_name(x, y))
+}
+
+/// Resolve `name` against `base`. `None` if it goes above the root.
+fn resolve(base: &[Vec<u16>], name: &[u16]) -> Option<Path> {
+    let mut path = if name.first() == Some(&SEPARATOR) {
+        Vec::new()
+    } else {
+        base.to_vec()
+    };
+    for component in name.split(|c| *c == SEPARATOR) {
+        match component {
+            [] | [0x2e] => {}
+            [0x2e, 0x2e] => {
+                path.pop()?;
+            }
+            x => path.push(x.to_vec()),
+        }
+    }
+    Some(path)
+}
+
+fn key(this: *mut file::Protocol) -> usize {
+    this as usize
+}
+
+pub(super) fn install(files: &[(&str, &[u8])]) -> Handle {
+    let time = with_state(|s| s.rtc);
+    let mut entries = std::vec![Entry::new(Vec::new(), file::DIRECTORY, time)];
+    for (name, data) in files {
+        let name: Vec<u16> = name.encode_utf16().collect();
+        let path = resolve(&[], &name).expect("path above the root");
+        for i in 1..path.len() {
+            if !entries.iter().any(|x| same_path(&x.path, &path[..i])) {
+                entries.push(Entry::new(path[..i].to_vec(), file::DIRECTORY, time));
+            }
+        }
+        let mut entry = Entry::new(path, file::ARCHIVE, time);
+        entry.data = data.to_vec();
+        entries.push(entry);
+    }
+
+    let mut protocol = Box::new(simple_file_system::Protocol {
+        revision: simple_file_system::REVISION,
+        open_volume,
+    });
+    let handle = protocols::install(
+        core::ptr::null_mut(),
+        &simple_file_system::PROTOCOL_GUID,
+        (&mut *protocol as *mut simple_file_system::Protocol).cast(),
+    )
+    .unwrap();
+
+    with_state(|s| {
+        s.volumes.push(Volume {
+            handle,
+            protocol,
+            entries,
+            label: "MOCK".encode_utf16().collect(),
+        })
+    });
+    handle
+}
+
+/// The content of the file at `path` on the volume installed on `handle`, if any.
+pub(super) fn data(handle: Handle, path: &str) -> Option<Vec<u8>> {
+    let name: Vec<u16> = path.encode_utf16().collect();
+    let path = resolve(&[], &name)?;
+    with_state(|s| {
+        let v = s.volumes.iter().find(|x| x.handle == handle)?;
+        let e = &v.entries[v.find(&path)?];
+        (!e.is_directory()).then(|| e.data.clone())
+    })
+}
+
+fn new_file(s: &mut State, volume: usize, path: Path, mode: u64) -> *mut file::Protocol {
+    let mut protocol = Box::new(file::Protocol {
+        revision: file::REVISION2,
+        open: file_open,
+        close: file_close,
+        delete: file_delete,
+        read: file_read,
+        write: file_write,
+        get_position: file_get_position,
+        set_position: file_set_position,
+        get_info: file_get_info,
+        set_info: file_set_info,
+        flush: file_flush,
+        open_ex: file_open_ex,
+        read_ex: file_read_ex,
+        write_ex: file_write_ex,
+        flush_ex: file_flush_ex,
+    });
+    let ptr: *mut file::Protocol = &mut *protocol;
+    s.files.insert(
+        key(ptr),
+        OpenFile {
+            _protocol: protocol,
+            volume,
+            path,
+            mode,
+            position: 0,
+        },
+    );
+    ptr
+}
+
+/// Run `f` with the open file `this` and its volume. Fails with `EFI_INVALID_PARAMETER` if the
+/// handle is not open, or `EFI_DEVICE_ERROR` if the file was deleted through another handle.
+fn with_file(
+    this: *mut file::Protocol,
+    f: impl FnOnce(&mut OpenFile, &mut Volume, usize) -> Status,
+) -> Status {
+    with_state(|s| {
+        let Some(file) = s.files.get_mut(&key(this)) else {
+            return Status::INVALID_PARAMETER;
+        };
+        let volume = &mut s.volumes[file.volume];
+        match volume.find(&file.path) {
+            Some(index) => f(file, volume, index),
+            None => Status::DEVICE_ERROR,
+        }
+    })
+}
+
+extern "efiapi" fn open_volume(
+    this: *mut simple_file_system::Protocol,
+    root: *mut *mut file::Protocol,
+) -> Status {
+    if root.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let r = intercept(&Call::OpenVolume);
+    if r.is_error() {
+        return r;
+    }
+
+    with_state(|s| {
+        let Some(volume) = s
+            .volumes
+            .iter()
+            .position(|x| core::ptr::eq(&*x.protocol, this))
+        else {
+            return Status::INVALID_PARAMETER;
+        };
+        let mode = file::MODE_READ | file::MODE_WRITE;
+        unsafe { *root = new_file(s, volume, Vec::new(), mode) };
+        r
+    })
+}
+
+extern "efiapi" fn file_open(
+    this: *mut file::Protocol,
+    new_handle: *mut *mut file::Protocol,
+    name: *mut u16,
+    mode: u64,
+    attributes: u64,
+) -> Status {
+    if new_handle.is_null() || name.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let name = unsafe { read_cstr16(name) };
+    let r = intercept(&Call::FileOpen {
+        name,
+        mode,
+        attributes,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    let read_write = file::MODE_READ | file::MODE_WRITE;
+    if mode != file::MODE_READ && mode != read_write && mode != read_write | file::MODE_CREATE {
+        return Status::INVALID_PARAMETER;
+    }
+
+    with_state(|s| {
+        let Some(parent) = s.files.get(&key(this)) else {
+            return Status::INVALID_PARAMETER;
+        };
+        let index = parent.volume;
+        let volume = &mut s.volumes[index];
+        // Names are relative to the directory, or to the parent of a file.
+        let mut base = parent.path.clone();
+        match volume.find(&base) {
+            Some(x) if volume.entries[x].is_directory() => {}
+            Some(_) => {
+                base.pop();
+            }
+            None => return Status::DEVICE_ERROR,
+        }
+        let Some(path) = resolve(&base, name) else {
+            return Status::NOT_FOUND;
+        };
+
+        match volume.find(&path) {
+            Some(x) => {
+                let entry = &volume.entries[x];
+                if mode & file::MODE_WRITE != 0 && entry.attribute & file::READ_ONLY != 0 {
+                    return Status::ACCESS_DENIED;
+                }
+                let path = entry.path.clone();
+                unsafe { *new_handle = new_file(s, index, path, mode) };
+            }
+            None => {
+                if mode & file::MODE_CREATE == 0 {
+                    return Status::NOT_FOUND;
+                }
+                if attributes & !file::VALID_ATTR != 0 {
+                    return Status::INVALID_PARAMETER;
+                }
+                let parent = volume.find(&path[..path.len() - 1]);
+                if !parent.is_some_and(|x| volume.entries[x].is_directory()) {
+                    return Status::NOT_FOUND;
+                }
+                volume
+                    .entries
+                    .push(Entry::new(path.clone(), attributes, s.rtc));
+                unsafe { *new_handle = new_file(s, index, path, mode) };
+            }
+        }
+        r
+    })
+}
+
+extern "efiapi" fn file_close(this: *mut file::Protocol) -> Status {
+    let r = intercept(&Call::FileClose);
+    match with_state(|s| s.files.remove(&key(this))) {
+        Some(_) => r,
+        None => Status::INVALID_PARAMETER,
+    }
+}
+
+extern "efiapi" fn file_delete(this: *mut file::Protocol) -> Status {
+    let r = intercept(&Call::FileDelete);
+    let deleted = with_state(|s| {
+        let file = s.files.remove(&key(this))?;
+        let volume = &mut s.volumes[file.volume];
+        let index = volume.find(&file.path)?;
+        let deletable = r == Status::SUCCESS
+            && file.mode & file::MODE_WRITE != 0
+            && !file.path.is_empty()
+            && volume.children(&file.path).next().is_none();
+        if deletable {
+            volume.entries.remove(index);
+        }
+        Some(deletable)
+    });
+    match deleted {
+        None => Status::INVALID_PARAMETER,
+        Some(true) => Status::SUCCESS,
+        Some(false) => Status::WARN_DELETE_FAILURE,
+    }
+}
+
+extern "efiapi" fn file_read(
+    this: *mut file::Protocol,
+    buffer_size: *mut usize,
+    buffer: *mut c_void,
+) -> Status {
+    if buffer_size.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let size = unsafe { &mut *buffer_size };
+    let r = intercept(&Call::FileRead { buffer_size: *size });
+    if r.is_error() {
+        return r;
+    }
+    if buffer.is_null() && *size != 0 {
+        return Status::INVALID_PARAMETER;
+    }
+
+    with_file(this, |file, volume, index| {
+        let data = if volume.entries[index].is_directory() {
+            let Some(entry) = volume.children(&file.path).nth(file.position as usize) else {
+                *size = 0;
+                return r;
+            };
+            let info = entry.info();
+            if *size < info.len() {
+                *size = info.len();
+                return Status::BUFFER_TOO_SMALL;
+            }
+            file.position += 1;
+            info
+        } else {
+            let data = &volume.entries[index].data;
+            let Some(rest) = data.get(file.position as usize..) else {
+                return Status::DEVICE_ERROR;
+            };
+            let n = rest.len().min(*size);
+            file.position += n as u64;
+            rest[..n].to_vec()
+        };
+        *size = data.len();
+        unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), buffer.cast(), data.len()) };
+        r
+    })
+}
+
+extern "efiapi" fn file_write(
+    this: *mut file::Protocol,
+    buffer_size: *mut usize,
+    buffer: *mut c_void,
+) -> Status {
+    if buffer_size.is_null() || (buffer.is_null() && unsafe { *buffer_size } != 0) {
+        return Status::INVALID_PARAMETER;
+    }
+    let size = unsafe { &mut *buffer_size };
+    let data: &[u8] = if *size == 0 {
+        &[]
+    } else {
+        unsafe { core::slice::from_raw_parts(buffer.cast(), *size) }
+    };
+    let r = intercept(&Call::FileWrite { data });
+    if r.is_error() {
+        *size = 0;
+        return r;
+    }
+
+    let time = with_state(|s| s.rtc);
+    with_file(this, |file, volume, index| {
+        let entry = &mut volume.entries[index];
+        if entry.is_directory() {
+            return Status::UNSUPPORTED;
+        }
+        if file.mode & file::MODE_WRITE == 0 {
+            return Status::ACCESS_DENIED;
+        }
+        let start = file.position as usize;
+        let end = start + data.len();
+        if entry.data.len() < end {
+            entry.data.resize(end, 0);
+        }
+        entry.data[start..end].copy_from_slice(data);
+        entry.modification_time = time;
+        file.position = end as u64;
+        r
+    })
+}
+
+extern "efiapi" fn file_get_position(this: *mut file::Protocol, position: *mut u64) -> Status {
+    if position.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let r = intercept(&Call::FileGetPosition);
+    if r.is_error() {
+        return r;
+    }
+
+    with_file(this, |file, volume, index| {
+        if volume.entries[index].is_directory() {
+            return Status::UNSUPPORTED;
+        }
+        unsafe { *position = file.position };
+        r
+    })
+}
+
+extern "efiapi" fn file_set_position(this: *mut file::Protocol, position: u64) -> Status {
+    let r = intercept(&Call::FileSetPosition { position });
+    if r.is_error() {
+        return r;
+    }
+
+    with_file(this, |file, volume, index| {
+        let entry = &volume.entries[index];
+        file.position = match position {
+            0 => 0,
+            _ if entry.is_directory() => return Status::UNSUPPORTED,
+            u64::MAX => entry.data.len() as u64,
+            x => x,
+        };
+        r
+    })
+}
+
+extern "efiapi" fn file_get_info(
+    this: *mut file::Protocol,
+    information_type: *mut Guid,
+    buffer_size: *mut usize,
+    buffer: *mut c_void,
+) -> Status {
+    if information_type.is_null() || buffer_size.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let info_type = unsafe { *information_type };
+    let size = unsafe { &mut *buffer_size };
+    let r = intercept(&Call::FileGetInfo {
+        info_type,
+        buffer_size: *size,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    with_file(this, |_, volume, index| {
+        let info = if info_type == file::INFO_ID {
+            volume.entries[index].info()
+        } else if info_type == file::SYSTEM_INFO_ID {
+            volume.system_info()
+        } else if info_type == file::SYSTEM_VOLUME_LABEL_ID {
+            volume
+                .label
+                .iter()
+                .chain(&[0])
+                .flat_map(|x| x.to_le_bytes())
+                .collect()
+        } else {
+            return Status::UNSUPPORTED;
+        };
+        if *size < info.len() {
+            *size = info.len();
+            return Status::BUFFER_TOO_SMALL;
+        }
+        if buffer.is_null() {
+            return Status::INVALID_PARAMETER;
+        }
+        *size = info.len();
+        unsafe { core::ptr::copy_nonoverlapping(info.as_ptr(), buffer.cast(), info.len()) };
+        r
+    })
+}
+
+extern "efiapi" fn file_set_info(
+    this: *mut file::Protocol,
+    information_type: *mut Guid,
+    buffer_size: usize,
+    buffer: *mut c_void,
+) -> Status {
+    if information_type.is_null() || buffer.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let info_type = unsafe { *information_type };
+    let data = unsafe { core::slice::from_raw_parts(buffer.cast::<u8>(), buffer_size) };
+    let r = intercept(&Call::FileSetInfo { info_type, data });
+    if r.is_error() {
+        return r;
+    }
+
+    let mut renamed = None;
+    let status = with_file(this, |file, volume, index| {
+        if file.mode & file::MODE_WRITE == 0 {
+            return Status::ACCESS_DENIED;
+        }
+        if info_type == file::INFO_ID {
+            let Ok(info) = FileInfo::parse(data) else {
+                return Status::BAD_BUFFER_SIZE;
+            };
+            match set_file_info(volume, index, &info) {
+                Ok(x) => {
+                    renamed = x.map(|paths| (file.volume, paths));
+                    Status::SUCCESS
+                }
+                Err(e) => e,
+            }
+        } else if info_type == file::SYSTEM_INFO_ID {
+            let Ok(info) = FileSystemInfo::parse(data) else {
+                return Status::BAD_BUFFER_SIZE;
+            };
+            volume.label = info.volume_label().units().collect();
+            Status::SUCCESS
+        } else if info_type == file::SYSTEM_VOLUME_LABEL_ID {
+            let Ok(info) = FileSystemVolumeLabel::parse(data) else {
+                return Status::BAD_BUFFER_SIZE;
+            };
+            volume.label = info.volume_label().units().collect();
+            Status::SUCCESS
+        } else {
+            Status::UNSUPPORTED
+        }
+    });
+    if status.is_error() {
+        return status;
+    }
+
+    if let Some((volume, (old, new))) = renamed {
+        with_state(|s| {
+            for f in s.files.values_mut() {
+                if f.volume == volume
+                    && f.path.len() >= old.len()
+                    && same_path(&f.path[..old.len()], &old)
+                {
+                    f.path.splice(..old.len(), new.iter().cloned());
+                }
+            }
+        });
+    }
+    r
+}
+
+/// Apply an `EFI_FILE_INFO` to the entry at `index`. A new name renames the entry within its
+/// directory, along with its children. Returns the old and new path if renamed.
+fn set_file_info(
+    volume: &mut Volume,
+    index: usize,
+    info: &FileInfo<'_>,
+) -> Result<Option<(Path, Path)>, Status> {
+    let entry = &volume.entries[index];
+    let attribute = info.attribute().bits();
+    if attribute & !file::VALID_ATTR != 0 {
+        return Err(Status::INVALID_PARAMETER);
+    }
+    if (attribute ^ entry.attribute) & file::DIRECTORY != 0
+        || (entry.is_directory() && info.file_size() != 0)
+    {
+        return Err(Status::ACCESS_DENIED);
+    }
+
+    let name: Vec<u16> = info.file_name().units().collect();
+    if name.is_empty() || name.contains(&SEPARATOR) {
+        return Err(Status::INVALID_PARAMETER);
+    }
+    let old = entry.path.clone();
+    let mut renamed = None;
+    if old.last().is_some_and(|x| !same_name(x, &name)) {
+        let mut new = old.clone();
+        *new.last_mut().unwrap() = name;
+        if volume.find(&new).is_some() {
+            return Err(Status::ACCESS_DENIED);
+        }
+        for e in &mut volume.entries {
+            if e.path.len() >= old.len() && same_path(&e.path[..old.len()], &old) {
+                e.path.splice(..old.len(), new.iter().cloned());
+            }
+        }
+        renamed = Some((old, new));
+    }
+
+    let entry = &mut volume.entries[index];
+    entry.data.resize(info.file_size() as usize, 0);
+    entry.attribute = attribute;
+    entry.create_time = info.create_time();
+    entry.last_access_time = info.last_access_time();
+    entry.modification_time = info.modification_time();
+    Ok(renamed)
+}
+
+extern "efiapi" fn file_flush(this: *mut file::Protocol) -> Status {
+    let r = intercept(&Call::FileFlush);
+    if r.is_error() {
+        return r;
+    }
+
+    with_file(this, |file, _, _| {
+        if file.mode & file::MODE_WRITE == 0 {
+            return Status::ACCESS_DENIED;
+        }
+        r
+    })
+}
+
+/// Store the result of an `*Ex` request in `token` and signal its event.
+fn complete(token: *mut file::IoToken, status: Status) -> Status {
+    let event = unsafe {
+        (*token).status = status;
+        (*token).event
+    };
+    if !event.is_null() {
+        events::signal(event);
+    }
+    Status::SUCCESS
+}
+
+extern "efiapi" fn file_open_ex(
+    this: *mut file::Protocol,
+    new_handle: *mut *mut file::Protocol,
+    name: *mut u16,
+    mode: u64,
+    attributes: u64,
+    token: *mut file::IoToken,
+) -> Status {
+    if token.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    complete(token, file_open(this, new_handle, name, mode, attributes))
+}
+
+extern "efiapi" fn file_read_ex(this: *mut file::Protocol, token: *mut file::IoToken) -> Status {
+    if token.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let status = unsafe { file_read(this, &mut (*token).buffer_size, (*token).buffer) };
+    complete(token, status)
+}
+
+extern "efiapi" fn file_write_ex(this: *mut file::Protocol, token: *mut file::IoToken) -> Status {
+    if token.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let status = unsafe { file_write(this, &mut (*token).buffer_size, (*token).buffer) };
+    complete(token, status)
+}
+
+extern "efiapi" fn file_flush_ex(this: *mut file::Protocol, token: *mut file::IoToken) -> Status {
+    if token.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    complete(token, file_flush(this))
+}
@@ -0,0 +1,134 @@
+//! Mock implementation of the Loaded Image and Shell Parameters protocols.
+//!
+//! The image has no code. Its base points to a zeroed buffer of `IMAGE_SIZE` bytes, and it was
+//! loaded from memory, so its device path is a lone end node.
+
+use std::boxed::Box;
+use std::vec::Vec;
+
+use core::ffi::c_void;
+
+use r_efi::efi::{self, Handle};
+use r_efi::protocols::{device_path, loaded_image, loaded_image_device_path, shell_parameters};
+
+use super::{protocols, with_state};
+
+const IMAGE_SIZE: usize = 0x1000;
+
+/// The device path of every mocked image.
+static END_NODE: [u8; 4] = [
+    device_path::TYPE_END,
+    device_path::End::SUBTYPE_ENTIRE,
+    4,
+    0,
+];
+
+/// The memory backing a loaded image. Boxed so that the pointers stay valid.
+pub(super) struct LoadedImage {
+    handle: Handle,
+    protocol: Box<loaded_image::Protocol>,
+    _options: Vec<u64>,
+    _image: Vec<u64>,
+}
+
+/// The memory backing the shell parameters of an image.
+pub(super) struct ShellParameters {
+    _protocol: Box<shell_parameters::Protocol>,
+    _argv: Vec<*mut u16>,
+    _args: Vec<Vec<u16>>,
+}
+
+pub(super) fn install(options: &[u8]) -> Handle {
+    // Copy into `u64`s so the options are aligned like a pool allocation.
+    let mut buffer = std::vec![0u64; options.len().div_ceil(8)];
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            options.as_ptr(),
+            buffer.as_mut_ptr().cast::<u8>(),
+            options.len(),
+        )
+    };
+    let mut image = std::vec![0u64; IMAGE_SIZE / 8];
+
+    let mut protocol = Box::new(loaded_image::Protocol {
+        revision: loaded_image::REVISION,
+        parent_handle: core::ptr::null_mut(),
+        system_table: with_state(|s| s.system_table),
+        device_handle: core::ptr::null_mut(),
+        file_path: core::ptr::null_mut(),
+        reserved: core::ptr::null_mut(),
+        load_options_size: options.len() as u32,
+        load_options: buffer.as_mut_ptr().cast(),
+        image_base: image.as_mut_ptr().cast(),
+        image_size: IMAGE_SIZE as u64,
+        image_code_type: efi::LOADER_CODE,
+        image_data_type: efi::LOADER_DATA,
+        unload: None,
+    });
+
+    let handle = protocols::install(
+        core::ptr::null_mut(),
+        &loaded_image::PROTOCOL_GUID,
+        (&mut *protocol as *mut loaded_image::Protocol).cast(),
+    )
+    .unwrap();
+    protocols::install(
+        handle,
+        &loaded_image_device_path::PROTOCOL_GUID,
+        END_NODE.as_ptr() as *mut c_void,
+    )
+    .unwrap();
+
+    with_state(|s| {
+        s.loaded_images.push(LoadedImage {
+            handle,
+            protocol,
+            _options: buffer,
+            _image: image,
+        })
+    });
+    handle
+}
+
+pub(super) fn set_device(image: Handle, device: Handle) {
+    with_state(|s| {
+        let image = s
+            .loaded_images
+            .iter_mut()
+            .find(|x| x.handle == image)
+            .expect("not a mocked image");
+        image.protocol.device_handle = device;
+    });
+}
+
+/// Install the Shell Parameters Protocol on `image`, without any standard handles.
+pub(super) fn install_shell_parameters(image: Handle, args: &[&str]) {
+    let mut args: Vec<Vec<u16>> = args
+        .iter()
+        .map(|arg| arg.encode_utf16().chain([0]).collect())
+        .collect();
+    let mut argv: Vec<*mut u16> = args.iter_mut().map(|arg| arg.as_mut_ptr()).collect();
+
+    let mut protocol = Box::new(shell_parameters::Protocol {
+        argv: argv.as_mut_ptr(),
+        argc: argv.len(),
+        std_in: core::ptr::null_mut(),
+        std_out: core::ptr::null_mut(),
+        std_err: core::ptr::null_mut(),
+    });
+
+    protocols::install(
+        image,
+        &shell_parameters::PROTOCOL_GUID,
+        (&mut *protocol as *mut shell_parameters::Protocol).cast(),
+    )
+    .expect("shell parameters already installed");
+
+    with_state(|s| {
+        s.shell_parameters.push(ShellParameters {
+            _protocol: protocol,
+            _argv: argv,
+            _args: args,
+        })
+    });
+}
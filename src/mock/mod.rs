@@ -0,0 +1,824 @@
+//! This module provides an in-process mock of the UEFI firmware.
+//!
+//! `MockSystemTable` builds a fully populated `SystemTable` in host memory. The `BootServices`,
+//! `RuntimeServices`, `con_in` and `con_out` tables point to functions backed by recorded state,
+//! so the wrappers in this crate can be unit-tested with `cargo test` on the host.
+//!
+//! Every call first goes through an optional hook closure (see [`MockSystemTable::set_hook`]).
+//! If the hook returns an error status, the call fails with it without touching the recorded
+//! state. Otherwise the default behaviour runs, and a warning returned by the hook is passed
+//! through to the caller.
+//!
+//! `WaitForEvent` cannot block, since nothing else runs while it waits. If none of the events is
+//! signaled, it advances the time to the next armed timer, or fails with `EFI_NOT_READY` if there
+//! is none.
+//!
+//! The state lives in a thread local, so only one mock can be active per thread. This matches the
+//! way `cargo test` runs each test on its own thread.
+
+mod acpi_tables;
+mod boot_services;
+mod capsules;
+mod console;
+mod device_path_text;
+mod events;
+mod file_system;
+mod loaded_images;
+mod protocols;
+mod runtime_services;
+mod time;
+mod variables;
+
+use std::alloc::Layout;
+use std::boxed::Box;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::string::String;
+use std::vec::Vec;
+
+use core::ffi::c_void;
+use core::marker::PhantomData;
+
+use r_efi::efi::{
+    AllocateType, BootServices, ConfigurationTable, Event, Guid, Handle, LocateSearchType,
+    MemoryDescriptor, MemoryType, OpenProtocolInformationEntry, PhysicalAddress, ResetType,
+    RuntimeServices, Status, SystemTable, TableHeader, Time, TimerDelay, Tpl,
+};
+use r_efi::protocols::{simple_text_input, simple_text_output};
+
+pub use capsules::CapsuleUpdate;
+
+/// A call made into the mock firmware, as seen by the hook closure.
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum Call<'a> {
+    AllocatePool {
+        memory_type: MemoryType,
+        size: usize,
+    },
+    FreePool {
+        buffer: *mut c_void,
+    },
+    AllocatePages {
+        alloc_type: AllocateType,
+        memory_type: MemoryType,
+        pages: usize,
+    },
+    FreePages {
+        memory: PhysicalAddress,
+        pages: usize,
+    },
+    GetMemoryMap {
+        size: usize,
+    },
+    CreateEvent {
+        event_type: u32,
+        notify_tpl: Tpl,
+        event_group: Option<Guid>,
+    },
+    CloseEvent {
+        event: Event,
+    },
+    SignalEvent {
+        event: Event,
+    },
+    CheckEvent {
+        event: Event,
+    },
+    WaitForEvent {
+        events: &'a [Event],
+    },
+    SetTimer {
+        event: Event,
+        timer_type: TimerDelay,
+        trigger_time: u64,
+    },
+    InstallProtocolInterface {
+        handle: Handle,
+        protocol: Guid,
+        interface: *mut c_void,
+    },
+    UninstallProtocolInterface {
+        handle: Handle,
+        protocol: Guid,
+        interface: *mut c_void,
+    },
+    ReinstallProtocolInterface {
+        handle: Handle,
+        protocol: Guid,
+        old_interface: *mut c_void,
+        new_interface: *mut c_void,
+    },
+    HandleProtocol {
+        handle: Handle,
+        protocol: Guid,
+    },
+    OpenProtocol {
+        handle: Handle,
+        protocol: Guid,
+        agent_handle: Handle,
+        controller_handle: Handle,
+        attributes: u32,
+    },
+    CloseProtocol {
+        handle: Handle,
+        protocol: Guid,
+        agent_handle: Handle,
+        controller_handle: Handle,
+    },
+    OpenProtocolInformation {
+        handle: Handle,
+        protocol: Guid,
+    },
+    ProtocolsPerHandle {
+        handle: Handle,
+    },
+    LocateHandle {
+        search_type: LocateSearchType,
+    },
+    LocateProtocol {
+        protocol: Guid,
+    },
+    LocateDevicePath {
+        protocol: Guid,
+    },
+    RegisterProtocolNotify {
+        protocol: Guid,
+        event: Event,
+    },
+    LoadImage {
+        boot_policy: bool,
+        parent_image_handle: Handle,
+        source_size: usize,
+    },
+    StartImage {
+        image_handle: Handle,
+    },
+    UnloadImage {
+        image_handle: Handle,
+    },
+    InstallConfigurationTable {
+        guid: Guid,
+        table: *mut c_void,
+    },
+    ExitBootServices {
+        image_handle: Handle,
+        map_key: usize,
+    },
+    GetVariable {
+        name: &'a [u16],
+        vendor: Guid,
+        data_size: usize,
+    },
+    GetNextVariableName {
+        name_size: usize,
+    },
+    SetVariable {
+        name: &'a [u16],
+        vendor: Guid,
+        attributes: u32,
+        data: &'a [u8],
+    },
+    QueryVariableInfo {
+        attributes: u32,
+    },
+    GetTime,
+    SetTime {
+        time: Time,
+    },
+    GetWakeupTime,
+    SetWakeupTime {
+        enable: bool,
+        time: Option<Time>,
+    },
+    GetNextHighMonotonicCount,
+    GetNextMonotonicCount,
+    Stall {
+        microseconds: usize,
+    },
+    SetVirtualAddressMap {
+        memory_map_size: usize,
+        descriptor_size: usize,
+        descriptor_version: u32,
+    },
+    ConvertPointer {
+        debug_disposition: usize,
+        address: *mut c_void,
+    },
+    UpdateCapsule {
+        capsule_count: usize,
+        scatter_gather_list: PhysicalAddress,
+    },
+    QueryCapsuleCapabilities {
+        capsule_count: usize,
+    },
+    ResetSystem {
+        reset_type: ResetType,
+        status: Status,
+        data: &'a [u8],
+    },
+    Exit {
+        image_handle: Handle,
+        status: Status,
+        data: &'a [u16],
+    },
+    InstallAcpiTable {
+        table: &'a [u8],
+    },
+    UninstallAcpiTable {
+        table_key: usize,
+    },
+    ConvertDeviceNodeToText {
+        node: &'a [u8],
+        display_only: bool,
+        allow_shortcuts: bool,
+    },
+    ConvertDevicePathToText {
+        path: &'a [u8],
+        display_only: bool,
+        allow_shortcuts: bool,
+    },
+    ConvertTextToDeviceNode {
+        text: &'a [u16],
+    },
+    ConvertTextToDevicePath {
+        text: &'a [u16],
+    },
+    OpenVolume,
+    FileOpen {
+        name: &'a [u16],
+        mode: u64,
+        attributes: u64,
+    },
+    FileClose,
+    FileDelete,
+    FileRead {
+        buffer_size: usize,
+    },
+    FileWrite {
+        data: &'a [u8],
+    },
+    FileGetPosition,
+    FileSetPosition {
+        position: u64,
+    },
+    FileGetInfo {
+        info_type: Guid,
+        buffer_size: usize,
+    },
+    FileSetInfo {
+        info_type: Guid,
+        data: &'a [u8],
+    },
+    FileFlush,
+    ConInReset {
+        extended_verification: bool,
+    },
+    ReadKeyStroke,
+    ConOutReset {
+        extended_verification: bool,
+    },
+    OutputString(&'a [u16]),
+    TestString(&'a [u16]),
+    QueryMode {
+        mode_number: usize,
+    },
+    SetMode {
+        mode_number: usize,
+    },
+    SetAttribute {
+        attribute: usize,
+    },
+    ClearScreen,
+    SetCursorPosition {
+        column: usize,
+        row: usize,
+    },
+    EnableCursor {
+        visible: bool,
+    },
+}
+
+/// Arguments passed to `Exit`, as recorded by the mock.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExitRecord {
+    pub image_handle: Handle,
+    pub status: Status,
+    pub data: Vec<u16>,
+}
+
+/// Arguments passed to `ResetSystem`, as recorded by the mock.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResetRecord {
+    pub reset_type: ResetType,
+    pub status: Status,
+    /// The reset data, as raw bytes.
+    pub data: Vec<u8>,
+}
+
+type Hook = Box<dyn FnMut(&Call<'_>) -> Status>;
+type ImageEntry = Box<dyn FnMut(Handle) -> Status>;
+
+pub(crate) struct State {
+    hook: Option<Hook>,
+    pool: HashMap<usize, Layout>,
+    pages: HashMap<PhysicalAddress, usize>,
+    memory_map: Vec<MemoryDescriptor>,
+    descriptor_size: usize,
+    map_key: usize,
+    tpl: Tpl,
+    events: HashMap<usize, events::MockEvent>,
+    next_event: usize,
+    time: u64,
+    interfaces: Vec<protocols::Interface>,
+    open: Vec<protocols::OpenRecord>,
+    notifies: Vec<protocols::Notify>,
+    next_handle: usize,
+    /// Loaded images, and whether they were started.
+    images: HashMap<usize, bool>,
+    image_entry: Option<ImageEntry>,
+    boot_services_exited: bool,
+    variables: Vec<variables::Variable>,
+    rtc: Time,
+    wakeup: Option<Time>,
+    wakeup_enabled: bool,
+    monotonic_count: u64,
+    keys: VecDeque<simple_text_input::InputKey>,
+    console_out: Vec<u16>,
+    exit: Option<ExitRecord>,
+    reset: Option<ResetRecord>,
+    capsule_updates: Vec<CapsuleUpdate>,
+    /// The map passed to `SetVirtualAddressMap`, once called.
+    virtual_map: Option<Vec<MemoryDescriptor>>,
+    /// The array pointed to by the system table.
+    configuration_tables: Vec<ConfigurationTable>,
+    system_table: *mut SystemTable,
+    /// Tables installed through the ACPI Table Protocol, with their keys.
+    acpi_tables: Vec<(usize, Vec<u8>)>,
+    next_acpi_table_key: usize,
+    loaded_images: Vec<loaded_images::LoadedImage>,
+    shell_parameters: Vec<loaded_images::ShellParameters>,
+    volumes: Vec<file_system::Volume>,
+    /// Open file handles, by the address of their protocol.
+    files: HashMap<usize, file_system::OpenFile>,
+}
+
+thread_local! {
+    static STATE: RefCell<Option<State>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with the state of the mock active on this thread.
+/// Panics if no `MockSystemTable` is alive.
+pub(crate) fn with_state<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        let state = s
+            .as_mut()
+            .expect("mock firmware called without a live MockSystemTable");
+        f(state)
+    })
+}
+
+/// Pass `call` to the hook, if any, and return its verdict.
+/// The hook is taken out of the state while it runs, so it may call back into the mock.
+pub(crate) fn intercept(call: &Call<'_>) -> Status {
+    let hook = with_state(|s| s.hook.take());
+    match hook {
+        Some(mut hook) => {
+            let r = hook(call);
+            with_state(|s| {
+                if s.hook.is_none() {
+                    s.hook = Some(hook);
+                }
+            });
+            r
+        }
+        None => Status::SUCCESS,
+    }
+}
+
+/// The tables handed out to the code under test. They are boxed so that the pointers between
+/// them stay valid for the lifetime of the mock.
+struct Tables {
+    st: SystemTable,
+    bs: BootServices,
+    rs: RuntimeServices,
+    con_in: simple_text_input::Protocol,
+    con_out: simple_text_output::Protocol,
+    mode: simple_text_output::Mode,
+    vendor: [u16; 5],
+}
+
+/// A fake `SystemTable` living in host memory.
+pub struct MockSystemTable {
+    tables: Box<Tables>,
+    // The state is thread local, so the mock must not leave its thread.
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl MockSystemTable {
+    /// Create a new mock and make it the active one on this thread.
+    pub fn new() -> Self {
+        STATE.with(|s| {
+            *s.borrow_mut() = Some(State {
+                hook: None,
+                pool: HashMap::new(),
+                pages: HashMap::new(),
+                memory_map: boot_services::default_memory_map(),
+                descriptor_size: core::mem::size_of::<MemoryDescriptor>(),
+                map_key: 1,
+                tpl: r_efi::efi::TPL_APPLICATION,
+                events: HashMap::new(),
+                next_event: 0,
+                time: 0,
+                interfaces: Vec::new(),
+                open: Vec::new(),
+                notifies: Vec::new(),
+                next_handle: 0,
+                images: HashMap::new(),
+                image_entry: None,
+                boot_services_exited: false,
+                variables: Vec::new(),
+                rtc: time::default_time(),
+                wakeup: None,
+                wakeup_enabled: false,
+                monotonic_count: 0,
+                keys: VecDeque::new(),
+                console_out: Vec::new(),
+                exit: None,
+                reset: None,
+                capsule_updates: Vec::new(),
+                virtual_map: None,
+                configuration_tables: Vec::new(),
+                system_table: core::ptr::null_mut(),
+                acpi_tables: Vec::new(),
+                next_acpi_table_key: 0,
+                loaded_images: Vec::new(),
+                shell_parameters: Vec::new(),
+                volumes: Vec::new(),
+                files: HashMap::new(),
+            })
+        });
+
+        let mut tables = Box::new(Tables {
+            st: SystemTable {
+                hdr: table_header::<SystemTable>(r_efi::efi::SYSTEM_TABLE_SIGNATURE),
+                firmware_vendor: core::ptr::null_mut(),
+                firmware_revision: 0x0001_0000,
+                console_in_handle: core::ptr::null_mut(),
+                con_in: core::ptr::null_mut(),
+                console_out_handle: core::ptr::null_mut(),
+                con_out: core::ptr::null_mut(),
+                standard_error_handle: core::ptr::null_mut(),
+                std_err: core::ptr::null_mut(),
+                runtime_services: core::ptr::null_mut(),
+                boot_services: core::ptr::null_mut(),
+                number_of_table_entries: 0,
+                configuration_table: core::ptr::null_mut(),
+            },
+            bs: boot_services::table(),
+            rs: runtime_services::table(),
+            con_in: console::con_in(),
+            con_out: console::con_out(),
+            mode: console::mode(),
+            // "Mock"
+            vendor: [0x004d, 0x006f, 0x0063, 0x006b, 0x0000],
+        });
+
+        let t = &mut *tables;
+        t.st.firmware_vendor = t.vendor.as_mut_ptr();
+        t.st.con_in = &mut t.con_in;
+        t.st.con_out = &mut t.con_out;
+        t.st.std_err = &mut t.con_out;
+        t.st.runtime_services = &mut t.rs;
+        t.st.boot_services = &mut t.bs;
+        t.con_out.mode = &mut t.mode;
+        with_state(|s| s.system_table = &mut t.st);
+
+        Self {
+            tables,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// The pointer to pass to the functions of this crate. It stays valid as long as `self` is
+    /// alive.
+    pub fn system_table(&mut self) -> *mut SystemTable {
+        &mut self.tables.st
+    }
+
+    /// Direct access to the `BootServices` table, e.g. to replace a function pointer or null out
+    /// an entry.
+    pub fn boot_services(&mut self) -> &mut BootServices {
+        &mut self.tables.bs
+    }
+
+    /// Direct access to the `RuntimeServices` table.
+    pub fn runtime_services(&mut self) -> &mut RuntimeServices {
+        &mut self.tables.rs
+    }
+
+    /// Direct access to the `Mode` of `con_out`.
+    pub fn console_mode(&mut self) -> &mut simple_text_output::Mode {
+        &mut self.tables.mode
+    }
+
+    /// Install a closure that sees every call before the default behaviour runs.
+    pub fn set_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(&Call<'_>) -> Status + 'static,
+    {
+        with_state(|s| s.hook = Some(Box::new(hook)));
+    }
+
+    /// Remove the hook installed with `set_hook`.
+    pub fn clear_hook(&mut self) {
+        with_state(|s| s.hook = None);
+    }
+
+    /// Queue a key to be returned by `ReadKeyStroke`.
+    pub fn push_key(&mut self, key: simple_text_input::InputKey) {
+        with_state(|s| s.keys.push_back(key));
+    }
+
+    /// Everything written through `OutputString` so far, as raw UCS-2.
+    pub fn console_output_raw(&self) -> Vec<u16> {
+        with_state(|s| s.console_out.clone())
+    }
+
+    /// Everything written through `OutputString` so far. Invalid characters are replaced.
+    pub fn console_output(&self) -> String {
+        String::from_utf16_lossy(&self.console_output_raw())
+    }
+
+    /// Forget everything written through `OutputString` so far.
+    pub fn clear_console_output(&mut self) {
+        with_state(|s| s.console_out.clear());
+    }
+
+    /// Set the entry point run by `StartImage` for every image. It may call `Exit`, in which case
+    /// `StartImage` returns the status and data passed to it. Without an entry point, images
+    /// return `EFI_SUCCESS` right away.
+    pub fn set_image_entry<F>(&mut self, entry: F)
+    where
+        F: FnMut(Handle) -> Status + 'static,
+    {
+        with_state(|s| s.image_entry = Some(Box::new(entry)));
+    }
+
+    /// Number of images which were loaded and not unloaded yet.
+    pub fn loaded_images(&self) -> usize {
+        with_state(|s| s.images.len())
+    }
+
+    /// Whether `ExitBootServices` succeeded.
+    pub fn boot_services_exited(&self) -> bool {
+        with_state(|s| s.boot_services_exited)
+    }
+
+    /// Create or replace a variable as if done by the firmware. Panics if `SetVariable` would
+    /// reject it.
+    pub fn set_variable(&mut self, name: &str, vendor: &Guid, attributes: u32, data: &[u8]) {
+        let name: Vec<u16> = name.encode_utf16().collect();
+        assert_eq!(
+            variables::set(&name, vendor, attributes, data),
+            Status::SUCCESS
+        );
+    }
+
+    /// The attributes and data of a variable, if it exists.
+    pub fn variable(&self, name: &str, vendor: &Guid) -> Option<(u32, Vec<u8>)> {
+        let name: Vec<u16> = name.encode_utf16().collect();
+        variables::get(&name, vendor)
+    }
+
+    /// Install, replace or, if `table` is null, remove a configuration table as if done by the
+    /// firmware. Panics if a table to remove is not installed.
+    pub fn install_configuration_table(&mut self, guid: &Guid, table: *mut c_void) {
+        assert_eq!(boot_services::install_table(guid, table), Status::SUCCESS);
+    }
+
+    /// Install the ACPI Table Protocol on a new handle.
+    pub fn install_acpi_table_protocol(&mut self) {
+        acpi_tables::install_protocol();
+    }
+
+    /// The tables installed through the ACPI Table Protocol and not uninstalled yet.
+    pub fn acpi_tables(&self) -> Vec<Vec<u8>> {
+        acpi_tables::tables()
+    }
+
+    /// Install the Device Path To Text and Device Path From Text protocols on a new handle.
+    pub fn install_device_path_text_protocols(&mut self) {
+        device_path_text::install_protocols();
+    }
+
+    /// Install the Loaded Image and Loaded Image Device Path protocols on a new image handle, as
+    /// done by the firmware before calling the entry point of an image. `options` become the
+    /// load options.
+    pub fn install_loaded_image(&mut self, options: &[u8]) -> Handle {
+        loaded_images::install(options)
+    }
+
+    /// Install the Shell Parameters Protocol on `image`, as done by the shell before starting an
+    /// application. Panics if it is already installed.
+    pub fn install_shell_parameters(&mut self, image: Handle, args: &[&str]) {
+        loaded_images::install_shell_parameters(image, args);
+    }
+
+    /// Set the device an image was loaded from, as reported by its Loaded Image Protocol.
+    pub fn set_image_device(&mut self, image: Handle, device: Handle) {
+        loaded_images::set_device(image, device);
+    }
+
+    /// Install the Simple File System Protocol on a new handle, backed by an in-memory volume
+    /// holding `files`. Paths are separated by `\`, and missing directories are created.
+    pub fn install_file_system(&mut self, files: &[(&str, &[u8])]) -> Handle {
+        file_system::install(files)
+    }
+
+    /// The content of the file at `path` on the volume installed on `handle`, if it exists.
+    pub fn file_data(&self, handle: Handle, path: &str) -> Option<Vec<u8>> {
+        file_system::data(handle, path)
+    }
+
+    /// Number of file handles which have not been closed yet.
+    pub fn open_files(&self) -> usize {
+        with_state(|s| s.files.len())
+    }
+
+    /// Arguments of the last call to `Exit`, if any.
+    pub fn exit_record(&self) -> Option<ExitRecord> {
+        with_state(|s| s.exit.clone())
+    }
+
+    /// Arguments of the last call to `ResetSystem`, if any.
+    pub fn reset_record(&self) -> Option<ResetRecord> {
+        with_state(|s| s.reset.clone())
+    }
+
+    /// The virtual map passed to a successful `SetVirtualAddressMap`, if any.
+    pub fn virtual_address_map(&self) -> Option<Vec<MemoryDescriptor>> {
+        with_state(|s| s.virtual_map.clone())
+    }
+
+    /// Every successful call to `UpdateCapsule` so far.
+    pub fn capsule_updates(&self) -> Vec<CapsuleUpdate> {
+        with_state(|s| s.capsule_updates.clone())
+    }
+
+    /// Number of pool allocations which have not been freed yet.
+    pub fn outstanding_pool_allocations(&self) -> usize {
+        with_state(|s| s.pool.len())
+    }
+
+    /// Number of page allocations which have not been freed yet.
+    pub fn outstanding_page_allocations(&self) -> usize {
+        with_state(|s| s.pages.len())
+    }
+
+    /// Replace the descriptors returned by `GetMemoryMap`. This changes the map key.
+    pub fn set_memory_map(&mut self, map: Vec<MemoryDescriptor>) {
+        with_state(|s| {
+            s.memory_map = map;
+            s.map_key += 1;
+        });
+    }
+
+    /// Set the `DescriptorSize` reported by `GetMemoryMap`. Real firmware often reports a larger
+    /// size than `size_of::<MemoryDescriptor>()`.
+    pub fn set_descriptor_size(&mut self, size: usize) {
+        assert!(size >= core::mem::size_of::<MemoryDescriptor>());
+        with_state(|s| s.descriptor_size = size);
+    }
+
+    /// The current map key.
+    pub fn map_key(&self) -> usize {
+        with_state(|s| s.map_key)
+    }
+
+    /// The current task priority level.
+    pub fn tpl(&self) -> Tpl {
+        with_state(|s| s.tpl)
+    }
+
+    /// Number of events which have been created and not closed yet.
+    pub fn open_events(&self) -> usize {
+        with_state(|s| s.events.len())
+    }
+
+    /// Signal `event` as if done by the firmware or another agent.
+    pub fn signal_event(&mut self, event: Event) {
+        assert_eq!(events::signal(event), Status::SUCCESS);
+    }
+
+    /// The time seen by timers, in units of 100ns since the mock was created.
+    pub fn time(&self) -> u64 {
+        with_state(|s| s.time)
+    }
+
+    /// Install a protocol interface as if done by the firmware or a driver. A new handle is
+    /// created if `handle` is `None`.
+    pub fn install_protocol(
+        &mut self,
+        handle: Option<Handle>,
+        protocol: &Guid,
+        interface: *mut c_void,
+    ) -> Handle {
+        protocols::install(handle.unwrap_or(core::ptr::null_mut()), protocol, interface)
+            .expect("protocol already installed on the handle")
+    }
+
+    /// The agents which have `protocol` on `handle` open.
+    pub fn open_protocol_information(
+        &self,
+        handle: Handle,
+        protocol: &Guid,
+    ) -> Vec<OpenProtocolInformationEntry> {
+        protocols::open_information(handle, protocol)
+    }
+
+    /// Advance the time by `delta`, firing all timers which expire in between.
+    pub fn advance_time(&mut self, delta: u64) {
+        events::advance(delta);
+    }
+
+    /// The time returned by `GetTime`. The clock does not tick on its own.
+    pub fn rtc(&self) -> Time {
+        with_state(|s| s.rtc)
+    }
+
+    /// Set the time returned by `GetTime`, as if done by the firmware.
+    pub fn set_rtc(&mut self, time: Time) {
+        with_state(|s| s.rtc = time);
+    }
+
+    /// The wakeup alarm set with `SetWakeupTime`, if enabled.
+    pub fn wakeup_time(&self) -> Option<Time> {
+        with_state(|s| s.wakeup.filter(|_| s.wakeup_enabled))
+    }
+
+    /// The current value of the monotonic counter.
+    pub fn monotonic_count(&self) -> u64 {
+        with_state(|s| s.monotonic_count)
+    }
+}
+
+impl Default for MockSystemTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for MockSystemTable {
+    fn drop(&mut self) {
+        let state = STATE.with(|s| s.borrow_mut().take());
+        if let Some(state) = state {
+            for (ptr, layout) in state.pool {
+                unsafe { std::alloc::dealloc(ptr as *mut u8, layout) };
+            }
+            for (addr, pages) in state.pages {
+                unsafe {
+                    std::alloc::dealloc(addr as usize as *mut u8, boot_services::page_layout(pages))
+                };
+            }
+        }
+    }
+}
+
+fn table_header<T>(signature: u64) -> TableHeader {
+    TableHeader {
+        signature,
+        revision: r_efi::efi::SYSTEM_TABLE_REVISION,
+        header_size: core::mem::size_of::<T>() as u32,
+        crc32: 0,
+        reserved: 0,
+    }
+}
+
+/// The event behind `con_in.wait_for_key`. It is signaled whenever a key is queued.
+static KEY_EVENT: u8 = 0;
+
+pub(crate) fn key_event() -> Event {
+    &KEY_EVENT as *const u8 as Event
+}
+
+/// Append a descriptor to the memory map, as an allocation splitting a free region would. This
+/// changes the map key. Unlike `MockSystemTable::set_memory_map`, it can be called from a hook.
+#[cfg(test)]
+pub(crate) fn push_memory_descriptor(desc: MemoryDescriptor) {
+    with_state(|s| {
+        s.memory_map.push(desc);
+        s.map_key += 1;
+    });
+}
+
+/// Read a NUL-terminated UCS-2 string, not including the NUL.
+/// SAFETY: `ptr` must point to a NUL-terminated string.
+pub(crate) unsafe fn read_cstr16<'a>(ptr: *const u16) -> &'a [u16] {
+    let mut len = 0;
+    while unsafe { *ptr.add(len) } != 0 {
+        len += 1;
+    }
+    unsafe { core::slice::from_raw_parts(ptr, len) }
+}
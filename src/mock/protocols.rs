@@ -0,0 +1,689 @@
+//! Mock implementations of the protocol handler services.
+//!
+//! Handles are created by installing the first protocol interface on them and disappear with the
+//! last one. The open information and notify registrations are tracked like real firmware does,
+//! but interfaces installed with `OPEN_PROTOCOL_BY_DRIVER` are not connected to anything.
+
+use std::boxed::Box;
+use std::collections::VecDeque;
+use std::vec::Vec;
+
+use core::ffi::c_void;
+
+use r_efi::efi::{
+    self, Event, Guid, Handle, InterfaceType, LocateSearchType, OpenProtocolInformationEntry,
+    Status,
+};
+use r_efi::protocols::device_path;
+
+use super::{boot_services, events, intercept, with_state, Call, State};
+
+pub(crate) struct Interface {
+    handle: Handle,
+    // Boxed so that the pointers returned by `ProtocolsPerHandle` stay valid.
+    guid: Box<Guid>,
+    interface: *mut c_void,
+}
+
+pub(crate) struct OpenRecord {
+    handle: Handle,
+    guid: Guid,
+    entry: OpenProtocolInformationEntry,
+}
+
+pub(crate) struct Notify {
+    guid: Guid,
+    event: Event,
+    key: usize,
+    pending: VecDeque<Handle>,
+}
+
+impl State {
+    fn find(&self, handle: Handle, guid: &Guid) -> Option<usize> {
+        self.interfaces
+            .iter()
+            .position(|x| x.handle == handle && *x.guid == *guid)
+    }
+
+    fn handle_exists(&self, handle: Handle) -> bool {
+        self.interfaces.iter().any(|x| x.handle == handle)
+    }
+
+    /// Unique handles in installation order, optionally only those supporting `guid`.
+    fn handles(&self, guid: Option<&Guid>) -> Vec<Handle> {
+        let mut r: Vec<Handle> = Vec::new();
+        for x in &self.interfaces {
+            if guid.is_some_and(|g| *x.guid != *g) || r.contains(&x.handle) {
+                continue;
+            }
+            r.push(x.handle);
+        }
+        r
+    }
+
+    /// Queue `handle` for the registrations of `guid` and return the events to signal.
+    fn notify(&mut self, handle: Handle, guid: &Guid) -> Vec<Event> {
+        self.notifies
+            .iter_mut()
+            .filter(|n| n.guid == *guid)
+            .map(|n| {
+                n.pending.push_back(handle);
+                n.event
+            })
+            .collect()
+    }
+}
+
+pub(super) fn install(
+    handle: Handle,
+    guid: &Guid,
+    interface: *mut c_void,
+) -> Result<Handle, Status> {
+    let (handle, to_signal) = with_state(|s| {
+        let handle = if handle.is_null() {
+            s.next_handle += 1;
+            (0xa4d0_0000usize + s.next_handle * 8) as Handle
+        } else if !s.handle_exists(handle) || s.find(handle, guid).is_some() {
+            return Err(Status::INVALID_PARAMETER);
+        } else {
+            handle
+        };
+
+        s.interfaces.push(Interface {
+            handle,
+            guid: Box::new(*guid),
+            interface,
+        });
+        Ok((handle, s.notify(handle, guid)))
+    })?;
+
+    for event in to_signal {
+        events::signal(event);
+    }
+    Ok(handle)
+}
+
+/// Copy `items` into a new pool allocation.
+fn to_pool<T: Copy>(items: &[T]) -> *mut T {
+    let ptr = boot_services::pool_alloc(core::mem::size_of_val(items)).cast::<T>();
+    unsafe { core::ptr::copy_nonoverlapping(items.as_ptr(), ptr, items.len()) };
+    ptr
+}
+
+/// Length in bytes of a device path, without the end node.
+/// SAFETY: `path` must point to a valid device path.
+unsafe fn device_path_len(path: *const device_path::Protocol) -> usize {
+    let mut len = 0;
+    loop {
+        let node = unsafe { &*path.cast::<u8>().add(len).cast::<device_path::Protocol>() };
+        if node.r#type == device_path::TYPE_END {
+            return len;
+        }
+        len += u16::from_le_bytes(node.length) as usize;
+    }
+}
+
+pub(super) fn open_information(handle: Handle, guid: &Guid) -> Vec<OpenProtocolInformationEntry> {
+    with_state(|s| {
+        s.open
+            .iter()
+            .filter(|x| x.handle == handle && x.guid == *guid)
+            .map(|x| x.entry)
+            .collect()
+    })
+}
+
+pub(super) extern "efiapi" fn install_protocol_interface(
+    handle: *mut Handle,
+    protocol: *mut Guid,
+    interface_type: InterfaceType,
+    interface: *mut c_void,
+) -> Status {
+    if handle.is_null() || protocol.is_null() || interface_type != efi::NATIVE_INTERFACE {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let guid = unsafe { *protocol };
+    let r = intercept(&Call::InstallProtocolInterface {
+        handle: unsafe { *handle },
+        protocol: guid,
+        interface,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    match install(unsafe { *handle }, &guid, interface) {
+        Ok(x) => {
+            unsafe { *handle = x };
+            r
+        }
+        Err(x) => x,
+    }
+}
+
+pub(super) extern "efiapi" fn uninstall_protocol_interface(
+    handle: Handle,
+    protocol: *mut Guid,
+    interface: *mut c_void,
+) -> Status {
+    if protocol.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let guid = unsafe { *protocol };
+    let r = intercept(&Call::UninstallProtocolInterface {
+        handle,
+        protocol: guid,
+        interface,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    with_state(|s| {
+        let i = match s.find(handle, &guid) {
+            Some(i) if s.interfaces[i].interface == interface => i,
+            _ => return Status::NOT_FOUND,
+        };
+        let by_driver = efi::OPEN_PROTOCOL_BY_DRIVER | efi::OPEN_PROTOCOL_EXCLUSIVE;
+        if s.open
+            .iter()
+            .any(|x| x.handle == handle && x.guid == guid && x.entry.attributes & by_driver != 0)
+        {
+            return Status::ACCESS_DENIED;
+        }
+
+        s.interfaces.remove(i);
+        s.open.retain(|x| x.handle != handle || x.guid != guid);
+        r
+    })
+}
+
+pub(super) extern "efiapi" fn reinstall_protocol_interface(
+    handle: Handle,
+    protocol: *mut Guid,
+    old_interface: *mut c_void,
+    new_interface: *mut c_void,
+) -> Status {
+    if protocol.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let guid = unsafe { *protocol };
+    let r = intercept(&Call::ReinstallProtocolInterface {
+        handle,
+        protocol: guid,
+        old_interface,
+        new_interface,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    let to_signal = with_state(|s| match s.find(handle, &guid) {
+        Some(i) if s.interfaces[i].interface == old_interface => {
+            s.interfaces[i].interface = new_interface;
+            Some(s.notify(handle, &guid))
+        }
+        _ => None,
+    });
+
+    match to_signal {
+        Some(x) => {
+            for event in x {
+                events::signal(event);
+            }
+            r
+        }
+        None => Status::NOT_FOUND,
+    }
+}
+
+pub(super) extern "efiapi" fn handle_protocol(
+    handle: Handle,
+    protocol: *mut Guid,
+    interface: *mut *mut c_void,
+) -> Status {
+    if protocol.is_null() || interface.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let guid = unsafe { *protocol };
+    let r = intercept(&Call::HandleProtocol {
+        handle,
+        protocol: guid,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    match with_state(|s| s.find(handle, &guid).map(|i| s.interfaces[i].interface)) {
+        Some(x) => {
+            unsafe { *interface = x };
+            r
+        }
+        None => Status::UNSUPPORTED,
+    }
+}
+
+pub(super) extern "efiapi" fn open_protocol(
+    handle: Handle,
+    protocol: *mut Guid,
+    interface: *mut *mut c_void,
+    agent_handle: Handle,
+    controller_handle: Handle,
+    attributes: u32,
+) -> Status {
+    if protocol.is_null() || (interface.is_null() && attributes != efi::OPEN_PROTOCOL_TEST_PROTOCOL)
+    {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let guid = unsafe { *protocol };
+    let r = intercept(&Call::OpenProtocol {
+        handle,
+        protocol: guid,
+        agent_handle,
+        controller_handle,
+        attributes,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    with_state(|s| {
+        let found = match s.find(handle, &guid) {
+            Some(i) => s.interfaces[i].interface,
+            None => return Status::UNSUPPORTED,
+        };
+        if attributes == efi::OPEN_PROTOCOL_TEST_PROTOCOL {
+            return r;
+        }
+        unsafe { *interface = found };
+
+        let by_driver = efi::OPEN_PROTOCOL_BY_DRIVER | efi::OPEN_PROTOCOL_EXCLUSIVE;
+        if attributes & by_driver != 0 {
+            let conflict = s.open.iter().find(|x| {
+                x.handle == handle && x.guid == guid && x.entry.attributes & by_driver != 0
+            });
+            if let Some(x) = conflict {
+                return if x.entry.agent_handle == agent_handle {
+                    Status::ALREADY_STARTED
+                } else {
+                    Status::ACCESS_DENIED
+                };
+            }
+        }
+
+        let existing = s.open.iter_mut().find(|x| {
+            x.handle == handle
+                && x.guid == guid
+                && x.entry.agent_handle == agent_handle
+                && x.entry.controller_handle == controller_handle
+                && x.entry.attributes == attributes
+        });
+        match existing {
+            Some(x) => x.entry.open_count += 1,
+            None => s.open.push(OpenRecord {
+                handle,
+                guid,
+                entry: OpenProtocolInformationEntry {
+                    agent_handle,
+                    controller_handle,
+                    attributes,
+                    open_count: 1,
+                },
+            }),
+        }
+        r
+    })
+}
+
+pub(super) extern "efiapi" fn close_protocol(
+    handle: Handle,
+    protocol: *mut Guid,
+    agent_handle: Handle,
+    controller_handle: Handle,
+) -> Status {
+    if protocol.is_null() || agent_handle.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let guid = unsafe { *protocol };
+    let r = intercept(&Call::CloseProtocol {
+        handle,
+        protocol: guid,
+        agent_handle,
+        controller_handle,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    with_state(|s| {
+        if s.find(handle, &guid).is_none() {
+            return Status::NOT_FOUND;
+        }
+
+        let before = s.open.len();
+        s.open.retain(|x| {
+            x.handle != handle
+                || x.guid != guid
+                || x.entry.agent_handle != agent_handle
+                || x.entry.controller_handle != controller_handle
+        });
+        if s.open.len() == before {
+            Status::NOT_FOUND
+        } else {
+            r
+        }
+    })
+}
+
+pub(super) extern "efiapi" fn open_protocol_information(
+    handle: Handle,
+    protocol: *mut Guid,
+    entry_buffer: *mut *mut OpenProtocolInformationEntry,
+    entry_count: *mut usize,
+) -> Status {
+    if protocol.is_null() || entry_buffer.is_null() || entry_count.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let guid = unsafe { *protocol };
+    let r = intercept(&Call::OpenProtocolInformation {
+        handle,
+        protocol: guid,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    let entries = with_state(|s| {
+        s.find(handle, &guid)?;
+        Some(
+            s.open
+                .iter()
+                .filter(|x| x.handle == handle && x.guid == guid)
+                .map(|x| x.entry)
+                .collect::<Vec<_>>(),
+        )
+    });
+    let entries = match entries {
+        Some(x) => x,
+        None => return Status::NOT_FOUND,
+    };
+
+    unsafe {
+        *entry_buffer = to_pool(&entries);
+        *entry_count = entries.len();
+    }
+    r
+}
+
+pub(super) extern "efiapi" fn protocols_per_handle(
+    handle: Handle,
+    protocol_buffer: *mut *mut *mut Guid,
+    protocol_buffer_count: *mut usize,
+) -> Status {
+    if protocol_buffer.is_null() || protocol_buffer_count.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let r = intercept(&Call::ProtocolsPerHandle { handle });
+    if r.is_error() {
+        return r;
+    }
+
+    let guids: Vec<*mut Guid> = with_state(|s| {
+        s.interfaces
+            .iter_mut()
+            .filter(|x| x.handle == handle)
+            .map(|x| &mut *x.guid as *mut Guid)
+            .collect()
+    });
+    if guids.is_empty() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    unsafe {
+        *protocol_buffer = to_pool(&guids);
+        *protocol_buffer_count = guids.len();
+    }
+    r
+}
+
+/// The handles matching a search, shared by `LocateHandle` and `LocateHandleBuffer`. For
+/// `BY_REGISTER_NOTIFY` the handle is only dequeued if `consume` is set.
+fn search(
+    search_type: LocateSearchType,
+    protocol: *mut Guid,
+    search_key: *mut c_void,
+    consume: bool,
+) -> Result<Vec<Handle>, Status> {
+    let handles = match search_type {
+        efi::ALL_HANDLES => with_state(|s| s.handles(None)),
+        efi::BY_PROTOCOL if !protocol.is_null() => {
+            with_state(|s| s.handles(Some(unsafe { &*protocol })))
+        }
+        efi::BY_REGISTER_NOTIFY => with_state(|s| {
+            let n = s
+                .notifies
+                .iter_mut()
+                .find(|n| n.key == search_key as usize)
+                .ok_or(Status::INVALID_PARAMETER)?;
+            let next = if consume {
+                n.pending.pop_front()
+            } else {
+                n.pending.front().copied()
+            };
+            Ok::<_, Status>(next.into_iter().collect())
+        })?,
+        _ => return Err(Status::INVALID_PARAMETER),
+    };
+
+    if handles.is_empty() {
+        Err(Status::NOT_FOUND)
+    } else {
+        Ok(handles)
+    }
+}
+
+pub(super) extern "efiapi" fn locate_handle(
+    search_type: LocateSearchType,
+    protocol: *mut Guid,
+    search_key: *mut c_void,
+    buffer_size: *mut usize,
+    buffer: *mut Handle,
+) -> Status {
+    if buffer_size.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let r = intercept(&Call::LocateHandle { search_type });
+    if r.is_error() {
+        return r;
+    }
+
+    let size = unsafe { *buffer_size };
+    let needed = match search(search_type, protocol, search_key, false) {
+        Ok(x) => x.len() * core::mem::size_of::<Handle>(),
+        Err(x) => return x,
+    };
+    unsafe { *buffer_size = needed };
+    if size < needed {
+        return Status::BUFFER_TOO_SMALL;
+    }
+    if buffer.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let handles = match search(search_type, protocol, search_key, true) {
+        Ok(x) => x,
+        Err(x) => return x,
+    };
+    unsafe { core::ptr::copy_nonoverlapping(handles.as_ptr(), buffer, handles.len()) };
+    r
+}
+
+pub(super) extern "efiapi" fn locate_handle_buffer(
+    search_type: LocateSearchType,
+    protocol: *mut Guid,
+    search_key: *mut c_void,
+    no_handles: *mut usize,
+    buffer: *mut *mut Handle,
+) -> Status {
+    if no_handles.is_null() || buffer.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let r = intercept(&Call::LocateHandle { search_type });
+    if r.is_error() {
+        return r;
+    }
+
+    let handles = match search(search_type, protocol, search_key, true) {
+        Ok(x) => x,
+        Err(x) => return x,
+    };
+    unsafe {
+        *buffer = to_pool(&handles);
+        *no_handles = handles.len();
+    }
+    r
+}
+
+pub(super) extern "efiapi" fn locate_protocol(
+    protocol: *mut Guid,
+    registration: *mut c_void,
+    interface: *mut *mut c_void,
+) -> Status {
+    if protocol.is_null() || interface.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let guid = unsafe { *protocol };
+    let r = intercept(&Call::LocateProtocol { protocol: guid });
+    if r.is_error() {
+        return r;
+    }
+
+    let found = with_state(|s| {
+        if registration.is_null() {
+            return s
+                .interfaces
+                .iter()
+                .find(|x| *x.guid == guid)
+                .map(|x| x.interface);
+        }
+
+        let handle = s
+            .notifies
+            .iter_mut()
+            .find(|n| n.key == registration as usize)?
+            .pending
+            .pop_front()?;
+        s.find(handle, &guid).map(|i| s.interfaces[i].interface)
+    });
+
+    match found {
+        Some(x) => {
+            unsafe { *interface = x };
+            r
+        }
+        None => {
+            unsafe { *interface = core::ptr::null_mut() };
+            Status::NOT_FOUND
+        }
+    }
+}
+
+pub(super) extern "efiapi" fn locate_device_path(
+    protocol: *mut Guid,
+    device_path: *mut *mut device_path::Protocol,
+    device: *mut Handle,
+) -> Status {
+    if protocol.is_null() || device_path.is_null() || unsafe { *device_path }.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let guid = unsafe { *protocol };
+    let r = intercept(&Call::LocateDevicePath { protocol: guid });
+    if r.is_error() {
+        return r;
+    }
+
+    let path = unsafe { *device_path };
+    let path_bytes =
+        unsafe { core::slice::from_raw_parts(path.cast::<u8>(), device_path_len(path)) };
+
+    let candidates: Vec<(Handle, *mut c_void)> = with_state(|s| {
+        s.handles(Some(&guid))
+            .into_iter()
+            .filter_map(|h| {
+                let i = s.find(h, &device_path::PROTOCOL_GUID)?;
+                Some((h, s.interfaces[i].interface))
+            })
+            .collect()
+    });
+
+    // Both paths consist of whole nodes, so a byte prefix is also a node prefix.
+    let best = candidates
+        .into_iter()
+        .filter_map(|(h, p)| {
+            let p = p.cast::<device_path::Protocol>();
+            let len = unsafe { device_path_len(p) };
+            let bytes = unsafe { core::slice::from_raw_parts(p.cast::<u8>(), len) };
+            path_bytes.starts_with(bytes).then_some((h, len))
+        })
+        .max_by_key(|&(_, len)| len);
+
+    match best {
+        Some((h, len)) => {
+            if device.is_null() {
+                return Status::INVALID_PARAMETER;
+            }
+            unsafe {
+                *device = h;
+                *device_path = path.cast::<u8>().add(len).cast();
+            }
+            r
+        }
+        None => Status::NOT_FOUND,
+    }
+}
+
+pub(super) extern "efiapi" fn register_protocol_notify(
+    protocol: *mut Guid,
+    event: Event,
+    registration: *mut *mut c_void,
+) -> Status {
+    if protocol.is_null() || registration.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let guid = unsafe { *protocol };
+    let r = intercept(&Call::RegisterProtocolNotify {
+        protocol: guid,
+        event,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    let key = with_state(|s| {
+        s.next_handle += 1;
+        let key = 0x7e60_0000usize + s.next_handle * 8;
+        s.notifies.push(Notify {
+            guid,
+            event,
+            key,
+            pending: VecDeque::new(),
+        });
+        key
+    });
+    unsafe { *registration = key as *mut c_void };
+
+    r
+}
@@ -0,0 +1,166 @@
+//! Mock implementations of the `RuntimeServices` table.
+
+use std::vec::Vec;
+
+use core::ffi::c_void;
+
+use r_efi::efi::{self, MemoryDescriptor, ResetType, RuntimeServices, Status};
+
+use super::capsules::{query_capsule_capabilities, update_capsule};
+use super::time::{get_next_high_mono_count, get_time, get_wakeup_time, set_time, set_wakeup_time};
+use super::variables::{get_next_variable_name, get_variable, query_variable_info, set_variable};
+use super::{events, intercept, with_state, Call, ResetRecord};
+
+pub(super) fn table() -> RuntimeServices {
+    RuntimeServices {
+        hdr: super::table_header::<RuntimeServices>(efi::RUNTIME_SERVICES_SIGNATURE),
+        get_time,
+        set_time,
+        get_wakeup_time,
+        set_wakeup_time,
+        set_virtual_address_map,
+        convert_pointer,
+        get_variable,
+        get_next_variable_name,
+        set_variable,
+        get_next_high_mono_count,
+        reset_system,
+        update_capsule,
+        query_capsule_capabilities,
+        query_variable_info,
+    }
+}
+
+/// The virtual map is checked against the memory map and recorded, but the tables of the mock
+/// stay at their host addresses. `EVT_SIGNAL_VIRTUAL_ADDRESS_CHANGE` events are signaled after
+/// the map is recorded, so they can use `ConvertPointer`.
+extern "efiapi" fn set_virtual_address_map(
+    memory_map_size: usize,
+    descriptor_size: usize,
+    descriptor_version: u32,
+    virtual_map: *mut MemoryDescriptor,
+) -> Status {
+    let r = intercept(&Call::SetVirtualAddressMap {
+        memory_map_size,
+        descriptor_size,
+        descriptor_version,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    if !with_state(|s| s.boot_services_exited && s.virtual_map.is_none()) {
+        return Status::UNSUPPORTED;
+    }
+    if descriptor_size < core::mem::size_of::<MemoryDescriptor>()
+        || descriptor_version != efi::MEMORY_DESCRIPTOR_VERSION
+        || virtual_map.is_null()
+    {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let map: Vec<MemoryDescriptor> = (0..memory_map_size / descriptor_size)
+        .map(|i| unsafe {
+            core::ptr::read_unaligned((virtual_map as *const u8).add(i * descriptor_size).cast())
+        })
+        .collect();
+    if map.iter().any(|x| x.attribute & efi::MEMORY_RUNTIME == 0) {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let to_signal = with_state(|s| {
+        let missing = s
+            .memory_map
+            .iter()
+            .filter(|x| x.attribute & efi::MEMORY_RUNTIME != 0)
+            .any(|x| !map.iter().any(|y| y.physical_start == x.physical_start));
+        if missing {
+            return None;
+        }
+        s.virtual_map = Some(map);
+        Some(events::group_events(
+            s,
+            efi::EVT_SIGNAL_VIRTUAL_ADDRESS_CHANGE,
+            &efi::EVENT_GROUP_VIRTUAL_ADDRESS_CHANGE,
+        ))
+    });
+
+    match to_signal {
+        Some(x) => {
+            for event in x {
+                events::signal(event);
+            }
+            r
+        }
+        None => Status::NO_MAPPING,
+    }
+}
+
+extern "efiapi" fn convert_pointer(debug_disposition: usize, address: *mut *mut c_void) -> Status {
+    if address.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let ptr = unsafe { *address };
+
+    let r = intercept(&Call::ConvertPointer {
+        debug_disposition,
+        address: ptr,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    if ptr.is_null() {
+        return if debug_disposition & efi::OPTIONAL_POINTER as usize != 0 {
+            r
+        } else {
+            Status::INVALID_PARAMETER
+        };
+    }
+
+    let converted = with_state(|s| {
+        let map = s.virtual_map.as_ref().ok_or(Status::UNSUPPORTED)?;
+        map.iter()
+            .find_map(|x| {
+                let size = x.number_of_pages * super::boot_services::PAGE_SIZE as u64;
+                let offset = (ptr as u64).checked_sub(x.physical_start)?;
+                (offset < size).then_some(x.virtual_start + offset)
+            })
+            .ok_or(Status::NOT_FOUND)
+    });
+    match converted {
+        Ok(x) => {
+            unsafe { *address = x as *mut c_void };
+            r
+        }
+        Err(e) => e,
+    }
+}
+
+/// Resets are recorded rather than performed, and return to the caller.
+extern "efiapi" fn reset_system(
+    reset_type: ResetType,
+    status: Status,
+    data_size: usize,
+    data: *mut c_void,
+) {
+    let data = if data.is_null() || data_size == 0 {
+        Vec::new()
+    } else {
+        unsafe { core::slice::from_raw_parts(data as *const u8, data_size) }.to_vec()
+    };
+
+    intercept(&Call::ResetSystem {
+        reset_type,
+        status,
+        data: &data,
+    });
+
+    with_state(|s| {
+        s.reset = Some(ResetRecord {
+            reset_type,
+            status,
+            data,
+        })
+    });
+}
@@ -0,0 +1,151 @@
+//! Mock implementations of the time services.
+//!
+//! The real time clock is a plain stored `EFI_TIME`, it does not tick. `SetTime` checks the
+//! ranges of the fields, but not the number of days in the month.
+
+use r_efi::efi::{self, Boolean, Status, Time, TimeCapabilities};
+
+use super::{intercept, with_state, Call};
+
+/// 2000-01-01 00:00:00 in an unspecified timezone.
+pub(super) fn default_time() -> Time {
+    Time {
+        year: 2000,
+        month: 1,
+        day: 1,
+        hour: 0,
+        minute: 0,
+        second: 0,
+        pad1: 0,
+        nanosecond: 0,
+        timezone: efi::UNSPECIFIED_TIMEZONE,
+        daylight: 0,
+        pad2: 0,
+    }
+}
+
+fn is_valid(t: &Time) -> bool {
+    (1900..=9999).contains(&t.year)
+        && (1..=12).contains(&t.month)
+        && (1..=31).contains(&t.day)
+        && t.hour < 24
+        && t.minute < 60
+        && t.second < 60
+        && t.nanosecond < 1_000_000_000
+        && ((-1440..=1440).contains(&t.timezone) || t.timezone == efi::UNSPECIFIED_TIMEZONE)
+        && t.daylight & !(efi::TIME_ADJUST_DAYLIGHT | efi::TIME_IN_DAYLIGHT) == 0
+}
+
+pub(super) extern "efiapi" fn get_time(
+    time: *mut Time,
+    capabilities: *mut TimeCapabilities,
+) -> Status {
+    if time.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let r = intercept(&Call::GetTime);
+    if r.is_error() {
+        return r;
+    }
+
+    unsafe { *time = with_state(|s| s.rtc) };
+    if !capabilities.is_null() {
+        unsafe {
+            *capabilities = TimeCapabilities {
+                resolution: 1,
+                accuracy: 50_000_000,
+                sets_to_zero: Boolean::FALSE,
+            }
+        };
+    }
+    r
+}
+
+pub(super) extern "efiapi" fn set_time(time: *mut Time) -> Status {
+    if time.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let time = unsafe { *time };
+
+    let r = intercept(&Call::SetTime { time });
+    if r.is_error() {
+        return r;
+    }
+
+    if !is_valid(&time) {
+        return Status::INVALID_PARAMETER;
+    }
+    with_state(|s| s.rtc = time);
+    r
+}
+
+pub(super) extern "efiapi" fn get_wakeup_time(
+    enabled: *mut Boolean,
+    pending: *mut Boolean,
+    time: *mut Time,
+) -> Status {
+    if enabled.is_null() || pending.is_null() || time.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let r = intercept(&Call::GetWakeupTime);
+    if r.is_error() {
+        return r;
+    }
+
+    let (wakeup, wakeup_enabled) = with_state(|s| (s.wakeup, s.wakeup_enabled));
+    unsafe {
+        *enabled = Boolean::from(wakeup_enabled);
+        *pending = Boolean::FALSE;
+        // Like real firmware, the time is left zeroed if the alarm was never programmed.
+        *time = wakeup.unwrap_or_default();
+    }
+    r
+}
+
+pub(super) extern "efiapi" fn set_wakeup_time(enable: Boolean, time: *mut Time) -> Status {
+    let enable = bool::from(enable);
+    let time = if time.is_null() {
+        None
+    } else {
+        Some(unsafe { *time })
+    };
+
+    let r = intercept(&Call::SetWakeupTime { enable, time });
+    if r.is_error() {
+        return r;
+    }
+
+    if enable {
+        match time {
+            Some(t) if is_valid(&t) => with_state(|s| {
+                s.wakeup = Some(t);
+                s.wakeup_enabled = true;
+            }),
+            _ => return Status::INVALID_PARAMETER,
+        }
+    } else {
+        with_state(|s| s.wakeup_enabled = false);
+    }
+    r
+}
+
+pub(super) extern "efiapi" fn get_next_high_mono_count(high_count: *mut u32) -> Status {
+    if high_count.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let r = intercept(&Call::GetNextHighMonotonicCount);
+    if r.is_error() {
+        return r;
+    }
+
+    let high = with_state(|s| {
+        let high = ((s.monotonic_count >> 32) as u32).wrapping_add(1);
+        s.monotonic_count = u64::from(high) << 32;
+        high
+    });
+    unsafe { *high_count = high };
+    r
+}
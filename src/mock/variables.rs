@@ -0,0 +1,299 @@
+//! Mock implementations of the variable services.
+//!
+//! Variables are kept in creation order, which is the order `GetNextVariableName` returns them
+//! in. The storage is limited to `VARIABLE_STORAGE_SIZE` bytes of names and data. Authenticated
+//! variables are not supported. After `ExitBootServices`, variables without
+//! `EFI_VARIABLE_RUNTIME_ACCESS` are hidden.
+
+use std::vec::Vec;
+
+use core::ffi::c_void;
+
+use r_efi::efi::{self, Char16, Guid, Status};
+
+use super::{intercept, read_cstr16, with_state, Call, State};
+
+/// Total size of the variable store.
+pub(crate) const VARIABLE_STORAGE_SIZE: u64 = 0x10000;
+/// Largest single variable, name and data included.
+pub(crate) const MAXIMUM_VARIABLE_SIZE: u64 = 0x8000;
+
+const AUTHENTICATED: u32 = efi::VARIABLE_AUTHENTICATED_WRITE_ACCESS
+    | efi::VARIABLE_TIME_BASED_AUTHENTICATED_WRITE_ACCESS
+    | efi::VARIABLE_ENHANCED_AUTHENTICATED_ACCESS;
+
+pub(crate) struct Variable {
+    /// Without the NUL.
+    name: Vec<u16>,
+    vendor: Guid,
+    attributes: u32,
+    data: Vec<u8>,
+}
+
+impl Variable {
+    fn size(&self) -> u64 {
+        ((self.name.len() + 1) * 2 + self.data.len()) as u64
+    }
+}
+
+impl State {
+    fn visible(&self, v: &Variable) -> bool {
+        !self.boot_services_exited || v.attributes & efi::VARIABLE_RUNTIME_ACCESS != 0
+    }
+
+    fn find_variable(&self, name: &[u16], vendor: &Guid) -> Option<usize> {
+        self.variables
+            .iter()
+            .position(|v| v.name == name && v.vendor == *vendor && self.visible(v))
+    }
+
+    fn used_variable_storage(&self) -> u64 {
+        self.variables.iter().map(Variable::size).sum()
+    }
+}
+
+/// Create, replace, append to or delete a variable. The attributes are checked like
+/// `SetVariable` does.
+pub(crate) fn set(name: &[u16], vendor: &Guid, attributes: u32, data: &[u8]) -> Status {
+    if name.is_empty() {
+        return Status::INVALID_PARAMETER;
+    }
+    if attributes & AUTHENTICATED != 0 {
+        return Status::UNSUPPORTED;
+    }
+    let append = attributes & efi::VARIABLE_APPEND_WRITE != 0;
+    let attributes = attributes & !efi::VARIABLE_APPEND_WRITE;
+    if attributes & efi::VARIABLE_RUNTIME_ACCESS != 0
+        && attributes & efi::VARIABLE_BOOTSERVICE_ACCESS == 0
+    {
+        return Status::INVALID_PARAMETER;
+    }
+
+    with_state(|s| {
+        if s.boot_services_exited
+            && attributes != 0
+            && attributes & efi::VARIABLE_RUNTIME_ACCESS == 0
+        {
+            return Status::INVALID_PARAMETER;
+        }
+
+        let index = s.find_variable(name, vendor);
+        if attributes == 0 || (data.is_empty() && !append) {
+            return match index {
+                Some(i) => {
+                    s.variables.remove(i);
+                    Status::SUCCESS
+                }
+                None => Status::NOT_FOUND,
+            };
+        }
+
+        let used = s.used_variable_storage();
+        match index {
+            Some(i) => {
+                let v = &mut s.variables[i];
+                if v.attributes != attributes {
+                    return Status::INVALID_PARAMETER;
+                }
+                let new_len = if append {
+                    v.data.len() + data.len()
+                } else {
+                    data.len()
+                } as u64;
+                let old_len = v.data.len() as u64;
+                let size = v.size() - old_len + new_len;
+                if size > MAXIMUM_VARIABLE_SIZE || used - old_len + new_len > VARIABLE_STORAGE_SIZE
+                {
+                    return Status::OUT_OF_RESOURCES;
+                }
+                if !append {
+                    v.data.clear();
+                }
+                v.data.extend_from_slice(data);
+            }
+            None => {
+                let v = Variable {
+                    name: name.to_vec(),
+                    vendor: *vendor,
+                    attributes,
+                    data: data.to_vec(),
+                };
+                if v.size() > MAXIMUM_VARIABLE_SIZE || used + v.size() > VARIABLE_STORAGE_SIZE {
+                    return Status::OUT_OF_RESOURCES;
+                }
+                s.variables.push(v);
+            }
+        }
+        Status::SUCCESS
+    })
+}
+
+/// The attributes and data of a variable, if it exists.
+pub(crate) fn get(name: &[u16], vendor: &Guid) -> Option<(u32, Vec<u8>)> {
+    with_state(|s| {
+        s.find_variable(name, vendor).map(|i| {
+            let v = &s.variables[i];
+            (v.attributes, v.data.clone())
+        })
+    })
+}
+
+pub(super) extern "efiapi" fn get_variable(
+    variable_name: *mut Char16,
+    vendor_guid: *mut Guid,
+    attributes: *mut u32,
+    data_size: *mut usize,
+    data: *mut c_void,
+) -> Status {
+    if variable_name.is_null() || vendor_guid.is_null() || data_size.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let name = unsafe { read_cstr16(variable_name) };
+    let vendor = unsafe { *vendor_guid };
+    let size = unsafe { *data_size };
+
+    let r = intercept(&Call::GetVariable {
+        name,
+        vendor,
+        data_size: size,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    let (attr, value) = match get(name, &vendor) {
+        Some(x) => x,
+        None => return Status::NOT_FOUND,
+    };
+    unsafe { *data_size = value.len() };
+    if size < value.len() {
+        return Status::BUFFER_TOO_SMALL;
+    }
+    if data.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    unsafe { core::ptr::copy_nonoverlapping(value.as_ptr(), data.cast(), value.len()) };
+    if !attributes.is_null() {
+        unsafe { *attributes = attr };
+    }
+    r
+}
+
+pub(super) extern "efiapi" fn get_next_variable_name(
+    variable_name_size: *mut usize,
+    variable_name: *mut Char16,
+    vendor_guid: *mut Guid,
+) -> Status {
+    if variable_name_size.is_null() || variable_name.is_null() || vendor_guid.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+    let size = unsafe { *variable_name_size };
+
+    let r = intercept(&Call::GetNextVariableName { name_size: size });
+    if r.is_error() {
+        return r;
+    }
+
+    // The previous name must be terminated within the buffer.
+    let buffer = unsafe { core::slice::from_raw_parts(variable_name, size / 2) };
+    let name = match buffer.iter().position(|&c| c == 0) {
+        Some(len) => &buffer[..len],
+        None => return Status::INVALID_PARAMETER,
+    };
+    let vendor = unsafe { *vendor_guid };
+
+    let next = with_state(|s| {
+        let start = if name.is_empty() {
+            0
+        } else {
+            s.find_variable(name, &vendor)
+                .ok_or(Status::INVALID_PARAMETER)?
+                + 1
+        };
+        s.variables[start..]
+            .iter()
+            .find(|v| s.visible(v))
+            .map(|v| (v.name.clone(), v.vendor))
+            .ok_or(Status::NOT_FOUND)
+    });
+    let (next_name, next_vendor) = match next {
+        Ok(x) => x,
+        Err(e) => return e,
+    };
+
+    let needed = (next_name.len() + 1) * 2;
+    unsafe { *variable_name_size = needed };
+    if size < needed {
+        return Status::BUFFER_TOO_SMALL;
+    }
+
+    unsafe {
+        core::ptr::copy_nonoverlapping(next_name.as_ptr(), variable_name, next_name.len());
+        *variable_name.add(next_name.len()) = 0;
+        *vendor_guid = next_vendor;
+    }
+    r
+}
+
+pub(super) extern "efiapi" fn set_variable(
+    variable_name: *mut Char16,
+    vendor_guid: *mut Guid,
+    attributes: u32,
+    data_size: usize,
+    data: *mut c_void,
+) -> Status {
+    if variable_name.is_null() || vendor_guid.is_null() || (data.is_null() && data_size != 0) {
+        return Status::INVALID_PARAMETER;
+    }
+    let name = unsafe { read_cstr16(variable_name) };
+    let vendor = unsafe { *vendor_guid };
+    let data: &[u8] = if data_size == 0 {
+        &[]
+    } else {
+        unsafe { core::slice::from_raw_parts(data.cast(), data_size) }
+    };
+
+    let r = intercept(&Call::SetVariable {
+        name,
+        vendor,
+        attributes,
+        data,
+    });
+    if r.is_error() {
+        return r;
+    }
+
+    match set(name, &vendor, attributes, data) {
+        Status::SUCCESS => r,
+        e => e,
+    }
+}
+
+pub(super) extern "efiapi" fn query_variable_info(
+    attributes: u32,
+    maximum_variable_storage_size: *mut u64,
+    remaining_variable_storage_size: *mut u64,
+    maximum_variable_size: *mut u64,
+) -> Status {
+    if maximum_variable_storage_size.is_null()
+        || remaining_variable_storage_size.is_null()
+        || maximum_variable_size.is_null()
+        || attributes & (efi::VARIABLE_BOOTSERVICE_ACCESS | efi::VARIABLE_RUNTIME_ACCESS) == 0
+    {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let r = intercept(&Call::QueryVariableInfo { attributes });
+    if r.is_error() {
+        return r;
+    }
+
+    let used = with_state(|s| s.used_variable_storage());
+    unsafe {
+        *maximum_variable_storage_size = VARIABLE_STORAGE_SIZE;
+        *remaining_variable_storage_size = VARIABLE_STORAGE_SIZE - used;
+        *maximum_variable_size = MAXIMUM_VARIABLE_SIZE;
+    }
+    r
+}
@@ -0,0 +1,229 @@
+//! This module contains functions related to the ACPI Table Protocol.
+//!
+//! The protocol is published by the firmware's ACPI support driver. Installed tables are copied
+//! into firmware-owned memory and linked into the RSDT and XSDT, so the caller's buffer can be
+//! freed right after installing.
+
+use core::ffi::c_void;
+
+#[cfg(any(test, feature = "alloc"))]
+use crate::acpi::{self, SdtHeader, SDT_HEADER_SIZE};
+use crate::boot_services::protocol_handler_services::locate_protocol;
+use crate::efi::{Guid, Status, SystemTable};
+use crate::status::Completion;
+use crate::{errors, helpers};
+#[cfg(any(test, feature = "alloc"))]
+use alloc::vec::Vec;
+
+pub type Result<T> = core::result::Result<T, errors::StatusNullError>;
+
+pub const PROTOCOL_GUID: Guid = Guid::from_fields(
+    0xffe06bdd,
+    0x6107,
+    0x46a6,
+    0x7b,
+    0xb2,
+    &[0x5a, 0x9c, 0x7e, 0xc5, 0x27, 0x5c],
+);
+
+pub type InstallAcpiTable =
+    extern "efiapi" fn(*mut Protocol, *mut c_void, usize, *mut usize) -> Status;
+
+pub type UninstallAcpiTable = extern "efiapi" fn(*mut Protocol, usize) -> Status;
+
+/// `EFI_ACPI_TABLE_PROTOCOL`, which `r-efi` does not define.
+#[repr(C)]
+pub struct Protocol {
+    pub install_acpi_table: InstallAcpiTable,
+    pub uninstall_acpi_table: UninstallAcpiTable,
+}
+
+/// The key identifying an installed table, to uninstall it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TableKey(pub usize);
+
+/// Call `InstallAcpiTable` function from `EFI_ACPI_TABLE_PROTOCOL`.
+/// `table` must hold a whole table, whose header length matches the slice. The firmware
+/// recomputes the checksum.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn install_acpi_table(st: *mut SystemTable, table: &[u8]) -> Result<Completion<TableKey>> {
+    let protocol = get_protocol(st)?;
+
+    let install_acpi_table_ptr = unsafe { (*protocol).install_acpi_table };
+
+    // The firmware copies the table and does not modify the buffer.
+    let mut key = 0;
+    let status = (install_acpi_table_ptr)(
+        protocol,
+        table.as_ptr() as *mut c_void,
+        table.len(),
+        &mut key,
+    );
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| TableKey(key)))
+}
+
+/// Call `UninstallAcpiTable` function from `EFI_ACPI_TABLE_PROTOCOL`.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn uninstall_acpi_table(st: *mut SystemTable, key: TableKey) -> Result<Completion<()>> {
+    let protocol = get_protocol(st)?;
+
+    let uninstall_acpi_table_ptr = unsafe { (*protocol).uninstall_acpi_table };
+
+    let status = (uninstall_acpi_table_ptr)(protocol, key.0);
+
+    helpers::status_to_result(status).map_err(|x| x.into())
+}
+
+/// Locate the `EFI_ACPI_TABLE_PROTOCOL`. It is looked up again on every call, since the
+/// interface goes away if the driver is unloaded.
+fn get_protocol(st: *mut SystemTable) -> Result<*mut Protocol> {
+    let interface = locate_protocol(st, &PROTOCOL_GUID, None)?.into_value();
+    helpers::null_check_mut(interface, "ACPI Table Protocol")?;
+    Ok(interface.cast())
+}
+
+/// Builds an ACPI table from a header and a body, filling in the length and checksum.
+#[cfg(any(test, feature = "alloc"))]
+#[derive(Clone, Debug)]
+pub struct AcpiTableBuilder {
+    header: SdtHeader,
+    body: Vec<u8>,
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl AcpiTableBuilder {
+    /// Start a table with an empty body. The OEM and creator fields are zero.
+    pub fn new(signature: [u8; 4], revision: u8) -> Self {
+        Self {
+            header: SdtHeader {
+                signature,
+                length: 0,
+                revision,
+                checksum: 0,
+                oem_id: [0; 6],
+                oem_table_id: [0; 8],
+                oem_revision: 0,
+                creator_id: 0,
+                creator_revision: 0,
+            },
+            body: Vec::new(),
+        }
+    }
+
+    /// Start from an existing table, e.g. to patch it and install the result.
+    pub fn from_sdt(sdt: &acpi::Sdt<'_>) -> Self {
+        Self {
+            header: *sdt.header(),
+            body: sdt.body().to_vec(),
+        }
+    }
+
+    pub fn oem_id(mut self, oem_id: [u8; 6]) -> Self {
+        self.header.oem_id = oem_id;
+        self
+    }
+
+    pub fn oem_table_id(mut self, oem_table_id: [u8; 8]) -> Self {
+        self.header.oem_table_id = oem_table_id;
+        self
+    }
+
+    pub fn oem_revision(mut self, oem_revision: u32) -> Self {
+        self.header.oem_revision = oem_revision;
+        self
+    }
+
+    pub fn creator(mut self, creator_id: u32, creator_revision: u32) -> Self {
+        self.header.creator_id = creator_id;
+        self.header.creator_revision = creator_revision;
+        self
+    }
+
+    /// Append `data` to the body.
+    pub fn body(mut self, data: &[u8]) -> Self {
+        self.body.extend_from_slice(data);
+        self
+    }
+
+    /// The body, e.g. to patch fields of a table started with `from_sdt`.
+    pub fn body_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.body
+    }
+
+    /// The table, with the length and checksum filled in.
+    pub fn build(&self) -> core::result::Result<Vec<u8>, errors::AcpiError> {
+        let mut table = alloc::vec![0; SDT_HEADER_SIZE];
+        self.header.write(&mut table)?;
+        table.extend_from_slice(&self.body);
+        acpi::finalize_table(&mut table)?;
+        Ok(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acpi::Sdt;
+    use crate::mock::MockSystemTable;
+    use crate::status::StatusCode;
+
+    #[test]
+    fn build_table() {
+        let table = AcpiTableBuilder::new(*b"SSDT", 2)
+            .oem_id(*b"OEMID ")
+            .oem_table_id(*b"TESTSSDT")
+            .creator(u32::from_le_bytes(*b"RUST"), 1)
+            .body(&[0x10, 0x05])
+            .body(&[0x5c, 0x00])
+            .build()
+            .unwrap();
+        assert_eq!(table.len(), SDT_HEADER_SIZE + 4);
+
+        let sdt = Sdt::parse(&table).unwrap();
+        assert_eq!(sdt.signature(), b"SSDT");
+        assert_eq!(&sdt.header().oem_table_id, b"TESTSSDT");
+        assert_eq!(sdt.body(), [0x10, 0x05, 0x5c, 0x00]);
+
+        let mut patched = AcpiTableBuilder::from_sdt(&sdt).oem_revision(2);
+        patched.body_mut()[1] = 0x06;
+        let patched = patched.build().unwrap();
+        let sdt = Sdt::parse(&patched).unwrap();
+        assert_eq!(sdt.header().oem_revision, 2);
+        assert_eq!(sdt.body()[1], 0x06);
+    }
+
+    #[test]
+    fn install_and_uninstall() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        let table = AcpiTableBuilder::new(*b"SSDT", 2).build().unwrap();
+
+        let r = install_acpi_table(st, &table);
+        assert_eq!(
+            r.unwrap_err(),
+            errors::StatusNullError::UefiError(StatusCode::NotFound)
+        );
+
+        mock.install_acpi_table_protocol();
+        let key = install_acpi_table(st, &table).unwrap().into_value();
+        assert_eq!(mock.acpi_tables(), core::slice::from_ref(&table));
+
+        let r = install_acpi_table(st, &table[..SDT_HEADER_SIZE - 1]);
+        assert_eq!(
+            r.unwrap_err(),
+            errors::StatusNullError::UefiError(StatusCode::InvalidParameter)
+        );
+
+        uninstall_acpi_table(st, key).unwrap().into_value();
+        assert!(mock.acpi_tables().is_empty());
+        let r = uninstall_acpi_table(st, key);
+        assert_eq!(
+            r.unwrap_err(),
+            errors::StatusNullError::UefiError(StatusCode::NotFound)
+        );
+    }
+}
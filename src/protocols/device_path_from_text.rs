@@ -0,0 +1,103 @@
+//! This module contains functions related to the Device Path From Text Protocol.
+//!
+//! The firmware returns the path in a pool allocation, which is freed when the returned buffer
+//! is dropped. On firmware without the protocol, `DevicePathBuf::from_text` parses the text
+//! instead.
+
+use crate::boot_services::memory_allocation_services::PoolBuffer;
+use crate::boot_services::protocol_handler_services::locate_protocol;
+use crate::device_path::{DevicePath, NODE_HEADER_SIZE};
+use crate::efi::SystemTable;
+use crate::status::StatusCode;
+use crate::string::CStr16;
+use crate::{errors, helpers};
+use r_efi::protocols::device_path_from_text;
+
+pub type Result<T> = core::result::Result<T, errors::StatusNullError>;
+
+/// Call `ConvertTextToDeviceNode` function from `EFI_DEVICE_PATH_FROM_TEXT_PROTOCOL`.
+/// Returns the node, which `Node::parse` reads. Invalid text fails with a `NullPtrError`, as the
+/// firmware returns no path, and a malformed node with `CompromisedData`.
+/// SAFETY : The `st` pointer must be valid for the lifetime of the returned buffer. This is
+/// gaurenteed if `GlobalData` is used to store the pointer.
+pub fn convert_text_to_device_node(st: *mut SystemTable, text: &CStr16) -> Result<PoolBuffer<u8>> {
+    let protocol = get_protocol(st)?;
+
+    let convert_text_to_device_node_ptr = unsafe { (*protocol).convert_text_to_device_node };
+
+    let node = (convert_text_to_device_node_ptr)(text.as_ptr());
+    helpers::null_check_mut(node, "Device Path")?;
+
+    let length = u16::from_le_bytes(unsafe { (*node).length }) as usize;
+    // The buffer frees the node on failure as well.
+    let buffer = unsafe { PoolBuffer::from_raw_parts(st, node.cast::<u8>(), length) };
+    if length < NODE_HEADER_SIZE {
+        return Err(errors::StatusNullError::UefiError(
+            StatusCode::CompromisedData,
+        ));
+    }
+    Ok(buffer)
+}
+
+/// Call `ConvertTextToDevicePath` function from `EFI_DEVICE_PATH_FROM_TEXT_PROTOCOL`.
+/// Returns the path, which `DevicePath::parse` reads. Errors are as for
+/// `convert_text_to_device_node`.
+/// SAFETY : The `st` pointer must be valid for the lifetime of the returned buffer. This is
+/// gaurenteed if `GlobalData` is used to store the pointer.
+pub fn convert_text_to_device_path(st: *mut SystemTable, text: &CStr16) -> Result<PoolBuffer<u8>> {
+    let protocol = get_protocol(st)?;
+
+    let convert_text_to_device_path_ptr = unsafe { (*protocol).convert_text_to_device_path };
+
+    let path = (convert_text_to_device_path_ptr)(text.as_ptr());
+    helpers::null_check_mut(path, "Device Path")?;
+
+    let size = unsafe { DevicePath::from_ptr(path) }.map(|x| x.size());
+    // The buffer frees the path on failure as well.
+    let buffer = unsafe { PoolBuffer::from_raw_parts(st, path.cast::<u8>(), size.unwrap_or(0)) };
+    match size {
+        Ok(_) => Ok(buffer),
+        Err(_) => Err(errors::StatusNullError::UefiError(
+            StatusCode::CompromisedData,
+        )),
+    }
+}
+
+fn get_protocol(st: *mut SystemTable) -> Result<*mut device_path_from_text::Protocol> {
+    let interface = locate_protocol(st, &device_path_from_text::PROTOCOL_GUID, None)?.into_value();
+    helpers::null_check_mut(interface, "Device Path From Text Protocol")?;
+    Ok(interface.cast())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_path::{DevicePathBuf, Node};
+    use crate::mock::MockSystemTable;
+    use crate::string::CString16;
+
+    #[test]
+    fn text_to_path() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        mock.install_device_path_text_protocols();
+
+        let text = "PciRoot(0x0)/Pci(0x1F,0x2)/HD(1,MBR,0x12345678,0x800,0x1000)";
+        let buffer =
+            convert_text_to_device_path(st, &CString16::try_from_str(text).unwrap()).unwrap();
+        let expected = DevicePathBuf::from_text(text).unwrap();
+        assert_eq!(DevicePath::parse(&buffer).unwrap(), expected.as_path());
+
+        let buffer =
+            convert_text_to_device_node(st, &CString16::try_from_str("Pci(0x1,0x2)").unwrap())
+                .unwrap();
+        assert_eq!(buffer.len(), 6);
+        assert_eq!(Node::parse(&buffer).unwrap().data(), [2, 1]);
+
+        let r = convert_text_to_device_path(st, &CString16::try_from_str("Pci(").unwrap());
+        assert_eq!(
+            r.unwrap_err(),
+            errors::StatusNullError::NullPtrError("Device Path")
+        );
+    }
+}
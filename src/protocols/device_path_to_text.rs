@@ -0,0 +1,108 @@
+//! This module contains functions related to the Device Path To Text Protocol.
+//!
+//! The firmware returns the text in a pool allocation, which is freed when the returned buffer
+//! is dropped. On firmware without the protocol, `DevicePath` implements `Display` instead.
+
+use crate::boot_services::memory_allocation_services::PoolBuffer;
+use crate::boot_services::protocol_handler_services::locate_protocol;
+use crate::device_path::{DevicePath, Node};
+use crate::efi::SystemTable;
+use crate::{errors, helpers};
+use r_efi::protocols::{device_path, device_path_to_text};
+
+pub type Result<T> = core::result::Result<T, errors::StatusNullError>;
+
+/// Call `ConvertDeviceNodeToText` function from `EFI_DEVICE_PATH_TO_TEXT_PROTOCOL`.
+/// Returns the text, including its NUL. With `display_only`, the shorter display form is used.
+/// With `allow_shortcuts`, shortcut forms such as `Floppy(0)` are used where possible.
+/// SAFETY : The `st` pointer must be valid for the lifetime of the returned buffer. This is
+/// gaurenteed if `GlobalData` is used to store the pointer.
+pub fn convert_device_node_to_text(
+    st: *mut SystemTable,
+    node: Node<'_>,
+    display_only: bool,
+    allow_shortcuts: bool,
+) -> Result<PoolBuffer<u16>> {
+    let protocol = get_protocol(st)?;
+
+    let convert_device_node_to_text_ptr = unsafe { (*protocol).convert_device_node_to_text };
+
+    // The firmware does not modify the node.
+    let text = (convert_device_node_to_text_ptr)(
+        node.as_bytes().as_ptr() as *mut device_path::Protocol,
+        display_only.into(),
+        allow_shortcuts.into(),
+    );
+
+    into_buffer(st, text)
+}
+
+/// Call `ConvertDevicePathToText` function from `EFI_DEVICE_PATH_TO_TEXT_PROTOCOL`.
+/// Returns the text, including its NUL. The flags are as for `convert_device_node_to_text`.
+/// SAFETY : The `st` pointer must be valid for the lifetime of the returned buffer. This is
+/// gaurenteed if `GlobalData` is used to store the pointer.
+pub fn convert_device_path_to_text(
+    st: *mut SystemTable,
+    path: DevicePath<'_>,
+    display_only: bool,
+    allow_shortcuts: bool,
+) -> Result<PoolBuffer<u16>> {
+    let protocol = get_protocol(st)?;
+
+    let convert_device_path_to_text_ptr = unsafe { (*protocol).convert_device_path_to_text };
+
+    let text = (convert_device_path_to_text_ptr)(
+        path.as_ptr() as *mut device_path::Protocol,
+        display_only.into(),
+        allow_shortcuts.into(),
+    );
+
+    into_buffer(st, text)
+}
+
+/// Take ownership of the text returned by the firmware, which is null on failure.
+fn into_buffer(st: *mut SystemTable, text: *mut u16) -> Result<PoolBuffer<u16>> {
+    helpers::null_check_mut(text, "Device Path Text")?;
+    let mut len = 0;
+    while unsafe { *text.add(len) } != 0 {
+        len += 1;
+    }
+    Ok(unsafe { PoolBuffer::from_raw_parts(st, text, len + 1) })
+}
+
+fn get_protocol(st: *mut SystemTable) -> Result<*mut device_path_to_text::Protocol> {
+    let interface = locate_protocol(st, &device_path_to_text::PROTOCOL_GUID, None)?.into_value();
+    helpers::null_check_mut(interface, "Device Path To Text Protocol")?;
+    Ok(interface.cast())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_path::DevicePathBuf;
+    use crate::mock::MockSystemTable;
+    use crate::status::StatusCode;
+    use crate::string::CStr16;
+
+    #[test]
+    fn path_to_text() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        let text = "PciRoot(0x0)/Pci(0x1F,0x2)/Sata(0x0,0xFFFF,0x0)";
+        let path = DevicePathBuf::from_text(text).unwrap();
+
+        let r = convert_device_path_to_text(st, path.as_path(), false, false);
+        assert_eq!(
+            r.unwrap_err(),
+            errors::StatusNullError::UefiError(StatusCode::NotFound)
+        );
+
+        mock.install_device_path_text_protocols();
+        let buffer = convert_device_path_to_text(st, path.as_path(), false, false).unwrap();
+        assert_eq!(CStr16::from_u16_with_nul(&buffer).unwrap(), text);
+
+        let node = path.as_path().nodes().nth(1).unwrap();
+        let buffer = convert_device_node_to_text(st, node, false, false).unwrap();
+        assert_eq!(CStr16::from_u16_with_nul(&buffer).unwrap(), "Pci(0x1F,0x2)");
+    }
+}
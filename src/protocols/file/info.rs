@@ -0,0 +1,547 @@
+//! Typed views of the information structures read with `GetInfo` and written with `SetInfo`.
+//!
+//! `EFI_FILE_INFO`, `EFI_FILE_SYSTEM_INFO` and `EFI_FILE_SYSTEM_VOLUME_LABEL` end with a
+//! NUL-terminated UCS-2 name of variable length. The views borrow the raw bytes, which need not be
+//! aligned, and read the fields on access. New structures are written into a caller-provided
+//! buffer with `new_in`.
+
+use core::fmt;
+
+use super::FileAttribute;
+use crate::efi::Guid;
+use crate::errors::FileInfoError;
+use crate::string::CStr16;
+#[cfg(any(test, feature = "alloc"))]
+use alloc::string::String;
+use r_efi::efi::Time;
+use r_efi::protocols::file;
+
+type Result<T> = core::result::Result<T, FileInfoError>;
+
+const TIME_SIZE: usize = 16;
+
+/// A structure which can be read with `File::get_info` and written with `File::set_info`.
+pub trait InfoType<'a>: Sized {
+    /// The information type passed to `GetInfo` and `SetInfo`.
+    const ID: Guid;
+
+    /// Parse the structure from the bytes returned by `GetInfo`.
+    fn parse(data: &'a [u8]) -> Result<Self>;
+
+    /// The bytes of the structure, including the name and its NUL.
+    fn as_bytes(&self) -> &'a [u8];
+}
+
+/// A UCS-2 name inside an information structure. It is not aligned and does not include the
+/// NUL.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Name<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Name<'a> {
+    /// Number of UCS-2 code units.
+    pub fn len(&self) -> usize {
+        self.data.len() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The UCS-2 code units of the name.
+    pub fn units(&self) -> impl Iterator<Item = u16> + 'a {
+        self.data
+            .chunks_exact(2)
+            .map(|x| u16::from_le_bytes([x[0], x[1]]))
+    }
+
+    /// The characters of the name. Surrogates are replaced with U+FFFD.
+    pub fn chars(&self) -> impl Iterator<Item = char> + 'a {
+        self.units()
+            .map(|c| char::from_u32(u32::from(c)).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    #[cfg(any(test, feature = "alloc"))]
+    pub fn to_string_lossy(&self) -> String {
+        self.chars().collect()
+    }
+}
+
+impl PartialEq<str> for Name<'_> {
+    fn eq(&self, other: &str) -> bool {
+        self.units().eq(other.encode_utf16())
+    }
+}
+
+impl PartialEq<&str> for Name<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl fmt::Display for Name<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use fmt::Write;
+        for c in self.chars() {
+            f.write_char(c)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Name<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in self.chars() {
+            fmt::Display::fmt(&c.escape_debug(), f)?;
+        }
+        f.write_str("\"")
+    }
+}
+
+/// The fields of an `EFI_FILE_INFO`, other than its size and name.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FileInfoFields {
+    pub file_size: u64,
+    pub physical_size: u64,
+    pub create_time: Time,
+    pub last_access_time: Time,
+    pub modification_time: Time,
+    pub attribute: FileAttribute,
+}
+
+/// An `EFI_FILE_INFO`, as returned for `file::INFO_ID` and by reading a directory.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct FileInfo<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> FileInfo<'a> {
+    /// Size of the structure without its name.
+    pub const FIXED_SIZE: usize = 80;
+
+    /// Size of the structure with `name`, as needed by `new_in`.
+    pub fn required_size(name: &CStr16) -> usize {
+        Self::FIXED_SIZE + name.size_in_bytes()
+    }
+
+    /// Write the structure into `buffer`.
+    pub fn new_in(buffer: &'a mut [u8], fields: &FileInfoFields, name: &CStr16) -> Result<Self> {
+        let size = Self::required_size(name);
+        let buffer = buffer
+            .get_mut(..size)
+            .ok_or(FileInfoError::BufferTooSmall)?;
+        buffer[0..8].copy_from_slice(&(size as u64).to_le_bytes());
+        buffer[8..16].copy_from_slice(&fields.file_size.to_le_bytes());
+        buffer[16..24].copy_from_slice(&fields.physical_size.to_le_bytes());
+        write_time(&mut buffer[24..40], &fields.create_time);
+        write_time(&mut buffer[40..56], &fields.last_access_time);
+        write_time(&mut buffer[56..72], &fields.modification_time);
+        buffer[72..80].copy_from_slice(&fields.attribute.bits().to_le_bytes());
+        write_name(&mut buffer[Self::FIXED_SIZE..], name);
+        Ok(Self { data: buffer })
+    }
+
+    /// Size of the structure, including the name.
+    pub fn size(&self) -> u64 {
+        read_u64(self.data, 0)
+    }
+
+    pub fn file_size(&self) -> u64 {
+        read_u64(self.data, 8)
+    }
+
+    /// Space taken on the volume, which may be larger than `file_size`.
+    pub fn physical_size(&self) -> u64 {
+        read_u64(self.data, 16)
+    }
+
+    pub fn create_time(&self) -> Time {
+        read_time(self.data, 24)
+    }
+
+    pub fn last_access_time(&self) -> Time {
+        read_time(self.data, 40)
+    }
+
+    pub fn modification_time(&self) -> Time {
+        read_time(self.data, 56)
+    }
+
+    pub fn attribute(&self) -> FileAttribute {
+        FileAttribute::from_bits(read_u64(self.data, 72))
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.attribute().contains(FileAttribute::DIRECTORY)
+    }
+
+    /// The name of the file, without its path.
+    pub fn file_name(&self) -> Name<'a> {
+        name_at(self.data, Self::FIXED_SIZE)
+    }
+
+    /// The fields, e.g. to write a modified copy with `new_in`.
+    pub fn fields(&self) -> FileInfoFields {
+        FileInfoFields {
+            file_size: self.file_size(),
+            physical_size: self.physical_size(),
+            create_time: self.create_time(),
+            last_access_time: self.last_access_time(),
+            modification_time: self.modification_time(),
+            attribute: self.attribute(),
+        }
+    }
+}
+
+impl<'a> InfoType<'a> for FileInfo<'a> {
+    const ID: Guid = file::INFO_ID;
+
+    fn parse(data: &'a [u8]) -> Result<Self> {
+        let data = parse_sized(data, Self::FIXED_SIZE)?;
+        Ok(Self { data })
+    }
+
+    fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl fmt::Debug for FileInfo<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileInfo")
+            .field("file_size", &self.file_size())
+            .field("physical_size", &self.physical_size())
+            .field("attribute", &self.attribute())
+            .field("file_name", &self.file_name())
+            .finish()
+    }
+}
+
+/// The fields of an `EFI_FILE_SYSTEM_INFO`, other than its size and volume label.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileSystemInfoFields {
+    pub read_only: bool,
+    pub volume_size: u64,
+    pub free_space: u64,
+    pub block_size: u32,
+}
+
+/// An `EFI_FILE_SYSTEM_INFO`, as returned for `file::SYSTEM_INFO_ID` on any file of a volume.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct FileSystemInfo<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> FileSystemInfo<'a> {
+    /// Size of the structure without its volume label. Unlike `size_of`, this has no padding at
+    /// the end.
+    pub const FIXED_SIZE: usize = 36;
+
+    /// Size of the structure with `volume_label`, as needed by `new_in`.
+    pub fn required_size(volume_label: &CStr16) -> usize {
+        Self::FIXED_SIZE + volume_label.size_in_bytes()
+    }
+
+    /// Write the structure into `buffer`.
+    pub fn new_in(
+        buffer: &'a mut [u8],
+        fields: &FileSystemInfoFields,
+        volume_label: &CStr16,
+    ) -> Result<Self> {
+        let size = Self::required_size(volume_label);
+        let buffer = buffer
+            .get_mut(..size)
+            .ok_or(FileInfoError::BufferTooSmall)?;
+        buffer[..Self::FIXED_SIZE].fill(0);
+        buffer[0..8].copy_from_slice(&(size as u64).to_le_bytes());
+        buffer[8] = u8::from(fields.read_only);
+        buffer[16..24].copy_from_slice(&fields.volume_size.to_le_bytes());
+        buffer[24..32].copy_from_slice(&fields.free_space.to_le_bytes());
+        buffer[32..36].copy_from_slice(&fields.block_size.to_le_bytes());
+        write_name(&mut buffer[Self::FIXED_SIZE..], volume_label);
+        Ok(Self { data: buffer })
+    }
+
+    /// Size of the structure, including the volume label.
+    pub fn size(&self) -> u64 {
+        read_u64(self.data, 0)
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.data[8] != 0
+    }
+
+    /// Size of the volume in bytes.
+    pub fn volume_size(&self) -> u64 {
+        read_u64(self.data, 16)
+    }
+
+    /// Free space on the volume in bytes.
+    pub fn free_space(&self) -> u64 {
+        read_u64(self.data, 24)
+    }
+
+    /// The nominal block size of the volume in bytes.
+    pub fn block_size(&self) -> u32 {
+        u32::from_le_bytes(self.data[32..36].try_into().unwrap())
+    }
+
+    pub fn volume_label(&self) -> Name<'a> {
+        name_at(self.data, Self::FIXED_SIZE)
+    }
+
+    /// The fields, e.g. to write a modified copy with `new_in`.
+    pub fn fields(&self) -> FileSystemInfoFields {
+        FileSystemInfoFields {
+            read_only: self.read_only(),
+            volume_size: self.volume_size(),
+            free_space: self.free_space(),
+            block_size: self.block_size(),
+        }
+    }
+}
+
+impl<'a> InfoType<'a> for FileSystemInfo<'a> {
+    const ID: Guid = file::SYSTEM_INFO_ID;
+
+    fn parse(data: &'a [u8]) -> Result<Self> {
+        let data = parse_sized(data, Self::FIXED_SIZE)?;
+        Ok(Self { data })
+    }
+
+    fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl fmt::Debug for FileSystemInfo<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileSystemInfo")
+            .field("read_only", &self.read_only())
+            .field("volume_size", &self.volume_size())
+            .field("free_space", &self.free_space())
+            .field("block_size", &self.block_size())
+            .field("volume_label", &self.volume_label())
+            .finish()
+    }
+}
+
+/// An `EFI_FILE_SYSTEM_VOLUME_LABEL`, as returned for `file::SYSTEM_VOLUME_LABEL_ID`. It is only
+/// the label, without a size field.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct FileSystemVolumeLabel<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> FileSystemVolumeLabel<'a> {
+    /// Write the structure into `buffer`.
+    pub fn new_in(buffer: &'a mut [u8], volume_label: &CStr16) -> Result<Self> {
+        let buffer = buffer
+            .get_mut(..volume_label.size_in_bytes())
+            .ok_or(FileInfoError::BufferTooSmall)?;
+        write_name(buffer, volume_label);
+        Ok(Self { data: buffer })
+    }
+
+    pub fn volume_label(&self) -> Name<'a> {
+        name_at(self.data, 0)
+    }
+}
+
+impl<'a> InfoType<'a> for FileSystemVolumeLabel<'a> {
+    const ID: Guid = file::SYSTEM_VOLUME_LABEL_ID;
+
+    /// The label ends at the first NUL. Anything after it is ignored.
+    fn parse(data: &'a [u8]) -> Result<Self> {
+        let nul = find_nul(data).ok_or(FileInfoError::MissingNul)?;
+        Ok(Self {
+            data: &data[..nul + 2],
+        })
+    }
+
+    fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl fmt::Debug for FileSystemVolumeLabel<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("FileSystemVolumeLabel")
+            .field(&self.volume_label())
+            .finish()
+    }
+}
+
+/// Check the `Size` field at offset 0 and that the name after `fixed_size` has a NUL. Returns the
+/// bytes covered by `Size`.
+fn parse_sized(data: &[u8], fixed_size: usize) -> Result<&[u8]> {
+    if data.len() < fixed_size {
+        return Err(FileInfoError::Truncated);
+    }
+    let size = read_u64(data, 0);
+    if size < fixed_size as u64 + 2 {
+        return Err(FileInfoError::InvalidSize);
+    }
+    let data = usize::try_from(size)
+        .ok()
+        .and_then(|x| data.get(..x))
+        .ok_or(FileInfoError::Truncated)?;
+    find_nul(&data[fixed_size..]).ok_or(FileInfoError::MissingNul)?;
+    Ok(data)
+}
+
+/// Byte offset of the first UCS-2 NUL.
+fn find_nul(data: &[u8]) -> Option<usize> {
+    data.chunks_exact(2)
+        .position(|x| x == [0, 0])
+        .map(|x| x * 2)
+}
+
+/// The name starting at `offset`. It was checked for a NUL when parsing.
+fn name_at(data: &[u8], offset: usize) -> Name<'_> {
+    let data = &data[offset..];
+    let end = find_nul(data).unwrap_or(data.len() & !1);
+    Name { data: &data[..end] }
+}
+
+fn write_name(buffer: &mut [u8], name: &CStr16) {
+    for (x, c) in buffer.chunks_exact_mut(2).zip(name.as_slice_with_nul()) {
+        x.copy_from_slice(&c.to_le_bytes());
+    }
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_time(data: &[u8], offset: usize) -> Time {
+    let x = &data[offset..offset + TIME_SIZE];
+    Time {
+        year: u16::from_le_bytes([x[0], x[1]]),
+        month: x[2],
+        day: x[3],
+        hour: x[4],
+        minute: x[5],
+        second: x[6],
+        pad1: x[7],
+        nanosecond: u32::from_le_bytes([x[8], x[9], x[10], x[11]]),
+        timezone: i16::from_le_bytes([x[12], x[13]]),
+        daylight: x[14],
+        pad2: x[15],
+    }
+}
+
+fn write_time(x: &mut [u8], time: &Time) {
+    x[0..2].copy_from_slice(&time.year.to_le_bytes());
+    x[2] = time.month;
+    x[3] = time.day;
+    x[4] = time.hour;
+    x[5] = time.minute;
+    x[6] = time.second;
+    x[7] = 0;
+    x[8..12].copy_from_slice(&time.nanosecond.to_le_bytes());
+    x[12..14].copy_from_slice(&time.timezone.to_le_bytes());
+    x[14] = time.daylight;
+    x[15] = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::string::CString16;
+
+    fn time(day: u8) -> Time {
+        Time {
+            year: 2024,
+            month: 5,
+            day,
+            hour: 12,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn file_info_round_trip() {
+        let name = CString16::from_str_lossy("grub.cfg");
+        let fields = FileInfoFields {
+            file_size: 1234,
+            physical_size: 4096,
+            create_time: time(1),
+            last_access_time: time(2),
+            modification_time: time(3),
+            attribute: FileAttribute::ARCHIVE | FileAttribute::READ_ONLY,
+        };
+        // Odd offset, so that nothing is aligned.
+        let mut buffer = [0xffu8; 128];
+        let size = FileInfo::required_size(&name);
+        assert_eq!(size, 80 + 18);
+        let written = FileInfo::new_in(&mut buffer[1..], &fields, &name).unwrap();
+        assert_eq!(written.as_bytes().len(), size);
+
+        let info = FileInfo::parse(&buffer[1..]).unwrap();
+        assert_eq!(info.size(), size as u64);
+        assert_eq!(info.file_size(), 1234);
+        assert_eq!(info.physical_size(), 4096);
+        assert_eq!(info.create_time().day, 1);
+        assert_eq!(info.modification_time().day, 3);
+        assert_eq!(info.attribute(), fields.attribute);
+        assert!(!info.is_directory());
+        assert_eq!(info.file_name(), "grub.cfg");
+        assert_eq!(info.file_name().len(), 8);
+        assert_eq!(std::format!("{}", info.file_name()), "grub.cfg");
+
+        let mut small = [0u8; 90];
+        assert_eq!(
+            FileInfo::new_in(&mut small, &fields, &name),
+            Err(FileInfoError::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(FileInfo::parse(&[0; 40]), Err(FileInfoError::Truncated));
+
+        let mut data = [0u8; 84];
+        data[0] = 80;
+        assert_eq!(FileInfo::parse(&data), Err(FileInfoError::InvalidSize));
+        data[0] = 90;
+        assert_eq!(FileInfo::parse(&data), Err(FileInfoError::Truncated));
+        data[0] = 84;
+        data[80..].copy_from_slice(&[b'a', 0, b'b', 0]);
+        assert_eq!(FileInfo::parse(&data), Err(FileInfoError::MissingNul));
+
+        // An empty name is a lone NUL.
+        data[80..].copy_from_slice(&[0, 0, b'b', 0]);
+        assert!(FileInfo::parse(&data).unwrap().file_name().is_empty());
+
+        assert_eq!(
+            FileSystemVolumeLabel::parse(&[b'a', 0]),
+            Err(FileInfoError::MissingNul)
+        );
+    }
+
+    #[test]
+    fn file_system_info() {
+        let label = CString16::from_str_lossy("ESP");
+        let fields = FileSystemInfoFields {
+            read_only: true,
+            volume_size: 1 << 20,
+            free_space: 1 << 19,
+            block_size: 512,
+        };
+        let mut buffer = [0xffu8; 64];
+        FileSystemInfo::new_in(&mut buffer, &fields, &label).unwrap();
+        let info = FileSystemInfo::parse(&buffer).unwrap();
+        assert_eq!(info.size(), 36 + 8);
+        assert_eq!(info.fields(), fields);
+        assert_eq!(info.volume_label(), "ESP");
+
+        let written = FileSystemVolumeLabel::new_in(&mut buffer, &label).unwrap();
+        assert_eq!(written.as_bytes().len(), 8);
+        let parsed = FileSystemVolumeLabel::parse(&buffer).unwrap();
+        assert_eq!(parsed.volume_label().to_string_lossy(), "ESP");
+        assert_eq!(parsed.as_bytes().len(), 8);
+    }
+}
@@ -0,0 +1,717 @@
+//! This module contains functions related to the File Protocol.
+//!
+//! An `EFI_FILE_PROTOCOL` is a handle to an open file or directory. The root directory of a volume
+//! is opened with `simple_file_system::SimpleFileSystem::open_volume`, and everything else is
+//! opened relative to an open directory. Paths use `\` as separator.
+//!
+//! Reading a directory returns one `EFI_FILE_INFO` per call, see `File::read_entry`.
+
+pub mod info;
+
+use core::ffi::c_void;
+
+use crate::efi::Guid;
+use crate::status::{Completion, StatusCode};
+use crate::string::CStr16;
+use crate::{errors, helpers};
+#[cfg(any(test, feature = "alloc"))]
+use alloc::vec::Vec;
+use r_efi::protocols::file;
+
+pub use file::IoToken;
+pub use info::{
+    FileInfo, FileInfoFields, FileSystemInfo, FileSystemInfoFields, FileSystemVolumeLabel,
+    InfoType, Name,
+};
+
+pub type Result<T> = core::result::Result<T, errors::StatusNullError>;
+
+/// The position passed to `set_position` to move to the end of a file.
+pub const END_POSITION: u64 = u64::MAX;
+
+/// The mode a file is opened with. Valid combinations are `READ`, `READ | WRITE` and
+/// `READ | WRITE | CREATE`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FileMode(u64);
+
+impl FileMode {
+    pub const READ: Self = Self(file::MODE_READ);
+    pub const WRITE: Self = Self(file::MODE_WRITE);
+    pub const CREATE: Self = Self(file::MODE_CREATE);
+
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl core::ops::BitOr for FileMode {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl core::ops::BitOrAssign for FileMode {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// The attributes of a file, a combination of the `EFI_FILE_*` attribute bits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FileAttribute(u64);
+
+impl FileAttribute {
+    pub const READ_ONLY: Self = Self(file::READ_ONLY);
+    pub const HIDDEN: Self = Self(file::HIDDEN);
+    pub const SYSTEM: Self = Self(file::SYSTEM);
+    pub const RESERVED: Self = Self(file::RESERVED);
+    pub const DIRECTORY: Self = Self(file::DIRECTORY);
+    pub const ARCHIVE: Self = Self(file::ARCHIVE);
+    /// All bits which may be set.
+    pub const VALID: Self = Self(file::VALID_ATTR);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl core::ops::BitOr for FileAttribute {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl core::ops::BitOrAssign for FileAttribute {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// An open file or directory. The handle is closed when this is dropped.
+pub struct File {
+    protocol: *mut file::Protocol,
+}
+
+impl File {
+    /// Take ownership of a file handle, e.g. one returned by `open_ex`.
+    ///
+    /// # Safety
+    ///
+    /// `protocol` must be a valid, open file handle which is not closed by anyone else.
+    pub unsafe fn from_raw(protocol: *mut file::Protocol) -> Self {
+        Self { protocol }
+    }
+
+    fn get(&self) -> &file::Protocol {
+        unsafe { &*self.protocol }
+    }
+
+    pub fn as_ptr(&self) -> *mut file::Protocol {
+        self.protocol
+    }
+
+    /// Give up ownership without closing the handle.
+    pub fn into_raw(self) -> *mut file::Protocol {
+        let protocol = self.protocol;
+        core::mem::forget(self);
+        protocol
+    }
+
+    /// The revision of the protocol. The `*_ex` functions need `file::REVISION2`.
+    pub fn revision(&self) -> u64 {
+        self.get().revision
+    }
+
+    /// Call `Open` function from `EFI_FILE_PROTOCOL`.
+    /// `name` is relative to this directory, or absolute if it starts with `\`. `attributes` are
+    /// only used when a file is created, and `FileAttribute::DIRECTORY` creates a directory.
+    pub fn open(
+        &self,
+        name: &CStr16,
+        mode: FileMode,
+        attributes: FileAttribute,
+    ) -> Result<Completion<File>> {
+        let mut new_handle = core::ptr::null_mut();
+        let status = (self.get().open)(
+            self.protocol,
+            &mut new_handle,
+            name.as_ptr() as *mut u16,
+            mode.bits(),
+            attributes.bits(),
+        );
+
+        let r = helpers::status_to_result(status)?;
+        helpers::null_check_mut(new_handle, "File Protocol")?;
+        Ok(r.map(|_| File {
+            protocol: new_handle,
+        }))
+    }
+
+    /// Call `Close` function from `EFI_FILE_PROTOCOL`.
+    /// Dropping the file closes it as well, but ignores errors.
+    pub fn close(self) -> Result<Completion<()>> {
+        let protocol = self.into_raw();
+        let status = unsafe { ((*protocol).close)(protocol) };
+
+        helpers::status_to_result(status).map_err(|x| x.into())
+    }
+
+    /// Call `Delete` function from `EFI_FILE_PROTOCOL`.
+    /// The handle is closed even if the file could not be deleted, which is reported as the
+    /// warning `WarnDeleteFailure`.
+    pub fn delete(self) -> Result<Completion<()>> {
+        let protocol = self.into_raw();
+        let status = unsafe { ((*protocol).delete)(protocol) };
+
+        helpers::status_to_result(status).map_err(|x| x.into())
+    }
+
+    /// Call `Read` function from `EFI_FILE_PROTOCOL` on a file.
+    /// Returns the number of bytes read, which is 0 at the end of the file.
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<Completion<usize>> {
+        let mut size = 0;
+        let r = self.read_raw(buffer, &mut size)?;
+        Ok(r.map(|_| size))
+    }
+
+    /// Read the next entry of a directory.
+    /// Returns `None` once all entries have been read. If `buffer` is too small for the entry,
+    /// this fails with `BufferTooSmall` and `size` is set to the size needed.
+    pub fn read_entry<'a>(
+        &mut self,
+        buffer: &'a mut [u8],
+        size: &mut usize,
+    ) -> Result<Completion<Option<FileInfo<'a>>>> {
+        let r = self.read_raw(buffer, size)?;
+        if *size == 0 {
+            return Ok(r.map(|_| None));
+        }
+        let info = FileInfo::parse(&buffer[..*size]).map_err(|_| compromised())?;
+        Ok(r.map(|_| Some(info)))
+    }
+
+    fn read_raw(&mut self, buffer: &mut [u8], size: &mut usize) -> Result<Completion<()>> {
+        *size = buffer.len();
+        let status = (self.get().read)(self.protocol, size, buffer.as_mut_ptr().cast());
+
+        helpers::status_to_result(status).map_err(|x| x.into())
+    }
+
+    /// Call `Write` function from `EFI_FILE_PROTOCOL`.
+    /// Returns the number of bytes written, which is less than `data.len()` only on error.
+    pub fn write(&mut self, data: &[u8]) -> Result<Completion<usize>> {
+        let mut size = data.len();
+        let status = (self.get().write)(self.protocol, &mut size, data.as_ptr() as *mut c_void);
+
+        let r = helpers::status_to_result(status)?;
+        Ok(r.map(|_| size))
+    }
+
+    /// Call `GetPosition` function from `EFI_FILE_PROTOCOL`.
+    /// Fails with `Unsupported` on a directory.
+    pub fn get_position(&self) -> Result<Completion<u64>> {
+        let mut position = 0;
+        let status = (self.get().get_position)(self.protocol, &mut position);
+
+        let r = helpers::status_to_result(status)?;
+        Ok(r.map(|_| position))
+    }
+
+    /// Call `SetPosition` function from `EFI_FILE_PROTOCOL`.
+    /// `END_POSITION` moves to the end of the file. Directories only support 0, which restarts
+    /// reading the entries.
+    pub fn set_position(&mut self, position: u64) -> Result<Completion<()>> {
+        let status = (self.get().set_position)(self.protocol, position);
+
+        helpers::status_to_result(status).map_err(|x| x.into())
+    }
+
+    /// Call `GetInfo` function from `EFI_FILE_PROTOCOL`.
+    /// If `buffer` is too small, this fails with `BufferTooSmall` and `size` is set to the size
+    /// needed. Otherwise `size` is set to the size of the information.
+    pub fn get_info_raw(
+        &self,
+        info_type: &Guid,
+        buffer: &mut [u8],
+        size: &mut usize,
+    ) -> Result<Completion<()>> {
+        *size = buffer.len();
+        let status = (self.get().get_info)(
+            self.protocol,
+            info_type as *const Guid as *mut Guid,
+            size,
+            buffer.as_mut_ptr().cast(),
+        );
+
+        helpers::status_to_result(status).map_err(|x| x.into())
+    }
+
+    /// Read typed information, e.g. `FileInfo`. Use `get_info_vec` to let the buffer be sized
+    /// automatically.
+    pub fn get_info<'a, T: InfoType<'a>>(
+        &self,
+        buffer: &'a mut [u8],
+        size: &mut usize,
+    ) -> Result<Completion<T>> {
+        let r = self.get_info_raw(&T::ID, buffer, size)?;
+        let info = T::parse(&buffer[..*size]).map_err(|_| compromised())?;
+        Ok(r.map(|_| info))
+    }
+
+    /// Read the information of `info_type`, growing the buffer until it fits. Parse the result
+    /// with `InfoType::parse`.
+    #[cfg(any(test, feature = "alloc"))]
+    pub fn get_info_vec(&self, info_type: &Guid) -> Result<Completion<Vec<u8>>> {
+        let mut buffer = Vec::new();
+        let mut size = 0;
+
+        loop {
+            match self.get_info_raw(info_type, &mut buffer, &mut size) {
+                Ok(r) => {
+                    buffer.truncate(size);
+                    return Ok(r.map(|_| buffer));
+                }
+                Err(errors::StatusNullError::UefiError(StatusCode::BufferTooSmall)) => {
+                    buffer.resize(size, 0);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Call `SetInfo` function from `EFI_FILE_PROTOCOL`.
+    pub fn set_info_raw(&mut self, info_type: &Guid, data: &[u8]) -> Result<Completion<()>> {
+        let status = (self.get().set_info)(
+            self.protocol,
+            info_type as *const Guid as *mut Guid,
+            data.len(),
+            data.as_ptr() as *mut c_void,
+        );
+
+        helpers::status_to_result(status).map_err(|x| x.into())
+    }
+
+    /// Write typed information, e.g. a `FileInfo` with a new name, size or attributes.
+    pub fn set_info<'a, T: InfoType<'a>>(&mut self, info: &T) -> Result<Completion<()>> {
+        self.set_info_raw(&T::ID, info.as_bytes())
+    }
+
+    /// Call `Flush` function from `EFI_FILE_PROTOCOL`.
+    pub fn flush(&mut self) -> Result<Completion<()>> {
+        let status = (self.get().flush)(self.protocol);
+
+        helpers::status_to_result(status).map_err(|x| x.into())
+    }
+
+    /// Read from the current position to the end of the file.
+    #[cfg(any(test, feature = "alloc"))]
+    pub fn read_to_end(&mut self) -> Result<Completion<Vec<u8>>> {
+        let info = self.get_info_vec(&file::INFO_ID)?.into_value();
+        let size = FileInfo::parse(&info)
+            .map_err(|_| compromised())?
+            .file_size();
+        let position = self.get_position()?.into_value();
+        let remaining = usize::try_from(size.saturating_sub(position))
+            .map_err(|_| errors::StatusNullError::UefiError(StatusCode::OutOfResources))?;
+
+        let mut buffer = alloc::vec![0; remaining];
+        let mut filled = 0;
+        let mut warning = None;
+        while filled < buffer.len() {
+            let (n, w) = self.read(&mut buffer[filled..])?.into_parts();
+            warning = warning.or(w);
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        buffer.truncate(filled);
+
+        Ok(match warning {
+            Some(w) => Completion::with_warning(buffer, w),
+            None => Completion::new(buffer),
+        })
+    }
+
+    /// Read the next entry of a directory, growing the buffer until it fits. Parse the result
+    /// with `FileInfo::parse`.
+    #[cfg(any(test, feature = "alloc"))]
+    pub fn read_entry_vec(&mut self) -> Result<Completion<Option<Vec<u8>>>> {
+        let mut buffer = Vec::new();
+        let mut size = 0;
+
+        loop {
+            match self.read_raw(&mut buffer, &mut size) {
+                Ok(r) if size == 0 => return Ok(r.map(|_| None)),
+                Ok(r) => {
+                    buffer.truncate(size);
+                    FileInfo::parse(&buffer).map_err(|_| compromised())?;
+                    return Ok(r.map(|_| Some(buffer)));
+                }
+                Err(errors::StatusNullError::UefiError(StatusCode::BufferTooSmall)) => {
+                    buffer.resize(size, 0);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn check_revision2(&self) -> Result<()> {
+        if self.revision() < file::REVISION2 {
+            return Err(errors::StatusNullError::UefiError(StatusCode::Unsupported));
+        }
+        Ok(())
+    }
+
+    /// Call `OpenEx` function from `EFI_FILE_PROTOCOL`.
+    /// Once `token.event` is signaled, `token.status` holds the result and `new_handle` the file,
+    /// which can be wrapped with `File::from_raw`. If the token has no event, the call completes
+    /// before returning. Fails with `Unsupported` if the revision is below `file::REVISION2`.
+    ///
+    /// # Safety
+    ///
+    /// `new_handle` and `token` must stay valid until the request completes.
+    pub unsafe fn open_ex(
+        &self,
+        name: &CStr16,
+        mode: FileMode,
+        attributes: FileAttribute,
+        new_handle: *mut *mut file::Protocol,
+        token: *mut IoToken,
+    ) -> Result<Completion<()>> {
+        self.check_revision2()?;
+        let status = (self.get().open_ex)(
+            self.protocol,
+            new_handle,
+            name.as_ptr() as *mut u16,
+            mode.bits(),
+            attributes.bits(),
+            token,
+        );
+
+        helpers::status_to_result(status).map_err(|x| x.into())
+    }
+
+    /// Call `ReadEx` function from `EFI_FILE_PROTOCOL`.
+    /// `token.buffer` and `token.buffer_size` describe the buffer to read into. Once
+    /// `token.event` is signaled, `token.status` holds the result and `token.buffer_size` the
+    /// number of bytes read. Fails with `Unsupported` if the revision is below
+    /// `file::REVISION2`.
+    ///
+    /// # Safety
+    ///
+    /// `token` and its buffer must stay valid until the request completes.
+    pub unsafe fn read_ex(&mut self, token: *mut IoToken) -> Result<Completion<()>> {
+        self.check_revision2()?;
+        let status = (self.get().read_ex)(self.protocol, token);
+
+        helpers::status_to_result(status).map_err(|x| x.into())
+    }
+
+    /// Call `WriteEx` function from `EFI_FILE_PROTOCOL`.
+    /// `token.buffer` and `token.buffer_size` describe the data to write. Once `token.event` is
+    /// signaled, `token.status` holds the result and `token.buffer_size` the number of bytes
+    /// written. Fails with `Unsupported` if the revision is below `file::REVISION2`.
+    ///
+    /// # Safety
+    ///
+    /// `token` and its buffer must stay valid until the request completes.
+    pub unsafe fn write_ex(&mut self, token: *mut IoToken) -> Result<Completion<()>> {
+        self.check_revision2()?;
+        let status = (self.get().write_ex)(self.protocol, token);
+
+        helpers::status_to_result(status).map_err(|x| x.into())
+    }
+
+    /// Call `FlushEx` function from `EFI_FILE_PROTOCOL`.
+    /// Once `token.event` is signaled, `token.status` holds the result. Fails with `Unsupported`
+    /// if the revision is below `file::REVISION2`.
+    ///
+    /// # Safety
+    ///
+    /// `token` must stay valid until the request completes.
+    pub unsafe fn flush_ex(&mut self, token: *mut IoToken) -> Result<Completion<()>> {
+        self.check_revision2()?;
+        let status = (self.get().flush_ex)(self.protocol, token);
+
+        helpers::status_to_result(status).map_err(|x| x.into())
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        let _ = (self.get().close)(self.protocol);
+    }
+}
+
+/// The error for information which the firmware returned but which cannot be parsed.
+fn compromised() -> errors::StatusNullError {
+    errors::StatusNullError::UefiError(StatusCode::CompromisedData)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boot_services::event_services::Event;
+    use crate::mock::{Call, MockSystemTable};
+    use crate::protocols::simple_file_system::SimpleFileSystem;
+    use crate::string::CString16;
+    use r_efi::efi;
+    use std::string::String;
+    use std::vec::Vec;
+
+    fn root(mock: &mut MockSystemTable, files: &[(&str, &[u8])]) -> (File, efi::Handle) {
+        let st = mock.system_table();
+        let image = mock.install_loaded_image(&[]);
+        let fs = mock.install_file_system(files);
+        let root = SimpleFileSystem::open(st, fs, image)
+            .unwrap()
+            .into_value()
+            .open_volume()
+            .unwrap()
+            .into_value();
+        (root, fs)
+    }
+
+    fn name(s: &str) -> CString16 {
+        CString16::from_str_lossy(s)
+    }
+
+    #[test]
+    fn read_directory() {
+        let mut mock = MockSystemTable::new();
+        let (root, _) = root(
+            &mut mock,
+            &[
+                (r"EFI\BOOT\BOOTX64.EFI", &[0; 600]),
+                (r"EFI\app.conf", b"x"),
+            ],
+        );
+        let mut dir = root
+            .open(&name("efi"), FileMode::READ, FileAttribute::empty())
+            .unwrap()
+            .into_value();
+        assert_eq!(
+            dir.get_position(),
+            Err(errors::StatusNullError::UefiError(StatusCode::Unsupported))
+        );
+
+        let mut size = 0;
+        assert_eq!(
+            dir.read_entry(&mut [0; 8], &mut size),
+            Err(errors::StatusNullError::UefiError(
+                StatusCode::BufferTooSmall
+            ))
+        );
+        assert_eq!(size, FileInfo::FIXED_SIZE + 10);
+        let mut buffer = [0; 128];
+        let info = dir.read_entry(&mut buffer, &mut size).unwrap().into_value();
+        let info = info.unwrap();
+        assert_eq!(info.file_name(), "BOOT");
+        assert!(info.is_directory());
+
+        let mut names = Vec::new();
+        while let Some(entry) = dir.read_entry_vec().unwrap().into_value() {
+            let info = FileInfo::parse(&entry).unwrap();
+            names.push((info.file_name().to_string_lossy(), info.file_size()));
+        }
+        assert_eq!(names, [(String::from("app.conf"), 1)]);
+
+        // Going back to 0 restarts the listing.
+        dir.set_position(0).unwrap().into_value();
+        assert!(dir.read_entry_vec().unwrap().into_value().is_some());
+        assert_eq!(
+            dir.set_position(1),
+            Err(errors::StatusNullError::UefiError(StatusCode::Unsupported))
+        );
+
+        let boot = dir
+            .open(
+                &name(r"BOOT\..\..\EFI\boot\bootx64.efi"),
+                FileMode::READ,
+                FileAttribute::empty(),
+            )
+            .unwrap()
+            .into_value();
+        let info = boot.get_info_vec(&FileInfo::ID).unwrap().into_value();
+        let info = FileInfo::parse(&info).unwrap();
+        assert_eq!(info.file_size(), 600);
+        assert_eq!(info.physical_size(), 1024);
+        assert_eq!(
+            root.open(&name("missing"), FileMode::READ, FileAttribute::empty())
+                .err()
+                .unwrap(),
+            errors::StatusNullError::UefiError(StatusCode::NotFound)
+        );
+    }
+
+    #[test]
+    fn create_write_and_set_info() {
+        let mut mock = MockSystemTable::new();
+        let (root, fs) = root(&mut mock, &[]);
+        let create = FileMode::READ | FileMode::WRITE | FileMode::CREATE;
+
+        root.open(&name("logs"), create, FileAttribute::DIRECTORY)
+            .unwrap()
+            .into_value();
+        let mut log = root
+            .open(&name(r"logs\boot.log"), create, FileAttribute::ARCHIVE)
+            .unwrap()
+            .into_value();
+        assert_eq!(log.write(b"hello").unwrap().into_value(), 5);
+        log.set_position(END_POSITION).unwrap().into_value();
+        log.write(b" world").unwrap().into_value();
+        log.flush().unwrap().into_value();
+        assert_eq!(
+            mock.file_data(fs, r"\logs\boot.log").unwrap(),
+            b"hello world"
+        );
+
+        // Truncate and rename in one call.
+        let mut buffer = [0; 128];
+        let mut size = 0;
+        let info: FileInfo = log.get_info(&mut buffer, &mut size).unwrap().into_value();
+        assert_eq!(size, info.as_bytes().len());
+        let mut fields = info.fields();
+        fields.file_size = 5;
+        let mut new = [0; 128];
+        let new = FileInfo::new_in(&mut new, &fields, &name("old.log")).unwrap();
+        log.set_info(&new).unwrap().into_value();
+        assert_eq!(mock.file_data(fs, r"logs\boot.log"), None);
+        assert_eq!(mock.file_data(fs, r"logs\old.log").unwrap(), b"hello");
+        // The handle follows the rename.
+        let info: FileInfo = log.get_info(&mut buffer, &mut size).unwrap().into_value();
+        assert_eq!(info.file_name(), "old.log");
+
+        let mut reader = root
+            .open(
+                &name(r"logs\old.log"),
+                FileMode::READ,
+                FileAttribute::empty(),
+            )
+            .unwrap()
+            .into_value();
+        assert_eq!(
+            reader.write(b"x"),
+            Err(errors::StatusNullError::UefiError(StatusCode::AccessDenied))
+        );
+        // Deleting through a read-only handle fails, but still closes it.
+        assert_eq!(
+            reader.delete().unwrap().warning(),
+            Some(StatusCode::WarnDeleteFailure)
+        );
+        log.delete().unwrap().into_value();
+        assert_eq!(mock.file_data(fs, r"logs\old.log"), None);
+        drop(root);
+        assert_eq!(mock.open_files(), 0);
+    }
+
+    #[test]
+    fn file_system_info() {
+        let mut mock = MockSystemTable::new();
+        let (mut root, _) = root(&mut mock, &[("a.txt", &[1; 100])]);
+
+        let data = root.get_info_vec(&FileSystemInfo::ID).unwrap().into_value();
+        let info = FileSystemInfo::parse(&data).unwrap();
+        assert!(!info.read_only());
+        assert_eq!(info.volume_size() - info.free_space(), 100);
+        assert_eq!(info.block_size(), 512);
+        assert_eq!(info.volume_label(), "MOCK");
+
+        let mut buffer = [0; 16];
+        let label = FileSystemVolumeLabel::new_in(&mut buffer, &name("ESP")).unwrap();
+        root.set_info(&label).unwrap().into_value();
+        let mut size = 0;
+        let label: FileSystemVolumeLabel =
+            root.get_info(&mut buffer, &mut size).unwrap().into_value();
+        assert_eq!(label.volume_label(), "ESP");
+
+        assert_eq!(
+            root.get_info_raw(
+                &efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]),
+                &mut buffer,
+                &mut size
+            ),
+            Err(errors::StatusNullError::UefiError(StatusCode::Unsupported))
+        );
+    }
+
+    #[test]
+    fn ex_functions() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        let (root, _) = root(&mut mock, &[("config.ini", b"[boot]\n")]);
+        let event = Event::new(st, 0, efi::TPL_APPLICATION, None, core::ptr::null_mut())
+            .unwrap()
+            .into_value();
+        let mut token = IoToken {
+            event: event.as_raw(),
+            status: efi::Status::NOT_READY,
+            buffer_size: 0,
+            buffer: core::ptr::null_mut(),
+        };
+
+        let mut handle = core::ptr::null_mut();
+        unsafe {
+            root.open_ex(
+                &name("config.ini"),
+                FileMode::READ,
+                FileAttribute::empty(),
+                &mut handle,
+                &mut token,
+            )
+        }
+        .unwrap()
+        .into_value();
+        assert!(event.check().unwrap().into_value());
+        assert_eq!(token.status, efi::Status::SUCCESS);
+        let mut file = unsafe { File::from_raw(handle) };
+
+        let mut buffer = [0u8; 16];
+        token.buffer = buffer.as_mut_ptr().cast();
+        token.buffer_size = buffer.len();
+        unsafe { file.read_ex(&mut token) }.unwrap().into_value();
+        assert!(event.check().unwrap().into_value());
+        assert_eq!(token.status, efi::Status::SUCCESS);
+        assert_eq!(&buffer[..token.buffer_size], b"[boot]\n");
+
+        // Errors end up in the token.
+        mock.set_hook(|call| match call {
+            Call::FileFlush => efi::Status::DEVICE_ERROR,
+            _ => efi::Status::SUCCESS,
+        });
+        unsafe { file.flush_ex(&mut token) }.unwrap().into_value();
+        assert_eq!(token.status, efi::Status::DEVICE_ERROR);
+        mock.clear_hook();
+
+        unsafe { (*file.as_ptr()).revision = file::REVISION };
+        assert_eq!(
+            unsafe { file.write_ex(&mut token) },
+            Err(errors::StatusNullError::UefiError(StatusCode::Unsupported))
+        );
+    }
+}
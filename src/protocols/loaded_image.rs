@@ -0,0 +1,238 @@
+//! This module contains functions related to the Loaded Image Protocol.
+//!
+//! Every image gets an `EFI_LOADED_IMAGE_PROTOCOL` on its image handle, which is the handle passed
+//! to its entry point. It tells an application where it was loaded from and with which options.
+
+use core::ffi::c_void;
+
+use crate::boot_services::protocol_handler_services::{
+    handle_protocol, open_protocol, ScopedProtocol,
+};
+use crate::efi::{Handle, MemoryType, SystemTable};
+use crate::status::Completion;
+use crate::string::CStr16;
+use crate::{errors, helpers};
+use r_efi::efi;
+use r_efi::protocols::{device_path, loaded_image, loaded_image_device_path};
+
+pub type Result<T> = core::result::Result<T, errors::StatusNullError>;
+
+pub use loaded_image::ProtocolUnload;
+
+/// The `EFI_LOADED_IMAGE_PROTOCOL` of an image, opened on its image handle.
+/// The protocol is closed again when this is dropped.
+pub struct LoadedImage {
+    protocol: ScopedProtocol<loaded_image::Protocol>,
+}
+
+impl LoadedImage {
+    /// Open the protocol on `image`, e.g. the handle passed to `efi_main`. The image itself is
+    /// used as the agent.
+    /// SAFETY : The `st` pointer must be valid for the lifetime of the returned value. This is
+    /// gaurenteed if `GlobalData` is used to store the pointer.
+    pub fn open(st: *mut SystemTable, image: Handle) -> Result<Completion<Self>> {
+        let r = open_protocol::<loaded_image::Protocol>(
+            st,
+            image,
+            &loaded_image::PROTOCOL_GUID,
+            image,
+            core::ptr::null_mut(),
+            efi::OPEN_PROTOCOL_GET_PROTOCOL,
+        )?;
+        helpers::null_check_mut(r.value().as_ptr(), "Loaded Image Protocol")?;
+        Ok(r.map(|protocol| Self { protocol }))
+    }
+
+    fn get(&self) -> &loaded_image::Protocol {
+        unsafe { &*self.protocol.as_ptr() }
+    }
+
+    /// The image handle the protocol was opened on.
+    pub fn handle(&self) -> Handle {
+        self.protocol.handle()
+    }
+
+    pub fn as_ptr(&self) -> *mut loaded_image::Protocol {
+        self.protocol.as_ptr()
+    }
+
+    pub fn revision(&self) -> u32 {
+        self.get().revision
+    }
+
+    /// The image which loaded this one, or null if loaded by the firmware.
+    pub fn parent_handle(&self) -> Handle {
+        self.get().parent_handle
+    }
+
+    /// The device the image was loaded from, or null if loaded from memory.
+    pub fn device_handle(&self) -> Handle {
+        self.get().device_handle
+    }
+
+    /// The file path of the image, relative to `device_handle`. May be null.
+    pub fn file_path(&self) -> *mut device_path::Protocol {
+        self.get().file_path
+    }
+
+    /// The address the image was loaded at.
+    pub fn image_base(&self) -> *mut c_void {
+        self.get().image_base
+    }
+
+    /// The size of the loaded image in bytes.
+    pub fn image_size(&self) -> u64 {
+        self.get().image_size
+    }
+
+    pub fn image_code_type(&self) -> MemoryType {
+        self.get().image_code_type
+    }
+
+    pub fn image_data_type(&self) -> MemoryType {
+        self.get().image_data_type
+    }
+
+    /// The raw load options.
+    pub fn load_options(&self) -> &[u8] {
+        let p = self.get();
+        if p.load_options.is_null() {
+            return &[];
+        }
+        unsafe { core::slice::from_raw_parts(p.load_options.cast(), p.load_options_size as usize) }
+    }
+
+    /// The load options as UCS-2, up to the first NUL if any. `None` if they are not aligned or
+    /// have an odd size, which means they are not a string.
+    pub fn load_options_u16(&self) -> Option<&[u16]> {
+        let options = self.load_options();
+        if options.is_empty() {
+            return Some(&[]);
+        }
+        if !options.len().is_multiple_of(2) || options.as_ptr().align_offset(2) != 0 {
+            return None;
+        }
+        let options = unsafe {
+            core::slice::from_raw_parts(options.as_ptr().cast::<u16>(), options.len() / 2)
+        };
+        let end = options
+            .iter()
+            .position(|x| *x == 0)
+            .unwrap_or(options.len());
+        Some(&options[..end])
+    }
+
+    /// The load options as a NUL-terminated UCS-2 command line, as passed by the shell and by
+    /// boot options. `None` if the options are not such a string.
+    pub fn command_line(&self) -> Option<&CStr16> {
+        let options = self.load_options_u16()?;
+        // `load_options_u16` stops at the NUL, which must be part of the options.
+        if options.len() * 2 >= self.load_options().len() {
+            return None;
+        }
+        let with_nul = unsafe { core::slice::from_raw_parts(options.as_ptr(), options.len() + 1) };
+        CStr16::from_u16_with_nul(with_nul).ok()
+    }
+
+    /// Set the load options, e.g. of an image loaded with `load_image` before starting it.
+    ///
+    /// # Safety
+    ///
+    /// `options` must stay valid until the image no longer uses them.
+    pub unsafe fn set_load_options(&mut self, options: &[u8]) {
+        let p = self.protocol.as_ptr();
+        unsafe {
+            (*p).load_options = options.as_ptr() as *mut c_void;
+            (*p).load_options_size = options.len() as u32;
+        }
+    }
+
+    /// The function called by `UnloadImage`, if the image supports being unloaded.
+    pub fn unload(&self) -> Option<ProtocolUnload> {
+        self.get().unload
+    }
+
+    /// Set the function called by `UnloadImage`. Applications normally leave this unset, while
+    /// drivers set it from their entry point.
+    pub fn set_unload(&mut self, unload: Option<ProtocolUnload>) {
+        unsafe { (*self.protocol.as_ptr()).unload = unload };
+    }
+}
+
+/// Get the `EFI_LOADED_IMAGE_DEVICE_PATH_PROTOCOL` of `image`, the full device path the image
+/// was loaded from. The path is null if the image was loaded from memory without one.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn loaded_image_device_path(
+    st: *mut SystemTable,
+    image: Handle,
+) -> Result<Completion<*mut device_path::Protocol>> {
+    let r = handle_protocol(st, image, &loaded_image_device_path::PROTOCOL_GUID)?;
+    Ok(r.map(|x| x.cast()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockSystemTable;
+    use crate::status::StatusCode;
+
+    fn options(s: &str) -> std::vec::Vec<u8> {
+        s.encode_utf16().flat_map(|x| x.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn introspection() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        let image = mock.install_loaded_image(&options("app.efi -v\0"));
+
+        let loaded = LoadedImage::open(st, image).unwrap().into_value();
+        assert_eq!(loaded.handle(), image);
+        assert_eq!(loaded.revision(), loaded_image::REVISION);
+        assert!(loaded.parent_handle().is_null());
+        assert!(!loaded.image_base().is_null());
+        assert_eq!(loaded.image_size(), 0x1000);
+        assert_eq!(loaded.image_code_type(), efi::LOADER_CODE);
+        assert_eq!(loaded.command_line().unwrap(), "app.efi -v");
+        assert_eq!(loaded.load_options_u16().unwrap().len(), 10);
+
+        let path = loaded_image_device_path(st, image).unwrap().into_value();
+        assert_eq!(unsafe { (*path).r#type }, device_path::TYPE_END);
+
+        let r = LoadedImage::open(st, core::ptr::null_mut());
+        assert_eq!(
+            r.err().unwrap(),
+            errors::StatusNullError::UefiError(StatusCode::Unsupported)
+        );
+    }
+
+    #[test]
+    fn options_and_unload() {
+        extern "efiapi" fn unload(_: Handle) -> efi::Status {
+            efi::Status::SUCCESS
+        }
+
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        let image = mock.install_loaded_image(&[1, 2, 3]);
+
+        let mut loaded = LoadedImage::open(st, image).unwrap().into_value();
+        assert_eq!(loaded.load_options(), [1, 2, 3]);
+        assert!(loaded.load_options_u16().is_none());
+        assert!(loaded.command_line().is_none());
+
+        // Options without a NUL are UCS-2, but not a command line.
+        let unterminated = [0x61u16, 0x62, 0x63];
+        let bytes = unsafe { core::slice::from_raw_parts(unterminated.as_ptr().cast::<u8>(), 6) };
+        unsafe { loaded.set_load_options(bytes) };
+        assert_eq!(loaded.load_options_u16().unwrap().len(), 3);
+        assert!(loaded.command_line().is_none());
+
+        assert!(loaded.unload().is_none());
+        loaded.set_unload(Some(unload));
+        drop(loaded);
+        let loaded = LoadedImage::open(st, image).unwrap().into_value();
+        assert!(loaded.unload().is_some());
+    }
+}
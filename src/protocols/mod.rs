@@ -1,2 +1,9 @@
+pub mod acpi_table;
+pub mod device_path_from_text;
+pub mod device_path_to_text;
+pub mod file;
+pub mod loaded_image;
+pub mod shell_parameters;
+pub mod simple_file_system;
 pub mod simple_text_input;
 pub mod simple_text_output;
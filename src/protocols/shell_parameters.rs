@@ -0,0 +1,123 @@
+//! This module contains functions related to the Shell Parameters Protocol.
+//!
+//! The UEFI Shell installs `EFI_SHELL_PARAMETERS_PROTOCOL` on the image handle of every
+//! application it starts. It holds the command line already split into arguments.
+
+use core::marker::PhantomData;
+
+use crate::boot_services::protocol_handler_services::{open_protocol, ScopedProtocol};
+use crate::efi::{Handle, SystemTable};
+use crate::status::Completion;
+use crate::string::CStr16;
+use crate::{errors, helpers};
+use r_efi::efi;
+use r_efi::protocols::shell_parameters;
+
+pub type Result<T> = core::result::Result<T, errors::StatusNullError>;
+
+/// The `EFI_SHELL_PARAMETERS_PROTOCOL` of an image, opened on its image handle.
+/// The protocol is closed again when this is dropped.
+pub struct ShellParameters {
+    protocol: ScopedProtocol<shell_parameters::Protocol>,
+}
+
+impl ShellParameters {
+    /// Open the protocol on `image`, e.g. the handle passed to `efi_main`. Fails with
+    /// `Unsupported` if the image was not started by the shell.
+    /// SAFETY : The `st` pointer must be valid for the lifetime of the returned value. This is
+    /// gaurenteed if `GlobalData` is used to store the pointer.
+    pub fn open(st: *mut SystemTable, image: Handle) -> Result<Completion<Self>> {
+        let r = open_protocol::<shell_parameters::Protocol>(
+            st,
+            image,
+            &shell_parameters::PROTOCOL_GUID,
+            image,
+            core::ptr::null_mut(),
+            efi::OPEN_PROTOCOL_GET_PROTOCOL,
+        )?;
+        helpers::null_check_mut(r.value().as_ptr(), "Shell Parameters Protocol")?;
+        Ok(r.map(|protocol| Self { protocol }))
+    }
+
+    fn get(&self) -> &shell_parameters::Protocol {
+        unsafe { &*self.protocol.as_ptr() }
+    }
+
+    pub fn as_ptr(&self) -> *mut shell_parameters::Protocol {
+        self.protocol.as_ptr()
+    }
+
+    /// The number of arguments, including the name of the application.
+    pub fn argc(&self) -> usize {
+        if self.get().argv.is_null() {
+            return 0;
+        }
+        self.get().argc
+    }
+
+    /// The arguments, starting with the name of the application. Null entries are skipped.
+    pub fn argv(&self) -> Argv<'_> {
+        Argv {
+            argv: self.get().argv,
+            range: 0..self.argc(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// An iterator over the arguments of a `ShellParameters`.
+pub struct Argv<'a> {
+    argv: *mut *mut u16,
+    range: core::ops::Range<usize>,
+    _marker: PhantomData<&'a ShellParameters>,
+}
+
+impl<'a> Iterator for Argv<'a> {
+    type Item = &'a CStr16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for i in self.range.by_ref() {
+            let arg = unsafe { *self.argv.add(i) };
+            if arg.is_null() {
+                continue;
+            }
+            let mut len = 0;
+            while unsafe { *arg.add(len) } != 0 {
+                len += 1;
+            }
+            // The shell does not check for surrogates. `CStr16::chars` replaces them.
+            return Some(unsafe {
+                CStr16::from_u16_with_nul_unchecked(core::slice::from_raw_parts(arg, len + 1))
+            });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockSystemTable;
+    use crate::status::StatusCode;
+
+    #[test]
+    fn argv() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        let image = mock.install_loaded_image(&[]);
+
+        let r = ShellParameters::open(st, image);
+        assert_eq!(
+            r.err().unwrap(),
+            errors::StatusNullError::UefiError(StatusCode::Unsupported)
+        );
+
+        mock.install_shell_parameters(image, &["app.efi", "a b", ""]);
+        let params = ShellParameters::open(st, image).unwrap().into_value();
+        assert_eq!(params.argc(), 3);
+        let argv: std::vec::Vec<_> = params.argv().collect();
+        assert_eq!(argv[0], "app.efi");
+        assert_eq!(argv[1], "a b");
+        assert!(argv[2].is_empty());
+    }
+}
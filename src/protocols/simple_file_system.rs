@@ -0,0 +1,135 @@
+//! This module contains functions related to the Simple File System Protocol.
+//!
+//! The firmware installs `EFI_SIMPLE_FILE_SYSTEM_PROTOCOL` on every device with a file system it
+//! understands, which includes the EFI System Partition an application was loaded from. Opening
+//! the volume returns its root directory as a `file::File`.
+
+use crate::boot_services::protocol_handler_services::{open_protocol, ScopedProtocol};
+use crate::efi::{Handle, SystemTable};
+use crate::protocols::file::File;
+use crate::protocols::loaded_image::LoadedImage;
+use crate::status::Completion;
+use crate::{errors, helpers};
+use r_efi::efi;
+use r_efi::protocols::simple_file_system;
+
+pub type Result<T> = core::result::Result<T, errors::StatusNullError>;
+
+/// The `EFI_SIMPLE_FILE_SYSTEM_PROTOCOL` of a device, opened on its handle.
+/// The protocol is closed again when this is dropped. Files opened through it stay open.
+pub struct SimpleFileSystem {
+    protocol: ScopedProtocol<simple_file_system::Protocol>,
+}
+
+impl SimpleFileSystem {
+    /// Open the protocol on `device` on behalf of `agent`, e.g. the image handle. Fails with
+    /// `Unsupported` if the device has no file system.
+    /// SAFETY : The `st` pointer must be valid for the lifetime of the returned value. This is
+    /// gaurenteed if `GlobalData` is used to store the pointer.
+    pub fn open(st: *mut SystemTable, device: Handle, agent: Handle) -> Result<Completion<Self>> {
+        let r = open_protocol::<simple_file_system::Protocol>(
+            st,
+            device,
+            &simple_file_system::PROTOCOL_GUID,
+            agent,
+            core::ptr::null_mut(),
+            efi::OPEN_PROTOCOL_GET_PROTOCOL,
+        )?;
+        helpers::null_check_mut(r.value().as_ptr(), "Simple File System Protocol")?;
+        Ok(r.map(|protocol| Self { protocol }))
+    }
+
+    fn get(&self) -> &simple_file_system::Protocol {
+        unsafe { &*self.protocol.as_ptr() }
+    }
+
+    /// The device handle the protocol was opened on.
+    pub fn handle(&self) -> Handle {
+        self.protocol.handle()
+    }
+
+    pub fn as_ptr(&self) -> *mut simple_file_system::Protocol {
+        self.protocol.as_ptr()
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.get().revision
+    }
+
+    /// Call `OpenVolume` function from `EFI_SIMPLE_FILE_SYSTEM_PROTOCOL`.
+    /// Returns the root directory of the volume.
+    pub fn open_volume(&self) -> Result<Completion<File>> {
+        let mut root = core::ptr::null_mut();
+        let status = (self.get().open_volume)(self.as_ptr(), &mut root);
+
+        let r = helpers::status_to_result(status)?;
+        helpers::null_check_mut(root, "File Protocol")?;
+        Ok(r.map(|_| unsafe { File::from_raw(root) }))
+    }
+}
+
+/// Open the root directory of the volume `image` was loaded from, usually the EFI System
+/// Partition. Fails with `Unsupported` if the image was not loaded from a file system.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn boot_volume(st: *mut SystemTable, image: Handle) -> Result<Completion<File>> {
+    let device = LoadedImage::open(st, image)?.into_value().device_handle();
+    SimpleFileSystem::open(st, device, image)?
+        .into_value()
+        .open_volume()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockSystemTable;
+    use crate::protocols::file::{FileAttribute, FileMode};
+    use crate::status::StatusCode;
+    use crate::string::CString16;
+
+    #[test]
+    fn read_config_from_boot_volume() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        let image = mock.install_loaded_image(&[]);
+        assert_eq!(
+            boot_volume(st, image).err().unwrap(),
+            errors::StatusNullError::UefiError(StatusCode::Unsupported)
+        );
+
+        let esp = mock.install_file_system(&[(r"EFI\app\app.conf", b"timeout=5\n")]);
+        mock.set_image_device(image, esp);
+
+        let root = boot_volume(st, image).unwrap().into_value();
+        let name = CString16::from_str_lossy(r"\efi\APP\app.conf");
+        let mut conf = root
+            .open(&name, FileMode::READ, FileAttribute::empty())
+            .unwrap()
+            .into_value();
+        assert_eq!(conf.read_to_end().unwrap().into_value(), b"timeout=5\n");
+        assert_eq!(conf.get_position().unwrap().into_value(), 10);
+        conf.close().unwrap().into_value();
+        drop(root);
+        assert_eq!(mock.open_files(), 0);
+    }
+
+    #[test]
+    fn open_volume_error() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        let image = mock.install_loaded_image(&[]);
+        let esp = mock.install_file_system(&[]);
+
+        mock.set_hook(|call| match call {
+            crate::mock::Call::OpenVolume => efi::Status::MEDIA_CHANGED,
+            _ => efi::Status::SUCCESS,
+        });
+        let fs = SimpleFileSystem::open(st, esp, image).unwrap().into_value();
+        assert_eq!(fs.handle(), esp);
+        assert_eq!(fs.revision(), simple_file_system::REVISION);
+        assert_eq!(
+            fs.open_volume().err().unwrap(),
+            errors::StatusNullError::UefiError(StatusCode::MediaChanged)
+        );
+    }
+}
@@ -4,44 +4,482 @@ use r_efi::{
     system::SystemTable,
 };
 
+use crate::boot_services::event_services;
+use crate::protocols::simple_text_output;
+use crate::status::{Completion, StatusCode};
+use crate::string::CStr16;
 use crate::{errors, helpers};
 
+pub type Result<T> = core::result::Result<T, errors::StatusNullError>;
+
+// Scan codes from `EFI_SIMPLE_TEXT_INPUT_PROTOCOL`.
+pub const SCAN_NULL: u16 = 0x0000;
+pub const SCAN_UP: u16 = 0x0001;
+pub const SCAN_DOWN: u16 = 0x0002;
+pub const SCAN_RIGHT: u16 = 0x0003;
+pub const SCAN_LEFT: u16 = 0x0004;
+pub const SCAN_HOME: u16 = 0x0005;
+pub const SCAN_END: u16 = 0x0006;
+pub const SCAN_INSERT: u16 = 0x0007;
+pub const SCAN_DELETE: u16 = 0x0008;
+pub const SCAN_PAGE_UP: u16 = 0x0009;
+pub const SCAN_PAGE_DOWN: u16 = 0x000a;
+pub const SCAN_ESC: u16 = 0x0017;
+
+const CHAR_BACKSPACE: u16 = 0x0008;
+const CHAR_LINEFEED: u16 = 0x000a;
+const CHAR_CARRIAGE_RETURN: u16 = 0x000d;
+
 /// Call `Reset` function from `EFI_SIMPLE_TEXT_INPUT_PROTOCOL`.
 /// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
 /// the pointer.
-pub fn reset(
-    st: *mut SystemTable,
-    extended_verification: bool,
-) -> Result<(), errors::StatusNullError> {
+pub fn reset(st: *mut SystemTable, extended_verification: bool) -> Result<Completion<()>> {
     let protocol = get_protocol(st)?;
     let reset_ptr = unsafe { (*protocol).reset };
 
     let r = (reset_ptr)(protocol, Boolean::from(extended_verification));
 
-    helpers::status_to_result(r)?;
-
-    Ok(())
+    helpers::status_to_result(r).map_err(|x| x.into())
 }
 
 /// Call `ReadKeyStroke` function from `EFI_SIMPLE_TEXT_INPUT_PROTOCOL`.
 /// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
 /// the pointer.
-pub fn read_key_stroke(st: *mut SystemTable) -> Result<InputKey, errors::StatusNullError> {
+pub fn read_key_stroke(st: *mut SystemTable) -> Result<Completion<InputKey>> {
     let protocol = get_protocol(st)?;
     let read_key_stroke_ptr = unsafe { (*protocol).read_key_stroke };
 
     let mut input_key = InputKey::default();
 
     let r = (read_key_stroke_ptr)(protocol, &mut input_key);
-    helpers::status_to_result(r)?;
+    let r = helpers::status_to_result(r)?;
 
-    Ok(input_key)
+    Ok(r.map(|_| input_key))
+}
+
+/// Block on the `WaitForKey` event of `EFI_SIMPLE_TEXT_INPUT_PROTOCOL` until a key is available.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn wait_for_key(st: *mut SystemTable) -> Result<Completion<()>> {
+    let protocol = get_protocol(st)?;
+    let event = unsafe { (*protocol).wait_for_key };
+
+    let r = event_services::wait_for_event(st, &mut [event])?;
+
+    Ok(r.map(|_| ()))
 }
 
 pub fn get_protocol(
     st: *mut SystemTable,
-) -> Result<*mut simple_text_input::Protocol, errors::NullPtrError> {
+) -> core::result::Result<*mut simple_text_input::Protocol, errors::NullPtrError> {
     let r = unsafe { (*st).con_in };
     helpers::null_check_mut(r, "Console In")?;
     Ok(r)
 }
+
+/// How `ConsoleIn` turns keys into bytes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InputMode {
+    /// Every key is returned as soon as it is pressed, without echo. Enter is returned as `\n`
+    /// and the cursor and editing keys as VT100 escape sequences.
+    Raw,
+    /// Keys are collected into a line, which is returned once Enter is pressed. Backspace removes
+    /// the last character. Keys are echoed to `con_out` if echo is enabled.
+    Cooked,
+}
+
+/// Size of the line buffer of `ConsoleIn` in bytes. Longer lines are truncated in cooked mode.
+const CONSOLE_IN_BUFFER_SIZE: usize = 512;
+
+/// A reader over `EFI_SIMPLE_TEXT_INPUT_PROTOCOL`, producing UTF-8.
+/// Reads block on the `WaitForKey` event rather than polling.
+/// Only the `SystemTable` pointer is kept. The protocols are looked up again on every call.
+pub struct ConsoleIn {
+    st: *mut SystemTable,
+    mode: InputMode,
+    echo: bool,
+    buf: [u8; CONSOLE_IN_BUFFER_SIZE],
+    start: usize,
+    end: usize,
+    /// An error which came after some bytes were returned, reported by the next call.
+    pending: Option<errors::StatusNullError>,
+}
+
+impl ConsoleIn {
+    /// Create a reader in cooked mode with echo enabled.
+    /// SAFETY : The `st` pointer must be valid for as long as the reader is used. This is
+    /// gaurenteed if `GlobalData` is used to store the pointer.
+    pub fn new(st: *mut SystemTable) -> Self {
+        Self {
+            st,
+            mode: InputMode::Cooked,
+            echo: true,
+            buf: [0; CONSOLE_IN_BUFFER_SIZE],
+            start: 0,
+            end: 0,
+            pending: None,
+        }
+    }
+
+    pub fn mode(&self) -> InputMode {
+        self.mode
+    }
+
+    /// Switch between raw and cooked mode. Input which has been read but not returned yet is
+    /// kept.
+    pub fn set_mode(&mut self, mode: InputMode) {
+        self.mode = mode;
+    }
+
+    /// Enable or disable echo in cooked mode.
+    pub fn set_echo(&mut self, echo: bool) {
+        self.echo = echo;
+    }
+
+    /// Block until a key is pressed and return it.
+    pub fn read_key(&mut self) -> Result<InputKey> {
+        loop {
+            match read_key_stroke(self.st) {
+                Ok(x) => return Ok(x.into_value()),
+                Err(errors::StatusNullError::UefiError(StatusCode::NotReady)) => {
+                    wait_for_key(self.st)?.into_value();
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Read some UTF-8 into `buf`, blocking until at least one byte is available.
+    /// In cooked mode this blocks until a whole line has been entered.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if let Some(e) = self.pending.take() {
+            return Err(e);
+        }
+        if self.start == self.end {
+            self.fill()?;
+        }
+
+        let n = core::cmp::min(buf.len(), self.end - self.start);
+        buf[..n].copy_from_slice(&self.buf[self.start..self.start + n]);
+        self.start += n;
+        Ok(n)
+    }
+
+    /// Read UTF-8 into `buf` up to and including the next `\n`, or until `buf` is full.
+    /// Returns the number of bytes read. If reading fails after some bytes were read, they are
+    /// returned and the error is reported by the next call.
+    pub fn read_line(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if let Some(e) = self.pending.take() {
+            return Err(e);
+        }
+        let mut n = 0;
+        while n < buf.len() {
+            if self.start == self.end {
+                if let Err(e) = self.fill() {
+                    if n == 0 {
+                        return Err(e);
+                    }
+                    self.pending = Some(e);
+                    break;
+                }
+            }
+
+            let b = self.buf[self.start];
+            self.start += 1;
+            buf[n] = b;
+            n += 1;
+
+            if b == b'\n' {
+                break;
+            }
+        }
+        Ok(n)
+    }
+
+    /// Refill the internal buffer, which must be empty. It stays empty on error.
+    fn fill(&mut self) -> Result<()> {
+        self.start = 0;
+        self.end = 0;
+        match self.mode {
+            InputMode::Raw => {
+                while self.end == 0 {
+                    let key = self.read_key()?;
+                    self.end = translate_raw(key, &mut self.buf);
+                }
+            }
+            InputMode::Cooked => {
+                // Reading a key failed. Drop the partial line, so that it is not returned
+                // without its `\n`.
+                if let Err(e) = self.edit_line() {
+                    self.end = 0;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Collect a line into the internal buffer, including the final `\n`.
+    /// Only reading a key can fail. Echo errors are kept for later, since the key is already in
+    /// the buffer.
+    fn edit_line(&mut self) -> Result<()> {
+        loop {
+            let key = self.read_key()?;
+            match key.unicode_char {
+                CHAR_CARRIAGE_RETURN | CHAR_LINEFEED => {
+                    self.buf[self.end] = b'\n';
+                    self.end += 1;
+                    self.echo(&[CHAR_CARRIAGE_RETURN, CHAR_LINEFEED, 0]);
+                    return Ok(());
+                }
+                CHAR_BACKSPACE => {
+                    if self.end > 0 {
+                        // Remove the last UTF-8 sequence.
+                        self.end -= 1;
+                        while self.end > 0 && self.buf[self.end] & 0xc0 == 0x80 {
+                            self.end -= 1;
+                        }
+                        self.echo(&[CHAR_BACKSPACE, 0x0020, CHAR_BACKSPACE, 0]);
+                    }
+                }
+                0 => {}
+                c if c < 0x0020 => {}
+                c => {
+                    let ch = decode_char(c);
+                    // Keep space for the final `\n`.
+                    if self.end + ch.len_utf8() < self.buf.len() {
+                        self.end += ch.encode_utf8(&mut self.buf[self.end..]).len();
+                        self.echo(&[c, 0]);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Echo `s` in cooked mode. The first error is reported by the next read.
+    fn echo(&mut self, s: &[u16]) {
+        if self.echo {
+            let s = unsafe { CStr16::from_u16_with_nul_unchecked(s) };
+            if let Err(e) = simple_text_output::output_string(self.st, s) {
+                self.pending.get_or_insert(e);
+            }
+        }
+    }
+}
+
+#[inline]
+fn decode_char(c: u16) -> char {
+    char::from_u32(u32::from(c)).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+/// Translate a key for raw mode. Returns the number of bytes written to `buf`, which is zero for
+/// keys without translation.
+fn translate_raw(key: InputKey, buf: &mut [u8]) -> usize {
+    let seq: &[u8] = match (key.unicode_char, key.scan_code) {
+        (CHAR_CARRIAGE_RETURN, _) => b"\n",
+        (0, SCAN_UP) => b"\x1b[A",
+        (0, SCAN_DOWN) => b"\x1b[B",
+        (0, SCAN_RIGHT) => b"\x1b[C",
+        (0, SCAN_LEFT) => b"\x1b[D",
+        (0, SCAN_HOME) => b"\x1b[H",
+        (0, SCAN_END) => b"\x1b[F",
+        (0, SCAN_INSERT) => b"\x1b[2~",
+        (0, SCAN_DELETE) => b"\x1b[3~",
+        (0, SCAN_PAGE_UP) => b"\x1b[5~",
+        (0, SCAN_PAGE_DOWN) => b"\x1b[6~",
+        (0, SCAN_ESC) => b"\x1b",
+        (0, _) => b"",
+        (c, _) => return decode_char(c).encode_utf8(buf).len(),
+    };
+    buf[..seq.len()].copy_from_slice(seq);
+    seq.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::efi::Status;
+    use crate::mock::{Call, MockSystemTable};
+    use crate::status::StatusCode;
+
+    #[test]
+    fn read_queued_key() {
+        let mut mock = MockSystemTable::new();
+        mock.push_key(InputKey {
+            scan_code: 0,
+            unicode_char: 0x0061,
+        });
+        let st = mock.system_table();
+
+        let key = read_key_stroke(st).unwrap().into_value();
+        assert_eq!(key.unicode_char, 0x0061);
+    }
+
+    #[test]
+    fn read_without_key() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        assert_eq!(
+            read_key_stroke(st).unwrap_err(),
+            errors::StatusNullError::UefiError(StatusCode::NotReady)
+        );
+    }
+
+    fn key(unicode_char: u16, scan_code: u16) -> InputKey {
+        InputKey {
+            scan_code,
+            unicode_char,
+        }
+    }
+
+    fn push_str(mock: &mut MockSystemTable, s: &str) {
+        for c in s.encode_utf16() {
+            mock.push_key(key(c, 0));
+        }
+    }
+
+    #[test]
+    fn wait_for_key_without_input() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        // The mock cannot block, so it reports that nothing is ready.
+        assert_eq!(
+            wait_for_key(st).unwrap_err(),
+            errors::StatusNullError::UefiError(StatusCode::NotReady)
+        );
+
+        mock.push_key(key(0x0061, 0));
+        assert!(wait_for_key(st).is_ok());
+    }
+
+    #[test]
+    fn cooked_line_editing() {
+        let mut mock = MockSystemTable::new();
+        push_str(&mut mock, "hex\u{8}llo wörld\r");
+        let mut stdin = ConsoleIn::new(mock.system_table());
+
+        let mut buf = [0u8; 64];
+        let n = stdin.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], "hello wörld\n".as_bytes());
+        assert_eq!(mock.console_output(), "hex\u{8} \u{8}llo wörld\r\n");
+    }
+
+    #[test]
+    fn cooked_backspace_multibyte() {
+        let mut mock = MockSystemTable::new();
+        push_str(&mut mock, "aé\u{8}\u{8}\u{8}b\r");
+        let mut stdin = ConsoleIn::new(mock.system_table());
+        stdin.set_echo(false);
+
+        let mut buf = [0u8; 8];
+        let n = stdin.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"b\n");
+        assert_eq!(mock.console_output(), "");
+    }
+
+    #[test]
+    fn cooked_partial_reads() {
+        let mut mock = MockSystemTable::new();
+        push_str(&mut mock, "abcdef\rxy\r");
+        let mut stdin = ConsoleIn::new(mock.system_table());
+
+        let mut buf = [0u8; 4];
+        assert_eq!(stdin.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"abcd");
+        assert_eq!(stdin.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], b"ef\n");
+
+        let mut line = [0u8; 16];
+        let n = stdin.read_line(&mut line).unwrap();
+        assert_eq!(&line[..n], b"xy\n");
+    }
+
+    #[test]
+    fn raw_mode() {
+        let mut mock = MockSystemTable::new();
+        mock.push_key(key(0x0071, 0));
+        mock.push_key(key(0, SCAN_UP));
+        mock.push_key(key(0, 0x0010));
+        mock.push_key(key(CHAR_CARRIAGE_RETURN, 0));
+        let mut stdin = ConsoleIn::new(mock.system_table());
+        stdin.set_mode(InputMode::Raw);
+
+        let mut buf = [0u8; 16];
+        let n = stdin.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"q");
+        let n = stdin.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"\x1b[A");
+        // Keys without translation are skipped.
+        let n = stdin.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"\n");
+        assert_eq!(mock.console_output(), "");
+    }
+
+    #[test]
+    fn read_errors_are_reported() {
+        let mut mock = MockSystemTable::new();
+        push_str(&mut mock, "ab");
+        let mut stdin = ConsoleIn::new(mock.system_table());
+
+        let mut buf = [0u8; 16];
+        assert_eq!(
+            stdin.read_line(&mut buf).unwrap_err(),
+            errors::StatusNullError::UefiError(StatusCode::NotReady)
+        );
+
+        // The partial line was dropped rather than returned without its `\n`.
+        push_str(&mut mock, "c\r");
+        let n = stdin.read_line(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"c\n");
+    }
+
+    #[test]
+    fn echo_errors_keep_the_line() {
+        let mut mock = MockSystemTable::new();
+        mock.set_hook(|call| match call {
+            Call::OutputString(s) if s.contains(&CHAR_LINEFEED) => Status::DEVICE_ERROR,
+            _ => Status::SUCCESS,
+        });
+        push_str(&mut mock, "ab\r");
+        let mut stdin = ConsoleIn::new(mock.system_table());
+
+        let mut buf = [0u8; 16];
+        let n = stdin.read_line(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"ab\n");
+        assert_eq!(
+            stdin.read_line(&mut buf).unwrap_err(),
+            errors::StatusNullError::UefiError(StatusCode::DeviceError)
+        );
+
+        mock.clear_hook();
+        push_str(&mut mock, "z\r");
+        let n = stdin.read_line(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"z\n");
+        assert_eq!(mock.console_output(), "abz\r\n");
+    }
+
+    #[test]
+    fn read_line_keeps_bytes_before_error() {
+        let mut mock = MockSystemTable::new();
+        push_str(&mut mock, "ab");
+        let mut stdin = ConsoleIn::new(mock.system_table());
+        stdin.set_mode(InputMode::Raw);
+
+        let mut buf = [0u8; 16];
+        let n = stdin.read_line(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"ab");
+        assert_eq!(
+            stdin.read_line(&mut buf).unwrap_err(),
+            errors::StatusNullError::UefiError(StatusCode::NotReady)
+        );
+
+        push_str(&mut mock, "c");
+        let n = stdin.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"c");
+    }
+}
@@ -1,6 +1,10 @@
 //! This module contains functions related to SimpleTextOutput Protocol
 
+use core::fmt;
+
 use crate::efi::{Boolean, SystemTable};
+use crate::status::{Completion, WarningPolicy};
+use crate::string::{self, CStr16};
 use crate::{errors, helpers};
 use r_efi::protocols::simple_text_output;
 
@@ -9,7 +13,7 @@ pub type Result<T> = core::result::Result<T, errors::StatusNullError>;
 /// Call `Reset` function from `EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL`.
 /// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
 /// the pointer.
-pub fn reset(st: *mut SystemTable, extended_verification: bool) -> Result<()> {
+pub fn reset(st: *mut SystemTable, extended_verification: bool) -> Result<Completion<()>> {
     let conn_out_protocol = unsafe { get_protocol(st) }?;
 
     let reset_ptr = unsafe { (*conn_out_protocol).reset };
@@ -22,12 +26,13 @@ pub fn reset(st: *mut SystemTable, extended_verification: bool) -> Result<()> {
 /// Call `OutputString` function from `EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL`.
 /// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
 /// the pointer.
-pub fn output_string(st: *mut SystemTable, string: &mut [u16]) -> Result<()> {
+pub fn output_string(st: *mut SystemTable, string: &CStr16) -> Result<Completion<()>> {
     let conn_out_protocol = unsafe { get_protocol(st) }?;
 
     let output_string_ptr = unsafe { (*conn_out_protocol).output_string };
 
-    let status = (output_string_ptr)(conn_out_protocol, string.as_mut_ptr());
+    // The firmware does not modify the string, even though the parameter is not const.
+    let status = (output_string_ptr)(conn_out_protocol, string.as_ptr() as *mut u16);
 
     helpers::status_to_result(status).map_err(|x| x.into())
 }
@@ -35,12 +40,12 @@ pub fn output_string(st: *mut SystemTable, string: &mut [u16]) -> Result<()> {
 /// Call `TestString` function from `EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL`.
 /// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
 /// the pointer.
-pub fn test_string(st: *mut SystemTable, string: &mut [u16]) -> Result<()> {
+pub fn test_string(st: *mut SystemTable, string: &CStr16) -> Result<Completion<()>> {
     let conn_out_protocol = unsafe { get_protocol(st) }?;
 
     let test_string_ptr = unsafe { (*conn_out_protocol).test_string };
 
-    let status = (test_string_ptr)(conn_out_protocol, string.as_mut_ptr());
+    let status = (test_string_ptr)(conn_out_protocol, string.as_ptr() as *mut u16);
 
     helpers::status_to_result(status).map_err(|x| x.into())
 }
@@ -53,7 +58,7 @@ pub fn query_mode(
     mode_number: usize,
     columns: &mut usize,
     rows: &mut usize,
-) -> Result<()> {
+) -> Result<Completion<()>> {
     let conn_out_protocol = unsafe { get_protocol(st) }?;
 
     let query_mode_ptr = unsafe { (*conn_out_protocol).query_mode };
@@ -66,7 +71,7 @@ pub fn query_mode(
 /// Call `SetMode` function from `EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL`.
 /// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
 /// the pointer.
-pub fn set_mode(st: *mut SystemTable, mode_number: usize) -> Result<()> {
+pub fn set_mode(st: *mut SystemTable, mode_number: usize) -> Result<Completion<()>> {
     let conn_out_protocol = unsafe { get_protocol(st) }?;
 
     let set_mode_ptr = unsafe { (*conn_out_protocol).set_mode };
@@ -79,7 +84,7 @@ pub fn set_mode(st: *mut SystemTable, mode_number: usize) -> Result<()> {
 /// Call `SetAttribute` function from `EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL`.
 /// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
 /// the pointer.
-pub fn set_attribute(st: *mut SystemTable, attribute: usize) -> Result<()> {
+pub fn set_attribute(st: *mut SystemTable, attribute: usize) -> Result<Completion<()>> {
     let conn_out_protocol = unsafe { get_protocol(st) }?;
 
     let set_attribute_ptr = unsafe { (*conn_out_protocol).set_attribute };
@@ -92,7 +97,7 @@ pub fn set_attribute(st: *mut SystemTable, attribute: usize) -> Result<()> {
 /// Call `ClearScreen` function from `EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL`.
 /// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
 /// the pointer.
-pub fn clear_screen(st: *mut SystemTable) -> Result<()> {
+pub fn clear_screen(st: *mut SystemTable) -> Result<Completion<()>> {
     let conn_out_protocol = unsafe { get_protocol(st) }?;
 
     let clear_screen_ptr = unsafe { (*conn_out_protocol).clear_screen };
@@ -105,7 +110,11 @@ pub fn clear_screen(st: *mut SystemTable) -> Result<()> {
 /// Call `SetCursorPostion` function from `EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL`.
 /// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
 /// the pointer.
-pub fn set_cursor_position(st: *mut SystemTable, column: usize, row: usize) -> Result<()> {
+pub fn set_cursor_position(
+    st: *mut SystemTable,
+    column: usize,
+    row: usize,
+) -> Result<Completion<()>> {
     let conn_out_protocol = unsafe { get_protocol(st) }?;
 
     let set_cursor_position_ptr = unsafe { (*conn_out_protocol).set_cursor_position };
@@ -118,7 +127,7 @@ pub fn set_cursor_position(st: *mut SystemTable, column: usize, row: usize) -> R
 /// Call `EnableCursor` function from `EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL`.
 /// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
 /// the pointer.
-pub fn enable_cursor(st: *mut SystemTable, visible: bool) -> Result<()> {
+pub fn enable_cursor(st: *mut SystemTable, visible: bool) -> Result<Completion<()>> {
     let conn_out_protocol = unsafe { get_protocol(st) }?;
 
     let enable_cursor_ptr = unsafe { (*conn_out_protocol).enable_cursor };
@@ -152,3 +161,219 @@ pub unsafe fn get_protocol(
     helpers::null_check_mut(conn_out_protocol, "Conn Out")?;
     Ok(conn_out_protocol)
 }
+
+/// Size of the buffer used by `ConsoleOut`, in UCS-2 characters including the NUL.
+const CONSOLE_OUT_BUFFER_SIZE: usize = 128;
+
+const CR: u16 = 0x000d;
+const LF: u16 = 0x000a;
+
+/// A `core::fmt::Write` implementation over `EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL`.
+/// UTF-8 is converted in chunks through a fixed stack buffer, so no allocation is needed.
+/// `\n` is translated to `\r\n`, and characters which cannot be represented in UCS-2 are
+/// replaced with U+FFFD.
+/// Only the `SystemTable` pointer is kept. The protocol is looked up again on every write.
+pub struct ConsoleOut {
+    st: *mut SystemTable,
+    warning_policy: WarningPolicy,
+    last_error: Option<errors::StatusNullError>,
+    last_was_cr: bool,
+}
+
+impl ConsoleOut {
+    /// Create a writer which ignores warnings, such as `EFI_WARN_UNKNOWN_GLYPH`.
+    /// SAFETY : The `st` pointer must be valid for as long as the writer is used. This is
+    /// gaurenteed if `GlobalData` is used to store the pointer.
+    pub fn new(st: *mut SystemTable) -> Self {
+        Self::with_warning_policy(st, WarningPolicy::Ignore)
+    }
+
+    pub fn with_warning_policy(st: *mut SystemTable, warning_policy: WarningPolicy) -> Self {
+        Self {
+            st,
+            warning_policy,
+            last_error: None,
+            last_was_cr: false,
+        }
+    }
+
+    /// The error behind the last `fmt::Error` returned by this writer, if any.
+    pub fn take_last_error(&mut self) -> Option<errors::StatusNullError> {
+        self.last_error.take()
+    }
+
+    /// Write `buf[..len]` and reset `len`.
+    fn flush(&mut self, buf: &mut [u16], len: &mut usize) -> fmt::Result {
+        buf[*len] = 0;
+        let s = unsafe { CStr16::from_u16_with_nul_unchecked(&buf[..=*len]) };
+        *len = 0;
+
+        match self.warning_policy.apply(output_string(self.st, s)) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                self.last_error = Some(e);
+                Err(fmt::Error)
+            }
+        }
+    }
+}
+
+impl fmt::Write for ConsoleOut {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut buf = [0u16; CONSOLE_OUT_BUFFER_SIZE];
+        let mut len = 0;
+
+        for c in s.chars() {
+            // Keep space for a `\r\n` pair and the NUL.
+            if len + 3 > buf.len() {
+                self.flush(&mut buf, &mut len)?;
+            }
+
+            if c == '\n' && !self.last_was_cr {
+                buf[len] = CR;
+                len += 1;
+            }
+            buf[len] = if c == '\n' {
+                LF
+            } else {
+                string::encode_char_lossy(c)
+            };
+            len += 1;
+            self.last_was_cr = c == '\r';
+        }
+
+        if len > 0 {
+            self.flush(&mut buf, &mut len)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::efi::Status;
+    use crate::mock::{Call, MockSystemTable};
+    use crate::status::StatusCode;
+    use core::fmt::Write;
+
+    #[test]
+    fn output_string_is_recorded() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let s = CStr16::from_u16_with_nul(&[0x0048, 0x0069, 0x000a, 0x0000]).unwrap();
+        assert!(output_string(st, s).is_ok());
+        assert_eq!(mock.console_output(), "Hi\n");
+    }
+
+    #[test]
+    fn output_string_warning() {
+        let mut mock = MockSystemTable::new();
+        mock.set_hook(|call| match call {
+            Call::OutputString(_) => Status::WARN_UNKNOWN_GLYPH,
+            _ => Status::SUCCESS,
+        });
+        let st = mock.system_table();
+
+        let s = CStr16::from_u16_with_nul(&[0x0048, 0x0000]).unwrap();
+        let r = output_string(st, s).unwrap();
+        assert_eq!(r.warning(), Some(StatusCode::WarnUnknownGlyph));
+        assert_eq!(mock.console_output(), "H");
+    }
+
+    #[test]
+    fn query_and_set_mode() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let (mut columns, mut rows) = (0, 0);
+        assert!(query_mode(st, 1, &mut columns, &mut rows).is_ok());
+        assert_eq!((columns, rows), (80, 50));
+
+        assert!(set_mode(st, 1).is_ok());
+        assert!(set_cursor_position(st, 3, 4).is_ok());
+        let mode = unsafe { *get_mode(st).unwrap() };
+        assert_eq!(mode.mode, 1);
+        assert_eq!((mode.cursor_column, mode.cursor_row), (3, 4));
+    }
+
+    #[test]
+    fn null_con_out() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        unsafe { (*st).con_out = core::ptr::null_mut() };
+
+        assert_eq!(
+            clear_screen(st),
+            Err(errors::StatusNullError::NullPtrError("Conn Out"))
+        );
+    }
+
+    #[test]
+    fn console_out_newlines() {
+        let mut mock = MockSystemTable::new();
+        let mut out = ConsoleOut::new(mock.system_table());
+
+        write!(out, "Hello\nWorld {}\r\n", 42).unwrap();
+        assert_eq!(mock.console_output(), "Hello\r\nWorld 42\r\n");
+    }
+
+    #[test]
+    fn console_out_chunks() {
+        let mut mock = MockSystemTable::new();
+        let mut out = ConsoleOut::new(mock.system_table());
+
+        let s = "abc\n".repeat(100);
+        out.write_str(&s).unwrap();
+        assert_eq!(mock.console_output(), s.replace('\n', "\r\n"));
+    }
+
+    #[test]
+    fn console_out_replacement() {
+        let mut mock = MockSystemTable::new();
+        let mut out = ConsoleOut::new(mock.system_table());
+
+        out.write_str("a\u{1F600}\0b").unwrap();
+        assert_eq!(mock.console_output(), "a\u{FFFD}\u{FFFD}b");
+    }
+
+    #[test]
+    fn console_out_warning_policy() {
+        let mut mock = MockSystemTable::new();
+        mock.set_hook(|call| match call {
+            Call::OutputString(_) => Status::WARN_UNKNOWN_GLYPH,
+            _ => Status::SUCCESS,
+        });
+
+        let mut out = ConsoleOut::new(mock.system_table());
+        assert!(out.write_str("a").is_ok());
+
+        let mut out = ConsoleOut::with_warning_policy(mock.system_table(), WarningPolicy::Promote);
+        assert!(out.write_str("a").is_err());
+        assert_eq!(
+            out.take_last_error(),
+            Some(errors::StatusNullError::UefiWarning(
+                StatusCode::WarnUnknownGlyph
+            ))
+        );
+    }
+
+    #[test]
+    fn console_out_error() {
+        let mut mock = MockSystemTable::new();
+        mock.set_hook(|call| match call {
+            Call::OutputString(_) => Status::DEVICE_ERROR,
+            _ => Status::SUCCESS,
+        });
+
+        let mut out = ConsoleOut::new(mock.system_table());
+        assert!(out.write_str("a").is_err());
+        assert_eq!(
+            out.take_last_error(),
+            Some(errors::StatusNullError::UefiError(StatusCode::DeviceError))
+        );
+        assert_eq!(out.take_last_error(), None);
+    }
+}
@@ -0,0 +1,724 @@
+//! This module contains functions related to Capsule Services.
+//!
+//! A capsule is an `EFI_CAPSULE_HEADER` followed by its payload. Capsules which persist across a
+//! reset are handed to the firmware through a scatter-gather list, a chain of
+//! `EFI_CAPSULE_BLOCK_DESCRIPTOR` arrays pointing at the physical memory of the capsules. Since
+//! memory is identity mapped during boot services, the addresses are plain pointers.
+
+#[cfg(any(test, feature = "alloc"))]
+use crate::{
+    boot_services::memory_allocation_services::{allocate_pool, PoolBuffer},
+    efi::LOADER_DATA,
+    runtime_services::time::EfiTime,
+    runtime_services::variable_services::get_variable_vec,
+    status::StatusCode,
+    string::CString16,
+};
+use crate::{
+    efi::{Guid, PhysicalAddress, SystemTable},
+    errors::{self, CapsuleError},
+    helpers,
+    status::Completion,
+};
+#[cfg(any(test, feature = "alloc"))]
+use alloc::vec::Vec;
+#[cfg(any(test, feature = "alloc"))]
+use core::marker::PhantomData;
+use core::mem::size_of;
+use r_efi::efi::{self, CapsuleHeader};
+#[cfg(any(test, feature = "alloc"))]
+use r_efi::efi::{CapsuleBlockDescriptor, CapsuleBlockDescriptorUnion};
+
+pub type Result<T> = core::result::Result<T, errors::StatusNullError>;
+
+/// The capsule GUID of Firmware Management Protocol capsules. Their `CapsuleResult####`
+/// variables carry the FMP specific fields.
+pub const FIRMWARE_MANAGEMENT_CAPSULE_ID_GUID: Guid = Guid::from_fields(
+    0x6dcbd5ed,
+    0xe82d,
+    0x4c44,
+    0xbd,
+    0xa1,
+    &[0x71, 0x94, 0x19, 0x9a, 0xd9, 0x2a],
+);
+
+/// How many descriptors `ScatterGatherList::new` puts in each descriptor block, including the
+/// continuation pointer.
+pub const DEFAULT_DESCRIPTORS_PER_BLOCK: usize = 64;
+
+/// The flags of a capsule. The low 16 bits are defined by the capsule GUID.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CapsuleFlags(u32);
+
+impl CapsuleFlags {
+    /// Keep the capsule in memory across a system reset, to be processed after it.
+    pub const PERSIST_ACROSS_RESET: Self = Self(efi::CAPSULE_FLAGS_PERSIST_ACROSS_RESET);
+    /// Install the capsule into the configuration table after the reset.
+    pub const POPULATE_SYSTEM_TABLE: Self = Self(efi::CAPSULE_FLAGS_POPULATE_SYSTEM_TABLE);
+    /// Let the firmware reset the system from `update_capsule`.
+    pub const INITIATE_RESET: Self = Self(efi::CAPSULE_FLAGS_INITIATE_RESET);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Check that the reset related flags are only set together with `PERSIST_ACROSS_RESET`.
+    pub fn validate(&self) -> core::result::Result<(), CapsuleError> {
+        let needs_persist = Self::POPULATE_SYSTEM_TABLE.0 | Self::INITIATE_RESET.0;
+        if self.0 & needs_persist != 0 && !self.contains(Self::PERSIST_ACROSS_RESET) {
+            Err(CapsuleError::InvalidFlags)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl core::ops::BitOr for CapsuleFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// A capsule in memory, e.g. read from a capsule-on-disk file.
+#[derive(Clone, Copy, Debug)]
+pub struct CapsuleRef<'a> {
+    header: CapsuleHeader,
+    data: &'a [u8],
+}
+
+impl<'a> CapsuleRef<'a> {
+    /// Parse the capsule at the start of `data`. Trailing bytes are ignored.
+    pub fn parse(data: &'a [u8]) -> core::result::Result<Self, CapsuleError> {
+        if data.len() < size_of::<CapsuleHeader>() {
+            return Err(CapsuleError::Truncated);
+        }
+        // Files are read into byte buffers, so the header may be unaligned.
+        let header = unsafe { (data.as_ptr() as *const CapsuleHeader).read_unaligned() };
+
+        let header_size = header.header_size as usize;
+        let image_size = header.capsule_image_size as usize;
+        if header_size < size_of::<CapsuleHeader>() || header_size > image_size {
+            return Err(CapsuleError::InvalidHeaderSize);
+        }
+        if data.len() < image_size {
+            return Err(CapsuleError::Truncated);
+        }
+        CapsuleFlags(header.flags).validate()?;
+
+        Ok(Self {
+            header,
+            data: &data[..image_size],
+        })
+    }
+
+    pub fn header(&self) -> &CapsuleHeader {
+        &self.header
+    }
+
+    pub fn guid(&self) -> Guid {
+        self.header.capsule_guid
+    }
+
+    pub fn flags(&self) -> CapsuleFlags {
+        CapsuleFlags(self.header.flags)
+    }
+
+    /// The data following the header, as given by `HeaderSize`.
+    pub fn payload(&self) -> &'a [u8] {
+        &self.data[self.header.header_size as usize..]
+    }
+
+    /// The whole capsule, header included.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+/// An iterator over the capsules stored back to back in a buffer, as in capsule-on-disk files.
+/// Stops after the first error.
+#[derive(Clone, Debug)]
+pub struct CapsuleIter<'a> {
+    data: &'a [u8],
+}
+
+/// Iterate over the capsules in the contents of a capsule-on-disk file.
+pub fn parse_capsules(data: &[u8]) -> CapsuleIter<'_> {
+    CapsuleIter { data }
+}
+
+impl<'a> Iterator for CapsuleIter<'a> {
+    type Item = core::result::Result<CapsuleRef<'a>, CapsuleError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        match CapsuleRef::parse(self.data) {
+            Ok(capsule) => {
+                self.data = &self.data[capsule.as_bytes().len()..];
+                Some(Ok(capsule))
+            }
+            Err(e) => {
+                self.data = &[];
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// An owned capsule, with the header placed at a suitably aligned address.
+#[cfg(any(test, feature = "alloc"))]
+#[derive(Clone)]
+pub struct Capsule {
+    // u64 so that the header is aligned.
+    buffer: Vec<u64>,
+    len: usize,
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl Capsule {
+    /// Build a capsule from its GUID, flags and payload.
+    pub fn new(
+        guid: Guid,
+        flags: CapsuleFlags,
+        payload: &[u8],
+    ) -> core::result::Result<Self, CapsuleError> {
+        flags.validate()?;
+        let len = size_of::<CapsuleHeader>() + payload.len();
+        let image_size = u32::try_from(len).map_err(|_| CapsuleError::TooLarge)?;
+
+        let header = CapsuleHeader {
+            capsule_guid: guid,
+            header_size: size_of::<CapsuleHeader>() as u32,
+            flags: flags.bits(),
+            capsule_image_size: image_size,
+        };
+        let mut capsule = Self {
+            buffer: alloc::vec![0; len.div_ceil(8)],
+            len,
+        };
+        unsafe { (capsule.buffer.as_mut_ptr() as *mut CapsuleHeader).write(header) };
+        capsule.as_bytes_mut()[size_of::<CapsuleHeader>()..].copy_from_slice(payload);
+        Ok(capsule)
+    }
+
+    /// Copy a capsule, e.g. one parsed from a capsule-on-disk file.
+    pub fn from_ref(capsule: &CapsuleRef<'_>) -> Self {
+        let bytes = capsule.as_bytes();
+        let mut r = Self {
+            buffer: alloc::vec![0; bytes.len().div_ceil(8)],
+            len: bytes.len(),
+        };
+        r.as_bytes_mut().copy_from_slice(bytes);
+        r
+    }
+
+    pub fn as_capsule_ref(&self) -> CapsuleRef<'_> {
+        CapsuleRef {
+            header: unsafe { *self.header_ptr() },
+            data: self.as_bytes(),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.buffer.as_ptr().cast(), self.len) }
+    }
+
+    fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.buffer.as_mut_ptr().cast(), self.len) }
+    }
+
+    /// The pointer passed to `update_capsule` and `query_capsule_capabilities`.
+    pub fn header_ptr(&self) -> *mut CapsuleHeader {
+        self.buffer.as_ptr() as *mut CapsuleHeader
+    }
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl core::fmt::Debug for Capsule {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Capsule")
+            .field("header", self.as_capsule_ref().header())
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+/// The scatter-gather list describing a set of capsules, as needed by `update_capsule` for
+/// capsules with `CapsuleFlags::PERSIST_ACROSS_RESET`.
+///
+/// The descriptors are split into pool allocated blocks. Every block but the last ends with a
+/// continuation pointer to the next block, the last one with a terminating null descriptor.
+#[cfg(any(test, feature = "alloc"))]
+pub struct ScatterGatherList<'a> {
+    blocks: Vec<PoolBuffer<CapsuleBlockDescriptor>>,
+    // The descriptors point into the capsules.
+    _capsules: PhantomData<&'a Capsule>,
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl<'a> ScatterGatherList<'a> {
+    /// Describe `capsules`, with `DEFAULT_DESCRIPTORS_PER_BLOCK` descriptors per block.
+    /// SAFETY : The `st` pointer must be valid for the lifetime of the list. This is gaurenteed
+    /// if `GlobalData` is used to store the pointer.
+    pub fn new(st: *mut SystemTable, capsules: &[&'a Capsule]) -> Result<Self> {
+        Self::with_descriptors_per_block(st, capsules, DEFAULT_DESCRIPTORS_PER_BLOCK)
+    }
+
+    /// Describe `capsules`, with at most `descriptors_per_block` descriptors in each block. Panics
+    /// if `descriptors_per_block` is less than 2, since each block needs room for a data
+    /// descriptor and the continuation pointer.
+    /// SAFETY : The `st` pointer must be valid for the lifetime of the list. This is gaurenteed
+    /// if `GlobalData` is used to store the pointer.
+    pub fn with_descriptors_per_block(
+        st: *mut SystemTable,
+        capsules: &[&'a Capsule],
+        descriptors_per_block: usize,
+    ) -> Result<Self> {
+        assert!(descriptors_per_block >= 2);
+
+        let mut blocks: Vec<PoolBuffer<CapsuleBlockDescriptor>> = Vec::new();
+        // Filled in reverse, so that each block can point to the one after it.
+        let chunks: Vec<_> = capsules.chunks(descriptors_per_block - 1).collect();
+        let mut next: PhysicalAddress = 0;
+        for chunk in chunks.iter().rev() {
+            let len = chunk.len() + 1;
+            let mut ptr = core::ptr::null_mut();
+            allocate_pool(
+                st,
+                LOADER_DATA,
+                len * size_of::<CapsuleBlockDescriptor>(),
+                &mut ptr,
+            )?
+            .into_value();
+            let ptr = ptr as *mut CapsuleBlockDescriptor;
+
+            for (i, capsule) in chunk.iter().enumerate() {
+                let descriptor = CapsuleBlockDescriptor {
+                    length: capsule.len as u64,
+                    data: CapsuleBlockDescriptorUnion {
+                        data_block: capsule.header_ptr() as PhysicalAddress,
+                    },
+                };
+                unsafe { ptr.add(i).write(descriptor) };
+            }
+            let link = CapsuleBlockDescriptor {
+                length: 0,
+                data: CapsuleBlockDescriptorUnion {
+                    continuation_pointer: next,
+                },
+            };
+            unsafe { ptr.add(chunk.len()).write(link) };
+
+            next = ptr as PhysicalAddress;
+            blocks.push(unsafe { PoolBuffer::from_raw_parts(st, ptr, len) });
+        }
+        blocks.reverse();
+
+        Ok(Self {
+            blocks,
+            _capsules: PhantomData,
+        })
+    }
+
+    /// The physical address of the first descriptor block, or 0 if there are no capsules.
+    pub fn address(&self) -> PhysicalAddress {
+        self.blocks
+            .first()
+            .map_or(0, |x| x.as_ptr() as PhysicalAddress)
+    }
+
+    /// The number of descriptor blocks.
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// The data descriptors, as `(address, length)`, in the order the firmware sees them.
+    pub fn data_blocks(&self) -> impl Iterator<Item = (PhysicalAddress, u64)> + '_ {
+        self.blocks.iter().flat_map(|block| {
+            block
+                .iter()
+                .filter(|x| x.length != 0)
+                .map(|x| (unsafe { x.data.data_block }, x.length))
+        })
+    }
+}
+
+/// What the firmware reported from `query_capsule_capabilities`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CapsuleCapabilities {
+    /// The largest capsule the firmware accepts, in bytes.
+    pub maximum_capsule_size: u64,
+    /// The kind of reset needed to process the capsules, one of the `efi::RESET_*` values.
+    pub reset_type: efi::ResetType,
+}
+
+/// Call EFI_UPDATE_CAPSULE runtime service function.
+/// `scatter_gather_list` is required if any capsule has `CapsuleFlags::PERSIST_ACROSS_RESET`,
+/// see `ScatterGatherList::address`.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer. `capsule_headers` must point to valid capsules, which must stay in place until
+/// the reset if they persist across it.
+pub fn update_capsule(
+    st: *mut SystemTable,
+    capsule_headers: &[*mut CapsuleHeader],
+    scatter_gather_list: Option<PhysicalAddress>,
+) -> Result<Completion<()>> {
+    let runtime_services = super::get_runtime_services(st)?;
+    let update_capsule_ptr = unsafe { (*runtime_services).update_capsule };
+
+    let status = (update_capsule_ptr)(
+        capsule_headers.as_ptr() as *mut *mut CapsuleHeader,
+        capsule_headers.len(),
+        scatter_gather_list.unwrap_or(0),
+    );
+
+    helpers::status_to_result(status).map_err(|x| x.into())
+}
+
+/// Call EFI_QUERY_CAPSULE_CAPABILITIES runtime service function.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer. `capsule_headers` must point to valid capsules.
+pub fn query_capsule_capabilities(
+    st: *mut SystemTable,
+    capsule_headers: &[*mut CapsuleHeader],
+) -> Result<Completion<CapsuleCapabilities>> {
+    let runtime_services = super::get_runtime_services(st)?;
+    let query_capsule_capabilities_ptr = unsafe { (*runtime_services).query_capsule_capabilities };
+
+    let mut capabilities = CapsuleCapabilities {
+        maximum_capsule_size: 0,
+        reset_type: efi::RESET_COLD,
+    };
+    let status = (query_capsule_capabilities_ptr)(
+        capsule_headers.as_ptr() as *mut *mut CapsuleHeader,
+        capsule_headers.len(),
+        &mut capabilities.maximum_capsule_size,
+        &mut capabilities.reset_type,
+    );
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| capabilities))
+}
+
+/// The FMP specific part of a `CapsuleResult####` variable.
+#[cfg(any(test, feature = "alloc"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapsuleResultFmp {
+    pub version: u16,
+    pub payload_index: u8,
+    pub update_image_index: u8,
+    pub update_image_type_id: Guid,
+    pub capsule_file_name: CString16,
+    pub capsule_target: CString16,
+}
+
+/// A parsed `CapsuleResult####` variable, reporting how a capsule was processed.
+#[cfg(any(test, feature = "alloc"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapsuleResult {
+    pub capsule_guid: Guid,
+    /// When the capsule was processed, or `None` if the firmware left the time invalid.
+    pub capsule_processed: Option<EfiTime>,
+    pub capsule_status: StatusCode,
+    /// Present for capsules with `FIRMWARE_MANAGEMENT_CAPSULE_ID_GUID`.
+    pub fmp: Option<CapsuleResultFmp>,
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl CapsuleResult {
+    /// Parse the data of a `CapsuleResult####` variable.
+    pub fn parse(data: &[u8]) -> core::result::Result<Self, CapsuleError> {
+        let header_size = size_of::<efi::CapsuleResultVariableHeader>();
+        if data.len() < header_size {
+            return Err(CapsuleError::Truncated);
+        }
+        let header =
+            unsafe { (data.as_ptr() as *const efi::CapsuleResultVariableHeader).read_unaligned() };
+        let total_size = header.variable_total_size as usize;
+        if total_size < header_size {
+            return Err(CapsuleError::InvalidHeaderSize);
+        }
+        let data = data.get(..total_size).ok_or(CapsuleError::Truncated)?;
+
+        let fmp = if header.capsule_guid == FIRMWARE_MANAGEMENT_CAPSULE_ID_GUID {
+            Some(parse_fmp(&data[header_size..])?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            capsule_guid: header.capsule_guid,
+            capsule_processed: EfiTime::from_raw(&header.capsule_processed).ok(),
+            capsule_status: header.capsule_status.into(),
+            fmp,
+        })
+    }
+}
+
+/// Parse the fields following the header of an FMP `CapsuleResult####` variable.
+#[cfg(any(test, feature = "alloc"))]
+fn parse_fmp(data: &[u8]) -> core::result::Result<CapsuleResultFmp, CapsuleError> {
+    let fixed_size = size_of::<efi::CapsuleResultVariableFMP>();
+    if data.len() < fixed_size {
+        return Err(CapsuleError::Truncated);
+    }
+    let fmp = unsafe { (data.as_ptr() as *const efi::CapsuleResultVariableFMP).read_unaligned() };
+
+    // Two NUL-terminated strings follow, the file name and the target.
+    let chars: Vec<u16> = data[fixed_size..]
+        .chunks_exact(2)
+        .map(|x| u16::from_le_bytes([x[0], x[1]]))
+        .collect();
+    let mut strings = chars.splitn(3, |&c| c == 0);
+    let capsule_file_name = strings.next().unwrap_or(&[]);
+    let capsule_target = strings.next().ok_or(CapsuleError::Truncated)?;
+    // Without a second NUL, `splitn` yields the unterminated rest as the target.
+    if strings.next().is_none() {
+        return Err(CapsuleError::Truncated);
+    }
+
+    Ok(CapsuleResultFmp {
+        version: fmp.version,
+        payload_index: fmp.payload_index,
+        update_image_index: fmp.update_image_index,
+        update_image_type_id: fmp.update_image_type_id,
+        capsule_file_name: CString16::from_u16_lossy(capsule_file_name),
+        capsule_target: CString16::from_u16_lossy(capsule_target),
+    })
+}
+
+/// Read and parse the `CapsuleResult####` variable with the given index. A malformed variable is
+/// reported as `EFI_COMPROMISED_DATA`.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+#[cfg(any(test, feature = "alloc"))]
+pub fn read_capsule_result(st: *mut SystemTable, index: u16) -> Result<Completion<CapsuleResult>> {
+    let name = CString16::from_str_lossy(&alloc::format!("CapsuleResult{:04X}", index));
+    let r = get_variable_vec(st, &name, &efi::CAPSULE_REPORT_GUID)?;
+    let result = CapsuleResult::parse(&r.value().0)
+        .map_err(|_| errors::StatusNullError::UefiError(StatusCode::CompromisedData))?;
+    Ok(r.map(|_| result))
+}
+
+/// The index of the most recent `CapsuleResult####` variable, from the `CapsuleLast` variable.
+/// Returns `None` if no capsule has been processed yet.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+#[cfg(any(test, feature = "alloc"))]
+pub fn last_capsule_result_index(st: *mut SystemTable) -> Result<Completion<Option<u16>>> {
+    let name = CString16::from_str_lossy("CapsuleLast");
+    let r = match get_variable_vec(st, &name, &efi::CAPSULE_REPORT_GUID) {
+        Ok(r) => r,
+        Err(errors::StatusNullError::UefiError(StatusCode::NotFound)) => {
+            return Ok(Completion::new(None))
+        }
+        Err(e) => return Err(e),
+    };
+    let data = &r.value().0;
+
+    // "CapsuleResult####", with or without the NUL.
+    let s: alloc::string::String = char::decode_utf16(
+        data.chunks_exact(2)
+            .map(|x| u16::from_le_bytes([x[0], x[1]]))
+            .take_while(|&c| c != 0),
+    )
+    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+    .collect();
+    let index = s
+        .strip_prefix("CapsuleResult")
+        .filter(|x| x.len() == 4)
+        .and_then(|x| u16::from_str_radix(x, 16).ok())
+        .ok_or(errors::StatusNullError::UefiError(
+            StatusCode::CompromisedData,
+        ))?;
+    Ok(r.map(|_| Some(index)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{Call, MockSystemTable};
+    use crate::runtime_services::variable_services::{set_variable, VariableAttributes};
+    use crate::status::StatusCode;
+
+    const GUID: Guid = Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]);
+
+    fn persist() -> CapsuleFlags {
+        CapsuleFlags::PERSIST_ACROSS_RESET | CapsuleFlags::from_bits(0x1234)
+    }
+
+    #[test]
+    fn build_and_parse() {
+        let capsule = Capsule::new(GUID, persist(), b"payload").unwrap();
+        assert_eq!(capsule.as_bytes().len(), 28 + 7);
+        assert_eq!(capsule.header_ptr() as usize % 8, 0);
+
+        // Capsule-on-disk files may hold several capsules back to back.
+        let second = Capsule::new(GUID, CapsuleFlags::empty(), b"x").unwrap();
+        let mut file = capsule.as_bytes().to_vec();
+        file.extend_from_slice(second.as_bytes());
+        let parsed: Vec<_> = parse_capsules(&file).map(|x| x.unwrap()).collect();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].guid(), GUID);
+        assert_eq!(parsed[0].flags(), persist());
+        assert_eq!(parsed[0].payload(), b"payload");
+        assert_eq!(parsed[1].payload(), b"x");
+        assert_eq!(Capsule::from_ref(&parsed[1]).as_bytes(), second.as_bytes());
+
+        assert_eq!(
+            CapsuleRef::parse(&file[..30]).unwrap_err(),
+            CapsuleError::Truncated
+        );
+        assert_eq!(
+            Capsule::new(GUID, CapsuleFlags::INITIATE_RESET, &[]).unwrap_err(),
+            CapsuleError::InvalidFlags
+        );
+    }
+
+    #[test]
+    fn scatter_gather_continuation() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let capsules: Vec<Capsule> = (0..5u8)
+            .map(|i| Capsule::new(GUID, persist(), &[i; 3]).unwrap())
+            .collect();
+        let refs: Vec<&Capsule> = capsules.iter().collect();
+        // Two data descriptors and a continuation pointer per block.
+        let list = ScatterGatherList::with_descriptors_per_block(st, &refs, 3).unwrap();
+        assert_eq!(list.block_count(), 3);
+        let expected: Vec<_> = capsules
+            .iter()
+            .map(|x| (x.header_ptr() as PhysicalAddress, 31))
+            .collect();
+        assert_eq!(list.data_blocks().collect::<Vec<_>>(), expected);
+
+        let headers: Vec<_> = capsules.iter().map(Capsule::header_ptr).collect();
+        update_capsule(st, &headers, Some(list.address()))
+            .unwrap()
+            .into_value();
+        let update = mock.capsule_updates().pop().unwrap();
+        assert_eq!(update.capsules.len(), 5);
+        assert_eq!(update.capsules[4], capsules[4].as_bytes());
+        // The mock followed the continuation pointers.
+        assert_eq!(update.data_blocks, expected);
+
+        drop(list);
+        assert_eq!(mock.outstanding_pool_allocations(), 0);
+    }
+
+    #[test]
+    fn update_requires_scatter_gather() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let capsule = Capsule::new(GUID, persist(), b"data").unwrap();
+        assert_eq!(
+            update_capsule(st, &[capsule.header_ptr()], None),
+            Err(errors::StatusNullError::UefiError(
+                StatusCode::InvalidParameter
+            ))
+        );
+
+        let capabilities = query_capsule_capabilities(st, &[capsule.header_ptr()])
+            .unwrap()
+            .into_value();
+        assert_eq!(capabilities.reset_type, efi::RESET_WARM);
+        assert!(capabilities.maximum_capsule_size > 0);
+    }
+
+    #[test]
+    fn capsule_result_variable() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let time = EfiTime::new(2024, 5, 6, 7, 8, 9, 0).unwrap();
+        let header = efi::CapsuleResultVariableHeader {
+            variable_total_size: 0,
+            reserved: 0,
+            capsule_guid: FIRMWARE_MANAGEMENT_CAPSULE_ID_GUID,
+            capsule_processed: time.to_raw(),
+            capsule_status: efi::Status::ABORTED,
+        };
+        let fmp = efi::CapsuleResultVariableFMP::<0> {
+            version: 1,
+            payload_index: 0,
+            update_image_index: 2,
+            update_image_type_id: GUID,
+            capsule_file_name_and_target: [],
+        };
+        let mut data = Vec::new();
+        data.extend_from_slice(unsafe {
+            core::slice::from_raw_parts(
+                &header as *const _ as *const u8,
+                size_of::<efi::CapsuleResultVariableHeader>(),
+            )
+        });
+        data.extend_from_slice(unsafe {
+            core::slice::from_raw_parts(
+                &fmp as *const _ as *const u8,
+                size_of::<efi::CapsuleResultVariableFMP>(),
+            )
+        });
+        for c in "a.cap\0\0".encode_utf16() {
+            data.extend_from_slice(&c.to_le_bytes());
+        }
+        let total = data.len() as u32;
+        data[..4].copy_from_slice(&total.to_le_bytes());
+
+        let attributes = VariableAttributes::NON_VOLATILE
+            | VariableAttributes::BOOTSERVICE_ACCESS
+            | VariableAttributes::RUNTIME_ACCESS;
+        let name = CString16::from_str_lossy("CapsuleResult000A");
+        set_variable(st, &name, &efi::CAPSULE_REPORT_GUID, attributes, &data)
+            .unwrap()
+            .into_value();
+        let last: Vec<u8> = "CapsuleResult000A\0"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let name = CString16::from_str_lossy("CapsuleLast");
+        set_variable(st, &name, &efi::CAPSULE_REPORT_GUID, attributes, &last)
+            .unwrap()
+            .into_value();
+
+        let index = last_capsule_result_index(st).unwrap().into_value().unwrap();
+        assert_eq!(index, 10);
+        mock.set_hook(|call| match call {
+            Call::GetVariable { .. } => efi::Status::WARN_STALE_DATA,
+            _ => efi::Status::SUCCESS,
+        });
+        let last = last_capsule_result_index(st).unwrap();
+        assert_eq!(last.warning(), Some(StatusCode::WarnStaleData));
+        assert_eq!(last.into_value(), Some(10));
+        mock.clear_hook();
+        let result = read_capsule_result(st, index).unwrap().into_value();
+        assert_eq!(result.capsule_status, StatusCode::Aborted);
+        assert_eq!(result.capsule_processed, Some(time));
+        let fmp = result.fmp.unwrap();
+        assert_eq!(fmp.update_image_index, 2);
+        assert_eq!(fmp.capsule_file_name, "a.cap");
+        assert_eq!(fmp.capsule_target, "");
+
+        assert_eq!(
+            CapsuleResult::parse(&data[..data.len() - 2]),
+            Err(CapsuleError::Truncated)
+        );
+    }
+}
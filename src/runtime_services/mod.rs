@@ -0,0 +1,143 @@
+//! This module provides APIS for various runtime services.
+
+pub mod capsule_services;
+pub mod time;
+pub mod variable_services;
+pub mod virtual_memory;
+
+use crate::{efi, errors, helpers, string::CStr16};
+
+/// The longest reason, in characters, passed along with `ResetType::PlatformSpecific`. Longer
+/// reasons are truncated, since the reset data is built on the stack.
+pub const MAX_PLATFORM_SPECIFIC_REASON_LEN: usize = 255;
+
+/// The GUID following the NUL of the reason takes 8 UCS-2 characters.
+const GUID_LEN: usize = core::mem::size_of::<efi::Guid>() / 2;
+
+pub fn get_runtime_services(
+    st: *mut efi::SystemTable,
+) -> Result<*mut efi::RuntimeServices, errors::NullPtrError> {
+    let runtime_services = unsafe { (*st).runtime_services };
+    helpers::null_check_mut(runtime_services, "Runtime Services")?;
+    Ok(unsafe { &mut *runtime_services })
+}
+
+/// The kind of reset requested from `reset_system`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResetType {
+    /// Reset all circuitry, like a power cycle.
+    Cold,
+    /// Reset the processors, keeping the memory contents where supported.
+    Warm,
+    /// Power off the system.
+    Shutdown,
+    /// A reset defined by the platform, identified by the GUID.
+    PlatformSpecific(efi::Guid),
+}
+
+impl ResetType {
+    pub fn as_raw(&self) -> r_efi::efi::ResetType {
+        match self {
+            Self::Cold => r_efi::efi::RESET_COLD,
+            Self::Warm => r_efi::efi::RESET_WARM,
+            Self::Shutdown => r_efi::efi::RESET_SHUTDOWN,
+            Self::PlatformSpecific(_) => r_efi::efi::RESET_PLATFORM_SPECIFIC,
+        }
+    }
+}
+
+/// Call EFI_RESET_SYSTEM runtime service function.
+/// `reason` is a human readable description passed to the firmware as reset data. For
+/// `ResetType::PlatformSpecific`, the data is the reason, truncated to
+/// `MAX_PLATFORM_SPECIFIC_REASON_LEN` characters, followed by the GUID.
+/// Panics if the runtime services are missing or the firmware returns.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn reset_system(
+    st: *mut efi::SystemTable,
+    reset_type: ResetType,
+    status: efi::Status,
+    reason: Option<&CStr16>,
+) -> ! {
+    let runtime_services = match get_runtime_services(st) {
+        Ok(x) => x,
+        Err(e) => panic!("{}", e),
+    };
+    let reset_system_ptr = unsafe { (*runtime_services).reset_system };
+
+    // Stays alive until the call, since the data may point into it.
+    let mut buffer = [0u16; MAX_PLATFORM_SPECIFIC_REASON_LEN + 1 + GUID_LEN];
+    let (data_size, data) = match (reset_type, reason) {
+        (ResetType::PlatformSpecific(guid), reason) => {
+            let reason = reason.map_or(&[][..], |x| x.as_slice());
+            let len = reason.len().min(MAX_PLATFORM_SPECIFIC_REASON_LEN);
+            buffer[..len].copy_from_slice(&reason[..len]);
+            // The GUID directly follows the NUL, so it is not necessarily 4-byte aligned.
+            let guid_ptr = buffer[len + 1..].as_mut_ptr() as *mut efi::Guid;
+            unsafe { guid_ptr.write_unaligned(guid) };
+            (
+                (len + 1 + GUID_LEN) * 2,
+                buffer.as_mut_ptr() as *mut core::ffi::c_void,
+            )
+        }
+        (_, Some(reason)) => (reason.size_in_bytes(), reason.as_ptr() as *mut _),
+        (_, None) => (0, core::ptr::null_mut()),
+    };
+
+    (reset_system_ptr)(reset_type.as_raw(), status, data_size, data);
+
+    panic!("EFI_RESET_SYSTEM returned");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockSystemTable;
+    use crate::string::CString16;
+    use std::panic::{self, AssertUnwindSafe};
+
+    /// The mock returns from ResetSystem, so `reset_system` panics afterwards.
+    fn reset(st: *mut efi::SystemTable, reset_type: ResetType, reason: Option<&CStr16>) {
+        let r = panic::catch_unwind(AssertUnwindSafe(|| {
+            reset_system(st, reset_type, efi::Status::SUCCESS, reason)
+        }));
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn reset_with_reason() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        reset(st, ResetType::Shutdown, None);
+        let record = mock.reset_record().unwrap();
+        assert_eq!(record.reset_type, r_efi::efi::RESET_SHUTDOWN);
+        assert!(record.data.is_empty());
+
+        let reason = CString16::from_str_lossy("ok");
+        reset(st, ResetType::Warm, Some(&reason));
+        let record = mock.reset_record().unwrap();
+        assert_eq!(record.reset_type, r_efi::efi::RESET_WARM);
+        assert_eq!(record.data, [b'o', 0, b'k', 0, 0, 0]);
+    }
+
+    #[test]
+    fn reset_platform_specific() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        let guid = efi::Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]);
+
+        let reason = CString16::from_str_lossy("a");
+        reset(st, ResetType::PlatformSpecific(guid), Some(&reason));
+        let record = mock.reset_record().unwrap();
+        assert_eq!(record.reset_type, r_efi::efi::RESET_PLATFORM_SPECIFIC);
+        assert_eq!(&record.data[..4], &[b'a', 0, 0, 0]);
+        assert_eq!(&record.data[4..], guid.as_bytes());
+
+        // Without a reason, the data is an empty string followed by the GUID.
+        reset(st, ResetType::PlatformSpecific(guid), None);
+        let record = mock.reset_record().unwrap();
+        assert_eq!(&record.data[..2], &[0, 0]);
+        assert_eq!(&record.data[2..], guid.as_bytes());
+    }
+}
@@ -0,0 +1,581 @@
+//! This module contains functions related to Time Services.
+//!
+//! The timezone of an `EfiTime` is the offset of the local time from UTC in minutes, such that
+//! `local time = UTC + timezone`. A time with an unspecified timezone is treated as UTC when
+//! converting to and from Unix timestamps. The daylight flags are informational and do not
+//! change the conversion.
+
+use crate::{
+    efi::SystemTable,
+    errors::{self, TimeError},
+    helpers,
+    status::{Completion, StatusCode},
+};
+use r_efi::efi;
+
+pub type Result<T> = core::result::Result<T, errors::StatusNullError>;
+
+const SECONDS_PER_DAY: i64 = 86400;
+/// Days from 0000-03-01 to 1970-01-01 in the proleptic Gregorian calendar.
+const UNIX_EPOCH_DAYS: i64 = 719468;
+const MIN_YEAR: u16 = 1900;
+const MAX_YEAR: u16 = 9999;
+const MAX_TIMEZONE: i16 = 1440;
+
+/// The daylight saving flags of an `EfiTime`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Daylight(u8);
+
+impl Daylight {
+    /// The time is affected by daylight saving time.
+    pub const ADJUST_DAYLIGHT: Self = Self(efi::TIME_ADJUST_DAYLIGHT);
+    /// The time has been adjusted for daylight saving time.
+    pub const IN_DAYLIGHT: Self = Self(efi::TIME_IN_DAYLIGHT);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Returns `None` if unknown bits are set.
+    pub const fn from_bits(bits: u8) -> Option<Self> {
+        if bits & !(efi::TIME_ADJUST_DAYLIGHT | efi::TIME_IN_DAYLIGHT) == 0 {
+            Some(Self(bits))
+        } else {
+            None
+        }
+    }
+
+    pub const fn bits(&self) -> u8 {
+        self.0
+    }
+
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl core::ops::BitOr for Daylight {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// A validated `EFI_TIME`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EfiTime {
+    year: u16,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+    nanosecond: u32,
+    timezone: Option<i16>,
+    daylight: Daylight,
+}
+
+impl EfiTime {
+    /// Create a time with an unspecified timezone and no daylight flags.
+    pub fn new(
+        year: u16,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+        nanosecond: u32,
+    ) -> core::result::Result<Self, TimeError> {
+        fn check(valid: bool, field: &'static str) -> core::result::Result<(), TimeError> {
+            if valid {
+                Ok(())
+            } else {
+                Err(TimeError::InvalidField { field })
+            }
+        }
+
+        check((MIN_YEAR..=MAX_YEAR).contains(&year), "year")?;
+        check((1..=12).contains(&month), "month")?;
+        check(day >= 1 && day <= days_in_month(year, month), "day")?;
+        check(hour < 24, "hour")?;
+        check(minute < 60, "minute")?;
+        check(second < 60, "second")?;
+        check(nanosecond < 1_000_000_000, "nanosecond")?;
+
+        Ok(Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            nanosecond,
+            timezone: None,
+            daylight: Daylight::empty(),
+        })
+    }
+
+    /// Set the offset from UTC in minutes, between -1440 and 1440. `None` is
+    /// `EFI_UNSPECIFIED_TIMEZONE`.
+    pub fn with_timezone(self, timezone: Option<i16>) -> core::result::Result<Self, TimeError> {
+        match timezone {
+            Some(x) if !(-MAX_TIMEZONE..=MAX_TIMEZONE).contains(&x) => {
+                Err(TimeError::InvalidField { field: "timezone" })
+            }
+            _ => Ok(Self { timezone, ..self }),
+        }
+    }
+
+    pub fn with_daylight(self, daylight: Daylight) -> Self {
+        Self { daylight, ..self }
+    }
+
+    /// Validate a raw `EFI_TIME`.
+    pub fn from_raw(time: &efi::Time) -> core::result::Result<Self, TimeError> {
+        let timezone = match time.timezone {
+            efi::UNSPECIFIED_TIMEZONE => None,
+            x => Some(x),
+        };
+        let daylight = Daylight::from_bits(time.daylight)
+            .ok_or(TimeError::InvalidField { field: "daylight" })?;
+
+        Ok(Self::new(
+            time.year,
+            time.month,
+            time.day,
+            time.hour,
+            time.minute,
+            time.second,
+            time.nanosecond,
+        )?
+        .with_timezone(timezone)?
+        .with_daylight(daylight))
+    }
+
+    pub fn to_raw(&self) -> efi::Time {
+        efi::Time {
+            year: self.year,
+            month: self.month,
+            day: self.day,
+            hour: self.hour,
+            minute: self.minute,
+            second: self.second,
+            pad1: 0,
+            nanosecond: self.nanosecond,
+            timezone: self.timezone.unwrap_or(efi::UNSPECIFIED_TIMEZONE),
+            daylight: self.daylight.bits(),
+            pad2: 0,
+        }
+    }
+
+    /// Create the time for a Unix timestamp, in the local time of `timezone`.
+    /// Fails if the local time falls outside the years 1900 to 9999, or if the timezone or
+    /// nanoseconds are invalid.
+    pub fn from_unix_timestamp(
+        seconds: i64,
+        nanosecond: u32,
+        timezone: Option<i16>,
+    ) -> core::result::Result<Self, TimeError> {
+        let local = seconds
+            .checked_add(i64::from(timezone.unwrap_or(0)) * 60)
+            .ok_or(TimeError::OutOfRange)?;
+        let days = local.div_euclid(SECONDS_PER_DAY);
+        let secs = local.rem_euclid(SECONDS_PER_DAY);
+
+        let (year, month, day) = civil_from_days(days).ok_or(TimeError::OutOfRange)?;
+        if !(MIN_YEAR..=MAX_YEAR).contains(&year) {
+            return Err(TimeError::OutOfRange);
+        }
+
+        Self::new(
+            year,
+            month,
+            day,
+            (secs / 3600) as u8,
+            (secs / 60 % 60) as u8,
+            (secs % 60) as u8,
+            nanosecond,
+        )?
+        .with_timezone(timezone)
+    }
+
+    /// Seconds since 1970-01-01 00:00:00 UTC. Sub-second precision is in `nanosecond`.
+    pub fn unix_timestamp(&self) -> i64 {
+        let days = days_from_civil(self.year, self.month, self.day);
+        let local = days * SECONDS_PER_DAY
+            + i64::from(self.hour) * 3600
+            + i64::from(self.minute) * 60
+            + i64::from(self.second);
+        local - i64::from(self.timezone.unwrap_or(0)) * 60
+    }
+
+    pub fn year(&self) -> u16 {
+        self.year
+    }
+
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    pub fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    pub fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    pub fn second(&self) -> u8 {
+        self.second
+    }
+
+    pub fn nanosecond(&self) -> u32 {
+        self.nanosecond
+    }
+
+    /// The offset from UTC in minutes, or `None` if unspecified.
+    pub fn timezone(&self) -> Option<i16> {
+        self.timezone
+    }
+
+    pub fn daylight(&self) -> Daylight {
+        self.daylight
+    }
+}
+
+fn is_leap_year(year: u16) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since the Unix epoch, from Howard Hinnant's `days_from_civil`.
+fn days_from_civil(year: u16, month: u8, day: u8) -> i64 {
+    let year = i64::from(year) - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_from_march = (i64::from(month) + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - UNIX_EPOCH_DAYS
+}
+
+/// The inverse of `days_from_civil`. Returns `None` if the year does not fit a `u16`.
+fn civil_from_days(days: i64) -> Option<(u16, u8, u8)> {
+    let days = days.checked_add(UNIX_EPOCH_DAYS)?;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    Some((u16::try_from(year).ok()?, month as u8, day as u8))
+}
+
+/// The capabilities of the real time clock, as returned by EFI_GET_TIME.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeCapabilities {
+    /// Resolution in counts per second.
+    pub resolution: u32,
+    /// Accuracy in parts per million, multiplied by 1000000.
+    pub accuracy: u32,
+    /// Whether setting the time clears the time below the resolution.
+    pub sets_to_zero: bool,
+}
+
+/// The state of the wakeup alarm, as returned by EFI_GET_WAKEUP_TIME.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WakeupTime {
+    pub enabled: bool,
+    /// Whether the alarm has fired and not been cleared yet.
+    pub pending: bool,
+    /// The time the alarm is set to. `None` if the alarm is disabled, as the firmware may not
+    /// report a valid time then, or if the firmware reported an invalid time.
+    pub time: Option<EfiTime>,
+}
+
+/// Validate a time returned by the firmware. An invalid time is reported as
+/// `EFI_DEVICE_ERROR`.
+fn validate(time: &efi::Time) -> Result<EfiTime> {
+    EfiTime::from_raw(time).map_err(|_| errors::StatusNullError::UefiError(StatusCode::DeviceError))
+}
+
+fn zero_time() -> efi::Time {
+    efi::Time {
+        year: 0,
+        month: 0,
+        day: 0,
+        hour: 0,
+        minute: 0,
+        second: 0,
+        pad1: 0,
+        nanosecond: 0,
+        timezone: 0,
+        daylight: 0,
+        pad2: 0,
+    }
+}
+
+/// Call EFI_GET_TIME runtime service function.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn get_time(st: *mut SystemTable) -> Result<Completion<EfiTime>> {
+    let r = get_time_and_capabilities(st)?;
+    Ok(r.map(|(time, _)| time))
+}
+
+/// Call EFI_GET_TIME runtime service function, also returning the capabilities of the clock.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn get_time_and_capabilities(
+    st: *mut SystemTable,
+) -> Result<Completion<(EfiTime, TimeCapabilities)>> {
+    let runtime_services = super::get_runtime_services(st)?;
+    let get_time_ptr = unsafe { (*runtime_services).get_time };
+
+    let mut time = zero_time();
+    let mut capabilities = efi::TimeCapabilities {
+        resolution: 0,
+        accuracy: 0,
+        sets_to_zero: efi::Boolean::FALSE,
+    };
+    let status = (get_time_ptr)(&mut time, &mut capabilities);
+
+    let r = helpers::status_to_result(status)?;
+    let time = validate(&time)?;
+    Ok(r.map(|_| {
+        (
+            time,
+            TimeCapabilities {
+                resolution: capabilities.resolution,
+                accuracy: capabilities.accuracy,
+                sets_to_zero: capabilities.sets_to_zero.into(),
+            },
+        )
+    }))
+}
+
+/// Call EFI_SET_TIME runtime service function.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn set_time(st: *mut SystemTable, time: &EfiTime) -> Result<Completion<()>> {
+    let runtime_services = super::get_runtime_services(st)?;
+    let set_time_ptr = unsafe { (*runtime_services).set_time };
+
+    let mut time = time.to_raw();
+    let status = (set_time_ptr)(&mut time);
+
+    helpers::status_to_result(status).map_err(|x| x.into())
+}
+
+/// Call EFI_GET_WAKEUP_TIME runtime service function.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn get_wakeup_time(st: *mut SystemTable) -> Result<Completion<WakeupTime>> {
+    let runtime_services = super::get_runtime_services(st)?;
+    let get_wakeup_time_ptr = unsafe { (*runtime_services).get_wakeup_time };
+
+    let mut enabled = efi::Boolean::FALSE;
+    let mut pending = efi::Boolean::FALSE;
+    let mut time = zero_time();
+    let status = (get_wakeup_time_ptr)(&mut enabled, &mut pending, &mut time);
+
+    let r = helpers::status_to_result(status)?;
+    let enabled = bool::from(enabled);
+    let time = if enabled {
+        EfiTime::from_raw(&time).ok()
+    } else {
+        None
+    };
+    Ok(r.map(|_| WakeupTime {
+        enabled,
+        pending: pending.into(),
+        time,
+    }))
+}
+
+/// Call EFI_SET_WAKEUP_TIME runtime service function.
+/// `None` disables the wakeup alarm.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn set_wakeup_time(st: *mut SystemTable, time: Option<&EfiTime>) -> Result<Completion<()>> {
+    let runtime_services = super::get_runtime_services(st)?;
+    let set_wakeup_time_ptr = unsafe { (*runtime_services).set_wakeup_time };
+
+    let mut raw = time.map(EfiTime::to_raw);
+    let time_ptr = match raw.as_mut() {
+        Some(x) => x as *mut efi::Time,
+        None => core::ptr::null_mut(),
+    };
+    let status = (set_wakeup_time_ptr)(efi::Boolean::from(time.is_some()), time_ptr);
+
+    helpers::status_to_result(status).map_err(|x| x.into())
+}
+
+/// Call EFI_GET_NEXT_HIGH_MONO_COUNT runtime service function.
+/// Returns the new high 32 bits of the platform's monotonic counter.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn get_next_high_monotonic_count(st: *mut SystemTable) -> Result<Completion<u32>> {
+    let runtime_services = super::get_runtime_services(st)?;
+    let get_next_high_mono_count_ptr = unsafe { (*runtime_services).get_next_high_mono_count };
+
+    let mut high_count = 0;
+    let status = (get_next_high_mono_count_ptr)(&mut high_count);
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| high_count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockSystemTable;
+
+    #[test]
+    fn validation() {
+        assert!(EfiTime::new(2024, 2, 29, 23, 59, 59, 999_999_999).is_ok());
+        assert_eq!(
+            EfiTime::new(2023, 2, 29, 0, 0, 0, 0),
+            Err(TimeError::InvalidField { field: "day" })
+        );
+        assert_eq!(
+            EfiTime::new(1899, 12, 31, 0, 0, 0, 0),
+            Err(TimeError::InvalidField { field: "year" })
+        );
+        assert_eq!(
+            EfiTime::new(2000, 1, 1, 0, 0, 0, 0)
+                .unwrap()
+                .with_timezone(Some(1441)),
+            Err(TimeError::InvalidField { field: "timezone" })
+        );
+
+        let mut raw = EfiTime::new(2000, 1, 1, 0, 0, 0, 0).unwrap().to_raw();
+        assert_eq!(raw.timezone, efi::UNSPECIFIED_TIMEZONE);
+        raw.daylight = 4;
+        assert_eq!(
+            EfiTime::from_raw(&raw),
+            Err(TimeError::InvalidField { field: "daylight" })
+        );
+    }
+
+    #[test]
+    fn unix_timestamp() {
+        let epoch = EfiTime::new(1970, 1, 1, 0, 0, 0, 0).unwrap();
+        assert_eq!(epoch.unix_timestamp(), 0);
+
+        let t = EfiTime::new(2024, 2, 29, 12, 34, 56, 789).unwrap();
+        assert_eq!(t.unix_timestamp(), 1709210096);
+        assert_eq!(EfiTime::from_unix_timestamp(1709210096, 789, None), Ok(t));
+
+        let first = EfiTime::new(1900, 1, 1, 0, 0, 0, 0).unwrap();
+        assert_eq!(first.unix_timestamp(), -2208988800);
+        assert_eq!(
+            EfiTime::from_unix_timestamp(-2208988801, 0, None),
+            Err(TimeError::OutOfRange)
+        );
+        assert_eq!(
+            EfiTime::from_unix_timestamp(i64::MAX, 0, None),
+            Err(TimeError::OutOfRange)
+        );
+    }
+
+    #[test]
+    fn unix_timestamp_timezone() {
+        // 2024-01-01 00:00:00 UTC is 05:30 in UTC+05:30.
+        let t = EfiTime::from_unix_timestamp(1704067200, 0, Some(330)).unwrap();
+        assert_eq!((t.day(), t.hour(), t.minute()), (1, 5, 30));
+        assert_eq!(t.unix_timestamp(), 1704067200);
+
+        // And still 2023-12-31 in UTC-08:00.
+        let t = EfiTime::from_unix_timestamp(1704067200, 0, Some(-480)).unwrap();
+        assert_eq!((t.year(), t.month(), t.day(), t.hour()), (2023, 12, 31, 16));
+        assert_eq!(t.unix_timestamp(), 1704067200);
+    }
+
+    #[test]
+    fn get_set_time() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let t = EfiTime::new(2031, 7, 4, 8, 15, 0, 0)
+            .unwrap()
+            .with_timezone(Some(60))
+            .unwrap()
+            .with_daylight(Daylight::ADJUST_DAYLIGHT | Daylight::IN_DAYLIGHT);
+        set_time(st, &t).unwrap().into_value();
+        assert_eq!(mock.rtc().timezone, 60);
+
+        let (time, capabilities) = get_time_and_capabilities(st).unwrap().into_value();
+        assert_eq!(time, t);
+        assert_eq!(capabilities.resolution, 1);
+
+        let mut raw = t.to_raw();
+        raw.month = 13;
+        mock.set_rtc(raw);
+        assert_eq!(
+            get_time(st),
+            Err(errors::StatusNullError::UefiError(StatusCode::DeviceError))
+        );
+    }
+
+    #[test]
+    fn wakeup_never_programmed() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        // The mock reports a zeroed time, which is not a valid `EfiTime`.
+        let wakeup = get_wakeup_time(st).unwrap().into_value();
+        assert_eq!(
+            wakeup,
+            WakeupTime {
+                enabled: false,
+                pending: false,
+                time: None,
+            }
+        );
+        assert!(mock.wakeup_time().is_none());
+    }
+
+    #[test]
+    fn wakeup_and_high_count() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let t = EfiTime::new(2030, 1, 1, 6, 0, 0, 0).unwrap();
+        set_wakeup_time(st, Some(&t)).unwrap().into_value();
+        let wakeup = get_wakeup_time(st).unwrap().into_value();
+        assert!(wakeup.enabled && !wakeup.pending);
+        assert_eq!(wakeup.time, Some(t));
+
+        set_wakeup_time(st, None).unwrap().into_value();
+        let wakeup = get_wakeup_time(st).unwrap().into_value();
+        assert!(!wakeup.enabled);
+        assert_eq!(wakeup.time, None);
+        assert!(mock.wakeup_time().is_none());
+
+        assert_eq!(get_next_high_monotonic_count(st).unwrap().into_value(), 1);
+        assert_eq!(get_next_high_monotonic_count(st).unwrap().into_value(), 2);
+        assert_eq!(mock.monotonic_count(), 2 << 32);
+    }
+}
@@ -0,0 +1,424 @@
+//! This module contains functions related to Variable Services.
+
+use core::ffi::c_void;
+
+use crate::{
+    efi::{Guid, SystemTable},
+    errors, helpers,
+    status::Completion,
+    string::CStr16,
+};
+#[cfg(any(test, feature = "alloc"))]
+use crate::{status::StatusCode, string::CString16};
+#[cfg(any(test, feature = "alloc"))]
+use alloc::{vec, vec::Vec};
+use r_efi::efi;
+
+pub type Result<T> = core::result::Result<T, errors::StatusNullError>;
+
+/// The vendor GUID of the variables defined by the UEFI specification, e.g. `BootOrder`.
+pub const GLOBAL_VARIABLE: Guid = Guid::from_fields(
+    0x8be4df61,
+    0x93ca,
+    0x11d2,
+    0xaa,
+    0x0d,
+    &[0x00, 0xe0, 0x98, 0x03, 0x2b, 0x8c],
+);
+
+/// The attributes of a variable, a combination of the `EFI_VARIABLE_*` bits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct VariableAttributes(u32);
+
+impl VariableAttributes {
+    pub const NON_VOLATILE: Self = Self(efi::VARIABLE_NON_VOLATILE);
+    pub const BOOTSERVICE_ACCESS: Self = Self(efi::VARIABLE_BOOTSERVICE_ACCESS);
+    pub const RUNTIME_ACCESS: Self = Self(efi::VARIABLE_RUNTIME_ACCESS);
+    pub const HARDWARE_ERROR_RECORD: Self = Self(efi::VARIABLE_HARDWARE_ERROR_RECORD);
+    pub const AUTHENTICATED_WRITE_ACCESS: Self = Self(efi::VARIABLE_AUTHENTICATED_WRITE_ACCESS);
+    pub const TIME_BASED_AUTHENTICATED_WRITE_ACCESS: Self =
+        Self(efi::VARIABLE_TIME_BASED_AUTHENTICATED_WRITE_ACCESS);
+    pub const APPEND_WRITE: Self = Self(efi::VARIABLE_APPEND_WRITE);
+    pub const ENHANCED_AUTHENTICATED_ACCESS: Self =
+        Self(efi::VARIABLE_ENHANCED_AUTHENTICATED_ACCESS);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl core::ops::BitOr for VariableAttributes {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl core::ops::BitOrAssign for VariableAttributes {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// The name and vendor GUID identifying a variable.
+#[cfg(any(test, feature = "alloc"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VariableKey {
+    pub name: CString16,
+    pub vendor: Guid,
+}
+
+/// The storage information returned by `query_variable_info`, in bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VariableStorageInfo {
+    pub maximum_variable_storage_size: u64,
+    pub remaining_variable_storage_size: u64,
+    pub maximum_variable_size: u64,
+}
+
+/// The firmware takes the names and GUIDs as `*mut`, but never writes through them.
+fn name_ptr(name: &CStr16) -> *mut u16 {
+    name.as_ptr() as *mut u16
+}
+
+fn guid_ptr(guid: &Guid) -> *mut Guid {
+    guid as *const Guid as *mut Guid
+}
+
+/// Call EFI_GET_VARIABLE runtime service function.
+/// On success and on `EFI_BUFFER_TOO_SMALL`, `data_size` is set to the size of the variable.
+/// Use `get_variable_vec` to let the buffer be sized automatically.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn get_variable(
+    st: *mut SystemTable,
+    name: &CStr16,
+    vendor: &Guid,
+    buffer: &mut [u8],
+    data_size: &mut usize,
+) -> Result<Completion<VariableAttributes>> {
+    let runtime_services = super::get_runtime_services(st)?;
+    let get_variable_ptr = unsafe { (*runtime_services).get_variable };
+
+    let mut attributes = 0;
+    *data_size = buffer.len();
+    let status = (get_variable_ptr)(
+        name_ptr(name),
+        guid_ptr(vendor),
+        &mut attributes,
+        data_size,
+        buffer.as_mut_ptr().cast(),
+    );
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| VariableAttributes(attributes)))
+}
+
+/// Read a whole variable, growing the buffer until it fits.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+#[cfg(any(test, feature = "alloc"))]
+pub fn get_variable_vec(
+    st: *mut SystemTable,
+    name: &CStr16,
+    vendor: &Guid,
+) -> Result<Completion<(Vec<u8>, VariableAttributes)>> {
+    let mut buffer = Vec::new();
+    let mut data_size = 0;
+
+    loop {
+        match get_variable(st, name, vendor, &mut buffer, &mut data_size) {
+            Ok(r) => {
+                buffer.truncate(data_size);
+                return Ok(r.map(|attributes| (buffer, attributes)));
+            }
+            Err(errors::StatusNullError::UefiError(StatusCode::BufferTooSmall)) => {
+                buffer.resize(data_size, 0);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Call EFI_SET_VARIABLE runtime service function.
+/// An empty `data` deletes the variable, unless `VariableAttributes::APPEND_WRITE` is set.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn set_variable(
+    st: *mut SystemTable,
+    name: &CStr16,
+    vendor: &Guid,
+    attributes: VariableAttributes,
+    data: &[u8],
+) -> Result<Completion<()>> {
+    let runtime_services = super::get_runtime_services(st)?;
+    let set_variable_ptr = unsafe { (*runtime_services).set_variable };
+
+    let status = (set_variable_ptr)(
+        name_ptr(name),
+        guid_ptr(vendor),
+        attributes.bits(),
+        data.len(),
+        data.as_ptr() as *mut c_void,
+    );
+
+    helpers::status_to_result(status).map_err(|x| x.into())
+}
+
+/// Delete a variable with EFI_SET_VARIABLE.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn delete_variable(
+    st: *mut SystemTable,
+    name: &CStr16,
+    vendor: &Guid,
+) -> Result<Completion<()>> {
+    set_variable(st, name, vendor, VariableAttributes::empty(), &[])
+}
+
+/// Call EFI_GET_NEXT_VARIABLE_NAME runtime service function.
+/// `name` and `vendor` must hold the previous variable, or an empty string to start. They are
+/// replaced with the next variable. `name_size` is the size of the string in `name` in bytes,
+/// including the NUL. On `EFI_BUFFER_TOO_SMALL`, it is set to the size needed.
+/// `EFI_NOT_FOUND` means that all variables have been returned. `variable_names` wraps this
+/// into an iterator.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn get_next_variable_name(
+    st: *mut SystemTable,
+    name: &mut [u16],
+    name_size: &mut usize,
+    vendor: &mut Guid,
+) -> Result<Completion<()>> {
+    let runtime_services = super::get_runtime_services(st)?;
+    let get_next_variable_name_ptr = unsafe { (*runtime_services).get_next_variable_name };
+
+    // The size passed in is the size of the buffer, the previous name is found by its NUL.
+    *name_size = core::mem::size_of_val(name);
+    let status = (get_next_variable_name_ptr)(name_size, name.as_mut_ptr(), vendor);
+
+    helpers::status_to_result(status).map_err(|x| x.into())
+}
+
+/// An iterator over the names and vendor GUIDs of all variables.
+#[cfg(any(test, feature = "alloc"))]
+pub struct VariableNames {
+    st: *mut SystemTable,
+    name: Vec<u16>,
+    vendor: Guid,
+    done: bool,
+}
+
+/// Iterate over all variables with EFI_GET_NEXT_VARIABLE_NAME, growing the name buffer as
+/// needed. Variables must not be added or deleted during the iteration.
+/// SAFETY : The `st` pointer must be valid for the lifetime of the iterator. This is gaurenteed
+/// if `GlobalData` is used to store the pointer.
+#[cfg(any(test, feature = "alloc"))]
+pub fn variable_names(st: *mut SystemTable) -> VariableNames {
+    VariableNames {
+        st,
+        name: vec![0; 64],
+        vendor: Guid::from_fields(0, 0, 0, 0, 0, &[0; 6]),
+        done: false,
+    }
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl Iterator for VariableNames {
+    type Item = Result<VariableKey>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        loop {
+            let mut name_size = 0;
+            match get_next_variable_name(self.st, &mut self.name, &mut name_size, &mut self.vendor)
+            {
+                Ok(r) => {
+                    r.into_value();
+                    return Some(Ok(VariableKey {
+                        name: CString16::from_u16_lossy(&self.name),
+                        vendor: self.vendor,
+                    }));
+                }
+                Err(errors::StatusNullError::UefiError(StatusCode::BufferTooSmall)) => {
+                    // The previous name stays in place, followed by more room.
+                    self.name.resize(name_size.div_ceil(2), 0);
+                }
+                Err(errors::StatusNullError::UefiError(StatusCode::NotFound)) => {
+                    self.done = true;
+                    return None;
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Call EFI_QUERY_VARIABLE_INFO runtime service function.
+/// Returns the storage available for variables with the given attributes.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer.
+pub fn query_variable_info(
+    st: *mut SystemTable,
+    attributes: VariableAttributes,
+) -> Result<Completion<VariableStorageInfo>> {
+    let runtime_services = super::get_runtime_services(st)?;
+    let query_variable_info_ptr = unsafe { (*runtime_services).query_variable_info };
+
+    let mut info = VariableStorageInfo {
+        maximum_variable_storage_size: 0,
+        remaining_variable_storage_size: 0,
+        maximum_variable_size: 0,
+    };
+    let status = (query_variable_info_ptr)(
+        attributes.bits(),
+        &mut info.maximum_variable_storage_size,
+        &mut info.remaining_variable_storage_size,
+        &mut info.maximum_variable_size,
+    );
+
+    let r = helpers::status_to_result(status)?;
+    Ok(r.map(|_| info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockSystemTable;
+    use crate::status::StatusCode;
+    use crate::string::CString16;
+
+    const BS_RT: VariableAttributes = VariableAttributes::from_bits(
+        efi::VARIABLE_BOOTSERVICE_ACCESS | efi::VARIABLE_RUNTIME_ACCESS,
+    );
+
+    #[test]
+    fn get_variable_sizes() {
+        let mut mock = MockSystemTable::new();
+        mock.set_variable("Timeout", &GLOBAL_VARIABLE, BS_RT.bits(), &[5, 0]);
+        let st = mock.system_table();
+        let name = CString16::from_str_lossy("Timeout");
+
+        let mut data_size = 0;
+        assert_eq!(
+            get_variable(st, &name, &GLOBAL_VARIABLE, &mut [0; 1], &mut data_size),
+            Err(errors::StatusNullError::UefiError(
+                StatusCode::BufferTooSmall
+            ))
+        );
+        assert_eq!(data_size, 2);
+
+        let mut buffer = [0; 8];
+        let attributes = get_variable(st, &name, &GLOBAL_VARIABLE, &mut buffer, &mut data_size)
+            .unwrap()
+            .into_value();
+        assert_eq!(attributes, BS_RT);
+        assert_eq!(&buffer[..data_size], &[5, 0]);
+
+        let missing = CString16::from_str_lossy("Missing");
+        assert_eq!(
+            get_variable_vec(st, &missing, &GLOBAL_VARIABLE),
+            Err(errors::StatusNullError::UefiError(StatusCode::NotFound))
+        );
+    }
+
+    #[test]
+    fn set_append_delete() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        let vendor = Guid::from_fields(1, 2, 3, 4, 5, &[6; 6]);
+        let name = CString16::from_str_lossy("Vendor");
+
+        set_variable(st, &name, &vendor, BS_RT, &[1, 2])
+            .unwrap()
+            .into_value();
+        set_variable(
+            st,
+            &name,
+            &vendor,
+            BS_RT | VariableAttributes::APPEND_WRITE,
+            &[3],
+        )
+        .unwrap()
+        .into_value();
+        let (data, attributes) = get_variable_vec(st, &name, &vendor).unwrap().into_value();
+        assert_eq!(data, vec![1, 2, 3]);
+        assert_eq!(attributes, BS_RT);
+
+        // The attributes of an existing variable cannot change.
+        assert_eq!(
+            set_variable(
+                st,
+                &name,
+                &vendor,
+                VariableAttributes::BOOTSERVICE_ACCESS,
+                &[1]
+            ),
+            Err(errors::StatusNullError::UefiError(
+                StatusCode::InvalidParameter
+            ))
+        );
+
+        delete_variable(st, &name, &vendor).unwrap().into_value();
+        assert_eq!(mock.variable("Vendor", &vendor), None);
+    }
+
+    #[test]
+    fn variable_names_grow_buffer() {
+        let mut mock = MockSystemTable::new();
+        // Longer than the initial buffer of the iterator.
+        let long = "x".repeat(100);
+        mock.set_variable("BootOrder", &GLOBAL_VARIABLE, BS_RT.bits(), &[0, 0]);
+        mock.set_variable(&long, &GLOBAL_VARIABLE, BS_RT.bits(), &[1]);
+        mock.set_variable("Boot0000", &GLOBAL_VARIABLE, BS_RT.bits(), &[2]);
+        let st = mock.system_table();
+
+        let names: std::vec::Vec<_> = variable_names(st)
+            .map(|x| x.unwrap().name.to_string())
+            .collect();
+        assert_eq!(names, ["BootOrder", long.as_str(), "Boot0000"]);
+    }
+
+    #[test]
+    fn query_storage() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+
+        let before = query_variable_info(st, BS_RT).unwrap().into_value();
+        let name = CString16::from_str_lossy("A");
+        set_variable(st, &name, &GLOBAL_VARIABLE, BS_RT, &[0; 16])
+            .unwrap()
+            .into_value();
+        let after = query_variable_info(st, BS_RT).unwrap().into_value();
+
+        assert_eq!(
+            before.remaining_variable_storage_size - after.remaining_variable_storage_size,
+            20
+        );
+        assert_eq!(
+            query_variable_info(st, VariableAttributes::empty()),
+            Err(errors::StatusNullError::UefiError(
+                StatusCode::InvalidParameter
+            ))
+        );
+    }
+}
@@ -0,0 +1,340 @@
+//! This module contains functions related to Virtual Memory Services.
+//!
+//! After `exit_boot_services`, an OS loader hands the firmware the virtual addresses of the
+//! runtime memory with `set_virtual_address_map`. From then on, the firmware only works through
+//! the virtual addresses. `RuntimeServices` keeps the system table pointer valid across that
+//! switch.
+
+use core::ffi::c_void;
+use core::mem::size_of;
+
+use crate::{
+    boot_services::memory_allocation_services::{MemoryMapInfo, PAGE_SIZE},
+    efi::{MemoryDescriptor, SystemTable},
+    errors, helpers,
+    status::{Completion, StatusCode},
+};
+use r_efi::efi;
+
+pub type Result<T> = core::result::Result<T, errors::StatusNullError>;
+
+/// Rewrite the memory map in `buffer` into the virtual map expected by
+/// `set_virtual_address_map`.
+///
+/// Only the descriptors with the `EFI_MEMORY_RUNTIME` attribute are kept. They are moved to the
+/// start of `buffer`, packed with a stride of `size_of::<MemoryDescriptor>()`, and their
+/// `virtual_start` is set to the address returned by `map_fn`. Returns the number of runtime
+/// descriptors. This works in place, so no memory needs to be allocated after
+/// `exit_boot_services`.
+pub fn build_virtual_map(
+    buffer: &mut [MemoryDescriptor],
+    info: &MemoryMapInfo,
+    mut map_fn: impl FnMut(&MemoryDescriptor) -> u64,
+) -> usize {
+    let len = info.map_size.checked_div(info.descriptor_size).unwrap_or(0);
+    assert!(info.descriptor_size >= size_of::<MemoryDescriptor>());
+    assert!(len * info.descriptor_size <= core::mem::size_of_val(buffer));
+
+    let base = buffer.as_mut_ptr() as *mut u8;
+    let mut count = 0;
+    for i in 0..len {
+        let mut descriptor: MemoryDescriptor =
+            unsafe { core::ptr::read_unaligned(base.add(i * info.descriptor_size).cast()) };
+        if descriptor.attribute & efi::MEMORY_RUNTIME == 0 {
+            continue;
+        }
+        descriptor.virtual_start = map_fn(&descriptor);
+        // The packed stride is never larger than the original one, so this never overwrites a
+        // descriptor which is yet to be read.
+        buffer[count] = descriptor;
+        count += 1;
+    }
+    count
+}
+
+/// Translate a physical address with a virtual map built by `build_virtual_map`.
+pub fn translate(virtual_map: &[MemoryDescriptor], address: u64) -> Option<u64> {
+    virtual_map.iter().find_map(|x| {
+        let size = x.number_of_pages * PAGE_SIZE as u64;
+        if (x.physical_start..x.physical_start + size).contains(&address) {
+            Some(x.virtual_start + (address - x.physical_start))
+        } else {
+            None
+        }
+    })
+}
+
+/// Call EFI_SET_VIRTUAL_ADDRESS_MAP runtime service function.
+/// `virtual_map` holds the runtime descriptors with their virtual addresses, see
+/// `build_virtual_map`. `descriptor_version` is the one returned with the memory map.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer. Boot services must have been exited. Afterwards, the firmware must only be used
+/// through virtual addresses, see `RuntimeServices`.
+pub fn set_virtual_address_map(
+    st: *mut SystemTable,
+    virtual_map: &mut [MemoryDescriptor],
+    descriptor_version: u32,
+) -> Result<Completion<()>> {
+    let runtime_services = super::get_runtime_services(st)?;
+    let set_virtual_address_map_ptr = unsafe { (*runtime_services).set_virtual_address_map };
+
+    let status = (set_virtual_address_map_ptr)(
+        core::mem::size_of_val(virtual_map),
+        size_of::<MemoryDescriptor>(),
+        descriptor_version,
+        virtual_map.as_mut_ptr(),
+    );
+
+    helpers::status_to_result(status).map_err(|x| x.into())
+}
+
+/// Call EFI_CONVERT_POINTER runtime service function.
+/// `address` is replaced with its virtual address. If `optional` is set, a null `address` is
+/// left alone rather than reported as an error.
+/// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to store
+/// the pointer. This may only be called while the firmware switches to virtual addresses, i.e.
+/// from an `EVT_SIGNAL_VIRTUAL_ADDRESS_CHANGE` event.
+pub fn convert_pointer(
+    st: *mut SystemTable,
+    address: &mut *mut c_void,
+    optional: bool,
+) -> Result<Completion<()>> {
+    let runtime_services = super::get_runtime_services(st)?;
+    let convert_pointer_ptr = unsafe { (*runtime_services).convert_pointer };
+
+    let debug_disposition = if optional {
+        efi::OPTIONAL_POINTER as usize
+    } else {
+        0
+    };
+    let status = (convert_pointer_ptr)(debug_disposition, address);
+
+    helpers::status_to_result(status).map_err(|x| x.into())
+}
+
+/// A handle to the runtime services which stays usable after the switch to virtual addresses.
+///
+/// The runtime services of this crate take the system table pointer. Before
+/// `set_virtual_address_map`, that is the physical address. Afterwards, the firmware has
+/// converted the pointers in the system table, and the table itself must be reached through its
+/// virtual address, which `system_table` returns.
+#[derive(Debug)]
+pub struct RuntimeServices {
+    st: *mut SystemTable,
+    virtual_mode: bool,
+}
+
+impl RuntimeServices {
+    /// SAFETY : The `st` pointer must be valid. This is gaurenteed if `GlobalData` is used to
+    /// store the pointer.
+    pub fn new(st: *mut SystemTable) -> Self {
+        Self {
+            st,
+            virtual_mode: false,
+        }
+    }
+
+    /// The system table pointer to pass to the runtime services, e.g. `time::get_time`.
+    pub fn system_table(&self) -> *mut SystemTable {
+        self.st
+    }
+
+    /// Whether `set_virtual_address_map` succeeded.
+    pub fn is_virtual(&self) -> bool {
+        self.virtual_mode
+    }
+
+    /// Build the virtual map from the memory map in `buffer` and switch the firmware to it.
+    /// `buffer` and `info` are the memory map returned by `exit_boot_services`. The buffer is
+    /// rewritten with the virtual map, see `build_virtual_map`, and `map_fn` returns the virtual
+    /// address of each runtime descriptor.
+    ///
+    /// Fails with `EFI_INVALID_PARAMETER` before calling the firmware if the system table is not
+    /// in runtime memory, as it would be unreachable afterwards.
+    pub fn set_virtual_address_map(
+        &mut self,
+        buffer: &mut [MemoryDescriptor],
+        info: &MemoryMapInfo,
+        map_fn: impl FnMut(&MemoryDescriptor) -> u64,
+    ) -> Result<Completion<()>> {
+        let count = build_virtual_map(buffer, info, map_fn);
+        let virtual_map = &mut buffer[..count];
+
+        let st = translate(virtual_map, self.st as u64).ok_or(
+            errors::StatusNullError::UefiError(StatusCode::InvalidParameter),
+        )?;
+        let r = set_virtual_address_map(self.st, virtual_map, info.descriptor_version)?;
+
+        self.st = st as *mut SystemTable;
+        self.virtual_mode = true;
+        Ok(r)
+    }
+
+    /// Call EFI_CONVERT_POINTER runtime service function. See `convert_pointer`.
+    pub fn convert_pointer(
+        &self,
+        address: &mut *mut c_void,
+        optional: bool,
+    ) -> Result<Completion<()>> {
+        convert_pointer(self.st, address, optional)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boot_services::event_services::Event;
+    use crate::boot_services::image_services::exit_boot_services;
+    use crate::boot_services::memory_allocation_services::get_memory_map;
+    use crate::efi::Handle;
+    use crate::mock::MockSystemTable;
+    use std::vec::Vec;
+
+    const OFFSET: u64 = 0xffff_8000_0000_0000;
+
+    fn empty_map() -> [MemoryDescriptor; 16] {
+        [MemoryDescriptor {
+            r#type: 0,
+            physical_start: 0,
+            virtual_start: 0,
+            number_of_pages: 0,
+            attribute: 0,
+        }; 16]
+    }
+
+    /// Add a runtime descriptor covering the mock's system table, so it stays reachable.
+    fn map_system_table(mock: &mut MockSystemTable) -> u64 {
+        let page = mock.system_table() as u64 & !(PAGE_SIZE as u64 - 1);
+        let mut map: Vec<MemoryDescriptor> = {
+            let st = mock.system_table();
+            let mut buffer = empty_map();
+            let mut info = MemoryMapInfo::default();
+            get_memory_map(st, &mut buffer, &mut info)
+                .unwrap()
+                .into_value();
+            buffer[..info.map_size / info.descriptor_size].to_vec()
+        };
+        map.push(MemoryDescriptor {
+            r#type: efi::RUNTIME_SERVICES_DATA,
+            physical_start: page,
+            virtual_start: 0,
+            number_of_pages: 2,
+            attribute: efi::MEMORY_WB | efi::MEMORY_RUNTIME,
+        });
+        mock.set_memory_map(map);
+        page
+    }
+
+    #[test]
+    fn build_in_place() {
+        let mut mock = MockSystemTable::new();
+        mock.set_descriptor_size(size_of::<MemoryDescriptor>() + 8);
+        let st = mock.system_table();
+
+        let mut buffer = empty_map();
+        let mut info = MemoryMapInfo::default();
+        get_memory_map(st, &mut buffer, &mut info)
+            .unwrap()
+            .into_value();
+
+        let count = build_virtual_map(&mut buffer, &info, |x| x.physical_start + OFFSET);
+        assert_eq!(count, 2);
+        assert_eq!(buffer[0].r#type, efi::RUNTIME_SERVICES_CODE);
+        assert_eq!(buffer[1].r#type, efi::RUNTIME_SERVICES_DATA);
+        assert_eq!(buffer[1].virtual_start, 0x31_0000 + OFFSET);
+        assert_eq!(
+            translate(&buffer[..count], 0x30_0123),
+            Some(0x30_0123 + OFFSET)
+        );
+        assert_eq!(translate(&buffer[..count], 0x40_0000), None);
+    }
+
+    struct Context {
+        st: *mut SystemTable,
+        address: *mut c_void,
+    }
+
+    extern "efiapi" fn convert_on_change(_event: efi::Event, context: *mut c_void) {
+        let context = unsafe { &mut *(context as *mut Context) };
+        convert_pointer(context.st, &mut context.address, false)
+            .unwrap()
+            .into_value();
+    }
+
+    #[test]
+    fn switch_to_virtual() {
+        let mut mock = MockSystemTable::new();
+        let table_page = map_system_table(&mut mock);
+        let st = mock.system_table();
+
+        let mut context = Context {
+            st,
+            address: 0x30_0010 as *mut c_void,
+        };
+        let _event = Event::new(
+            st,
+            efi::EVT_SIGNAL_VIRTUAL_ADDRESS_CHANGE,
+            efi::TPL_NOTIFY,
+            Some(convert_on_change),
+            &mut context as *mut Context as *mut c_void,
+        )
+        .unwrap()
+        .into_value();
+
+        let mut runtime = RuntimeServices::new(st);
+        let (buffer, info) = exit_boot_services(st, 0x1000 as Handle)
+            .unwrap()
+            .into_value();
+        // The system table stays identity mapped, so that the host can still reach it.
+        runtime
+            .set_virtual_address_map(buffer, &info, |x| {
+                if x.physical_start == table_page {
+                    x.physical_start
+                } else {
+                    x.physical_start + OFFSET
+                }
+            })
+            .unwrap()
+            .into_value();
+
+        assert!(runtime.is_virtual());
+        assert_eq!(runtime.system_table(), st);
+        assert_eq!(context.address as u64, 0x30_0010 + OFFSET);
+        assert_eq!(mock.virtual_address_map().unwrap().len(), 3);
+
+        let mut address = core::ptr::null_mut();
+        runtime
+            .convert_pointer(&mut address, true)
+            .unwrap()
+            .into_value();
+        assert!(address.is_null());
+    }
+
+    #[test]
+    fn switch_errors() {
+        let mut mock = MockSystemTable::new();
+        let st = mock.system_table();
+        let mut runtime = RuntimeServices::new(st);
+
+        let mut buffer = empty_map();
+        let mut info = MemoryMapInfo::default();
+        get_memory_map(st, &mut buffer, &mut info)
+            .unwrap()
+            .into_value();
+        let mut virtual_map = buffer;
+        let count = build_virtual_map(&mut virtual_map, &info, |x| x.physical_start);
+        assert_eq!(
+            set_virtual_address_map(st, &mut virtual_map[..count], info.descriptor_version),
+            Err(errors::StatusNullError::UefiError(StatusCode::Unsupported))
+        );
+
+        // The system table is not in runtime memory.
+        assert_eq!(
+            runtime.set_virtual_address_map(&mut buffer, &info, |x| x.physical_start),
+            Err(errors::StatusNullError::UefiError(
+                StatusCode::InvalidParameter
+            ))
+        );
+        assert!(!runtime.is_virtual());
+    }
+}
@@ -0,0 +1,425 @@
+//! This module contains a decoder for the SMBIOS structure table found through the SMBIOS entry
+//! point configuration table.
+//!
+//! Each structure is a formatted area, starting with a type, length and handle, followed by a
+//! string-set of NUL-terminated strings ending with an extra NUL. Fields refer to strings by their
+//! 1-based index in the string-set, with 0 meaning no string.
+//!
+//! Parsing works on byte slices, so it can run on table dumps captured from a running system. On
+//! firmware, memory is identity mapped, and `EntryPoint::structure_table` maps the table directly.
+
+pub mod types;
+
+use core::ffi::c_void;
+
+use crate::configuration_table::SmbiosEntryPoint;
+use crate::errors::SmbiosError;
+
+type Result<T> = core::result::Result<T, SmbiosError>;
+
+/// Size of the SMBIOS 2.1 32-bit entry point.
+pub const ENTRY_POINT_V2_SIZE: usize = 0x1f;
+/// Size of the SMBIOS 3.0 64-bit entry point.
+pub const ENTRY_POINT_V3_SIZE: usize = 0x18;
+
+/// The type of the structure marking the end of the table.
+pub const END_OF_TABLE: u8 = 127;
+
+fn checksum(data: &[u8]) -> Result<()> {
+    match data.iter().fold(0u8, |acc, x| acc.wrapping_add(*x)) {
+        0 => Ok(()),
+        _ => Err(SmbiosError::InvalidChecksum),
+    }
+}
+
+/// A validated SMBIOS 2.x or 3.x entry point.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntryPoint {
+    pub major_version: u8,
+    pub minor_version: u8,
+    /// The docrev of SMBIOS 3.x, 0 for SMBIOS 2.x.
+    pub docrev: u8,
+    pub table_address: u64,
+    /// The exact length of the table for SMBIOS 2.x, or its maximum length for SMBIOS 3.x.
+    pub table_length: u32,
+    /// The number of structures, only given by SMBIOS 2.x.
+    pub number_of_structures: Option<u16>,
+}
+
+impl EntryPoint {
+    pub const ANCHOR_V2: &'static [u8; 4] = b"_SM_";
+    pub const ANCHOR_V3: &'static [u8; 5] = b"_SM3_";
+    pub const INTERMEDIATE_ANCHOR: &'static [u8; 5] = b"_DMI_";
+
+    /// Parse and validate the entry point at the start of `data`.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.starts_with(Self::ANCHOR_V3) {
+            Self::parse_v3(data)
+        } else if data.starts_with(Self::ANCHOR_V2) {
+            Self::parse_v2(data)
+        } else if data.len() < Self::ANCHOR_V3.len() {
+            Err(SmbiosError::Truncated)
+        } else {
+            Err(SmbiosError::InvalidAnchor)
+        }
+    }
+
+    fn parse_v2(data: &[u8]) -> Result<Self> {
+        let length = *data.get(5).ok_or(SmbiosError::Truncated)? as usize;
+        // SMBIOS 2.1 erroneously gave 0x1e, which is accepted for compatibility.
+        if length < ENTRY_POINT_V2_SIZE - 1 {
+            return Err(SmbiosError::InvalidLength);
+        }
+        let data = data
+            .get(..length.max(ENTRY_POINT_V2_SIZE))
+            .ok_or(SmbiosError::Truncated)?;
+        checksum(&data[..length])?;
+        if &data[0x10..0x15] != Self::INTERMEDIATE_ANCHOR {
+            return Err(SmbiosError::InvalidAnchor);
+        }
+        checksum(&data[0x10..ENTRY_POINT_V2_SIZE])?;
+
+        Ok(Self {
+            major_version: data[6],
+            minor_version: data[7],
+            docrev: 0,
+            table_address: u64::from(read_u32(data, 0x18)?),
+            table_length: u32::from(read_u16(data, 0x16)?),
+            number_of_structures: Some(read_u16(data, 0x1c)?),
+        })
+    }
+
+    fn parse_v3(data: &[u8]) -> Result<Self> {
+        let length = *data.get(6).ok_or(SmbiosError::Truncated)? as usize;
+        if length < ENTRY_POINT_V3_SIZE {
+            return Err(SmbiosError::InvalidLength);
+        }
+        let data = data.get(..length).ok_or(SmbiosError::Truncated)?;
+        checksum(data)?;
+
+        Ok(Self {
+            major_version: data[7],
+            minor_version: data[8],
+            docrev: data[9],
+            table_address: read_u64(data, 0x10)?,
+            table_length: read_u32(data, 0x0c)?,
+            number_of_structures: None,
+        })
+    }
+
+    /// Parse the entry point at `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to readable memory holding an SMBIOS entry point.
+    pub unsafe fn from_ptr(ptr: *const c_void) -> Result<Self> {
+        let ptr = ptr.cast::<u8>();
+        let head = unsafe { core::slice::from_raw_parts(ptr, 7) };
+        let length = match head {
+            [b'_', b'S', b'M', b'3', b'_', _, length] => *length as usize,
+            [b'_', b'S', b'M', b'_', _, length, _] => (*length as usize).max(ENTRY_POINT_V2_SIZE),
+            _ => return Err(SmbiosError::InvalidAnchor),
+        };
+        Self::parse(unsafe { core::slice::from_raw_parts(ptr, length.max(head.len())) })
+    }
+
+    /// Parse the entry point found by `configuration_table::smbios_entry_point`.
+    ///
+    /// # Safety
+    ///
+    /// The pointer in `entry_point` must point to readable memory holding an SMBIOS entry point.
+    pub unsafe fn from_configuration_table(entry_point: SmbiosEntryPoint) -> Result<Self> {
+        unsafe { Self::from_ptr(entry_point.as_ptr()) }
+    }
+
+    /// The structure table, when memory is identity mapped, as during boot services.
+    ///
+    /// # Safety
+    ///
+    /// `table_address` and `table_length` must describe readable memory, which must not be
+    /// freed or changed while the table is in use.
+    pub unsafe fn structure_table(&self) -> StructureTable<'static> {
+        let ptr = self.table_address as usize as *const u8;
+        if ptr.is_null() {
+            return StructureTable::new(&[]);
+        }
+        StructureTable::new(unsafe { core::slice::from_raw_parts(ptr, self.table_length as usize) })
+    }
+}
+
+fn read<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N]> {
+    data.get(offset..offset + N)
+        .and_then(|x| x.try_into().ok())
+        .ok_or(SmbiosError::Truncated)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    read(data, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    read(data, offset).map(u32::from_le_bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64> {
+    read(data, offset).map(u64::from_le_bytes)
+}
+
+/// A single structure, with raw access to its formatted area and string-set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Structure<'a> {
+    formatted: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> Structure<'a> {
+    /// Parse the structure at the start of `data`, and return it with the number of bytes it
+    /// takes up, string-set included.
+    pub fn parse(data: &'a [u8]) -> Result<(Self, usize)> {
+        let length = *data.get(1).ok_or(SmbiosError::Truncated)? as usize;
+        if length < 4 {
+            return Err(SmbiosError::InvalidLength);
+        }
+        let formatted = data.get(..length).ok_or(SmbiosError::Truncated)?;
+
+        // The string-set ends with two NULs, which are its only content if there are no strings.
+        let rest = &data[length..];
+        let end = rest
+            .windows(2)
+            .position(|x| x == [0, 0])
+            .ok_or(SmbiosError::Truncated)?;
+        let structure = Self {
+            formatted,
+            strings: &rest[..end],
+        };
+        Ok((structure, length + end + 2))
+    }
+
+    pub fn structure_type(&self) -> u8 {
+        self.formatted[0]
+    }
+
+    pub fn handle(&self) -> u16 {
+        u16::from_le_bytes([self.formatted[2], self.formatted[3]])
+    }
+
+    /// The formatted area, header included.
+    pub fn formatted(&self) -> &'a [u8] {
+        self.formatted
+    }
+
+    /// The strings of the string-set, in order.
+    pub fn strings(&self) -> impl Iterator<Item = &'a [u8]> + 'a {
+        let strings = self.strings;
+        strings
+            .split(|x| *x == 0)
+            .take_while(move |_| !strings.is_empty())
+    }
+
+    /// The string with the 1-based `index`, as raw bytes.
+    pub fn string_bytes(&self, index: u8) -> Option<&'a [u8]> {
+        match index {
+            0 => None,
+            x => self.strings().nth(x as usize - 1),
+        }
+    }
+
+    /// The string with the 1-based `index`. `None` for index 0, missing strings and strings
+    /// which are not valid UTF-8.
+    pub fn string(&self, index: u8) -> Option<&'a str> {
+        self.string_bytes(index)
+            .and_then(|x| core::str::from_utf8(x).ok())
+    }
+
+    /// The byte at `offset` in the formatted area, if the structure is long enough.
+    pub fn byte(&self, offset: usize) -> Option<u8> {
+        self.formatted.get(offset).copied()
+    }
+
+    pub fn word(&self, offset: usize) -> Option<u16> {
+        read_u16(self.formatted, offset).ok()
+    }
+
+    pub fn dword(&self, offset: usize) -> Option<u32> {
+        read_u32(self.formatted, offset).ok()
+    }
+
+    pub fn qword(&self, offset: usize) -> Option<u64> {
+        read_u64(self.formatted, offset).ok()
+    }
+
+    /// The string referred to by the byte at `offset`.
+    pub fn string_at(&self, offset: usize) -> Option<&'a str> {
+        self.string(self.byte(offset)?)
+    }
+
+    /// Decode the structure, if its type is one of those in `types`.
+    pub fn decode(&self) -> types::Decoded<'a> {
+        types::Decoded::new(*self)
+    }
+}
+
+/// The SMBIOS structure table.
+#[derive(Clone, Copy, Debug)]
+pub struct StructureTable<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StructureTable<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    /// Iterate over the structures, up to and including the end-of-table structure. Stops after
+    /// the first error.
+    pub fn structures(&self) -> Structures<'a> {
+        Structures { data: self.data }
+    }
+
+    /// The structures of type `structure_type`. Malformed structures end the search.
+    pub fn of_type(&self, structure_type: u8) -> impl Iterator<Item = Structure<'a>> + 'a {
+        self.structures()
+            .map_while(|x| x.ok())
+            .filter(move |x| x.structure_type() == structure_type)
+    }
+
+    /// The structure with `handle`.
+    pub fn find_handle(&self, handle: u16) -> Option<Structure<'a>> {
+        self.structures()
+            .map_while(|x| x.ok())
+            .find(|x| x.handle() == handle)
+    }
+}
+
+/// An iterator over the structures of a table, see `StructureTable::structures`.
+#[derive(Clone, Debug)]
+pub struct Structures<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Structures<'a> {
+    type Item = Result<Structure<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        match Structure::parse(self.data) {
+            Ok((x, len)) => {
+                self.data = match x.structure_type() {
+                    END_OF_TABLE => &[],
+                    _ => &self.data[len..],
+                };
+                Some(Ok(x))
+            }
+            Err(e) => {
+                self.data = &[];
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Build a structure from its type, the formatted area after the header and its strings, for the
+/// tests of the decoders.
+#[cfg(test)]
+pub(crate) fn make_structure(
+    structure_type: u8,
+    handle: u16,
+    body: &[u8],
+    strings: &[&str],
+) -> std::vec::Vec<u8> {
+    let mut data = std::vec![structure_type, (4 + body.len()) as u8];
+    data.extend_from_slice(&handle.to_le_bytes());
+    data.extend_from_slice(body);
+    for s in strings {
+        data.extend_from_slice(s.as_bytes());
+        data.push(0);
+    }
+    if strings.is_empty() {
+        data.push(0);
+    }
+    data.push(0);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn fix_checksum(data: &mut [u8], index: usize) {
+        data[index] = 0;
+        data[index] = 0u8.wrapping_sub(data.iter().fold(0u8, |acc, x| acc.wrapping_add(*x)));
+    }
+
+    #[test]
+    fn entry_points() {
+        let mut v3 = std::vec![0u8; ENTRY_POINT_V3_SIZE];
+        v3[..5].copy_from_slice(EntryPoint::ANCHOR_V3);
+        v3[6] = ENTRY_POINT_V3_SIZE as u8;
+        v3[7..10].copy_from_slice(&[3, 6, 0]);
+        v3[0x0c..0x10].copy_from_slice(&0x200u32.to_le_bytes());
+        v3[0x10..0x18].copy_from_slice(&0x1_0000_0000u64.to_le_bytes());
+        fix_checksum(&mut v3, 5);
+        let ep = unsafe { EntryPoint::from_ptr(v3.as_ptr().cast()) }.unwrap();
+        assert_eq!((ep.major_version, ep.minor_version), (3, 6));
+        assert_eq!(ep.table_address, 0x1_0000_0000);
+        assert_eq!(ep.number_of_structures, None);
+
+        let mut v2 = std::vec![0u8; ENTRY_POINT_V2_SIZE];
+        v2[..4].copy_from_slice(EntryPoint::ANCHOR_V2);
+        v2[5] = ENTRY_POINT_V2_SIZE as u8;
+        v2[6..8].copy_from_slice(&[2, 8]);
+        v2[0x10..0x15].copy_from_slice(EntryPoint::INTERMEDIATE_ANCHOR);
+        v2[0x16..0x18].copy_from_slice(&0x100u16.to_le_bytes());
+        v2[0x18..0x1c].copy_from_slice(&0xf0000u32.to_le_bytes());
+        v2[0x1c..0x1e].copy_from_slice(&12u16.to_le_bytes());
+        fix_checksum(&mut v2[0x10..], 5);
+        fix_checksum(&mut v2, 4);
+        let ep = EntryPoint::parse(&v2).unwrap();
+        assert_eq!((ep.table_address, ep.table_length), (0xf0000, 0x100));
+        assert_eq!(ep.number_of_structures, Some(12));
+
+        v2[0x18] = 1;
+        assert_eq!(EntryPoint::parse(&v2), Err(SmbiosError::InvalidChecksum));
+        assert_eq!(
+            EntryPoint::parse(b"_SM4_ and more"),
+            Err(SmbiosError::InvalidAnchor)
+        );
+    }
+
+    #[test]
+    fn walk_table() {
+        let mut data = Vec::new();
+        data.extend(make_structure(
+            1,
+            0x10,
+            &[1, 0, 2],
+            &["Vendor", "Product", "Other"],
+        ));
+        data.extend(make_structure(0x80, 0x11, &[], &[]));
+        data.extend(make_structure(END_OF_TABLE, 0xfeff, &[], &[]));
+        data.extend(make_structure(1, 0x12, &[], &[]));
+        let table = StructureTable::new(&data);
+
+        let handles: Vec<_> = table.structures().map(|x| x.unwrap().handle()).collect();
+        assert_eq!(handles, [0x10, 0x11, 0xfeff]);
+
+        let s = table.find_handle(0x10).unwrap();
+        assert_eq!(s.structure_type(), 1);
+        assert_eq!(s.string_at(4), Some("Vendor"));
+        assert_eq!(s.string_at(5), None);
+        assert_eq!(s.string(2), Some("Product"));
+        assert_eq!(s.string(3), Some("Other"));
+        assert_eq!(s.string(4), None);
+        assert_eq!(s.byte(7), None);
+        assert_eq!(table.find_handle(0x11).unwrap().strings().count(), 0);
+
+        let mut structures = StructureTable::new(&data[..32]).structures();
+        assert!(structures.next().unwrap().is_ok());
+        assert_eq!(structures.next().unwrap(), Err(SmbiosError::Truncated));
+        assert!(structures.next().is_none());
+    }
+}
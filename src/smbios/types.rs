@@ -0,0 +1,378 @@
+//! Typed views of the common SMBIOS structures.
+//!
+//! Structures grew with every SMBIOS version, so the accessors return `None` for fields past the
+//! end of an older, shorter structure, as well as for missing strings.
+
+use super::Structure;
+
+pub const BIOS_INFORMATION: u8 = 0;
+pub const SYSTEM_INFORMATION: u8 = 1;
+pub const BASEBOARD_INFORMATION: u8 = 2;
+pub const SYSTEM_ENCLOSURE: u8 = 3;
+pub const PROCESSOR_INFORMATION: u8 = 4;
+pub const MEMORY_DEVICE: u8 = 17;
+pub const MEMORY_ARRAY_MAPPED_ADDRESS: u8 = 19;
+
+/// A structure decoded by its type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decoded<'a> {
+    Bios(BiosInformation<'a>),
+    System(SystemInformation<'a>),
+    Baseboard(BaseboardInformation<'a>),
+    Enclosure(SystemEnclosure<'a>),
+    Processor(ProcessorInformation<'a>),
+    MemoryDevice(MemoryDevice<'a>),
+    MemoryArrayMappedAddress(MemoryArrayMappedAddress<'a>),
+    /// A structure without a typed view.
+    Other(Structure<'a>),
+}
+
+impl<'a> Decoded<'a> {
+    pub(super) fn new(s: Structure<'a>) -> Self {
+        match s.structure_type() {
+            BIOS_INFORMATION => Self::Bios(BiosInformation(s)),
+            SYSTEM_INFORMATION => Self::System(SystemInformation(s)),
+            BASEBOARD_INFORMATION => Self::Baseboard(BaseboardInformation(s)),
+            SYSTEM_ENCLOSURE => Self::Enclosure(SystemEnclosure(s)),
+            PROCESSOR_INFORMATION => Self::Processor(ProcessorInformation(s)),
+            MEMORY_DEVICE => Self::MemoryDevice(MemoryDevice(s)),
+            MEMORY_ARRAY_MAPPED_ADDRESS => {
+                Self::MemoryArrayMappedAddress(MemoryArrayMappedAddress(s))
+            }
+            _ => Self::Other(s),
+        }
+    }
+}
+
+/// Define a typed view over a `Structure`, with accessors for its fields.
+macro_rules! structure_view {
+    (
+        $(#[$meta:meta])*
+        $name:ident {
+            $($(#[$fmeta:meta])* $field:ident: $kind:ident @ $offset:expr,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub struct $name<'a>(Structure<'a>);
+
+        impl<'a> $name<'a> {
+            /// The underlying structure, for raw access to the other fields.
+            pub fn structure(&self) -> &Structure<'a> {
+                &self.0
+            }
+
+            $(
+                $(#[$fmeta])*
+                pub fn $field(&self) -> structure_view!(@type $kind) {
+                    structure_view!(@read self.0, $kind, $offset)
+                }
+            )*
+        }
+    };
+    (@type string) => { Option<&'a str> };
+    (@type byte) => { Option<u8> };
+    (@type word) => { Option<u16> };
+    (@type dword) => { Option<u32> };
+    (@type qword) => { Option<u64> };
+    (@read $s:expr, string, $offset:expr) => { $s.string_at($offset) };
+    (@read $s:expr, $kind:ident, $offset:expr) => { $s.$kind($offset) };
+}
+
+structure_view! {
+    /// Type 0, BIOS Information.
+    BiosInformation {
+        vendor: string @ 0x04,
+        version: string @ 0x05,
+        starting_address_segment: word @ 0x06,
+        release_date: string @ 0x08,
+        /// The ROM size in 64 KiB blocks, minus one. See `rom_size` for the decoded size.
+        rom_size_raw: byte @ 0x09,
+        characteristics: qword @ 0x0a,
+        system_bios_major_release: byte @ 0x14,
+        system_bios_minor_release: byte @ 0x15,
+        embedded_controller_major_release: byte @ 0x16,
+        embedded_controller_minor_release: byte @ 0x17,
+    }
+}
+
+impl BiosInformation<'_> {
+    /// The size of the BIOS ROM in bytes, using the extended size of SMBIOS 3.1 when needed.
+    pub fn rom_size(&self) -> Option<u64> {
+        match self.rom_size_raw()? {
+            0xff => {
+                let extended = self.0.word(0x18)?;
+                let size = u64::from(extended & 0x3fff);
+                match extended >> 14 {
+                    0 => Some(size << 20),
+                    1 => Some(size << 30),
+                    _ => None,
+                }
+            }
+            x => Some((u64::from(x) + 1) << 16),
+        }
+    }
+}
+
+structure_view! {
+    /// Type 1, System Information.
+    SystemInformation {
+        manufacturer: string @ 0x04,
+        product_name: string @ 0x05,
+        version: string @ 0x06,
+        serial_number: string @ 0x07,
+        wake_up_type: byte @ 0x18,
+        sku_number: string @ 0x19,
+        family: string @ 0x1a,
+    }
+}
+
+impl SystemInformation<'_> {
+    /// The system UUID, as stored. The first three fields are little-endian, as in `efi::Guid`.
+    /// `None` if unknown or not present.
+    pub fn uuid(&self) -> Option<[u8; 16]> {
+        let uuid: [u8; 16] = self.0.formatted().get(0x08..0x18)?.try_into().ok()?;
+        match uuid {
+            x if x == [0x00; 16] || x == [0xff; 16] => None,
+            x => Some(x),
+        }
+    }
+}
+
+structure_view! {
+    /// Type 2, Baseboard Information.
+    BaseboardInformation {
+        manufacturer: string @ 0x04,
+        product: string @ 0x05,
+        version: string @ 0x06,
+        serial_number: string @ 0x07,
+        asset_tag: string @ 0x08,
+        feature_flags: byte @ 0x09,
+        location_in_chassis: string @ 0x0a,
+        chassis_handle: word @ 0x0b,
+        board_type: byte @ 0x0d,
+    }
+}
+
+structure_view! {
+    /// Type 3, System Enclosure or Chassis.
+    SystemEnclosure {
+        manufacturer: string @ 0x04,
+        /// The chassis type in bits 0 to 6, and whether a lock is present in bit 7.
+        enclosure_type: byte @ 0x05,
+        version: string @ 0x06,
+        serial_number: string @ 0x07,
+        asset_tag: string @ 0x08,
+        boot_up_state: byte @ 0x09,
+        power_supply_state: byte @ 0x0a,
+        thermal_state: byte @ 0x0b,
+        security_status: byte @ 0x0c,
+    }
+}
+
+structure_view! {
+    /// Type 4, Processor Information.
+    ProcessorInformation {
+        socket_designation: string @ 0x04,
+        processor_type: byte @ 0x05,
+        /// The processor family, or 0xfe if given by `processor_family_2`.
+        processor_family: byte @ 0x06,
+        manufacturer: string @ 0x07,
+        /// The raw processor ID, e.g. the CPUID signature and feature flags on x86.
+        processor_id: qword @ 0x08,
+        version: string @ 0x10,
+        voltage: byte @ 0x11,
+        /// The external clock in MHz, 0 if unknown.
+        external_clock: word @ 0x12,
+        /// The maximum speed in MHz, 0 if unknown.
+        max_speed: word @ 0x14,
+        /// The current speed in MHz, 0 if unknown.
+        current_speed: word @ 0x16,
+        status: byte @ 0x18,
+        processor_upgrade: byte @ 0x19,
+        serial_number: string @ 0x20,
+        asset_tag: string @ 0x21,
+        part_number: string @ 0x22,
+        processor_characteristics: word @ 0x26,
+        processor_family_2: word @ 0x28,
+    }
+}
+
+impl ProcessorInformation<'_> {
+    /// Whether the socket is populated.
+    pub fn is_populated(&self) -> Option<bool> {
+        Some(self.status()? & 0x40 != 0)
+    }
+
+    /// The number of cores, using the SMBIOS 3.0 count if the byte field overflows.
+    pub fn core_count(&self) -> Option<u16> {
+        self.count(0x23, 0x2a)
+    }
+
+    pub fn core_enabled(&self) -> Option<u16> {
+        self.count(0x24, 0x2c)
+    }
+
+    pub fn thread_count(&self) -> Option<u16> {
+        self.count(0x25, 0x2e)
+    }
+
+    fn count(&self, offset: usize, offset_2: usize) -> Option<u16> {
+        match self.0.byte(offset)? {
+            0 => None,
+            0xff => self.0.word(offset_2).filter(|x| *x != 0),
+            x => Some(u16::from(x)),
+        }
+    }
+}
+
+structure_view! {
+    /// Type 17, Memory Device.
+    MemoryDevice {
+        physical_memory_array_handle: word @ 0x04,
+        total_width: word @ 0x08,
+        data_width: word @ 0x0a,
+        form_factor: byte @ 0x0e,
+        device_locator: string @ 0x10,
+        bank_locator: string @ 0x11,
+        memory_type: byte @ 0x12,
+        type_detail: word @ 0x13,
+        /// The maximum speed in MT/s, 0 if unknown.
+        speed: word @ 0x15,
+        manufacturer: string @ 0x17,
+        serial_number: string @ 0x18,
+        asset_tag: string @ 0x19,
+        part_number: string @ 0x1a,
+        /// The configured speed in MT/s, 0 if unknown.
+        configured_memory_speed: word @ 0x20,
+    }
+}
+
+impl MemoryDevice<'_> {
+    /// The size of the device in bytes, `Some(0)` if the slot is empty and `None` if unknown.
+    pub fn size(&self) -> Option<u64> {
+        match self.0.word(0x0c)? {
+            0xffff => None,
+            0x7fff => Some(u64::from(self.0.dword(0x1c)? & 0x7fff_ffff) << 20),
+            x if x & 0x8000 != 0 => Some(u64::from(x & 0x7fff) << 10),
+            x => Some(u64::from(x) << 20),
+        }
+    }
+}
+
+structure_view! {
+    /// Type 19, Memory Array Mapped Address.
+    MemoryArrayMappedAddress {
+        memory_array_handle: word @ 0x0c,
+        partition_width: byte @ 0x0e,
+    }
+}
+
+impl MemoryArrayMappedAddress<'_> {
+    /// The physical address of the first byte of the range.
+    pub fn start(&self) -> Option<u64> {
+        match self.0.dword(0x04)? {
+            0xffff_ffff => self.0.qword(0x0f),
+            x => Some(u64::from(x) << 10),
+        }
+    }
+
+    /// The physical address of the last byte of the range.
+    pub fn end(&self) -> Option<u64> {
+        match self.0.dword(0x04)? {
+            0xffff_ffff => self.0.qword(0x17),
+            _ => Some((u64::from(self.0.dword(0x08)?) << 10) | 0x3ff),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smbios::{make_structure, StructureTable};
+    use std::vec::Vec;
+
+    #[test]
+    fn system_and_bios() {
+        let mut body = std::vec![0u8; 0x1b - 4];
+        body[0] = 1;
+        body[1] = 2;
+        body[3] = 3;
+        body[4..20].copy_from_slice(&[0x11; 16]);
+        body[0x1a - 4] = 4;
+        let mut data = make_structure(1, 1, &body, &["Acme", "Rocket", "SN-1", "Gadgets"]);
+
+        let mut bios = std::vec![0u8; 0x18 - 4];
+        bios[0] = 1;
+        bios[5] = 0xff;
+        data.extend(make_structure(0, 0, &bios, &["Firmware Inc."]));
+        let table = StructureTable::new(&data);
+
+        let decoded: Vec<_> = table.structures().map(|x| x.unwrap().decode()).collect();
+        let system = match decoded[0] {
+            Decoded::System(x) => x,
+            _ => panic!("not a system information structure"),
+        };
+        assert_eq!(system.manufacturer(), Some("Acme"));
+        assert_eq!(system.product_name(), Some("Rocket"));
+        assert_eq!(system.version(), None);
+        assert_eq!(system.serial_number(), Some("SN-1"));
+        assert_eq!(system.family(), Some("Gadgets"));
+        assert_eq!(system.uuid(), Some([0x11; 16]));
+
+        let bios = match decoded[1] {
+            Decoded::Bios(x) => x,
+            _ => panic!("not a BIOS information structure"),
+        };
+        assert_eq!(bios.vendor(), Some("Firmware Inc."));
+        // The extended ROM size is past the end of the structure.
+        assert_eq!(bios.rom_size(), None);
+        assert_eq!(bios.embedded_controller_minor_release(), Some(0));
+    }
+
+    #[test]
+    fn processor_and_memory() {
+        let mut cpu = std::vec![0u8; 0x30 - 4];
+        cpu[0x18 - 4] = 0x41;
+        cpu[0x16 - 4..0x18 - 4].copy_from_slice(&3200u16.to_le_bytes());
+        cpu[0x23 - 4] = 0xff;
+        cpu[0x2a - 4..0x2c - 4].copy_from_slice(&512u16.to_le_bytes());
+        cpu[0x25 - 4] = 16;
+        let mut data = make_structure(4, 4, &cpu, &[]);
+
+        let mut dimm = std::vec![0u8; 0x22 - 4];
+        dimm[0x0c - 4..0x0e - 4].copy_from_slice(&0x7fffu16.to_le_bytes());
+        dimm[0x10 - 4] = 1;
+        dimm[0x1c - 4..0x20 - 4].copy_from_slice(&(64u32 * 1024).to_le_bytes());
+        data.extend(make_structure(17, 17, &dimm, &["DIMM 0"]));
+
+        let mut range = std::vec![0u8; 0x0f - 4];
+        range[0..4].copy_from_slice(&0x10_0000u32.to_le_bytes());
+        range[4..8].copy_from_slice(&0x1f_ffffu32.to_le_bytes());
+        data.extend(make_structure(19, 19, &range, &[]));
+        let table = StructureTable::new(&data);
+
+        let cpu = match table
+            .of_type(PROCESSOR_INFORMATION)
+            .next()
+            .unwrap()
+            .decode()
+        {
+            Decoded::Processor(x) => x,
+            _ => panic!("not a processor information structure"),
+        };
+        assert_eq!(cpu.is_populated(), Some(true));
+        assert_eq!(cpu.current_speed(), Some(3200));
+        assert_eq!(cpu.core_count(), Some(512));
+        assert_eq!(cpu.thread_count(), Some(16));
+        assert_eq!(cpu.core_enabled(), None);
+
+        let dimm = MemoryDevice(table.find_handle(17).unwrap());
+        assert_eq!(dimm.device_locator(), Some("DIMM 0"));
+        assert_eq!(dimm.size(), Some(64 << 30));
+
+        let range = MemoryArrayMappedAddress(table.find_handle(19).unwrap());
+        assert_eq!(range.start(), Some(0x4000_0000));
+        assert_eq!(range.end(), Some(0x7fff_ffff));
+    }
+}
@@ -0,0 +1,326 @@
+//! This module provides a typed view of the UEFI status codes.
+
+use core::fmt;
+
+use r_efi::efi::Status;
+
+use crate::errors::StatusError;
+
+macro_rules! status_codes {
+    ($($variant:ident => $status:ident, $name:literal;)*) => {
+        /// The status codes defined in Appendix D of the UEFI specification, plus the protocol
+        /// specific network errors.
+        /// Codes which are not known to this crate are kept as `Other`, holding the raw value.
+        /// Use `StatusCode::from` to construct one, so that known codes never end up in `Other`.
+        #[derive(Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum StatusCode {
+            $($variant,)*
+            Other(usize),
+        }
+
+        impl StatusCode {
+            /// The name used for this code in the specification, e.g. `EFI_NOT_FOUND`.
+            /// Returns `None` for codes unknown to this crate.
+            pub fn name(&self) -> Option<&'static str> {
+                match self {
+                    $(Self::$variant => Some($name),)*
+                    Self::Other(_) => None,
+                }
+            }
+
+            /// Convert back to the raw `efi::Status`.
+            pub fn as_status(&self) -> Status {
+                match self {
+                    $(Self::$variant => Status::$status,)*
+                    Self::Other(x) => Status::from_usize(*x),
+                }
+            }
+
+            /// Convert a raw `efi::Status`. The high bit is interpreted according to the width of
+            /// `usize` on the target.
+            pub fn from_status(status: Status) -> Self {
+                $(
+                    if status == Status::$status {
+                        return Self::$variant;
+                    }
+                )*
+                Self::Other(status.as_usize())
+            }
+        }
+    };
+}
+
+status_codes! {
+    Success => SUCCESS, "EFI_SUCCESS";
+
+    LoadError => LOAD_ERROR, "EFI_LOAD_ERROR";
+    InvalidParameter => INVALID_PARAMETER, "EFI_INVALID_PARAMETER";
+    Unsupported => UNSUPPORTED, "EFI_UNSUPPORTED";
+    BadBufferSize => BAD_BUFFER_SIZE, "EFI_BAD_BUFFER_SIZE";
+    BufferTooSmall => BUFFER_TOO_SMALL, "EFI_BUFFER_TOO_SMALL";
+    NotReady => NOT_READY, "EFI_NOT_READY";
+    DeviceError => DEVICE_ERROR, "EFI_DEVICE_ERROR";
+    WriteProtected => WRITE_PROTECTED, "EFI_WRITE_PROTECTED";
+    OutOfResources => OUT_OF_RESOURCES, "EFI_OUT_OF_RESOURCES";
+    VolumeCorrupted => VOLUME_CORRUPTED, "EFI_VOLUME_CORRUPTED";
+    VolumeFull => VOLUME_FULL, "EFI_VOLUME_FULL";
+    NoMedia => NO_MEDIA, "EFI_NO_MEDIA";
+    MediaChanged => MEDIA_CHANGED, "EFI_MEDIA_CHANGED";
+    NotFound => NOT_FOUND, "EFI_NOT_FOUND";
+    AccessDenied => ACCESS_DENIED, "EFI_ACCESS_DENIED";
+    NoResponse => NO_RESPONSE, "EFI_NO_RESPONSE";
+    NoMapping => NO_MAPPING, "EFI_NO_MAPPING";
+    Timeout => TIMEOUT, "EFI_TIMEOUT";
+    NotStarted => NOT_STARTED, "EFI_NOT_STARTED";
+    AlreadyStarted => ALREADY_STARTED, "EFI_ALREADY_STARTED";
+    Aborted => ABORTED, "EFI_ABORTED";
+    IcmpError => ICMP_ERROR, "EFI_ICMP_ERROR";
+    TftpError => TFTP_ERROR, "EFI_TFTP_ERROR";
+    ProtocolError => PROTOCOL_ERROR, "EFI_PROTOCOL_ERROR";
+    IncompatibleVersion => INCOMPATIBLE_VERSION, "EFI_INCOMPATIBLE_VERSION";
+    SecurityViolation => SECURITY_VIOLATION, "EFI_SECURITY_VIOLATION";
+    CrcError => CRC_ERROR, "EFI_CRC_ERROR";
+    EndOfMedia => END_OF_MEDIA, "EFI_END_OF_MEDIA";
+    EndOfFile => END_OF_FILE, "EFI_END_OF_FILE";
+    InvalidLanguage => INVALID_LANGUAGE, "EFI_INVALID_LANGUAGE";
+    CompromisedData => COMPROMISED_DATA, "EFI_COMPROMISED_DATA";
+    IpAddressConflict => IP_ADDRESS_CONFLICT, "EFI_IP_ADDRESS_CONFLICT";
+    HttpError => HTTP_ERROR, "EFI_HTTP_ERROR";
+
+    NetworkUnreachable => NETWORK_UNREACHABLE, "EFI_NETWORK_UNREACHABLE";
+    HostUnreachable => HOST_UNREACHABLE, "EFI_HOST_UNREACHABLE";
+    ProtocolUnreachable => PROTOCOL_UNREACHABLE, "EFI_PROTOCOL_UNREACHABLE";
+    PortUnreachable => PORT_UNREACHABLE, "EFI_PORT_UNREACHABLE";
+    ConnectionFin => CONNECTION_FIN, "EFI_CONNECTION_FIN";
+    ConnectionReset => CONNECTION_RESET, "EFI_CONNECTION_RESET";
+    ConnectionRefused => CONNECTION_REFUSED, "EFI_CONNECTION_REFUSED";
+
+    WarnUnknownGlyph => WARN_UNKNOWN_GLYPH, "EFI_WARN_UNKNOWN_GLYPH";
+    WarnDeleteFailure => WARN_DELETE_FAILURE, "EFI_WARN_DELETE_FAILURE";
+    WarnWriteFailure => WARN_WRITE_FAILURE, "EFI_WARN_WRITE_FAILURE";
+    WarnBufferTooSmall => WARN_BUFFER_TOO_SMALL, "EFI_WARN_BUFFER_TOO_SMALL";
+    WarnStaleData => WARN_STALE_DATA, "EFI_WARN_STALE_DATA";
+    WarnFileSystem => WARN_FILE_SYSTEM, "EFI_WARN_FILE_SYSTEM";
+    WarnResetRequired => WARN_RESET_REQUIRED, "EFI_WARN_RESET_REQUIRED";
+}
+
+impl StatusCode {
+    pub fn is_error(&self) -> bool {
+        self.as_status().is_error()
+    }
+
+    pub fn is_warning(&self) -> bool {
+        self.as_status().is_warning()
+    }
+
+    /// The raw integer value, as defined by the specification.
+    pub fn as_usize(&self) -> usize {
+        self.as_status().as_usize()
+    }
+}
+
+impl From<Status> for StatusCode {
+    fn from(x: Status) -> Self {
+        Self::from_status(x)
+    }
+}
+
+impl From<StatusCode> for Status {
+    fn from(x: StatusCode) -> Self {
+        x.as_status()
+    }
+}
+
+impl fmt::Debug for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "EFI_STATUS({:#x})", self.as_usize()),
+        }
+    }
+}
+
+/// The successful result of a UEFI call.
+/// UEFI functions may succeed with a warning status, in which case the output is still valid. The
+/// warning is kept alongside the value, so callers can decide what to do with it.
+#[must_use]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Completion<T> {
+    value: T,
+    warning: Option<StatusCode>,
+}
+
+impl<T> Completion<T> {
+    /// A completion without warning.
+    pub fn new(value: T) -> Self {
+        Self {
+            value,
+            warning: None,
+        }
+    }
+
+    /// A completion which carries `warning`.
+    pub fn with_warning(value: T, warning: StatusCode) -> Self {
+        Self {
+            value,
+            warning: Some(warning),
+        }
+    }
+
+    /// The warning returned by the firmware, if any.
+    pub fn warning(&self) -> Option<StatusCode> {
+        self.warning
+    }
+
+    pub fn is_warning(&self) -> bool {
+        self.warning.is_some()
+    }
+
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// Return the value, discarding the warning.
+    pub fn into_value(self) -> T {
+        self.value
+    }
+
+    pub fn into_parts(self) -> (T, Option<StatusCode>) {
+        (self.value, self.warning)
+    }
+
+    /// Map the value, keeping the warning.
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Completion<U> {
+        Completion {
+            value: f(self.value),
+            warning: self.warning,
+        }
+    }
+
+    /// Return the value, or the warning as `StatusError::UefiWarning`.
+    pub fn strict(self) -> Result<T, StatusError> {
+        match self.warning {
+            Some(w) => Err(StatusError::UefiWarning(w)),
+            None => Ok(self.value),
+        }
+    }
+}
+
+/// How warnings returned by the wrappers in this crate should be handled.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum WarningPolicy {
+    /// Treat warnings as success. This is what the specification mandates.
+    #[default]
+    Ignore,
+    /// Promote warnings to `UefiWarning` errors.
+    Promote,
+}
+
+impl WarningPolicy {
+    /// Apply the policy to the result of any wrapper in this crate.
+    pub fn apply<T, E>(self, r: Result<Completion<T>, E>) -> Result<T, E>
+    where
+        E: From<StatusError>,
+    {
+        let c = r?;
+        match self {
+            Self::Ignore => Ok(c.into_value()),
+            Self::Promote => c.strict().map_err(E::from),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::format;
+
+    const HIGH_BIT: usize = 1 << (usize::BITS - 1);
+
+    #[test]
+    fn round_trip() {
+        for raw in (0..40).chain(100..110) {
+            for v in [raw, raw | HIGH_BIT] {
+                let status = Status::from_usize(v);
+                let code = StatusCode::from(status);
+                assert_eq!(Status::from(code), status);
+            }
+        }
+    }
+
+    #[test]
+    fn high_bit() {
+        assert_eq!(
+            StatusCode::from(Status::from_usize(14 | HIGH_BIT)),
+            StatusCode::NotFound
+        );
+        assert_eq!(
+            StatusCode::from(Status::from_usize(1)),
+            StatusCode::WarnUnknownGlyph
+        );
+        assert!(StatusCode::NotFound.is_error());
+        assert!(StatusCode::WarnUnknownGlyph.is_warning());
+        assert!(!StatusCode::Success.is_error());
+        assert!(!StatusCode::Success.is_warning());
+    }
+
+    #[test]
+    fn unknown_code() {
+        let status = Status::from_usize(29 | HIGH_BIT);
+        let code = StatusCode::from(status);
+        assert_eq!(code, StatusCode::Other(29 | HIGH_BIT));
+        assert!(code.is_error());
+        assert_eq!(code.name(), None);
+        assert_eq!(
+            format!("{}", code),
+            format!("EFI_STATUS({:#x})", 29 | HIGH_BIT)
+        );
+    }
+
+    #[test]
+    fn completion_strict() {
+        assert_eq!(Completion::new(1).strict(), Ok(1));
+        assert_eq!(
+            Completion::with_warning(1, StatusCode::WarnStaleData).strict(),
+            Err(StatusError::UefiWarning(StatusCode::WarnStaleData))
+        );
+    }
+
+    #[test]
+    fn warning_policy() {
+        let r = || -> Result<_, StatusError> {
+            Ok(Completion::with_warning(1, StatusCode::WarnUnknownGlyph))
+        };
+        assert_eq!(WarningPolicy::Ignore.apply(r()), Ok(1));
+        assert_eq!(
+            WarningPolicy::Promote.apply(r()),
+            Err(StatusError::UefiWarning(StatusCode::WarnUnknownGlyph))
+        );
+
+        let r: Result<Completion<u32>, StatusError> =
+            Err(StatusError::UefiError(StatusCode::NotFound));
+        assert_eq!(
+            WarningPolicy::Ignore.apply(r),
+            Err(StatusError::UefiError(StatusCode::NotFound))
+        );
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(
+            format!("{}", StatusCode::BufferTooSmall),
+            "EFI_BUFFER_TOO_SMALL"
+        );
+        assert_eq!(format!("{:?}", StatusCode::Success), "EFI_SUCCESS");
+        assert_eq!(
+            format!("{:?}", StatusCode::WarnUnknownGlyph),
+            "EFI_WARN_UNKNOWN_GLYPH"
+        );
+    }
+}
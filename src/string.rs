@@ -0,0 +1,507 @@
+//! This module provides NUL-terminated UCS-2 string types, as used by the UEFI interfaces.
+//!
+//! `CStr16` is the borrowed form and works without `alloc`. `CString16` is the owned form and
+//! needs the `alloc` feature.
+//! Both guarantee a terminating NUL and no interior NUL. Strings created through the checked
+//! constructors also contain no surrogates, since UEFI uses UCS-2 rather than UTF-16.
+
+use core::fmt;
+
+use crate::errors::Ucs2Error;
+
+#[cfg(any(test, feature = "alloc"))]
+use alloc::{borrow::ToOwned, string::String, vec::Vec};
+
+const NUL: u16 = 0;
+const REPLACEMENT_CHARACTER: u16 = 0xfffd;
+
+#[inline]
+fn is_surrogate(c: u16) -> bool {
+    (0xd800..=0xdfff).contains(&c)
+}
+
+/// Check that `s` has no NUL and no surrogates.
+fn validate(s: &[u16]) -> Result<(), Ucs2Error> {
+    for (index, &c) in s.iter().enumerate() {
+        if c == NUL {
+            return Err(Ucs2Error::InteriorNul { index });
+        }
+        if is_surrogate(c) {
+            return Err(Ucs2Error::Surrogate { index });
+        }
+    }
+    Ok(())
+}
+
+/// Convert a single `char` to UCS-2.
+/// In lossy mode, characters which cannot be represented are replaced with U+FFFD.
+#[inline]
+fn encode_char(c: char, index: usize, lossy: bool) -> Result<u16, Ucs2Error> {
+    let x = c as u32;
+    if x == 0 {
+        if lossy {
+            Ok(REPLACEMENT_CHARACTER)
+        } else {
+            Err(Ucs2Error::InteriorNul { index })
+        }
+    } else if x > 0xffff {
+        if lossy {
+            Ok(REPLACEMENT_CHARACTER)
+        } else {
+            Err(Ucs2Error::Unrepresentable { index })
+        }
+    } else {
+        Ok(x as u16)
+    }
+}
+
+/// Convert a single `char` to UCS-2, replacing characters which cannot be represented with
+/// U+FFFD.
+#[inline]
+pub(crate) fn encode_char_lossy(c: char) -> u16 {
+    encode_char(c, 0, true).unwrap_or(REPLACEMENT_CHARACTER)
+}
+
+/// Encode `s` into `buf` and append a NUL.
+fn encode_str_with_buf<'a>(
+    s: &str,
+    buf: &'a mut [u16],
+    lossy: bool,
+) -> Result<&'a CStr16, Ucs2Error> {
+    let mut len = 0;
+    for c in s.chars() {
+        // Every character takes one code unit, so `len` is also its index.
+        let c = encode_char(c, len, lossy)?;
+        // Keep space for the NUL.
+        if len + 1 >= buf.len() {
+            return Err(Ucs2Error::BufferTooSmall);
+        }
+        buf[len] = c;
+        len += 1;
+    }
+
+    let nul = buf.get_mut(len).ok_or(Ucs2Error::BufferTooSmall)?;
+    *nul = NUL;
+    Ok(unsafe { CStr16::from_u16_with_nul_unchecked(&buf[..=len]) })
+}
+
+/// A borrowed NUL-terminated UCS-2 string.
+#[repr(transparent)]
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CStr16([u16]);
+
+impl CStr16 {
+    /// Create a `CStr16` from a slice whose last element is the only NUL.
+    /// Surrogates are rejected.
+    pub fn from_u16_with_nul(s: &[u16]) -> Result<&Self, Ucs2Error> {
+        match s.split_last() {
+            Some((&NUL, rest)) => {
+                validate(rest)?;
+                Ok(unsafe { Self::from_u16_with_nul_unchecked(s) })
+            }
+            _ => Err(Ucs2Error::NotNulTerminated),
+        }
+    }
+
+    /// Create a `CStr16` from the part of `s` up to and including the first NUL.
+    /// Surrogates are rejected.
+    pub fn from_u16_until_nul(s: &[u16]) -> Result<&Self, Ucs2Error> {
+        let nul = s
+            .iter()
+            .position(|&c| c == NUL)
+            .ok_or(Ucs2Error::NotNulTerminated)?;
+        Self::from_u16_with_nul(&s[..=nul])
+    }
+
+    /// # Safety
+    /// `s` must end with a NUL and contain no other NUL.
+    pub unsafe fn from_u16_with_nul_unchecked(s: &[u16]) -> &Self {
+        unsafe { &*(s as *const [u16] as *const Self) }
+    }
+
+    /// Create a `CStr16` from a NUL-terminated string handed out by the firmware.
+    /// Surrogates are rejected.
+    ///
+    /// # Safety
+    /// `ptr` must be non-null and point to a NUL-terminated string which stays valid and
+    /// unmodified for `'a`.
+    pub unsafe fn from_ptr<'a>(ptr: *const u16) -> Result<&'a Self, Ucs2Error> {
+        let mut len = 0;
+        while unsafe { *ptr.add(len) } != NUL {
+            len += 1;
+        }
+        Self::from_u16_with_nul(unsafe { core::slice::from_raw_parts(ptr, len + 1) })
+    }
+
+    /// Encode `s` into `buf` without allocating.
+    /// Fails if `s` contains a NUL or a character outside the Basic Multilingual Plane, or if
+    /// `buf` cannot hold the string and its NUL.
+    pub fn from_str_with_buf<'a>(s: &str, buf: &'a mut [u16]) -> Result<&'a Self, Ucs2Error> {
+        encode_str_with_buf(s, buf, false)
+    }
+
+    /// Encode `s` into `buf` without allocating, replacing characters which cannot be
+    /// represented with U+FFFD.
+    /// Fails only if `buf` cannot hold the string and its NUL.
+    pub fn from_str_lossy_with_buf<'a>(s: &str, buf: &'a mut [u16]) -> Result<&'a Self, Ucs2Error> {
+        encode_str_with_buf(s, buf, true)
+    }
+
+    /// The pointer to pass to the firmware.
+    pub fn as_ptr(&self) -> *const u16 {
+        self.0.as_ptr()
+    }
+
+    /// The characters, without the terminating NUL.
+    pub fn as_slice(&self) -> &[u16] {
+        &self.0[..self.0.len() - 1]
+    }
+
+    /// The characters, including the terminating NUL.
+    pub fn as_slice_with_nul(&self) -> &[u16] {
+        &self.0
+    }
+
+    /// Number of characters, not including the NUL.
+    pub fn len(&self) -> usize {
+        self.0.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Size in bytes, including the NUL, as expected by many UEFI functions.
+    pub fn size_in_bytes(&self) -> usize {
+        core::mem::size_of_val(&self.0)
+    }
+
+    /// Iterate over the characters. Surrogates, which can only be present if the string was
+    /// created unchecked, are replaced with U+FFFD.
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.as_slice()
+            .iter()
+            .map(|&c| char::from_u32(u32::from(c)).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    #[cfg(any(test, feature = "alloc"))]
+    pub fn to_string_lossy(&self) -> String {
+        self.chars().collect()
+    }
+}
+
+impl PartialEq<str> for CStr16 {
+    fn eq(&self, other: &str) -> bool {
+        self.chars().eq(other.chars())
+    }
+}
+
+impl PartialEq<&str> for CStr16 {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl AsRef<CStr16> for CStr16 {
+    fn as_ref(&self) -> &CStr16 {
+        self
+    }
+}
+
+impl fmt::Display for CStr16 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.chars() {
+            fmt::Write::write_char(f, c)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for CStr16 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in self.chars().flat_map(char::escape_debug) {
+            fmt::Write::write_char(f, c)?;
+        }
+        f.write_str("\"")
+    }
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl ToOwned for CStr16 {
+    type Owned = CString16;
+
+    fn to_owned(&self) -> CString16 {
+        CString16(self.0.to_vec())
+    }
+}
+
+/// An owned NUL-terminated UCS-2 string.
+#[cfg(any(test, feature = "alloc"))]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CString16(Vec<u16>);
+
+#[cfg(any(test, feature = "alloc"))]
+impl CString16 {
+    /// An empty string.
+    pub fn new() -> Self {
+        Self(alloc::vec![NUL])
+    }
+
+    /// Encode `s`. Fails if `s` contains a NUL or a character outside the Basic Multilingual
+    /// Plane.
+    pub fn try_from_str(s: &str) -> Result<Self, Ucs2Error> {
+        let mut r = Self::new();
+        r.push_str(s)?;
+        Ok(r)
+    }
+
+    /// Encode `s`, replacing characters which cannot be represented with U+FFFD.
+    pub fn from_str_lossy(s: &str) -> Self {
+        let mut v = Vec::with_capacity(s.len() + 1);
+        for c in s.chars() {
+            v.push(encode_char_lossy(c));
+        }
+        v.push(NUL);
+        Self(v)
+    }
+
+    /// Copy a string received from the firmware. Everything from the first NUL onwards is
+    /// dropped and surrogates are replaced with U+FFFD.
+    pub fn from_u16_lossy(s: &[u16]) -> Self {
+        let mut v: Vec<u16> = s
+            .iter()
+            .take_while(|&&c| c != NUL)
+            .map(|&c| {
+                if is_surrogate(c) {
+                    REPLACEMENT_CHARACTER
+                } else {
+                    c
+                }
+            })
+            .collect();
+        v.push(NUL);
+        Self(v)
+    }
+
+    /// Take ownership of a buffer whose last element is the only NUL.
+    pub fn from_vec_with_nul(v: Vec<u16>) -> Result<Self, Ucs2Error> {
+        CStr16::from_u16_with_nul(&v)?;
+        Ok(Self(v))
+    }
+
+    /// Append a single character.
+    pub fn push(&mut self, c: char) -> Result<(), Ucs2Error> {
+        let c = encode_char(c, self.len(), false)?;
+        self.0.insert(self.0.len() - 1, c);
+        Ok(())
+    }
+
+    /// Append `s`. Nothing is appended if `s` cannot be encoded.
+    pub fn push_str(&mut self, s: &str) -> Result<(), Ucs2Error> {
+        let len = self.0.len() - 1;
+        self.0.pop();
+        for (i, c) in s.chars().enumerate() {
+            match encode_char(c, len + i, false) {
+                Ok(c) => self.0.push(c),
+                Err(e) => {
+                    self.0.truncate(len);
+                    self.0.push(NUL);
+                    return Err(e);
+                }
+            }
+        }
+        self.0.push(NUL);
+        Ok(())
+    }
+
+    pub fn as_c_str16(&self) -> &CStr16 {
+        unsafe { CStr16::from_u16_with_nul_unchecked(&self.0) }
+    }
+
+    /// The underlying buffer, including the NUL.
+    pub fn into_vec_with_nul(self) -> Vec<u16> {
+        self.0
+    }
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl Default for CString16 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl core::ops::Deref for CString16 {
+    type Target = CStr16;
+
+    fn deref(&self) -> &CStr16 {
+        self.as_c_str16()
+    }
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl core::borrow::Borrow<CStr16> for CString16 {
+    fn borrow(&self) -> &CStr16 {
+        self.as_c_str16()
+    }
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl AsRef<CStr16> for CString16 {
+    fn as_ref(&self) -> &CStr16 {
+        self.as_c_str16()
+    }
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl From<&CStr16> for CString16 {
+    fn from(x: &CStr16) -> Self {
+        x.to_owned()
+    }
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl TryFrom<&str> for CString16 {
+    type Error = Ucs2Error;
+
+    fn try_from(x: &str) -> Result<Self, Self::Error> {
+        Self::try_from_str(x)
+    }
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl PartialEq<str> for CString16 {
+    fn eq(&self, other: &str) -> bool {
+        self.as_c_str16() == other
+    }
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl PartialEq<&str> for CString16 {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_c_str16() == *other
+    }
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl fmt::Display for CString16 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_c_str16(), f)
+    }
+}
+
+#[cfg(any(test, feature = "alloc"))]
+impl fmt::Debug for CString16 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_c_str16(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::format;
+
+    #[test]
+    fn from_u16_with_nul() {
+        let s = CStr16::from_u16_with_nul(&[0x0048, 0x0069, 0x0000]).unwrap();
+        assert_eq!(s, "Hi");
+        assert_eq!(s.len(), 2);
+        assert_eq!(s.size_in_bytes(), 6);
+
+        assert_eq!(
+            CStr16::from_u16_with_nul(&[0x0048, 0x0069]),
+            Err(Ucs2Error::NotNulTerminated)
+        );
+        assert_eq!(
+            CStr16::from_u16_with_nul(&[0x0048, 0x0000, 0x0069, 0x0000]),
+            Err(Ucs2Error::InteriorNul { index: 1 })
+        );
+        assert_eq!(
+            CStr16::from_u16_with_nul(&[0xd83d, 0xde00, 0x0000]),
+            Err(Ucs2Error::Surrogate { index: 0 })
+        );
+    }
+
+    #[test]
+    fn from_u16_until_nul() {
+        let s = CStr16::from_u16_until_nul(&[0x0041, 0x0000, 0x0042, 0x0000]).unwrap();
+        assert_eq!(s, "A");
+        assert!(CStr16::from_u16_until_nul(&[0x0000]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn from_str_with_buf() {
+        let mut buf = [0u16; 4];
+        let s = CStr16::from_str_with_buf("abc", &mut buf).unwrap();
+        assert_eq!(s.as_slice_with_nul(), &[0x61, 0x62, 0x63, 0x00]);
+
+        let mut buf = [0u16; 3];
+        assert_eq!(
+            CStr16::from_str_with_buf("abc", &mut buf),
+            Err(Ucs2Error::BufferTooSmall)
+        );
+
+        let mut buf = [0u16; 1];
+        assert!(CStr16::from_str_with_buf("", &mut buf).unwrap().is_empty());
+    }
+
+    #[test]
+    fn from_str_strict_and_lossy() {
+        let mut buf = [0u16; 8];
+        assert_eq!(
+            CStr16::from_str_with_buf("a\u{1F600}", &mut buf),
+            Err(Ucs2Error::Unrepresentable { index: 1 })
+        );
+        assert_eq!(
+            CStr16::from_str_with_buf("a\0b", &mut buf),
+            Err(Ucs2Error::InteriorNul { index: 1 })
+        );
+        // The index counts code units, not UTF-8 bytes.
+        assert_eq!(
+            CStr16::from_str_with_buf("\u{e9}\0", &mut buf),
+            Err(Ucs2Error::InteriorNul { index: 1 })
+        );
+
+        let s = CStr16::from_str_lossy_with_buf("a\u{1F600}é", &mut buf).unwrap();
+        assert_eq!(s.as_slice(), &[0x61, 0xfffd, 0xe9]);
+    }
+
+    #[test]
+    fn owned() {
+        let mut s = CString16::try_from_str("Hello").unwrap();
+        s.push(' ').unwrap();
+        s.push_str("World").unwrap();
+        assert_eq!(s, "Hello World");
+        assert_eq!(s.as_slice_with_nul().last(), Some(&0));
+
+        assert_eq!(
+            s.push_str("\u{e9}\u{1F600}"),
+            Err(Ucs2Error::Unrepresentable { index: 12 })
+        );
+        assert_eq!(s, "Hello World");
+
+        assert_eq!(CString16::from_str_lossy("x\u{1F600}"), "x\u{FFFD}");
+        assert_eq!(
+            CString16::from_u16_lossy(&[0x0041, 0xd800, 0x0000, 0x0042]),
+            "A\u{FFFD}"
+        );
+        assert!(CString16::new().is_empty());
+    }
+
+    #[test]
+    fn formatting() {
+        let s = CString16::try_from_str("a\"b").unwrap();
+        assert_eq!(format!("{}", s), "a\"b");
+        assert_eq!(format!("{:?}", s), "\"a\\\"b\"");
+    }
+
+    #[test]
+    fn from_ptr() {
+        let buf = [0x0041u16, 0x0042, 0x0000, 0x0043];
+        let s = unsafe { CStr16::from_ptr(buf.as_ptr()) }.unwrap();
+        assert_eq!(s, "AB");
+    }
+}